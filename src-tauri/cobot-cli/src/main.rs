@@ -0,0 +1,721 @@
+//! Headless control of the COBOT, for CI and automated test rigs.
+//!
+//! ```text
+//! cobot-cli [options] <command> [command options]
+//!
+//! Options:
+//!     --port <name>       Serial port of the COBOT. Required by every command but `list-ports`,
+//!                         unless the plan given to `run-plan` names one.
+//!     --baud <rate>       Baud rate of the serial port. Defaults to 115200.
+//!     --json              Print one JSON object per line instead of text.
+//!     --no-wait           Return once the COBOT acknowledges a move, calibration or stop,
+//!                         without waiting for it to finish.
+//!     --escape-payloads   Escape start bytes in payloads. The firmware must escape them too.
+//!
+//! Commands:
+//!     list-ports
+//!     init
+//!     calibrate [--joints <joint,...>]
+//!     move --joint <joint> --angle <degrees> [--speed <degrees per second>]
+//!     get-joints
+//!     stop (--all | --joints <joint,...>) [--immediately]
+//!     run-plan <plan.yaml>
+//! ```
+//!
+//! `calibrate` calibrates every joint unless given `--joints`. `run-plan` runs a test plan in the
+//! format the config tester reads, described in `cobot_comms::plan`. Angles and speeds are
+//! sent to the firmware as given, since the display frame and units are settings of the config
+//! tester. For the same reason, `enable_motion` steps do nothing here.
+//!
+//! Each invocation opens its own connection, so the joints are assumed to have been calibrated by
+//! an earlier one. The firmware still rejects moves of joints it hasn't calibrated.
+//!
+//! The exit code tells apart what went wrong:
+//!
+//! | Code | Meaning                                                         |
+//! |------|-----------------------------------------------------------------|
+//! | 0    | Success                                                         |
+//! | 2    | Invalid arguments or plan                                       |
+//! | 3    | The COBOT couldn't be reached, or stopped answering             |
+//! | 4    | The firmware returned an error                                  |
+//! | 5    | An `expect_angle` or `expect_stopped` step of a plan failed     |
+
+use std::{
+    error::Error,
+    fs,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use cobot_comms::{
+    plan::{self, Action, Step},
+    CobotConnection, CobotError, CommsError, JointMask, JointState, FIRMWARE_VERSION,
+};
+use log::info;
+use serde::Serialize;
+use serde_json::json;
+
+/// Baud rate used if none is given.
+const DEFAULT_BAUD_RATE: u32 = 115200;
+
+/// Time to wait for a response to a request.
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Time between readings of the joints while an expectation is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Exit code for invalid arguments or an invalid plan.
+const EXIT_USAGE: u8 = 2;
+
+/// Exit code for a failure to reach the COBOT or to communicate with it.
+const EXIT_CONNECTION: u8 = 3;
+
+/// Exit code for an error returned by the firmware.
+const EXIT_FIRMWARE: u8 = 4;
+
+/// Exit code for a failed expectation in a plan.
+const EXIT_ASSERTION: u8 = 5;
+
+const USAGE: &str = "\
+Usage: cobot-cli [--port <name>] [--baud <rate>] [--json] [--no-wait] <command>
+
+Commands:
+    list-ports
+    init
+    calibrate [--joints <joint,...>]
+    move --joint <joint> --angle <degrees> [--speed <degrees per second>]
+    get-joints
+    stop (--all | --joints <joint,...>) [--immediately]
+    run-plan <plan.yaml>";
+
+/// Invalid arguments or plan.
+#[derive(Debug)]
+struct UsageError(String);
+impl std::fmt::Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl Error for UsageError {}
+
+/// Failed expectation in a plan.
+#[derive(Debug)]
+struct AssertionError(String);
+impl std::fmt::Display for AssertionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl Error for AssertionError {}
+
+/// Kind of failure, deciding the exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum FailureKind {
+    Usage,
+    Connection,
+    Firmware,
+    Assertion,
+}
+
+impl FailureKind {
+    /// Classify an error. An argument the library refuses to send is a usage error, and a request
+    /// the firmware doesn't support a firmware error. Anything that isn't a usage error, a
+    /// firmware error or a failed expectation came from reaching the COBOT or talking to it.
+    fn of(error: &(dyn Error + 'static)) -> Self {
+        match error.downcast_ref::<CommsError>() {
+            Some(CommsError::InvalidArgument { .. }) => FailureKind::Usage,
+            Some(CommsError::Unsupported { .. }) => FailureKind::Firmware,
+            _ if error.is::<UsageError>() => FailureKind::Usage,
+            _ if error.is::<CobotError>() => FailureKind::Firmware,
+            _ if error.is::<AssertionError>() => FailureKind::Assertion,
+            _ => FailureKind::Connection,
+        }
+    }
+
+    /// Exit code for the failure.
+    fn exit_code(self) -> u8 {
+        match self {
+            FailureKind::Usage => EXIT_USAGE,
+            FailureKind::Connection => EXIT_CONNECTION,
+            FailureKind::Firmware => EXIT_FIRMWARE,
+            FailureKind::Assertion => EXIT_ASSERTION,
+        }
+    }
+}
+
+/// Options that apply to every command.
+#[derive(Debug)]
+struct Options {
+    /// Serial port of the COBOT.
+    port_name: Option<String>,
+
+    /// Baud rate of the serial port.
+    baud_rate: u32,
+
+    /// Whether output is one JSON object per line.
+    json: bool,
+
+    /// Whether moves, calibrations and stops wait for the COBOT to finish them.
+    wait: bool,
+
+    /// Whether payloads are escaped so they never contain a start byte.
+    escape_payloads: bool,
+}
+
+/// Command to run.
+#[derive(Debug)]
+enum Command {
+    ListPorts,
+    Init,
+    Calibrate {
+        joints: Option<JointMask>,
+    },
+    Move {
+        joint: u8,
+        angle: f32,
+        speed: Option<f32>,
+    },
+    GetJoints,
+    Stop {
+        joints: Option<JointMask>,
+        immediately: bool,
+    },
+    RunPlan {
+        path: String,
+    },
+}
+
+/// Outcome of a single step of a plan.
+#[derive(Debug, Serialize)]
+struct StepReport<'a> {
+    index: usize,
+    step: &'a Step,
+    passed: bool,
+    error: Option<String>,
+    kind: Option<FailureKind>,
+    duration_ms: u64,
+}
+
+/// Parse the value of an option.
+///
+/// # Arguments
+///
+/// * `args` - Remaining arguments, the next of which is the value.
+/// * `flag` - Option the value belongs to, for the error message.
+fn value<T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+) -> Result<T, UsageError> {
+    let value = args
+        .next()
+        .ok_or_else(|| UsageError(format!("{} needs a value", flag)))?;
+    value
+        .parse()
+        .map_err(|_| UsageError(format!("Invalid value for {}: {}", flag, value)))
+}
+
+/// Parse a comma-separated list of joints into a mask.
+fn joint_list(list: &str) -> Result<JointMask, UsageError> {
+    list.split(',')
+        .try_fold(JointMask::default(), |mask, joint| {
+            match joint.trim().parse::<u8>() {
+                Ok(joint) if joint < JointMask::MAX_JOINTS => Ok(mask | JointMask::joint(joint)),
+                _ => Err(UsageError(format!("Invalid joint: {}", joint))),
+            }
+        })
+}
+
+/// Parse the command line. Global options may come before or after the command.
+fn parse_args(args: impl Iterator<Item = String>) -> Result<(Options, Command), UsageError> {
+    let mut options = Options {
+        port_name: None,
+        baud_rate: DEFAULT_BAUD_RATE,
+        json: false,
+        wait: true,
+        escape_payloads: false,
+    };
+    let mut command = None;
+    let mut rest = Vec::new();
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => options.port_name = Some(value(&mut args, "--port")?),
+            "--baud" => options.baud_rate = value(&mut args, "--baud")?,
+            "--json" => options.json = true,
+            "--no-wait" => options.wait = false,
+            "--escape-payloads" => options.escape_payloads = true,
+            _ if command.is_none() && !arg.starts_with('-') => command = Some(arg),
+            _ => rest.push(arg),
+        }
+    }
+
+    let command = command.ok_or_else(|| UsageError("No command given".into()))?;
+    let mut joint = None;
+    let mut angle = None;
+    let mut speed = None;
+    let mut joints = None;
+    let mut all = false;
+    let mut immediately = false;
+    let mut positional = Vec::new();
+
+    let mut rest = rest.into_iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--joint" => joint = Some(value(&mut rest, "--joint")?),
+            "--angle" => angle = Some(value(&mut rest, "--angle")?),
+            "--speed" => speed = Some(value(&mut rest, "--speed")?),
+            "--joints" => joints = Some(joint_list(&value::<String>(&mut rest, "--joints")?)?),
+            "--all" => all = true,
+            "--immediately" => immediately = true,
+            _ if arg.starts_with('-') => {
+                return Err(UsageError(format!("Unknown option: {}", arg)))
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let command = match command.as_str() {
+        "list-ports" => Command::ListPorts,
+        "init" => Command::Init,
+        "calibrate" => Command::Calibrate { joints },
+        "move" => Command::Move {
+            joint: joint.ok_or_else(|| UsageError("move needs --joint".into()))?,
+            angle: angle.ok_or_else(|| UsageError("move needs --angle".into()))?,
+            speed,
+        },
+        "get-joints" => Command::GetJoints,
+        "stop" => {
+            if all == joints.is_some() {
+                return Err(UsageError("stop needs one of --all or --joints".into()));
+            }
+            Command::Stop {
+                joints,
+                immediately,
+            }
+        }
+        "run-plan" => Command::RunPlan {
+            path: positional
+                .pop()
+                .ok_or_else(|| UsageError("run-plan needs a plan file".into()))?,
+        },
+        _ => return Err(UsageError(format!("Unknown command: {}", command))),
+    };
+    if !positional.is_empty() {
+        return Err(UsageError(format!(
+            "Unexpected argument: {}",
+            positional[0]
+        )));
+    }
+
+    Ok((options, command))
+}
+
+/// Open a serial port and check that a COBOT answers on it.
+fn open(
+    options: &Options,
+    port_name: &str,
+    baud_rate: u32,
+) -> Result<CobotConnection, Box<dyn Error>> {
+    let port = serialport::new(port_name, baud_rate)
+        .timeout(Duration::from_millis(1000))
+        .open()
+        .map_err(|e| format!("Failed to open {}: {}", port_name, e))?;
+    let mut cobot = CobotConnection::new(port, FIRMWARE_VERSION, RESPONSE_TIMEOUT);
+    cobot.set_payload_escaping(options.escape_payloads);
+    cobot.probe()?;
+    cobot.assume_calibrated(JointMask::first(JointMask::MAX_JOINTS));
+    info!("Connected to {} at {} baud", port_name, baud_rate);
+
+    Ok(cobot)
+}
+
+/// Get a mask of every joint on the COBOT, reading the joints first to learn how many there are.
+fn all_joints(cobot: &mut CobotConnection) -> Result<JointMask, Box<dyn Error>> {
+    if cobot.joint_count().is_none() {
+        cobot.get_joints()?;
+    }
+    Ok(cobot.all_joints())
+}
+
+/// Print the outcome of a command that either finished or was only acknowledged.
+fn print_completion(options: &Options, command: &str) {
+    let status = if options.wait { "done" } else { "acknowledged" };
+    if options.json {
+        println!("{}", json!({ "command": command, "status": status }));
+    } else {
+        println!("{}: {}", command, status);
+    }
+}
+
+/// Print the state of every joint.
+fn print_joints(options: &Options, states: &[JointState]) {
+    for (joint, state) in states.iter().enumerate() {
+        if options.json {
+            println!(
+                "{}",
+                json!({
+                    "joint": joint,
+                    "angle": state.angle,
+                    "speed": state.speed,
+                    "current_ma": state.current_ma,
+                })
+            );
+            continue;
+        }
+        print!(
+            "joint {}: {:.3} deg, {:.3} deg/s",
+            joint, state.angle, state.speed
+        );
+        match state.current_ma {
+            Some(current_ma) => println!(", {} mA", current_ma),
+            None => println!(),
+        }
+    }
+}
+
+/// Move a joint, waiting for the move to finish unless told not to.
+fn move_joint(
+    cobot: &mut CobotConnection,
+    options: &Options,
+    joint: u8,
+    angle: f32,
+    speed: Option<f32>,
+) -> Result<(), Box<dyn Error>> {
+    let command_id = cobot.start_move_to(&[(joint, angle, speed)])?;
+    if options.wait {
+        cobot.wait_for_done(command_id)?;
+    }
+    Ok(())
+}
+
+/// Calibrate joints, waiting for the calibration to finish unless told not to.
+fn calibrate(
+    cobot: &mut CobotConnection,
+    options: &Options,
+    joints: JointMask,
+) -> Result<(), Box<dyn Error>> {
+    if options.wait {
+        cobot.calibrate(joints)
+    } else {
+        cobot.start_calibrate(joints).map(|_| ())
+    }
+}
+
+/// Stop joints, waiting for them to stop unless told not to.
+fn stop(
+    cobot: &mut CobotConnection,
+    options: &Options,
+    joints: JointMask,
+    immediately: bool,
+) -> Result<(), Box<dyn Error>> {
+    if options.wait {
+        cobot.stop(joints, immediately)
+    } else {
+        cobot.start_stop(joints, immediately).map(|_| ())
+    }
+}
+
+/// Wait for a joint to reach an angle and stay within the tolerance for the settle time.
+fn expect_angle(
+    cobot: &mut CobotConnection,
+    joint: u8,
+    target: f32,
+    tolerance: f32,
+    timeout: Duration,
+    settle: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    let mut in_band_since = None;
+    loop {
+        let angles = cobot.get_joints()?;
+        let &(achieved, _) = angles
+            .get(joint as usize)
+            .ok_or_else(|| UsageError(format!("Joint {} doesn't exist", joint)))?;
+
+        let now = Instant::now();
+        if (achieved - target).abs() <= tolerance {
+            let since = *in_band_since.get_or_insert(now);
+            if now - since >= settle {
+                return Ok(());
+            }
+        } else {
+            in_band_since = None;
+        }
+
+        if now - start >= timeout {
+            return Err(Box::new(AssertionError(format!(
+                "Joint {} at {}, expected {} ± {}",
+                joint, achieved, target, tolerance
+            ))));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Wait for the given joints to slow to within a speed threshold.
+fn expect_stopped(
+    cobot: &mut CobotConnection,
+    joints: &[u8],
+    speed_threshold: f32,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    loop {
+        let sample = cobot.get_joints()?;
+        let speeds = joints
+            .iter()
+            .map(|&joint| {
+                sample
+                    .get(joint as usize)
+                    .map(|&(_, speed)| speed)
+                    .ok_or_else(|| UsageError(format!("Joint {} doesn't exist", joint)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if speeds.iter().all(|speed| speed.abs() <= speed_threshold) {
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(Box::new(AssertionError(format!(
+                "Joints {:?} still moving at {:?}",
+                joints, speeds
+            ))));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Run a single step of a plan.
+fn run_step(
+    cobot: &mut CobotConnection,
+    options: &Options,
+    action: &Action,
+) -> Result<(), Box<dyn Error>> {
+    match *action {
+        Action::Init { force } => {
+            if force || !cobot.is_initialized() {
+                cobot.init()?;
+            }
+        }
+        Action::EnableMotion => {}
+        Action::Calibrate { .. } => calibrate(cobot, options, action.joints())?,
+        Action::MoveJoint {
+            joint,
+            angle,
+            speed,
+        } => move_joint(cobot, options, joint, angle, speed)?,
+        Action::StopAll { immediately } => {
+            let all_joints = all_joints(cobot)?;
+            stop(cobot, options, all_joints, immediately)?;
+        }
+        Action::Wait { ms } => std::thread::sleep(Duration::from_millis(ms)),
+        Action::ExpectAngle {
+            joint,
+            angle,
+            tolerance,
+            within_ms,
+            settle_ms,
+        } => expect_angle(
+            cobot,
+            joint,
+            angle,
+            tolerance,
+            Duration::from_millis(within_ms),
+            Duration::from_millis(settle_ms),
+        )?,
+        Action::ExpectStopped {
+            ref joints,
+            speed_threshold,
+            within_ms,
+        } => expect_stopped(
+            cobot,
+            joints,
+            speed_threshold,
+            Duration::from_millis(within_ms),
+        )?,
+    }
+
+    Ok(())
+}
+
+/// Read a plan, connect and run every step, printing the outcome of each.
+///
+/// # Returns
+///
+/// The kind of the first failure that stopped the plan or was let through, if any, or an error
+/// if the plan couldn't be read or the COBOT couldn't be reached.
+fn run_plan(options: &Options, path: &str) -> Result<Option<FailureKind>, Box<dyn Error>> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| UsageError(format!("Failed to read {}: {}", path, e)))?;
+    let plan = plan::parse(&source).map_err(UsageError)?;
+    let steps = plan.steps;
+
+    let (port_name, baud_rate) = match (&options.port_name, plan.connect) {
+        (Some(port_name), _) => (port_name.clone(), options.baud_rate),
+        (None, Some(connect)) => (connect.port_name, connect.baud_rate),
+        (None, None) => return Err(Box::new(UsageError("No port given".into()))),
+    };
+    let mut cobot = open(options, &port_name, baud_rate)?;
+
+    let mut failure = None;
+    let mut steps_run = 0;
+    for (index, step) in steps.iter().enumerate() {
+        let start = Instant::now();
+        let result = run_step(&mut cobot, options, &step.action);
+        steps_run += 1;
+
+        let kind = result.as_ref().err().map(|e| FailureKind::of(e.as_ref()));
+        let report = StepReport {
+            index,
+            step,
+            passed: result.is_ok(),
+            error: result.err().map(|e| e.to_string()),
+            kind,
+            duration_ms: start.elapsed().as_millis() as u64,
+        };
+        if options.json {
+            println!("{}", serde_json::to_string(&report)?);
+        } else {
+            match &report.error {
+                None => println!("[{}/{}] {:?}: passed", index + 1, steps.len(), step.action),
+                Some(error) => println!(
+                    "[{}/{}] {:?}: FAILED: {}",
+                    index + 1,
+                    steps.len(),
+                    step.action,
+                    error
+                ),
+            }
+        }
+
+        failure = failure.or(kind);
+        if kind.is_some() && !step.continue_on_failure {
+            break;
+        }
+    }
+
+    if options.json {
+        println!(
+            "{}",
+            json!({
+                "plan": plan.name,
+                "passed": failure.is_none(),
+                "steps_run": steps_run,
+                "total_steps": steps.len(),
+            })
+        );
+    } else {
+        println!(
+            "{}: {} ({} of {} steps run)",
+            plan.name.as_deref().unwrap_or(path),
+            if failure.is_none() {
+                "passed"
+            } else {
+                "FAILED"
+            },
+            steps_run,
+            steps.len()
+        );
+    }
+
+    Ok(failure)
+}
+
+/// Run a command.
+///
+/// # Returns
+///
+/// The kind of failure a plan ended with, if any, or the error that stopped the command.
+fn run(options: &Options, command: Command) -> Result<Option<FailureKind>, Box<dyn Error>> {
+    let connect = || -> Result<CobotConnection, Box<dyn Error>> {
+        let port_name = options
+            .port_name
+            .as_deref()
+            .ok_or_else(|| UsageError("No port given".into()))?;
+        open(options, port_name, options.baud_rate)
+    };
+
+    match command {
+        Command::ListPorts => {
+            for port in serialport::available_ports()? {
+                if options.json {
+                    println!("{}", json!({ "port_name": port.port_name }));
+                } else {
+                    println!("{}", port.port_name);
+                }
+            }
+        }
+        Command::Init => {
+            connect()?.init()?;
+            print_completion(options, "init");
+        }
+        Command::Calibrate { joints } => {
+            let mut cobot = connect()?;
+            let joints = match joints {
+                Some(joints) => joints,
+                None => all_joints(&mut cobot)?,
+            };
+            calibrate(&mut cobot, options, joints)?;
+            print_completion(options, "calibrate");
+        }
+        Command::Move {
+            joint,
+            angle,
+            speed,
+        } => {
+            move_joint(&mut connect()?, options, joint, angle, speed)?;
+            print_completion(options, "move");
+        }
+        Command::GetJoints => {
+            let states = connect()?.get_joint_states()?;
+            print_joints(options, &states);
+        }
+        Command::Stop {
+            joints,
+            immediately,
+        } => {
+            let mut cobot = connect()?;
+            let joints = match joints {
+                Some(joints) => joints,
+                None => all_joints(&mut cobot)?,
+            };
+            stop(&mut cobot, options, joints, immediately)?;
+            print_completion(options, "stop");
+        }
+        Command::RunPlan { path } => return run_plan(options, &path),
+    }
+
+    Ok(None)
+}
+
+fn main() -> ExitCode {
+    flexi_logger::Logger::try_with_env_or_str("warn")
+        .unwrap()
+        .start()
+        .unwrap();
+
+    let (options, command) = match parse_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let kind = match run(&options, command) {
+        Ok(None) => return ExitCode::SUCCESS,
+        Ok(Some(kind)) => kind,
+        Err(e) => {
+            let kind = FailureKind::of(e.as_ref());
+            if options.json {
+                println!("{}", json!({ "error": e.to_string(), "kind": kind }));
+            } else {
+                eprintln!("{}", e);
+            }
+            kind
+        }
+    };
+    ExitCode::from(kind.exit_code())
+}
@@ -1,3 +1,6 @@
+//! CRC-8/CCITT (polynomial 0x07), which checks the payload of every frame.
+
+/// Remainder of every byte value, for computing the CRC a byte at a time.
 const CRC_TABLE: [u8; 256] = [
     0x00, 0x07, 0x0E, 0x09, 0x1C, 0x1B, 0x12, 0x15, 0x38, 0x3F, 0x36, 0x31, 0x24, 0x23, 0x2A, 0x2D,
     0x70, 0x77, 0x7E, 0x79, 0x6C, 0x6B, 0x62, 0x65, 0x48, 0x4F, 0x46, 0x41, 0x54, 0x53, 0x5A, 0x5D,
@@ -17,6 +20,7 @@ const CRC_TABLE: [u8; 256] = [
     0xDE, 0xD9, 0xD0, 0xD7, 0xC2, 0xC5, 0xCC, 0xCB, 0xE6, 0xE1, 0xE8, 0xEF, 0xFA, 0xFD, 0xF4, 0xF3,
 ];
 
+/// Compute the CRC of some data.
 pub fn crc8ccitt(data: &[u8]) -> u8 {
     let mut val = 0;
 
@@ -27,6 +31,7 @@ pub fn crc8ccitt(data: &[u8]) -> u8 {
     val
 }
 
+/// Check some data against the CRC it was sent with.
 pub fn crc8ccitt_check(data: &[u8], checksum: u8) -> bool {
     crc8ccitt(data) == checksum
 }
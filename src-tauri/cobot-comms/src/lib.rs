@@ -0,0 +1,4205 @@
+//! Implementation of the COBOT's serial protocol: framing and parsing of messages, the requests
+//! and responses, and a `CobotConnection` that drives a COBOT over any `Transport`.
+//!
+//! Logging goes through the `log` facade, so the application chooses where it ends up. The
+//! `serialport` feature, on by default, lets a connection run over a serial port. Without it, the
+//! codec and `MockTransport` are still available. The `plan` feature, also on by default, adds
+//! the format of YAML test plans, in `plan`.
+//!
+//! Responses that no request has claimed yet are kept in a buffer of at most 64, which never grows:
+//! when it's full, the oldest is discarded. By default the buffer is a `Vec` allocated once when
+//! connecting. The `no-alloc` feature makes it an `ArrayVec` stored inline in the connection
+//! instead, so keeping a response never touches the allocator, at the cost of a connection a few
+//! kilobytes larger that is more expensive to move. Payloads are still `Vec<u8>` either way, as
+//! protocol version 2 allows payloads of up to 65535 bytes, too many to reserve for each response.
+//!
+//! # Binary Protocol
+//!
+//! Each message begins with a 3-byte header followed by a payload.
+//!
+//! | Byte | Description                |
+//! | ---- | -------------------------- |
+//! | 0    | Start byte (0x24)          |
+//! | 1    | Payload length             |
+//! | 2    | CRC of payload (crc8ccitt) |
+//! | 3... | Payload                    |
+//!
+//! Once protocol version 2 has been negotiated on init, the payload length is 2 bytes instead, so
+//! payloads can be longer than 255 bytes:
+//!
+//! | Byte | Description                |
+//! | ---- | -------------------------- |
+//! | 0    | Start byte (0x24)          |
+//! | 1-2  | Payload length (uint16)    |
+//! | 3    | CRC of payload (crc8ccitt) |
+//! | 4... | Payload                    |
+//!
+//! ### Payload Escaping
+//!
+//! A decoder that loses its place looks for the next start byte, and may find one inside a
+//! payload. With payload escaping, which both ends must have enabled, no payload byte is ever a
+//! start byte:
+//!
+//! | Byte | Sent as      |
+//! | ---- | ------------ |
+//! | 0x24 | 0x25 0x44    |
+//! | 0x25 | 0x25 0x45    |
+//! | Else | Byte as is   |
+//!
+//! The escape byte 0x25 is followed by the original byte XORed with 0x60. The payload length
+//! counts the escaped bytes, while the CRC is of the payload before escaping.
+//!
+//! ## Outgoing Message Payloads
+//!
+//! ### Log
+//!
+//! | Byte | Description    |
+//! | ---- | -------------- |
+//! | 0    | 0x00 (log)     |
+//! | 1    | Log level      |
+//! | 2    | Message length |
+//! | 3... | Message        |
+//!
+//! From firmware version 6, the log level is followed by a byte tagging the firmware subsystem the
+//! message came from (0 for none):
+//!
+//! | Byte | Description    |
+//! | ---- | -------------- |
+//! | 0    | 0x00 (log)     |
+//! | 1    | Log level      |
+//! | 2    | Subsystem tag  |
+//! | 3    | Message length |
+//! | 4... | Message        |
+//!
+//! ### Fault
+//!
+//! Sent by the COBOT on its own whenever a fault occurs, without any request outstanding.
+//!
+//! | Byte | Description  |
+//! | ---- | ------------ |
+//! | 0    | 0x02 (fault) |
+//! | 1    | Fault code   |
+//! | 2    | Severity     |
+//! | 3... | Message      |
+//!
+//! ### Response
+//!
+//! | Byte | Description      |
+//! | ---- | ---------------- |
+//! | 0    | 0x01 (response)  |
+//! | 1    | Response type    |
+//! | 2-5  | Command ID       |
+//! | 6... | Response payload |
+//!
+//! #### Ack Response
+//!
+//! No payload, except in response to an Init that offers protocol version 2:
+//!
+//! | Byte | Description               |
+//! | ---- | ------------------------- |
+//! | 0    | Protocol version selected |
+//!
+//! Firmware that only speaks protocol version 1 sends no payload.
+//!
+//! #### Done Response
+//!
+//! No payload
+//!
+//! ### Error Response
+//!
+//! | Byte | Description          |
+//! | ---- | -------------------- |
+//! | 0    | Error code           |
+//! | 1    | Error message length |
+//! | 2... | Error message        |
+//!
+//! #### Joints Response
+//!
+//! | Byte     | Description                              |
+//! | -------- | ---------------------------------------- |
+//! | 0        | Number of joints                         |
+//! | N + 1-4  | Joint N angle (int32) (deg \* 10^-3)     |
+//! | N + 5-8  | Joint N speed (int32) (deg \* 10^-3) / s |
+//! | N + 9-10 | Joint N motor current (int16) (mA)       |
+//!
+//! The motor current is only sent by newer firmware. Which layout is in use is detected from the
+//! payload length.
+//!
+//! While feedback is streamed (see Set Feedback), the COBOT also sends Joints responses on its
+//! own, with the reserved command ID 0xFFFFFFFF.
+//!
+//! #### Info Response
+//!
+//! A sequence of tag-length-value entries, in any order:
+//!
+//! | Byte | Description  |
+//! | ---- | ------------ |
+//! | 0    | Tag          |
+//! | 1    | Value length |
+//! | 2... | Value        |
+//!
+//! | Tag  | Value                                                       |
+//! | ---- | ----------------------------------------------------------- |
+//! | 0x01 | Uptime (uint32) (s)                                         |
+//! | 0x02 | Board temperature (int16) (°C \* 0.1)                       |
+//! | 0x03 | Supply voltage (uint16) (mV)                                |
+//! | 0x04 | Joint ID, then joint driver temperature (int16) (°C \* 0.1) |
+//!
+//! Unknown tags are skipped. If a tag is repeated, the last value wins.
+//!
+//! #### Error Log Response
+//!
+//! | Byte     | Description                |
+//! | -------- | -------------------------- |
+//! | 0        | Number of entries          |
+//! | N + 0    | Entry N error code         |
+//! | N + 1-4  | Entry N timestamp (uint32) |
+//! | N + 5    | Entry N message length     |
+//! | N + 6... | Entry N message            |
+//!
+//! #### Full Status Response
+//!
+//! | Byte     | Description                              |
+//! | -------- | ---------------------------------------- |
+//! | 0        | Number of joints                         |
+//! | N + 1-4  | Joint N angle (int32) (deg \* 10^-3)     |
+//! | N + 5-8  | Joint N speed (int32) (deg \* 10^-3) / s |
+//! | M        | Error flags                              |
+//! | M + 1... | Bitfield of joints with feedback enabled |
+//!
+//! where M is 1 + 8 \* the number of joints.
+//!
+//! ## Incoming Message Payloads
+//!
+//! | Byte | Description  |
+//! | ---- | ------------ |
+//! | 0    | Request type |
+//! | 1-4  | Command ID   |
+//!
+//! ### Init
+//!
+//! | Byte  | Description                                 |
+//! | ----- | ------------------------------------------- |
+//! | 0 - 3 | Expected firmware version                   |
+//! | 4     | Highest protocol version offered (optional) |
+//!
+//! ### Calibrate
+//!
+//! | Byte   | Description                     |
+//! | ------ | ------------------------------- |
+//! | 0 (-1) | Bitfield of joints to calibrate |
+//!
+//! ### Override
+//!
+//! | Byte    | Description                      |
+//! | ------- | -------------------------------- |
+//! | N + 0   | Joint ID                         |
+//! | N + 1-4 | New angle (int32) (deg \* 10^-3) |
+//!
+//! ### Get Joints
+//!
+//! No payload
+//!
+//! ### Move To
+//!
+//! | Byte    | Description                      |
+//! | ------- | -------------------------------- |
+//! | N + 0   | Joint ID                         |
+//! | N + 1-4 | New angle (int32) (deg \* 10^-3) |
+//! | N + 5-8 | Speed (int32) (deg \* 10^-3) / s |
+//!
+//! ### Move Speed
+//!
+//! | Byte    | Description                      |
+//! | ------- | -------------------------------- |
+//! | N + 0   | Joint ID                         |
+//! | N + 1-4 | Speed (int32) (deg \* 10^-3) / s |
+//!
+//! ### Follow Trajectory
+//!
+//! For N in [0 - 5]:
+//!
+//! | Byte    | Description                         |
+//! | ------- | ----------------------------------- |
+//! | N + 0-3 | Target angle (int32) (deg \* 10^-3) |
+//! | N + 4-7 | Speed (int32) (deg \* 10^-3) / s    |
+//!
+//! ### Stop
+//!
+//! | Byte   | Description                |
+//! | ------ | -------------------------- |
+//! | 0      | Stop immediately?          |
+//! | 1 (-2) | Bitfield of joints to stop |
+//!
+//! ### Go Home
+//!
+//! | Byte   | Description                   |
+//! | ------ | ----------------------------- |
+//! | 0 (-1) | Bitfield of joints to go home |
+//!
+//! ### Reset
+//!
+//! | Byte | Description                                       |
+//! | ---- | ------------------------------------------------- |
+//! | 0    | Optional. Reset mode (0 = normal, 1 = bootloader) |
+//!
+//! When resetting into the bootloader, the COBOT only sends an ACK before it stops responding.
+//! Firmware without a bootloader rejects the mode byte as a malformed request.
+//!
+//! ### Set Log Level
+//!
+//! | Byte | Description                 |
+//! | ---- | --------------------------- |
+//! | 0    | Log level (0-3, 4 for none) |
+//!
+//! ### Set Feedback
+//!
+//! | Byte     | Description                                   |
+//! | -------- | --------------------------------------------- |
+//! | 0 (-1)   | Bitfield of joints to enable/disable feedback |
+//! | 1 (or 2) | Optional. Reporting period (10 ms)            |
+//!
+//! With a reporting period, the COBOT streams the joints with feedback enabled as Joints responses
+//! at that period. The period byte is understood from firmware version 7. Older firmware ignores
+//! it and only reports joints when asked, so it isn't sent to them.
+//!
+//! ### Set Servo
+//!
+//! | Byte     | Description                          |
+//! | -------- | ------------------------------------ |
+//! | 0 (-1)   | Bitfield of joints to enable/disable |
+//! | 1 (or 2) | Enable servos?                       |
+//!
+//! ### Set Gripper
+//!
+//! | Byte | Description                         |
+//! | ---- | ----------------------------------- |
+//! | 0-1  | Target opening (uint16) (mm \* 0.1) |
+//! | 2    | Force (0 for the default)           |
+//!
+//! ### Get Info
+//!
+//! No payload
+//!
+//! ### Get Error Log
+//!
+//! No payload
+//!
+//! ### Get Full Status
+//!
+//! No payload
+//!
+//! ## Joint Bitfields
+//!
+//! Bitfields of joints are a single byte when the COBOT has up to 8 joints. When the JOINTS
+//! response reports more than 8 joints, bitfields are 2 bytes, little-endian.
+
+pub mod checksum;
+#[cfg(feature = "plan")]
+pub mod plan;
+pub mod targets;
+pub mod transport;
+
+#[cfg(test)]
+mod tests;
+
+use crate::checksum::{crc8ccitt, crc8ccitt_check};
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+pub use targets::{Target, TargetQueue};
+use tokio::sync::{broadcast, mpsc};
+pub use transport::{MockTransport, Transport};
+
+/// Byte every frame begins with.
+const START_BYTE: u8 = 0x24;
+
+/// Firmware version this host is written against. Sent to the COBOT on init.
+pub const FIRMWARE_VERSION: u32 = 5;
+
+/// Default time to wait for a DONE response, which long operations such as calibration need.
+pub const DEFAULT_CALIBRATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// First firmware version whose log messages carry a subsystem tag.
+const LOG_TAG_FIRMWARE_VERSION: u32 = 6;
+
+/// First firmware version that streams feedback at a requested period.
+const FEEDBACK_PERIOD_FIRMWARE_VERSION: u32 = 7;
+
+/// Unit of the feedback reporting period.
+const FEEDBACK_PERIOD_UNIT: Duration = Duration::from_millis(10);
+
+/// Command ID of the Joints responses the COBOT streams on its own.
+pub const STREAM_COMMAND_ID: u32 = 0xFFFF_FFFF;
+
+/// Number of faults from the COBOT kept for debug reports.
+const RECENT_FAULT_CAPACITY: usize = 20;
+
+/// Original framing, with a 1-byte payload length.
+pub const PROTOCOL_V1: u8 = 1;
+
+/// Framing with a 2-byte payload length, negotiated on init.
+pub const PROTOCOL_V2: u8 = 2;
+
+/// Number of round trips measured by a loopback test.
+const LOOPBACK_ROUND_TRIPS: u32 = 10;
+
+/// Time the COBOT has to answer the probe sent when connecting.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Maximum number of traffic events kept for grading the link.
+const LINK_HISTORY_CAPACITY: usize = 4096;
+
+/// Number of log messages from the COBOT kept for debug reports.
+const RECENT_LOG_CAPACITY: usize = 50;
+
+/// Number of requests and responses kept in the session log.
+const SESSION_LOG_CAPACITY: usize = 10_000;
+
+/// Number of `CommsEvent`s buffered for each subscriber. A subscriber that falls further behind
+/// misses the oldest events.
+const COMMS_EVENT_CAPACITY: usize = 256;
+
+/// Interval between joint polls while waiting for contact.
+const CONTACT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Interval between joint polls during a monitored speed move.
+const MONITOR_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Time after starting a contact move during which a slow joint is assumed to still be
+/// accelerating, unless it reaches `CONTACT_ARM_RATIO` of the commanded speed sooner.
+const CONTACT_SPIN_UP: Duration = Duration::from_millis(500);
+
+/// Fraction of the commanded speed that arms contact detection once reached.
+const CONTACT_ARM_RATIO: f32 = 0.5;
+
+/// Fraction of the commanded speed below which an armed joint is considered stalled.
+const CONTACT_STALL_RATIO: f32 = 0.2;
+
+/// Number of consecutive stalled polls needed to report contact.
+const CONTACT_STALL_SAMPLES: u32 = 3;
+
+/// Longest time a wait blocks on the serial port before checking for cancellation.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time an unclaimed response is kept before it's discarded.
+const RESPONSE_EXPIRY: Duration = Duration::from_secs(30);
+
+/// Most unclaimed responses kept at once. The buffer is allocated at this size when connecting and
+/// never grows, so receiving a response doesn't reallocate it. Far more than are ever pending.
+const RESPONSE_CAPACITY: usize = 64;
+
+/// Longest payload of a protocol version 1 frame, whose length is a single byte.
+const MAX_V1_PAYLOAD_SIZE: usize = u8::MAX as usize;
+
+// Under protocol version 1 alone, payloads could be fixed-size arrays, and a full response buffer
+// would still fit in 16 KiB.
+const _: () = assert!(RESPONSE_CAPACITY * MAX_V1_PAYLOAD_SIZE <= 16 * 1024);
+
+/// Buffer of unclaimed responses and the time they were received, at most `RESPONSE_CAPACITY`.
+#[cfg(not(feature = "no-alloc"))]
+type ResponseBuffer = Vec<(Response, Instant)>;
+
+/// Buffer of unclaimed responses and the time they were received, at most `RESPONSE_CAPACITY`.
+#[cfg(feature = "no-alloc")]
+type ResponseBuffer = arrayvec::ArrayVec<(Response, Instant), RESPONSE_CAPACITY>;
+
+/// Interval between checks for an abort while a joint calibrates.
+const CALIBRATION_ABORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Interval between joint polls while watching a move for stalls.
+const STALL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time a single write to the serial port may block before it counts as stalled.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Number of writes in a row that may stall before a frame is abandoned.
+const WRITE_ATTEMPTS: u32 = 3;
+
+/// Map of error codes to error messages.
+pub const ERROR_CODES: [&str; 8] = [
+    "Other",
+    "Malformed request",
+    "Out of range",
+    "Invalid joint",
+    "Not initialized",
+    "Not calibrated",
+    "Cancelled",
+    "Invalid firmware version",
+];
+
+/// Modes the COBOT can reset into.
+pub mod reset_mode {
+    pub const _NORMAL: u8 = 0x00;
+    pub const BOOTLOADER: u8 = 0x01;
+}
+
+/// Log levels used by the COBOT.
+pub mod log_level {
+    pub const DEBUG: u8 = 0x00;
+    pub const INFO: u8 = 0x01;
+    pub const WARN: u8 = 0x02;
+    pub const ERROR: u8 = 0x03;
+    pub const NONE: u8 = 0x04;
+
+    /// Every log level, with its name.
+    pub const NAMES: [(u8, &str); 5] = [
+        (DEBUG, "DEBUG"),
+        (INFO, "INFO"),
+        (WARN, "WARN"),
+        (ERROR, "ERROR"),
+        (NONE, "NONE"),
+    ];
+}
+
+/// Message types that can be received from the COBOT
+pub mod received_msg_type {
+    pub const LOG: u8 = 0x00;
+    pub const RESPONSE: u8 = 0x01;
+    pub const FAULT: u8 = 0x02;
+}
+
+/// Type of response message.
+pub mod response_type {
+    pub const ACK: u8 = 0x00;
+    pub const DONE: u8 = 0x01;
+    pub const ERROR: u8 = 0x02;
+    pub const JOINTS: u8 = 0x03;
+    pub const INFO: u8 = 0x04;
+    pub const ERROR_LOG: u8 = 0x05;
+    pub const FULL_STATUS: u8 = 0x06;
+
+    /// Every response type, with its name.
+    pub const NAMES: [(u8, &str); 7] = [
+        (ACK, "ACK"),
+        (DONE, "DONE"),
+        (ERROR, "ERROR"),
+        (JOINTS, "JOINTS"),
+        (INFO, "INFO"),
+        (ERROR_LOG, "ERROR_LOG"),
+        (FULL_STATUS, "FULL_STATUS"),
+    ];
+}
+
+/// Get the name of a response type, for logs and error messages.
+///
+/// # Arguments
+///
+/// * `t` - Response type.
+///
+/// # Returns
+///
+/// The name of the response type, or `"Unknown(N)"` with the raw value if it isn't a known type.
+pub fn response_type_str(t: u8) -> Cow<'static, str> {
+    response_type::NAMES
+        .iter()
+        .find(|(value, _)| *value == t)
+        .map_or_else(
+            || Cow::Owned(format!("Unknown({})", t)),
+            |(_, name)| Cow::Borrowed(*name),
+        )
+}
+
+/// Check that a value is finite before it's encoded, since casting NaN or infinity to an integer
+/// silently gives 0 or the integer's limit.
+///
+/// # Arguments
+///
+/// * `value` - Value to check.
+/// * `field` - Name of the value, for the error.
+fn validate_finite(value: f32, field: &'static str) -> Result<(), CommsError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(CommsError::InvalidArgument {
+            field,
+            reason: "not finite",
+        })
+    }
+}
+
+/// Grade a link from the traffic events recorded over a window, by whichever rate is worse: CRC
+/// errors per message read, or timeouts per request sent. A window without traffic is good.
+///
+/// # Arguments
+///
+/// * `events` - Events recorded over the window.
+/// * `thresholds` - Rates to grade the link by.
+fn grade_link(
+    events: impl IntoIterator<Item = LinkEvent>,
+    thresholds: &LinkQualityThresholds,
+) -> LinkQuality {
+    let (mut crc_errors, mut messages, mut requests, mut timeouts) = (0.0, 0.0, 0.0, 0.0);
+    for event in events {
+        match event {
+            LinkEvent::CrcError => {
+                crc_errors += 1.0;
+                messages += 1.0;
+            }
+            LinkEvent::MessageReceived => messages += 1.0,
+            LinkEvent::RequestSent => requests += 1.0,
+            LinkEvent::Timeout => timeouts += 1.0,
+        }
+    }
+
+    let rate = |failures: f32, total: f32| if total > 0.0 { failures / total } else { 0.0 };
+    let worst = rate(crc_errors, messages).max(rate(timeouts, requests));
+    if worst >= thresholds.bad_rate {
+        LinkQuality::Bad
+    } else if worst >= thresholds.degraded_rate {
+        LinkQuality::Degraded
+    } else {
+        LinkQuality::Good
+    }
+}
+
+/// Compute the speed a joint must move at to reach a target angle in a given time.
+///
+/// # Arguments
+///
+/// * `current_angle` - Angle of the joint now, in degrees.
+/// * `target_angle` - Angle to move to, in degrees.
+/// * `desired_duration` - Time the move should take.
+///
+/// # Returns
+///
+/// The speed in degrees per second. Infinite if the duration is zero and the joint has to move.
+pub fn compute_feed_forward_speed(
+    current_angle: f32,
+    target_angle: f32,
+    desired_duration: Duration,
+) -> f32 {
+    let distance = (target_angle - current_angle).abs();
+    if distance == 0.0 {
+        return 0.0;
+    }
+    distance / desired_duration.as_secs_f32()
+}
+
+/// Format bytes as space-separated hex, such as `"24 02 A1"`.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse bytes formatted by `to_hex`. Any whitespace between the bytes is accepted.
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    hex.split_whitespace()
+        .map(|byte| {
+            u8::from_str_radix(byte, 16).map_err(|_| format!("Invalid hex byte \"{}\"", byte))
+        })
+        .collect()
+}
+
+/// Get the length of a frame's header after the start byte, which holds the payload length and
+/// CRC.
+///
+/// # Arguments
+///
+/// * `protocol_version` - Framing in use, either `PROTOCOL_V1` or `PROTOCOL_V2`.
+fn header_len(protocol_version: u8) -> usize {
+    if protocol_version == PROTOCOL_V2 {
+        3
+    } else {
+        2
+    }
+}
+
+/// Split a frame's header after the start byte into the payload length and CRC.
+///
+/// # Arguments
+///
+/// * `header` - Header, `header_len` bytes long.
+fn parse_header(header: &[u8]) -> (usize, u8) {
+    match *header {
+        [low, high, crc] => (u16::from_le_bytes([low, high]) as usize, crc),
+        [length, crc, ..] => (length as usize, crc),
+        _ => (0, 0),
+    }
+}
+
+/// Byte that starts an escape sequence in an escaped payload.
+const ESCAPE_BYTE: u8 = 0x25;
+
+/// Value XORed with an escaped byte.
+const ESCAPE_XOR: u8 = 0x60;
+
+/// Escape a payload, so it contains neither start bytes nor unescaped escape bytes.
+fn escape_payload(payload: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(payload.len());
+    for &byte in payload {
+        if byte == START_BYTE || byte == ESCAPE_BYTE {
+            escaped.extend_from_slice(&[ESCAPE_BYTE, byte ^ ESCAPE_XOR]);
+        } else {
+            escaped.push(byte);
+        }
+    }
+    escaped
+}
+
+/// Undo `escape_payload`.
+///
+/// # Returns
+///
+/// The original payload, or `FrameError::Malformed` if the payload ends in the middle of an
+/// escape sequence.
+fn unescape_payload(escaped: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let mut payload = Vec::with_capacity(escaped.len());
+    let mut bytes = escaped.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte != ESCAPE_BYTE {
+            payload.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(&escaped) => payload.push(escaped ^ ESCAPE_XOR),
+            None => {
+                return Err(FrameError::Malformed {
+                    reason: "payload ends with an escape byte".to_string(),
+                })
+            }
+        }
+    }
+    Ok(payload)
+}
+
+/// Check a payload against the CRC from its frame's header.
+fn check_crc(payload: &[u8], crc: u8) -> Result<(), FrameError> {
+    if crc8ccitt_check(payload, crc) {
+        Ok(())
+    } else {
+        Err(FrameError::CrcMismatch {
+            expected: crc,
+            computed: crc8ccitt(payload),
+        })
+    }
+}
+
+/// Parse the payload of a frame into a message.
+///
+/// # Arguments
+///
+/// * `payload` - Payload of the frame.
+/// * `firmware_version` - Firmware version of the COBOT, which decides the layout of log
+///   messages.
+///
+/// # Returns
+///
+/// The message, or why the payload isn't a valid message.
+pub fn parse_message(payload: &[u8], firmware_version: u32) -> Result<Message, FrameError> {
+    let malformed = |reason: &str| FrameError::Malformed {
+        reason: reason.to_string(),
+    };
+
+    let Some(&message_type) = payload.first() else {
+        return Err(malformed("empty payload"));
+    };
+    match message_type {
+        received_msg_type::LOG => {
+            let Some(&level) = payload.get(1) else {
+                return Err(malformed("log message without a log level"));
+            };
+            if level > log_level::NONE {
+                return Err(FrameError::InvalidLogLevel { level });
+            }
+            let (tag, message) = match payload.get(2) {
+                Some(&tag) if firmware_version >= LOG_TAG_FIRMWARE_VERSION => {
+                    (Some(tag), payload.get(4..).unwrap_or_default())
+                }
+                _ => (None, payload.get(3..).unwrap_or_default()),
+            };
+            Ok(Message::Log {
+                level,
+                tag,
+                message: String::from_utf8_lossy(message).to_string(),
+            })
+        }
+        received_msg_type::RESPONSE => {
+            let [_, response_type, a, b, c, d, ref payload @ ..] = *payload else {
+                return Err(malformed("response shorter than its header"));
+            };
+            Ok(Message::Response(Response {
+                command_id: u32::from_le_bytes([a, b, c, d]),
+                response_type,
+                payload: payload.to_vec(),
+            }))
+        }
+        received_msg_type::FAULT => {
+            let [_, code, severity, ref message @ ..] = *payload else {
+                return Err(malformed("fault without a code and severity"));
+            };
+            Ok(Message::Fault(CobotFault {
+                code,
+                severity,
+                message: String::from_utf8_lossy(message).to_string(),
+            }))
+        }
+        message_type => Err(FrameError::UnknownMessageType { message_type }),
+    }
+}
+
+/// Decode a complete frame received from the COBOT, without a connection. JOINTS responses are
+/// parsed down to the joint states.
+///
+/// # Arguments
+///
+/// * `frame` - Bytes of the frame, from the start byte to the end of the payload.
+/// * `protocol_version` - Framing in use, either `PROTOCOL_V1` or `PROTOCOL_V2`.
+/// * `firmware_version` - Firmware version of the COBOT.
+///
+/// # Returns
+///
+/// The decoded frame, or where decoding failed.
+pub fn decode_frame(
+    frame: &[u8],
+    protocol_version: u8,
+    firmware_version: u32,
+) -> Result<DecodedFrame, FrameError> {
+    let header_end = 1 + header_len(protocol_version);
+    match frame.first() {
+        None => {
+            return Err(FrameError::Truncated {
+                expected: header_end,
+                actual: 0,
+            })
+        }
+        Some(&START_BYTE) => {}
+        Some(&found) => return Err(FrameError::BadStartByte { found }),
+    }
+    let Some(header) = frame.get(1..header_end) else {
+        return Err(FrameError::Truncated {
+            expected: header_end,
+            actual: frame.len(),
+        });
+    };
+    let (length, crc) = parse_header(header);
+    let frame_end = header_end + length;
+    let Some(payload) = frame.get(header_end..frame_end) else {
+        return Err(FrameError::Truncated {
+            expected: frame_end,
+            actual: frame.len(),
+        });
+    };
+    if frame.len() > frame_end {
+        return Err(FrameError::TrailingBytes {
+            count: frame.len() - frame_end,
+        });
+    }
+    check_crc(payload, crc)?;
+
+    let message = parse_message(payload, firmware_version)?;
+    let joints = match &message {
+        Message::Response(response) if response.response_type == response_type::JOINTS => Some(
+            parse_joint_states(&response.payload).map_err(|e| FrameError::Malformed {
+                reason: e.to_string(),
+            })?,
+        ),
+        _ => None,
+    };
+
+    Ok(DecodedFrame { message, joints })
+}
+
+/// Tags of the entries in an INFO response.
+mod info_tag {
+    pub const UPTIME: u8 = 0x01;
+    pub const BOARD_TEMPERATURE: u8 = 0x02;
+    pub const SUPPLY_VOLTAGE: u8 = 0x03;
+    pub const JOINT_TEMPERATURE: u8 = 0x04;
+    pub const FIRMWARE_VERSION: u8 = 0x05;
+    pub const JOINT_COUNT: u8 = 0x06;
+    pub const MAX_SPEED: u8 = 0x07;
+    pub const MAX_ANGLE: u8 = 0x08;
+}
+
+/// Parse the payload of an INFO response. Unknown tags are skipped, and later entries replace
+/// earlier ones with the same tag.
+///
+/// # Arguments
+///
+/// * `payload` - Payload of the response.
+fn parse_device_info(payload: &[u8]) -> Result<DeviceInfo, Box<dyn Error>> {
+    let malformed = |reason: &str| {
+        Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Malformed INFO response: {}", reason),
+        ))
+    };
+    let int16_tenths = |value: &[u8]| i16::from_le_bytes([value[0], value[1]]) as f32 / 10.0;
+    let int32_thousandths =
+        |value: &[u8]| i32::from_le_bytes([value[0], value[1], value[2], value[3]]) as f32 / 1000.0;
+
+    let mut info = DeviceInfo::default();
+    let mut rest = payload;
+    while !rest.is_empty() {
+        let [tag, length, tail @ ..] = rest else {
+            return Err(malformed("truncated entry header"));
+        };
+        let length = *length as usize;
+        if tail.len() < length {
+            return Err(malformed("truncated entry value"));
+        }
+        let (value, tail) = tail.split_at(length);
+        rest = tail;
+
+        let expected_length = match *tag {
+            info_tag::UPTIME
+            | info_tag::FIRMWARE_VERSION
+            | info_tag::MAX_SPEED
+            | info_tag::MAX_ANGLE => 4,
+            info_tag::BOARD_TEMPERATURE | info_tag::SUPPLY_VOLTAGE => 2,
+            info_tag::JOINT_TEMPERATURE => 3,
+            info_tag::JOINT_COUNT => 1,
+            _ => continue,
+        };
+        if length != expected_length {
+            return Err(malformed("entry of the wrong length"));
+        }
+
+        match *tag {
+            info_tag::UPTIME => {
+                info.uptime_s = Some(u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+            }
+            info_tag::BOARD_TEMPERATURE => info.board_temperature_c = Some(int16_tenths(value)),
+            info_tag::SUPPLY_VOLTAGE => {
+                info.supply_voltage_v =
+                    Some(u16::from_le_bytes([value[0], value[1]]) as f32 / 1000.0)
+            }
+            info_tag::FIRMWARE_VERSION => {
+                info.firmware_version =
+                    Some(u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+            }
+            info_tag::JOINT_COUNT => info.joint_count = Some(value[0]),
+            info_tag::MAX_SPEED => info.max_speed_deg_s = Some(int32_thousandths(value)),
+            info_tag::MAX_ANGLE => info.max_angle_deg = Some(int32_thousandths(value)),
+            _ => {
+                let joint = value[0] as usize;
+                if info.joint_temperatures_c.len() <= joint {
+                    info.joint_temperatures_c.resize(joint + 1, None);
+                }
+                info.joint_temperatures_c[joint] = Some(int16_tenths(&value[1..]));
+            }
+        }
+    }
+
+    Ok(info)
+}
+
+/// Interpret the response expected to be a DONE.
+///
+/// # Arguments
+///
+/// * `response` - The response, or `None` if it wasn't received before the timeout.
+fn done_result(response: Option<Response>) -> Result<(), Box<dyn Error>> {
+    match response {
+        Some(response) => match response.response_type {
+            response_type::DONE => Ok(()),
+            response_type::ERROR => Err(cobot_error(&response)),
+            actual => Err(unexpected_response(response_type::DONE, actual)),
+        },
+        None => Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "Timed out waiting for response",
+        ))),
+    }
+}
+
+/// Parse the payload of an ERROR_LOG response.
+///
+/// # Arguments
+///
+/// * `payload` - Payload of the response.
+fn parse_error_log(payload: &[u8]) -> Result<Vec<CobotLogEntry>, Box<dyn Error>> {
+    let malformed = || {
+        Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Malformed ERROR_LOG response of {} bytes", payload.len()),
+        ))
+    };
+
+    let Some((count, mut rest)) = payload.split_first() else {
+        return Err(malformed());
+    };
+    let mut entries = Vec::with_capacity(*count as usize);
+    for _ in 0..*count {
+        let [error_code, t0, t1, t2, t3, length, tail @ ..] = rest else {
+            return Err(malformed());
+        };
+        let length = *length as usize;
+        if tail.len() < length {
+            return Err(malformed());
+        }
+        let (message, tail) = tail.split_at(length);
+        rest = tail;
+
+        entries.push(CobotLogEntry {
+            error_code: *error_code,
+            timestamp: u32::from_le_bytes([*t0, *t1, *t2, *t3]),
+            message: String::from_utf8_lossy(message).to_string(),
+        });
+    }
+    if !rest.is_empty() {
+        return Err(malformed());
+    }
+
+    Ok(entries)
+}
+
+/// Parse the payload of a JOINTS response. Both the original layout and the one with motor
+/// currents are accepted; any other length is rejected.
+///
+/// # Arguments
+///
+/// * `payload` - Payload of the response.
+fn parse_joint_states(payload: &[u8]) -> Result<Vec<JointState>, Box<dyn Error>> {
+    let malformed = || {
+        Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Malformed JOINTS response of {} bytes", payload.len()),
+        ))
+    };
+
+    let (&joint_count, joints) = payload.split_first().ok_or_else(malformed)?;
+    let joint_count = joint_count as usize;
+    let stride = match joints.len() {
+        len if len == joint_count * 8 => 8,
+        len if joint_count > 0 && len == joint_count * 10 => 10,
+        _ => return Err(malformed()),
+    };
+
+    let read_i32 = |bytes: &[u8]| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Ok(joints
+        .chunks_exact(stride)
+        .map(|joint| JointState {
+            angle: read_i32(&joint[0..4]) as f32 / 1000.0,
+            speed: read_i32(&joint[4..8]) as f32 / 1000.0,
+            current_ma: (stride == 10).then(|| i16::from_le_bytes([joint[8], joint[9]])),
+        })
+        .collect())
+}
+
+/// Parse the payload of a FULL_STATUS response.
+///
+/// # Arguments
+///
+/// * `payload` - Payload of the response.
+fn parse_full_status(payload: &[u8]) -> Result<FullStatus, Box<dyn Error>> {
+    let malformed = || {
+        Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Malformed FULL_STATUS response of {} bytes", payload.len()),
+        ))
+    };
+
+    let joint_count = *payload.first().ok_or_else(malformed)?;
+    let joints_end = 1 + joint_count as usize * 8;
+    let mask_length = if joint_count > 8 { 2 } else { 1 };
+    if payload.len() != joints_end + 1 + mask_length {
+        return Err(malformed());
+    }
+
+    let mask = &payload[joints_end + 1..];
+    Ok(FullStatus {
+        joints: parse_joint_states(&payload[..joints_end])?,
+        error_flags: Some(payload[joints_end]),
+        feedback_mask: Some(JointMask(u16::from_le_bytes([
+            mask[0],
+            mask.get(1).copied().unwrap_or(0),
+        ]))),
+    })
+}
+
+/// Build the error reported by an ERROR response. The payload is the error code, a byte the host
+/// doesn't use, then the message; a payload too short to hold a code is malformed.
+///
+/// # Arguments
+///
+/// * `response` - ERROR response.
+fn cobot_error(response: &Response) -> Box<dyn Error> {
+    match response.payload.first() {
+        Some(&code) => Box::new(CobotError {
+            code,
+            message: String::from_utf8_lossy(response.payload.get(2..).unwrap_or_default())
+                .to_string(),
+        }),
+        None => Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Malformed ERROR response of 0 bytes",
+        )),
+    }
+}
+
+/// Build the error returned when a response of the wrong type is received.
+///
+/// # Arguments
+///
+/// * `expected` - Response type that was expected.
+/// * `actual` - Response type that was received.
+fn unexpected_response(expected: u8, actual: u8) -> Box<dyn Error> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!(
+            "Expected {} response but received {} ({:#04x})",
+            response_type_str(expected),
+            response_type_str(actual),
+            actual
+        ),
+    ))
+}
+
+/// Message types that can be sent to the COBOT.
+pub mod request_type {
+    pub const INIT: u8 = 0x00;
+    pub const CALIBRATE: u8 = 0x01;
+    pub const OVERRIDE: u8 = 0x02;
+    pub const GET_JOINTS: u8 = 0x03;
+    pub const MOVE_TO: u8 = 0x04;
+    pub const MOVE_SPEED: u8 = 0x05;
+    pub const FOLLOW_TRAJECTORY: u8 = 0x06;
+    pub const STOP: u8 = 0x07;
+    pub const GO_HOME: u8 = 0x08;
+    pub const RESET: u8 = 0x09;
+    pub const SET_LOG_LEVEL: u8 = 0x0A;
+    pub const SET_FEEDBACK: u8 = 0x0B;
+    pub const SET_GRIPPER: u8 = 0x0C;
+    pub const SET_SERVO: u8 = 0x0D;
+    pub const GET_INFO: u8 = 0x0E;
+    pub const GET_ERROR_LOG: u8 = 0x0F;
+    pub const GET_FULL_STATUS: u8 = 0x10;
+
+    /// Every request type, with its name.
+    pub const NAMES: [(u8, &str); 17] = [
+        (INIT, "INIT"),
+        (CALIBRATE, "CALIBRATE"),
+        (OVERRIDE, "OVERRIDE"),
+        (GET_JOINTS, "GET_JOINTS"),
+        (MOVE_TO, "MOVE_TO"),
+        (MOVE_SPEED, "MOVE_SPEED"),
+        (FOLLOW_TRAJECTORY, "FOLLOW_TRAJECTORY"),
+        (STOP, "STOP"),
+        (GO_HOME, "GO_HOME"),
+        (RESET, "RESET"),
+        (SET_LOG_LEVEL, "SET_LOG_LEVEL"),
+        (SET_FEEDBACK, "SET_FEEDBACK"),
+        (SET_GRIPPER, "SET_GRIPPER"),
+        (SET_SERVO, "SET_SERVO"),
+        (GET_INFO, "GET_INFO"),
+        (GET_ERROR_LOG, "GET_ERROR_LOG"),
+        (GET_FULL_STATUS, "GET_FULL_STATUS"),
+    ];
+}
+
+/// Value of a protocol constant, with its name.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ProtocolConstant {
+    /// Value sent on the wire.
+    pub value: u8,
+
+    /// Name of the constant.
+    pub name: &'static str,
+}
+
+/// Constants of the protocol, for tools that build or inspect raw messages.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProtocolInfo {
+    /// Types of request that can be sent to the COBOT.
+    pub request_types: Vec<ProtocolConstant>,
+
+    /// Types of response the COBOT sends.
+    pub response_types: Vec<ProtocolConstant>,
+
+    /// Error codes of ERROR responses, named by their messages.
+    pub error_codes: Vec<ProtocolConstant>,
+
+    /// Log levels of LOG messages.
+    pub log_levels: Vec<ProtocolConstant>,
+}
+
+impl ProtocolInfo {
+    /// Collect the constants defined in this module.
+    pub fn new() -> Self {
+        let constants = |names: &[(u8, &'static str)]| {
+            names
+                .iter()
+                .map(|&(value, name)| ProtocolConstant { value, name })
+                .collect()
+        };
+        let error_codes = ERROR_CODES
+            .iter()
+            .enumerate()
+            .map(|(code, &name)| ProtocolConstant {
+                value: code as u8,
+                name,
+            })
+            .collect();
+
+        ProtocolInfo {
+            request_types: constants(&request_type::NAMES),
+            response_types: constants(&response_type::NAMES),
+            error_codes,
+            log_levels: constants(&log_level::NAMES),
+        }
+    }
+}
+
+impl Default for ProtocolInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Source of the current time for a connection's timeouts, so they can be driven by something
+/// other than the system clock.
+pub trait Clock: Send {
+    /// Get the current time.
+    fn now(&self) -> Instant;
+}
+
+/// Clock that reads the system's monotonic clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves when advanced, so timeouts can be tested to the exact instant without
+/// waiting for them. Clones share the same time, so a test can keep one to advance the clock of
+/// a connection.
+#[derive(Clone, Debug)]
+pub struct MockClock(Arc<Mutex<Instant>>);
+
+impl MockClock {
+    /// Create a clock stopped at the current time.
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Transport a `CobotConnection` runs over unless told otherwise: a serial port with the
+/// `serialport` feature, or any boxed transport without it.
+#[cfg(feature = "serialport")]
+pub type DefaultTransport = Box<dyn serialport::SerialPort>;
+
+/// Transport a `CobotConnection` runs over unless told otherwise: a serial port with the
+/// `serialport` feature, or any boxed transport without it.
+#[cfg(not(feature = "serialport"))]
+pub type DefaultTransport = Box<dyn Transport>;
+
+/// Connection to the COBOT. Handles sending and receiving messages.
+///
+/// This struct will pass any received log messages to the standard logger. Responses are accessed
+/// by ID and will be buffered for up to 1 second before being discarded.
+pub struct CobotConnection<T: Transport = DefaultTransport> {
+    /// Transport to communicate with the COBOT over, usually a serial port.
+    port: T,
+
+    /// Firmware version of the COBOT.
+    firmware_version: u32,
+
+    /// Framing in use, either `PROTOCOL_V1` or `PROTOCOL_V2`.
+    protocol_version: u8,
+
+    /// Joints feedback was last enabled for on this connection, if it has been set.
+    feedback: Option<JointMask>,
+
+    /// Period feedback was last requested to be streamed at, if it is streamed.
+    feedback_period: Option<Duration>,
+
+    /// Whether the COBOT has acknowledged an INIT on this connection and not since been reset.
+    initialized: bool,
+
+    /// Joints the COBOT has finished calibrating since it was last initialized or reported a
+    /// fault.
+    calibrated: JointMask,
+
+    /// Last joint states read from the COBOT and when they were read, for `get_joints_cached`.
+    /// Cleared by requests that redefine the angles, such as calibration.
+    joints_cache: Option<(Instant, Vec<JointState>)>,
+
+    /// Cancels the wait in progress when triggered.
+    cancel: CancelHandle,
+
+    /// Clock every timeout and timestamp on this connection is measured by.
+    clock: Box<dyn Clock>,
+
+    /// Limit on how often requests are sent, if any.
+    rate_limit: Option<RateLimit>,
+
+    /// Time the last rate-limited request was sent.
+    last_request_at: Option<Instant>,
+
+    /// Why the serial link was lost, once a write fails or the port disappears. The connection
+    /// must be closed after this.
+    link_lost: Option<String>,
+
+    /// Whether payloads are escaped in both directions.
+    payload_escaping: bool,
+
+    /// Command ID to use for the next command.
+    next_command_id: u32,
+
+    /// Time to wait for a response before timing out.
+    timeout: Duration,
+
+    /// Time to wait for a DONE response before timing out.
+    calibration_timeout: Duration,
+
+    /// List of responses and the time they were received.
+    responses: ResponseBuffer,
+
+    /// Counters of the traffic on this connection.
+    stats: CommsStats,
+
+    /// Number of joints the COBOT reported in its last JOINTS response, if any.
+    joint_count: Option<u8>,
+
+    /// Opening the gripper was last moved to, in mm, if it has been moved on this connection.
+    gripper_opening: Option<f32>,
+
+    /// Most recent log messages from the COBOT, oldest first.
+    recent_logs: VecDeque<String>,
+
+    /// Most recent faults reported by the COBOT, oldest first.
+    recent_faults: VecDeque<CobotFault>,
+
+    /// Called with every fault as soon as it is received.
+    fault_handler: Option<FaultHandler>,
+
+    /// Called with the joints the COBOT streams, as soon as they are received.
+    joints_handler: Option<JointsHandler>,
+
+    /// Severity at or above which a fault stops every joint immediately, if any.
+    fault_stop_severity: Option<u8>,
+
+    /// Thresholds for detecting stalled joints during moves, or `None` to not watch moves.
+    stall_detection: Option<StallDetection>,
+
+    /// Called with each joint suspected to have stalled.
+    stall_handler: Option<StallHandler>,
+
+    /// Command ID and monitor of the last move started, until its DONE response is awaited.
+    stall_monitor: Option<(u32, StallMonitor)>,
+
+    /// Time of the last request the COBOT acknowledged, if any.
+    last_successful_command_at: Option<Instant>,
+
+    /// Recent traffic events and the time they happened, oldest first.
+    link_history: VecDeque<(Instant, LinkEvent)>,
+
+    /// Time of the last successful read of the joints, if any.
+    last_successful_joints_at: Option<Instant>,
+
+    /// Lowest level of COBOT log message passed on to the logger. Messages below it are still
+    /// counted and kept in `recent_logs`.
+    log_display_level: u8,
+
+    /// Protocol events, broadcast to every subscriber.
+    events: broadcast::Sender<CommsEvent>,
+
+    /// Requests sent and responses received.
+    session_log: SessionLog,
+}
+
+/// Thresholds for detecting a joint that stalls partway through a move, without firmware support.
+#[derive(Clone, Copy, Debug)]
+pub struct StallDetection {
+    /// Time a moving joint has to make `min_progress` towards its target.
+    pub window: Duration,
+
+    /// Progress towards the target, in degrees, a moving joint must make within `window`.
+    pub min_progress: f32,
+
+    /// Whether to stop a joint as soon as it is suspected to have stalled.
+    pub auto_stop: bool,
+}
+
+/// Function called with each joint suspected to have stalled.
+pub type StallHandler = Box<dyn FnMut(u8) + Send>;
+
+/// Watches the joints of a single move for a lack of progress towards their targets.
+struct StallMonitor {
+    detection: StallDetection,
+    joints: Vec<WatchedJoint>,
+}
+
+/// Joint watched by a `StallMonitor`.
+struct WatchedJoint {
+    joint: u8,
+
+    /// Angle the joint is moving to, in degrees.
+    target: f32,
+
+    /// Angle of the joint and the time it was at that angle, when it last made progress.
+    progress: Option<(f32, Instant)>,
+}
+
+impl StallMonitor {
+    fn new(detection: StallDetection, joints: &[(u8, f32, Option<f32>)]) -> Self {
+        StallMonitor {
+            detection,
+            joints: joints
+                .iter()
+                .map(|(joint, target, _)| WatchedJoint {
+                    joint: *joint,
+                    target: *target,
+                    progress: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Update the monitor with the current joint angles.
+    ///
+    /// # Returns
+    ///
+    /// The joints newly suspected to have stalled. They are no longer watched.
+    fn update(&mut self, angles: &[f32], now: Instant) -> Vec<u8> {
+        let StallDetection {
+            window,
+            min_progress,
+            ..
+        } = self.detection;
+        let mut stalled = Vec::new();
+
+        self.joints.retain_mut(|watched| {
+            let Some(&angle) = angles.get(watched.joint as usize) else {
+                return true;
+            };
+            // The last stretch of a move is where the joint decelerates, so slow progress there
+            // is expected.
+            let remaining = (watched.target - angle).abs();
+            if remaining <= 2.0 * min_progress {
+                return false;
+            }
+
+            let Some((last_angle, since)) = watched.progress else {
+                watched.progress = Some((angle, now));
+                return true;
+            };
+            if (watched.target - last_angle).abs() - remaining >= min_progress {
+                watched.progress = Some((angle, now));
+                true
+            } else if now.duration_since(since) >= window {
+                stalled.push(watched.joint);
+                false
+            } else {
+                true
+            }
+        });
+
+        stalled
+    }
+}
+
+/// Function called with the joints the COBOT streams on its own.
+pub type JointsHandler = Box<dyn FnMut(&[JointState]) + Send>;
+
+/// Function called with each fault reported by the COBOT.
+pub type FaultHandler = Box<dyn FnMut(&CobotFault) + Send>;
+
+/// Fault reported by the COBOT on its own, such as a thermal shutdown or a following error.
+#[derive(Clone, Debug, Serialize)]
+pub struct CobotFault {
+    /// Fault code.
+    pub code: u8,
+
+    /// Severity of the fault. Higher is more severe.
+    pub severity: u8,
+
+    /// Description of the fault.
+    pub message: String,
+}
+impl std::fmt::Display for CobotFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "COBOT FAULT {} (severity {}): {}",
+            self.code, self.severity, self.message
+        )
+    }
+}
+
+/// Counters of the traffic on a connection.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct CommsStats {
+    /// Number of requests sent.
+    pub requests_sent: u64,
+
+    /// Number of responses received.
+    pub responses_received: u64,
+
+    /// Number of log messages received.
+    pub logs_received: u64,
+
+    /// Number of messages discarded because their CRC didn't match.
+    pub crc_errors: u64,
+
+    /// Number of responses that weren't received before their timeout.
+    pub timeouts: u64,
+
+    /// Number of move targets replaced by a newer target for the same joint before being sent.
+    pub coalesced: u64,
+
+    /// Number of requests delayed or rejected by the rate limit.
+    pub throttled: u64,
+
+    /// Number of bytes discarded while looking for the start of a message.
+    pub unframed_bytes: u64,
+
+    /// Number of bytes written to the serial port.
+    pub bytes_written: u64,
+
+    /// Number of bytes read from the serial port.
+    pub bytes_read: u64,
+}
+
+/// Entry of the error log kept by the COBOT's firmware.
+#[derive(Clone, Debug, Serialize)]
+pub struct CobotLogEntry {
+    /// Error code, as in `ERROR_CODES`.
+    pub error_code: u8,
+
+    /// Time the error was logged, as the firmware's timestamp.
+    pub timestamp: u32,
+
+    /// Description of the error.
+    pub message: String,
+}
+
+/// Everything the COBOT reports about its state, read in a single round trip where supported.
+#[derive(Clone, Debug, Serialize)]
+pub struct FullStatus {
+    /// State of each joint.
+    pub joints: Vec<JointState>,
+
+    /// Error flags of the controller, or `None` if the firmware can't report them.
+    pub error_flags: Option<u8>,
+
+    /// Joints with feedback enabled, or `None` if unknown.
+    pub feedback_mask: Option<JointMask>,
+}
+
+/// Health readout of the COBOT's controller. Each field is `None` if the firmware doesn't report
+/// it.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DeviceInfo {
+    /// Time since the controller started, in seconds.
+    pub uptime_s: Option<u32>,
+
+    /// Temperature of the controller board, in °C.
+    pub board_temperature_c: Option<f32>,
+
+    /// Supply voltage, in V.
+    pub supply_voltage_v: Option<f32>,
+
+    /// Temperature of each joint's driver, in °C, indexed by joint.
+    pub joint_temperatures_c: Vec<Option<f32>>,
+
+    /// Version of the firmware.
+    pub firmware_version: Option<u32>,
+
+    /// Number of joints the controller drives.
+    pub joint_count: Option<u8>,
+
+    /// Fastest speed the firmware allows any joint, in degrees per second.
+    pub max_speed_deg_s: Option<f32>,
+
+    /// Largest angle the firmware allows any joint either side of zero, in degrees.
+    pub max_angle_deg: Option<f32>,
+}
+
+/// Hardware metadata reported by the COBOT's firmware.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct CobotInfo {
+    /// Version of the firmware.
+    pub firmware_version: u32,
+
+    /// Number of joints the controller drives.
+    pub joint_count: u8,
+
+    /// Fastest speed the firmware allows any joint, in degrees per second.
+    pub max_speed_deg_s: f32,
+
+    /// Largest angle the firmware allows any joint either side of zero, in degrees.
+    pub max_angle_deg: f32,
+}
+
+/// Overall health of the link to the COBOT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkQuality {
+    Good,
+    Degraded,
+    Bad,
+}
+
+/// Limit on how often requests are sent to the COBOT, so a runaway caller can't flood the
+/// firmware's buffers. STOP requests are never limited.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Shortest time between two requests, in milliseconds. Jogging and polling the joints send
+    /// requests continuously, so this should leave room for them.
+    pub min_interval_ms: u64,
+
+    /// Whether a request that comes too soon is rejected, rather than delayed until it's allowed.
+    pub reject: bool,
+}
+
+/// Thresholds for grading the link to the COBOT from its recent CRC error and timeout rates.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct LinkQualityThresholds {
+    /// Length of the sliding window the rates are measured over, in milliseconds.
+    pub window_ms: u64,
+
+    /// Fraction of messages with a bad CRC, or of requests timing out, at or above which the link
+    /// is degraded.
+    pub degraded_rate: f32,
+
+    /// Fraction of messages with a bad CRC, or of requests timing out, at or above which the link
+    /// is bad.
+    pub bad_rate: f32,
+}
+
+impl Default for LinkQualityThresholds {
+    fn default() -> Self {
+        LinkQualityThresholds {
+            window_ms: 30_000,
+            degraded_rate: 0.01,
+            bad_rate: 0.1,
+        }
+    }
+}
+
+/// Traffic event recorded for grading the link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LinkEvent {
+    RequestSent,
+    Timeout,
+    MessageReceived,
+    CrcError,
+}
+
+/// Protocol event of a connection, broadcast to every subscriber of `CobotConnection::subscribe`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommsEvent {
+    /// Log message received from the COBOT, whatever the display level.
+    LogReceived {
+        /// Log level, as in `log_level`.
+        level: u8,
+
+        /// Subsystem the message came from, if the firmware tags its messages.
+        tag: Option<u8>,
+
+        /// Text of the message.
+        message: String,
+    },
+
+    /// Response to a request received. Streamed joints aren't included.
+    ResponseReceived(Response),
+
+    /// Message dropped because its CRC didn't match.
+    CrcError,
+
+    /// Response to a request not received in time.
+    Timeout { command_id: u32 },
+
+    /// COBOT initialized.
+    Connected,
+
+    /// Link to the COBOT lost, or the connection closed to hand its port to another user.
+    Disconnected,
+}
+
+/// Direction of a frame in the session log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Request sent to the COBOT.
+    Sent,
+
+    /// Response received from the COBOT.
+    Received,
+}
+
+/// Request or response recorded in the session log.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionEntry {
+    /// Time the frame was sent or received, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+
+    /// Whether the frame was sent or received.
+    pub direction: Direction,
+
+    /// Request type of a sent frame, or response type of a received one.
+    pub request_or_response_type: u8,
+
+    /// Command ID of the request, or of the request the response answers.
+    pub command_id: u32,
+
+    /// Payload of the frame, as space-separated hex.
+    pub payload_hex: String,
+}
+
+/// Requests and responses of a connection, oldest first, for post-mortem debugging. The oldest
+/// entries are dropped once the log is full.
+#[derive(Clone, Debug)]
+pub struct SessionLog {
+    entries: VecDeque<SessionEntry>,
+    max_entries: usize,
+}
+
+impl SessionLog {
+    /// Create an empty log holding at most `max_entries` entries.
+    pub fn new(max_entries: usize) -> Self {
+        SessionLog {
+            entries: VecDeque::new(),
+            max_entries,
+        }
+    }
+
+    /// Record a frame, dropping the oldest entry if the log is full.
+    fn push(&mut self, direction: Direction, frame_type: u8, command_id: u32, payload: &[u8]) {
+        if self.max_entries == 0 {
+            return;
+        }
+        if self.entries.len() >= self.max_entries {
+            self.entries.pop_front();
+        }
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.entries.push_back(SessionEntry {
+            timestamp_ms,
+            direction,
+            request_or_response_type: frame_type,
+            command_id,
+            payload_hex: to_hex(payload),
+        });
+    }
+
+    /// Get the most recent `count` entries, oldest first.
+    pub fn last(&self, count: usize) -> Vec<SessionEntry> {
+        let skip = self.entries.len().saturating_sub(count);
+        self.entries.iter().skip(skip).cloned().collect()
+    }
+
+    /// Remove every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Response received from the COBOT.
+#[derive(Clone, Debug)]
+pub struct Response {
+    /// Command ID of the command that generated this response.
+    pub command_id: u32,
+
+    /// Type of response.
+    pub response_type: u8,
+
+    /// Payload of the response.
+    pub payload: Vec<u8>,
+}
+impl std::fmt::Display for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} response to command {}: [{}]",
+            response_type_str(self.response_type),
+            self.command_id,
+            to_hex(&self.payload)
+        )
+    }
+}
+impl Serialize for Response {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ResponseDto::from(self).serialize(serializer)
+    }
+}
+impl<'de> Deserialize<'de> for Response {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ResponseDto::deserialize(deserializer)?
+            .try_into()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serialized form of a `Response`, for the frontend and remote clients. The response type is kept
+/// as its value alongside its name, and the payload as hex, so a response survives the round trip
+/// unchanged.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResponseDto {
+    /// Command ID of the command that generated the response.
+    pub command_id: u32,
+
+    /// Type of response, as in `response_type`.
+    pub response_type: u8,
+
+    /// Name of the response type. Ignored when deserializing.
+    #[serde(default)]
+    pub response_type_name: String,
+
+    /// Payload of the response, as space-separated hex.
+    pub payload: String,
+}
+impl From<&Response> for ResponseDto {
+    fn from(response: &Response) -> Self {
+        ResponseDto {
+            command_id: response.command_id,
+            response_type: response.response_type,
+            response_type_name: response_type_str(response.response_type).to_string(),
+            payload: to_hex(&response.payload),
+        }
+    }
+}
+impl TryFrom<ResponseDto> for Response {
+    type Error = String;
+
+    fn try_from(dto: ResponseDto) -> Result<Self, Self::Error> {
+        Ok(Response {
+            command_id: dto.command_id,
+            response_type: dto.response_type,
+            payload: from_hex(&dto.payload)?,
+        })
+    }
+}
+
+/// Message received from the COBOT, decoded from the payload of a frame.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Message {
+    /// Log message.
+    Log {
+        /// Log level, as in `log_level`.
+        level: u8,
+
+        /// Subsystem the message came from, if the firmware tags its messages. 0 for none.
+        tag: Option<u8>,
+
+        /// Text of the message.
+        message: String,
+    },
+
+    /// Response to a request.
+    Response(Response),
+
+    /// Fault reported by the COBOT on its own.
+    Fault(CobotFault),
+}
+
+/// Frame decoded without a connection, such as one captured with a logic analyzer.
+#[derive(Clone, Debug, Serialize)]
+pub struct DecodedFrame {
+    /// Message carried by the frame.
+    pub message: Message,
+
+    /// Joint states, if the message is a JOINTS response.
+    pub joints: Option<Vec<JointState>>,
+}
+
+/// Reason a frame or its payload couldn't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame ended before its header or payload did.
+    Truncated {
+        /// Number of bytes the frame needs.
+        expected: usize,
+
+        /// Number of bytes the frame has.
+        actual: usize,
+    },
+
+    /// The frame doesn't begin with the start byte.
+    BadStartByte {
+        /// Byte found instead.
+        found: u8,
+    },
+
+    /// The CRC in the header doesn't match the payload.
+    CrcMismatch {
+        /// CRC in the header.
+        expected: u8,
+
+        /// CRC computed from the payload.
+        computed: u8,
+    },
+
+    /// Bytes follow the end of the payload.
+    TrailingBytes {
+        /// Number of extra bytes.
+        count: usize,
+    },
+
+    /// The payload's message type isn't known.
+    UnknownMessageType {
+        /// Message type found.
+        message_type: u8,
+    },
+
+    /// A log message has a log level that isn't known.
+    InvalidLogLevel {
+        /// Log level found.
+        level: u8,
+    },
+
+    /// The payload is too short or otherwise doesn't match its message type.
+    Malformed {
+        /// What is wrong with the payload.
+        reason: String,
+    },
+}
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Truncated { expected, actual } => {
+                write!(
+                    f,
+                    "Truncated frame: expected {} bytes, got {}",
+                    expected, actual
+                )
+            }
+            FrameError::BadStartByte { found } => {
+                write!(
+                    f,
+                    "Bad start byte: expected 0x{:02X}, found 0x{:02X}",
+                    START_BYTE, found
+                )
+            }
+            FrameError::CrcMismatch { expected, computed } => {
+                write!(
+                    f,
+                    "CRC mismatch: expected 0x{:02X}, computed 0x{:02X}",
+                    expected, computed
+                )
+            }
+            FrameError::TrailingBytes { count } => {
+                write!(f, "{} trailing bytes after the payload", count)
+            }
+            FrameError::UnknownMessageType { message_type } => {
+                write!(f, "Unknown message type 0x{:02X}", message_type)
+            }
+            FrameError::InvalidLogLevel { level } => write!(f, "Invalid log level 0x{:02X}", level),
+            FrameError::Malformed { reason } => write!(f, "Malformed payload: {}", reason),
+        }
+    }
+}
+impl std::error::Error for FrameError {}
+
+/// Error returned by the COBOT.
+#[derive(Clone, Debug)]
+pub struct CobotError {
+    /// Error code.
+    pub code: u8,
+
+    /// Error message.
+    pub message: String,
+}
+impl std::fmt::Display for CobotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error_message = if self.code < ERROR_CODES.len() as u8 {
+            ERROR_CODES[self.code as usize]
+        } else {
+            "Unknown error"
+        };
+        write!(
+            f,
+            "COBOT ERROR {} ({}): {}",
+            self.code, error_message, self.message
+        )
+    }
+}
+impl std::error::Error for CobotError {}
+
+/// Error detected by the host while communicating with the COBOT.
+#[derive(Clone, Debug)]
+pub enum CommsError {
+    /// An argument can't be sent to the COBOT.
+    InvalidArgument {
+        /// Name of the invalid argument.
+        field: &'static str,
+
+        /// Why the argument is invalid.
+        reason: &'static str,
+    },
+
+    /// The COBOT's firmware doesn't support a request.
+    Unsupported {
+        /// Name of the unsupported feature.
+        feature: &'static str,
+    },
+
+    /// A wait for a response was cancelled through a `CancelHandle`.
+    Cancelled,
+
+    /// Some of the joints to move haven't been calibrated since the COBOT was initialized.
+    NotCalibrated {
+        /// Joints that still need calibrating.
+        joints: JointMask,
+    },
+
+    /// A request came too soon after the previous one and the rate limit rejects such requests.
+    Throttled,
+
+    /// The serial port stopped accepting a frame partway through. The connection can't be used
+    /// after this, since the COBOT may be waiting for the rest of the frame.
+    WriteTimeout {
+        /// Number of bytes of the frame that were written.
+        sent: usize,
+
+        /// Length of the frame, in bytes.
+        total: usize,
+    },
+
+    /// The device on the serial port didn't answer the probe sent when connecting with a valid
+    /// message, so it's probably not a COBOT, or is running at another baud rate.
+    NotACobot {
+        /// Whether the device sent bytes that couldn't be read as messages, rather than nothing.
+        garbage: bool,
+    },
+}
+impl std::fmt::Display for CommsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommsError::InvalidArgument { field, reason } => {
+                write!(f, "Invalid {}: {}", field, reason)
+            }
+            CommsError::Unsupported { feature } => {
+                write!(f, "{} not supported by this firmware", feature)
+            }
+            CommsError::Cancelled => write!(f, "Cancelled while waiting for the COBOT"),
+            CommsError::Throttled => write!(f, "Request rejected by the rate limit"),
+            CommsError::WriteTimeout { sent, total } => write!(
+                f,
+                "Timed out writing to the serial port after {} of {} bytes",
+                sent, total
+            ),
+            CommsError::NotCalibrated { joints } => {
+                write!(f, "Joints {} not calibrated, run calibration first", joints)
+            }
+            CommsError::NotACobot { garbage: false } => {
+                write!(f, "No response from the device, it may not be a COBOT")
+            }
+            CommsError::NotACobot { garbage: true } => write!(
+                f,
+                "The device responded with unreadable data, check the baud rate"
+            ),
+        }
+    }
+}
+impl std::error::Error for CommsError {}
+
+/// Handle for cancelling a wait on a connection from outside it, such as while another thread is
+/// blocked waiting for a move to finish.
+///
+/// Cancelling only releases the waiting caller, with `CommsError::Cancelled`. Requests already
+/// sent can't be unsent, so the COBOT carries on with them. A response that arrives after its wait
+/// was cancelled is buffered like any other unclaimed response until it expires, and can't be
+/// mistaken for the response to a later request since responses are matched by command ID.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Cancel the wait in progress, or the next wait if none is in progress and `reset` isn't
+    /// called first.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Forget any cancellation that hasn't been acted on yet.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// State of a single joint, as reported by the COBOT.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct JointState {
+    /// Angle, in degrees.
+    pub angle: f32,
+
+    /// Speed, in degrees per second.
+    pub speed: f32,
+
+    /// Motor current, in mA, if the firmware reports it.
+    pub current_ma: Option<i16>,
+}
+
+/// Round-trip times measured by a loopback test. Times only cover successful round trips.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct LoopbackStats {
+    /// Average round-trip time, in milliseconds.
+    pub avg_ms: f64,
+
+    /// Shortest round-trip time, in milliseconds.
+    pub min_ms: u64,
+
+    /// Longest round-trip time, in milliseconds.
+    pub max_ms: u64,
+
+    /// Number of round trips that failed or timed out.
+    pub failures: u32,
+}
+
+/// Set of joints, sent to the COBOT as a bitfield. Serialized as the bitfield itself, with bit N
+/// set for joint N.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct JointMask(u16);
+
+impl JointMask {
+    /// Largest number of joints a mask can hold.
+    pub const MAX_JOINTS: u8 = 16;
+
+    /// Create a mask holding a single joint. Joints beyond `MAX_JOINTS` give an empty mask.
+    pub fn joint(joint: u8) -> Self {
+        JointMask(1u16.checked_shl(joint as u32).unwrap_or(0))
+    }
+
+    /// Create a mask holding joints `0..count`.
+    pub fn first(count: u8) -> Self {
+        JointMask(
+            1u32.checked_shl(count as u32)
+                .map_or(u16::MAX, |bit| (bit - 1) as u16),
+        )
+    }
+
+    /// Check whether the mask holds the given joint.
+    pub fn contains(self, joint: u8) -> bool {
+        !(self & JointMask::joint(joint)).is_empty()
+    }
+
+    /// Check whether the mask holds no joints.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Get the number of joints needed to hold every joint in the mask.
+    fn joints_needed(self) -> u8 {
+        (u16::BITS - self.0.leading_zeros()) as u8
+    }
+
+    /// Encode the mask for a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `wide` - Whether the COBOT expects 2-byte bitfields.
+    fn encode(self, wide: bool) -> Vec<u8> {
+        if wide {
+            self.0.to_le_bytes().to_vec()
+        } else {
+            vec![self.0 as u8]
+        }
+    }
+}
+
+impl std::ops::BitOr for JointMask {
+    type Output = JointMask;
+    fn bitor(self, rhs: JointMask) -> JointMask {
+        JointMask(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitAnd for JointMask {
+    type Output = JointMask;
+    fn bitand(self, rhs: JointMask) -> JointMask {
+        JointMask(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::Not for JointMask {
+    type Output = JointMask;
+    fn not(self) -> JointMask {
+        JointMask(!self.0)
+    }
+}
+
+impl std::fmt::Display for JointMask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let joints = (0..JointMask::MAX_JOINTS)
+            .filter(|joint| self.contains(*joint))
+            .map(|joint| joint.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "[{}]", joints.join(", "))
+    }
+}
+
+impl<T: Transport> CobotConnection<T> {
+    /// Creates a new connection to the COBOT.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - Transport to communicate with the COBOT over, usually a serial port.
+    /// * `firmware_version` - Firmware version of the COBOT.
+    pub fn new(port: T, firmware_version: u32, timeout: Duration) -> Self {
+        CobotConnection {
+            port,
+            firmware_version,
+            protocol_version: PROTOCOL_V1,
+            feedback: None,
+            feedback_period: None,
+            initialized: false,
+            calibrated: JointMask::default(),
+            joints_cache: None,
+            cancel: CancelHandle::default(),
+            clock: Box::new(SystemClock),
+            rate_limit: None,
+            last_request_at: None,
+            link_lost: None,
+            payload_escaping: false,
+            next_command_id: 0,
+            timeout,
+            calibration_timeout: DEFAULT_CALIBRATION_TIMEOUT,
+            #[cfg(not(feature = "no-alloc"))]
+            responses: Vec::with_capacity(RESPONSE_CAPACITY),
+            #[cfg(feature = "no-alloc")]
+            responses: ResponseBuffer::new(),
+            stats: CommsStats::default(),
+            recent_logs: VecDeque::new(),
+            joint_count: None,
+            gripper_opening: None,
+            recent_faults: VecDeque::new(),
+            fault_handler: None,
+            joints_handler: None,
+            fault_stop_severity: None,
+            log_display_level: log_level::DEBUG,
+            stall_detection: None,
+            stall_handler: None,
+            stall_monitor: None,
+            last_successful_command_at: None,
+            link_history: VecDeque::new(),
+            last_successful_joints_at: None,
+            events: broadcast::channel(COMMS_EVENT_CAPACITY).0,
+            session_log: SessionLog::new(SESSION_LOG_CAPACITY),
+        }
+    }
+
+    /// Get the requests sent and responses received on this connection.
+    pub fn session_log(&self) -> &SessionLog {
+        &self.session_log
+    }
+
+    /// Remove every entry from the session log.
+    pub fn clear_session_log(&mut self) {
+        self.session_log.clear();
+    }
+
+    /// Subscribe to the protocol events of this connection. The channel closes when the
+    /// connection is dropped.
+    pub fn subscribe(&self) -> broadcast::Receiver<CommsEvent> {
+        self.events.subscribe()
+    }
+
+    /// Set the thresholds for detecting stalled joints during moves. `None` stops watching moves.
+    pub fn set_stall_detection(&mut self, detection: Option<StallDetection>) {
+        self.stall_detection = detection;
+        if detection.is_none() {
+            self.stall_monitor = None;
+        }
+    }
+
+    /// Set a function to call with each joint suspected to have stalled during a move.
+    pub fn set_stall_handler(&mut self, handler: StallHandler) {
+        self.stall_handler = Some(handler);
+    }
+
+    /// Set a function to call with every fault as soon as it is received, even while waiting for
+    /// the response to another request.
+    pub fn set_fault_handler(&mut self, handler: FaultHandler) {
+        self.fault_handler = Some(handler);
+    }
+
+    /// Set a function to call with the joints the COBOT streams once feedback is streamed with
+    /// `set_feedback`. Streamed joints are never returned to a caller waiting on a request.
+    pub fn set_joints_handler(&mut self, handler: JointsHandler) {
+        self.joints_handler = Some(handler);
+    }
+
+    /// Check whether the COBOT was asked to stream its joints.
+    pub fn is_streaming(&self) -> bool {
+        self.feedback_period.is_some() && self.feedback.is_some_and(|joints| !joints.is_empty())
+    }
+
+    /// Set the severity at or above which a fault stops every joint immediately. `None` never
+    /// stops the joints on a fault.
+    pub fn set_fault_stop_severity(&mut self, severity: Option<u8>) {
+        self.fault_stop_severity = severity;
+    }
+
+    /// Set the lowest level of COBOT log message passed on to the logger, without changing what
+    /// the firmware sends. See `set_log_level` to change the firmware's level.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Lowest level to log, from `log_level`. `log_level::NONE` hides every message.
+    pub fn set_log_display_level(&mut self, level: u8) -> Result<(), Box<dyn Error>> {
+        if level > log_level::NONE {
+            return Err(CommsError::InvalidArgument {
+                field: "level",
+                reason: "not a log level",
+            }
+            .into());
+        }
+        self.log_display_level = level;
+        Ok(())
+    }
+
+    /// Get the most recent faults reported by the COBOT, oldest first.
+    pub fn recent_faults(&self) -> impl Iterator<Item = &CobotFault> {
+        self.recent_faults.iter()
+    }
+
+    /// Set the time to wait for a DONE response, which bounds long operations such as calibration.
+    pub fn set_calibration_timeout(&mut self, timeout: Duration) {
+        self.calibration_timeout = timeout;
+    }
+
+    /// Get the time to wait for a DONE response.
+    pub fn calibration_timeout(&self) -> Duration {
+        self.calibration_timeout
+    }
+
+    /// Set the time to wait for any other response, such as an ACK.
+    pub fn set_response_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the time to wait for any response other than a DONE.
+    pub fn response_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Set the handle that cancels waits on this connection.
+    pub fn set_cancel_handle(&mut self, cancel: CancelHandle) {
+        self.cancel = cancel;
+    }
+
+    /// Whether the COBOT has been initialized on this connection and not since been reset.
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// Get the joints calibrated since the COBOT was last initialized or reported a fault.
+    pub fn calibrated_joints(&self) -> JointMask {
+        self.calibrated
+    }
+
+    /// Enable or disable payload escaping, which the firmware must have enabled as well. Disabled
+    /// by default, since older firmware doesn't understand it.
+    pub fn set_payload_escaping(&mut self, enabled: bool) {
+        self.payload_escaping = enabled;
+    }
+
+    /// Set the limit on how often requests are sent. `None`, the default, sends every request as
+    /// soon as it's made.
+    pub fn set_rate_limit(&mut self, rate_limit: Option<RateLimit>) {
+        self.rate_limit = rate_limit;
+    }
+
+    /// Replace the clock every timeout and timestamp on this connection is measured by.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Get the framing in use, either `PROTOCOL_V1` or `PROTOCOL_V2`.
+    pub fn protocol_version(&self) -> u8 {
+        self.protocol_version
+    }
+
+    /// Get the opening the gripper was last moved to, in mm, if it has been moved on this
+    /// connection.
+    pub fn gripper_opening(&self) -> Option<f32> {
+        self.gripper_opening
+    }
+
+    /// Grade the link from its CRC error and timeout rates over the last window. The link is graded
+    /// by whichever rate is worse: CRC errors per message read, or timeouts per request sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `thresholds` - Window and rates to grade the link by.
+    pub fn link_quality(&mut self, thresholds: &LinkQualityThresholds) -> LinkQuality {
+        let window = Duration::from_millis(thresholds.window_ms);
+        while let Some((time, _)) = self.link_history.front() {
+            if self.elapsed_since(*time) < window {
+                break;
+            }
+            self.link_history.pop_front();
+        }
+
+        grade_link(
+            self.link_history.iter().map(|(_, event)| *event),
+            thresholds,
+        )
+    }
+
+    /// Record a traffic event for grading the link.
+    fn record_link_event(&mut self, event: LinkEvent) {
+        if self.link_history.len() >= LINK_HISTORY_CAPACITY {
+            self.link_history.pop_front();
+        }
+        self.link_history.push_back((self.clock.now(), event));
+    }
+
+    /// Get the time of the last request the COBOT acknowledged, if any.
+    pub fn last_successful_command_at(&self) -> Option<Instant> {
+        self.last_successful_command_at
+    }
+
+    /// Get the time of the last successful read of the joints, if any.
+    pub fn last_successful_joints_at(&self) -> Option<Instant> {
+        self.last_successful_joints_at
+    }
+
+    /// Get the number of joints the COBOT has, if it has reported them yet. The count is learned
+    /// from the first `get_joints` call.
+    pub fn joint_count(&self) -> Option<u8> {
+        self.joint_count
+    }
+
+    /// Get a mask of every joint on the COBOT. If the joint count isn't known yet, this covers
+    /// the 8 joints a single-byte bitfield can address.
+    pub fn all_joints(&self) -> JointMask {
+        JointMask::first(self.joint_count.unwrap_or(8))
+    }
+
+    /// Check that a joint exists on the COBOT. Before the joint count is known, any joint a mask
+    /// can hold is accepted.
+    fn check_joint(&self, joint: u8) -> Result<(), CommsError> {
+        let joint_count = self.joint_count.unwrap_or(JointMask::MAX_JOINTS);
+        if joint >= joint_count.min(JointMask::MAX_JOINTS) {
+            return Err(CommsError::InvalidArgument {
+                field: "joint",
+                reason: "not on this COBOT",
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that every one of the given joints has been calibrated, so a move the firmware would
+    /// reject is refused without a round trip.
+    fn check_calibrated(&self, joints: JointMask) -> Result<(), CommsError> {
+        let missing = joints & !self.calibrated;
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(CommsError::NotCalibrated { joints: missing })
+        }
+    }
+
+    /// Check that every joint in a mask exists on the COBOT and encode it for a request.
+    fn encode_mask(&self, joints: JointMask) -> Result<Vec<u8>, CommsError> {
+        if let Some(highest) = joints.joints_needed().checked_sub(1) {
+            self.check_joint(highest)?;
+        }
+        let wide = self.joint_count.is_some_and(|count| count > 8);
+        if !wide && joints.joints_needed() > 8 {
+            return Err(CommsError::InvalidArgument {
+                field: "joints",
+                reason: "more than 8 joints need a COBOT with more than 8 joints",
+            });
+        }
+        Ok(joints.encode(wide))
+    }
+
+    /// Get why the serial link was lost, if a write failed or the port disappeared. The connection
+    /// can't be used once it has, and should be closed.
+    pub fn link_lost(&self) -> Option<&str> {
+        self.link_lost.as_deref()
+    }
+
+    /// Get the name of the serial port, if it has one.
+    pub fn port_name(&self) -> Option<String> {
+        self.port.name()
+    }
+
+    /// Close the connection, handing back its serial port.
+    pub fn into_port(self) -> T {
+        if self.link_lost.is_none() {
+            self.send_event(CommsEvent::Disconnected);
+        }
+        self.port
+    }
+
+    /// Get the baud rate of the serial port.
+    pub fn baud_rate(&self) -> Result<u32, Box<dyn Error>> {
+        Ok(self.port.baud_rate()?)
+    }
+
+    /// Get the counters of the traffic on this connection.
+    pub fn stats(&self) -> CommsStats {
+        self.stats
+    }
+
+    /// Zero every traffic counter, so the traffic of a single operation can be measured.
+    pub fn reset_stats(&mut self) {
+        self.stats = CommsStats::default();
+    }
+
+    /// Zero the count of messages discarded because of a CRC mismatch, leaving the other counters.
+    pub fn reset_crc_error_count(&mut self) {
+        self.stats.crc_errors = 0;
+    }
+
+    /// Get the most recent log messages from the COBOT, oldest first.
+    pub fn recent_logs(&self) -> impl Iterator<Item = &str> {
+        self.recent_logs.iter().map(String::as_str)
+    }
+
+    /// Get the number of responses received but not yet claimed by a request.
+    pub fn buffered_responses(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// Discard every response received but not yet claimed, so stale responses can't be mistaken
+    /// for answers to later requests.
+    ///
+    /// # Returns
+    ///
+    /// The number of responses discarded.
+    pub fn flush_responses(&mut self) -> usize {
+        let flushed = self.responses.len();
+        self.responses.clear();
+        flushed
+    }
+
+    /// Stop the COBOT streaming its joints, keeping feedback enabled for the same joints. Does
+    /// nothing if it isn't streaming.
+    pub fn stop_streaming(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.is_streaming() {
+            return Ok(());
+        }
+        self.set_feedback(self.feedback.unwrap_or_default(), None)
+    }
+
+    /// Sends a request to the COBOT.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_type` - Type of request to send.
+    /// * `payload` - Payload of the request.
+    ///
+    /// # Returns
+    ///
+    /// The command ID of the request.
+    pub fn send_request(
+        &mut self,
+        request_type: u8,
+        payload: &[u8],
+    ) -> Result<u32, Box<dyn Error>> {
+        if request_type != request_type::STOP {
+            self.throttle()?;
+        }
+
+        let command_id = self.next_command_id;
+        self.next_command_id += 1;
+
+        let mut body = vec![request_type];
+        body.extend_from_slice(&command_id.to_le_bytes());
+        body.extend_from_slice(payload);
+        let crc = crc8ccitt(&body);
+        if self.payload_escaping {
+            body = escape_payload(&body);
+        }
+
+        let mut message = vec![0x24];
+        match self.protocol_version {
+            PROTOCOL_V2 => match u16::try_from(body.len()) {
+                Ok(length) => message.extend_from_slice(&length.to_le_bytes()),
+                Err(_) => {
+                    return Err(Box::new(CommsError::InvalidArgument {
+                        field: "payload",
+                        reason: "longer than 65535 bytes",
+                    }))
+                }
+            },
+            _ => match u8::try_from(body.len()) {
+                Ok(length) => message.push(length),
+                Err(_) => {
+                    return Err(Box::new(CommsError::InvalidArgument {
+                        field: "payload",
+                        reason: "longer than 255 bytes, which needs protocol version 2",
+                    }))
+                }
+            },
+        }
+        message.push(crc);
+        message.extend_from_slice(&body);
+
+        self.write_frame(&message)?;
+        self.stats.requests_sent += 1;
+        self.record_link_event(LinkEvent::RequestSent);
+        self.session_log
+            .push(Direction::Sent, request_type, command_id, payload);
+
+        Ok(command_id)
+    }
+
+    /// Waits for a response from the COBOT. This will continually read from the serial port until
+    /// a response is received, or the timeout is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_id` - Command ID of the request to wait for.
+    /// * `timeout` - Maximum time to wait for the response.
+    ///
+    /// # Returns
+    ///
+    /// The response, or `None` if the response was not received before the timeout.
+    pub fn wait_for_response(
+        &mut self,
+        command_id: u32,
+        timeout: Duration,
+    ) -> Result<Option<Response>, Box<dyn Error>> {
+        let response = self.poll_for_response(command_id, timeout)?;
+        if response.is_none() {
+            self.stats.timeouts += 1;
+            self.record_link_event(LinkEvent::Timeout);
+            self.send_event(CommsEvent::Timeout { command_id });
+        }
+
+        Ok(response)
+    }
+
+    /// Read from the serial port until the response to the given request is received, or the
+    /// timeout is reached. Unlike `wait_for_response`, reaching the timeout isn't counted as a
+    /// timeout of the request, so this can be used to poll for a response.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_id` - Command ID of the request to wait for.
+    /// * `timeout` - Maximum time to wait for the response.
+    ///
+    /// # Returns
+    ///
+    /// The response, or `None` if the response was not received before the timeout.
+    fn poll_for_response(
+        &mut self,
+        command_id: u32,
+        timeout: Duration,
+    ) -> Result<Option<Response>, Box<dyn Error>> {
+        let start_time = self.clock.now();
+
+        loop {
+            // Filter out any responses that are too old.
+            let now = self.clock.now();
+            self.responses
+                .retain(|(_, time)| now.saturating_duration_since(*time) < RESPONSE_EXPIRY);
+
+            // Check if the response has been received and return it if it has.
+            if let Some(response_idx) = self
+                .responses
+                .iter()
+                .position(|(response, _)| response.command_id == command_id)
+            {
+                return Ok(Some(self.responses.swap_remove(response_idx).0));
+            }
+
+            if self.cancel.0.swap(false, Ordering::Relaxed) {
+                return Err(Box::new(CommsError::Cancelled));
+            }
+
+            // Check if the timeout has been reached.
+            let time_elapsed = self.elapsed_since(start_time);
+            if time_elapsed >= timeout {
+                return Ok(None);
+            }
+
+            // Read a response from the serial port, in slices so a cancellation is noticed
+            // promptly.
+            self.read_response(CANCEL_POLL_INTERVAL.min(timeout.saturating_sub(time_elapsed)))?;
+        }
+    }
+
+    /// Send a request and wait for the COBOT to acknowledge it and then finish it.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_type` - Type of request to send.
+    /// * `payload` - Payload of the request.
+    ///
+    /// # Returns
+    ///
+    /// Ok once a DONE response to the request was received, or the first error: failing to send,
+    /// an error response instead of the ACK or the DONE, or a timeout.
+    fn send_and_complete(
+        &mut self,
+        request_type: u8,
+        payload: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let command_id = self.send_request(request_type, payload)?;
+        self.wait_for_ack(command_id)?;
+        self.wait_for_done(command_id)
+    }
+
+    /// Wait for an ACK response from the COBOT. If an error response is received, it will be
+    /// returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_id` - Command ID of the request to wait for.
+    ///
+    /// # Returns
+    ///
+    /// Ok if an ACK response was received, or an error if an error response was received.
+    pub fn wait_for_ack(&mut self, command_id: u32) -> Result<(), Box<dyn Error>> {
+        self.wait_for_ack_response(command_id)?;
+        Ok(())
+    }
+
+    /// Wait for an ACK response from the COBOT, keeping its payload. If an error response is
+    /// received, it will be returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_id` - Command ID of the request to wait for.
+    fn wait_for_ack_response(&mut self, command_id: u32) -> Result<Response, Box<dyn Error>> {
+        match self.wait_for_response(command_id, self.timeout)? {
+            Some(response) => match response.response_type {
+                response_type::ACK => {
+                    self.last_successful_command_at = Some(self.clock.now());
+                    Ok(response)
+                }
+                response_type::ERROR => Err(cobot_error(&response)),
+                actual => Err(unexpected_response(response_type::ACK, actual)),
+            },
+            None => Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "Timed out waiting for response",
+            ))),
+        }
+    }
+
+    /// Wait for a DONE response from the COBOT. If an error response is received, it will be
+    /// returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_id` - Command ID of the request to wait for.
+    ///
+    /// # Returns
+    ///
+    /// Ok if a DONE response was received, or an error if an error response was received.
+    pub fn wait_for_done(&mut self, command_id: u32) -> Result<(), Box<dyn Error>> {
+        let response = match self.stall_monitor.take() {
+            Some((monitor_id, monitor)) if monitor_id == command_id => {
+                self.wait_for_monitored_done(command_id, monitor)?
+            }
+            _ => self.wait_for_response(command_id, self.calibration_timeout)?,
+        };
+
+        done_result(response)
+    }
+
+    /// Wait for the DONE response of a move, polling the joints in the meantime to detect stalls.
+    /// Each suspected stall is passed to the stall handler, and the joint is stopped if configured
+    /// to.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_id` - Command ID of the move.
+    /// * `monitor` - Monitor of the move.
+    ///
+    /// # Returns
+    ///
+    /// The response, or `None` if the response was not received before the timeout.
+    fn wait_for_monitored_done(
+        &mut self,
+        command_id: u32,
+        mut monitor: StallMonitor,
+    ) -> Result<Option<Response>, Box<dyn Error>> {
+        let start_time = self.clock.now();
+
+        loop {
+            let time_elapsed = self.elapsed_since(start_time);
+            if time_elapsed >= self.calibration_timeout {
+                return Ok(None);
+            }
+            let wait =
+                STALL_POLL_INTERVAL.min(self.calibration_timeout.saturating_sub(time_elapsed));
+            if let Some(response) = self.poll_for_response(command_id, wait)? {
+                return Ok(Some(response));
+            }
+
+            let angles = self
+                .get_joints()?
+                .into_iter()
+                .map(|joint| joint.0)
+                .collect::<Vec<_>>();
+            for joint in monitor.update(&angles, self.clock.now()) {
+                warn!("Joint {} is not making progress, suspected stall", joint);
+                if let Some(handler) = &mut self.stall_handler {
+                    handler(joint);
+                }
+                if monitor.detection.auto_stop {
+                    self.stop(JointMask::joint(joint), true)?;
+                }
+            }
+        }
+    }
+
+    /// Initialize the COBOT.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT was initialized successfully, or an error if the COBOT failed to initialize.
+    pub fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        // Init is always framed as protocol version 1, since the firmware may not speak any other.
+        self.protocol_version = PROTOCOL_V1;
+        self.initialized = false;
+        self.calibrated = JointMask::default();
+        self.joints_cache = None;
+        self.feedback_period = None;
+
+        let mut payload = self.firmware_version.to_le_bytes().to_vec();
+        payload.push(PROTOCOL_V2);
+        let command_id = self.send_request(request_type::INIT, &payload)?;
+        let selected = match self.wait_for_ack_response(command_id) {
+            Ok(response) => response.payload.first().copied().unwrap_or(PROTOCOL_V1),
+            // Older firmware may reject the offer as malformed, so offer nothing instead.
+            Err(e) if matches!(e.downcast_ref(), Some(CobotError { code: 1, .. })) => {
+                let payload = self.firmware_version.to_le_bytes();
+                let command_id = self.send_request(request_type::INIT, &payload)?;
+                self.wait_for_ack(command_id)?;
+                PROTOCOL_V1
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.protocol_version = match selected {
+            PROTOCOL_V2 => PROTOCOL_V2,
+            _ => PROTOCOL_V1,
+        };
+        self.initialized = true;
+        self.send_event(CommsEvent::Connected);
+
+        Ok(())
+    }
+
+    /// Calibrate the COBOT.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints to calibrate.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT was calibrated successfully, or an error if the COBOT failed to calibrate.
+    pub fn calibrate(&mut self, joints: JointMask) -> Result<(), Box<dyn Error>> {
+        let command_id = self.start_calibrate(joints)?;
+        self.wait_for_done(command_id)?;
+        self.calibrated = self.calibrated | joints;
+
+        Ok(())
+    }
+
+    /// Start calibrating the given joints, returning once the COBOT has acknowledged the request.
+    /// The caller is responsible for waiting for the DONE response. The joints aren't counted as
+    /// calibrated on this connection until `assume_calibrated` is called for them.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints to calibrate.
+    ///
+    /// # Returns
+    ///
+    /// The command ID of the calibration, or an error if the COBOT rejected it.
+    pub fn start_calibrate(&mut self, joints: JointMask) -> Result<u32, Box<dyn Error>> {
+        self.joints_cache = None;
+        let payload = self.encode_mask(joints)?;
+        let command_id = self.send_request(request_type::CALIBRATE, &payload)?;
+        self.wait_for_ack(command_id)?;
+
+        Ok(command_id)
+    }
+
+    /// Count the given joints as calibrated, without calibrating them. This is for a connection
+    /// opened to a COBOT that was calibrated over an earlier one, so moves aren't refused locally.
+    /// The firmware still rejects moves of joints it hasn't calibrated.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints to count as calibrated.
+    pub fn assume_calibrated(&mut self, joints: JointMask) {
+        self.calibrated = self.calibrated | joints;
+    }
+
+    /// Calibrate a single joint, stopping it if `abort` is set before it finishes.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint` - Joint to calibrate.
+    /// * `timeout` - Maximum time to wait for the joint to finish calibrating.
+    /// * `abort` - Flag checked while waiting. Once set, the joint is stopped and an error is
+    ///   returned.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the joint was calibrated successfully, or an error if it failed, timed out, or was
+    /// aborted.
+    pub fn calibrate_joint(
+        &mut self,
+        joint: u8,
+        timeout: Duration,
+        abort: &AtomicBool,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_joint(joint)?;
+        self.joints_cache = None;
+        let payload = self.encode_mask(JointMask::joint(joint))?;
+        let command_id = self.send_request(request_type::CALIBRATE, &payload)?;
+        self.wait_for_ack(command_id)?;
+
+        let start_time = self.clock.now();
+        loop {
+            if abort.load(Ordering::Relaxed) {
+                self.stop(JointMask::joint(joint), true)?;
+                return Err("Calibration aborted".into());
+            }
+
+            let time_elapsed = self.elapsed_since(start_time);
+            if time_elapsed >= timeout {
+                self.stats.timeouts += 1;
+                self.record_link_event(LinkEvent::Timeout);
+                self.send_event(CommsEvent::Timeout { command_id });
+                return done_result(None);
+            }
+            let wait = CALIBRATION_ABORT_POLL_INTERVAL.min(timeout.saturating_sub(time_elapsed));
+            if let Some(response) = self.poll_for_response(command_id, wait)? {
+                done_result(Some(response))?;
+                self.calibrated = self.calibrated | JointMask::joint(joint);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Check that the device on the serial port is a COBOT, by clearing the port's buffers and
+    /// sending it a GET_JOINTS request, which doesn't change its state. Any valid response passes,
+    /// including an error.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the device responded, or `CommsError::NotACobot` if it didn't respond with a valid
+    /// message within `PROBE_TIMEOUT`.
+    pub fn probe(&mut self) -> Result<(), Box<dyn Error>> {
+        self.port.clear()?;
+        self.responses.clear();
+        let before = self.stats;
+
+        let command_id = self.send_request(request_type::GET_JOINTS, &[])?;
+        let response = self.wait_for_response(command_id, PROBE_TIMEOUT);
+        if self.link_lost.is_some() {
+            return response.map(|_| ());
+        }
+        if let Ok(Some(_)) = response {
+            return Ok(());
+        }
+
+        let garbage = response.is_err()
+            || self.stats.unframed_bytes > before.unframed_bytes
+            || self.stats.crc_errors > before.crc_errors;
+        Err(Box::new(CommsError::NotACobot { garbage }))
+    }
+
+    /// Check the serial link by timing several GET_JOINTS round trips, whose response is known
+    /// and doesn't change the COBOT's state.
+    ///
+    /// # Returns
+    ///
+    /// The round-trip statistics, or an error if every round trip failed.
+    pub fn loopback_test(&mut self) -> Result<LoopbackStats, Box<dyn Error>> {
+        let mut times = Vec::new();
+        let mut failures = 0;
+        let mut last_error = None;
+
+        for _ in 0..LOOPBACK_ROUND_TRIPS {
+            let start_time = self.clock.now();
+            match self.get_joints() {
+                Ok(_) => times.push(self.elapsed_since(start_time)),
+                Err(e) => {
+                    failures += 1;
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if times.is_empty() {
+            return Err(last_error.unwrap_or_else(|| "No round trips attempted".into()));
+        }
+
+        let total = times.iter().sum::<Duration>();
+        Ok(LoopbackStats {
+            avg_ms: total.as_secs_f64() * 1000.0 / times.len() as f64,
+            min_ms: times.iter().min().unwrap().as_millis() as u64,
+            max_ms: times.iter().max().unwrap().as_millis() as u64,
+            failures,
+        })
+    }
+
+    /// Override the angles the COBOT believes the given joints are at, without moving them.
+    ///
+    /// This is destructive: it replaces the joints' calibration, and only recalibrating restores
+    /// it.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - List of tuples containing the joint ID and its new angle, in degrees.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the angles were overridden, or an error if the COBOT rejected the override.
+    pub fn override_angles(&mut self, joints: &[(u8, f32)]) -> Result<(), Box<dyn Error>> {
+        for (joint_id, angle_f) in joints {
+            self.check_joint(*joint_id)?;
+            validate_finite(*angle_f, "angle")?;
+        }
+
+        let mut payload = Vec::new();
+        for (joint_id, angle_f) in joints {
+            let angle = (angle_f * 1000.0) as i32;
+            payload.extend_from_slice(&joint_id.to_le_bytes());
+            payload.extend_from_slice(&angle.to_le_bytes());
+        }
+        self.joints_cache = None;
+        let command_id = self.send_request(request_type::OVERRIDE, &payload)?;
+        self.wait_for_ack(command_id)?;
+        self.wait_for_done(command_id)?;
+
+        Ok(())
+    }
+
+    /// Declare the current position of a joint to be 0°, without moving it or any other joint.
+    ///
+    /// This is destructive: it discards the joint's calibration.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint` - Joint to zero.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the joint was zeroed, or an error if the COBOT rejected the override.
+    pub fn zero_joint(&mut self, joint: u8) -> Result<(), Box<dyn Error>> {
+        self.override_angles(&[(joint, 0.0)])
+    }
+
+    /// Declare the current position of every joint to be 0°, in a single override.
+    ///
+    /// This is destructive: it discards the calibration of every joint.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the joints were zeroed, or an error if the COBOT rejected the override.
+    pub fn zero_all_joints(&mut self) -> Result<(), Box<dyn Error>> {
+        let joint_count = self.get_joints()?.len() as u8;
+        let joints = (0..joint_count)
+            .map(|joint| (joint, 0.0))
+            .collect::<Vec<_>>();
+        self.override_angles(&joints)
+    }
+
+    /// Get the current joint angles and speeds.
+    ///
+    /// # Returns
+    ///
+    /// Vector of tuples containing the joint angles and speeds in degrees and degrees per second,
+    /// respectively.
+    pub fn get_joints(&mut self) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        Ok(self
+            .get_joint_states()?
+            .into_iter()
+            .map(|joint| (joint.angle, joint.speed))
+            .collect())
+    }
+
+    /// Get the angle and speed of every joint, reusing the last reading if it's recent enough, so
+    /// several consumers reading the joints in quick succession share one query. Every query,
+    /// including those made while polling during a move, refreshes the reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age` - Oldest reading that may be reused. `Duration::ZERO` always queries the
+    ///   COBOT.
+    ///
+    /// # Returns
+    ///
+    /// The angle and speed of each joint. They were read at `last_successful_joints_at`.
+    pub fn get_joints_cached(
+        &mut self,
+        max_age: Duration,
+    ) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        let states = match &self.joints_cache {
+            Some((read_at, states))
+                if !max_age.is_zero() && self.elapsed_since(*read_at) <= max_age =>
+            {
+                states.clone()
+            }
+            _ => self.get_joint_states()?,
+        };
+        Ok(states
+            .into_iter()
+            .map(|joint| (joint.angle, joint.speed))
+            .collect())
+    }
+
+    /// Get the current state of every joint, including the motor currents if the firmware
+    /// reports them.
+    ///
+    /// # Returns
+    ///
+    /// The state of each joint, or an error if the response is malformed.
+    pub fn get_joint_states(&mut self) -> Result<Vec<JointState>, Box<dyn Error>> {
+        let command_id = self.send_request(request_type::GET_JOINTS, &[])?;
+        let response = self.wait_for_response(command_id, self.timeout)?;
+        match response {
+            Some(response) => match response.response_type {
+                response_type::JOINTS => {
+                    let joints = parse_joint_states(&response.payload)?;
+                    let now = self.clock.now();
+                    self.joint_count = Some(joints.len() as u8);
+                    self.last_successful_joints_at = Some(now);
+                    self.joints_cache = Some((now, joints.clone()));
+                    Ok(joints)
+                }
+                response_type::ERROR => Err(cobot_error(&response)),
+                actual => Err(unexpected_response(response_type::JOINTS, actual)),
+            },
+            None => Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "Timed out waiting for response",
+            ))),
+        }
+    }
+
+    /// Move the given joints to the given angles at the given speeds. If a speed is `0` or `None`,
+    /// the COBOT will use the default speed. Speeds must be finite and non-negative.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - List of tuples containing the joint ID, angle, and speed to move to.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT moved successfully, or an error if the COBOT failed to move.
+    pub fn move_to(&mut self, joints: &[(u8, f32, Option<f32>)]) -> Result<(), Box<dyn Error>> {
+        let command_id = self.start_move_to(joints)?;
+        self.wait_for_done(command_id)?;
+
+        Ok(())
+    }
+
+    /// Start moving the given joints to the given angles at the given speeds, returning once the
+    /// COBOT has acknowledged the move. The caller is responsible for waiting for the DONE
+    /// response.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - List of tuples containing the joint ID, angle, and speed to move to.
+    ///
+    /// # Returns
+    ///
+    /// The command ID of the move, or an error if the COBOT rejected the move.
+    pub fn start_move_to(
+        &mut self,
+        joints: &[(u8, f32, Option<f32>)],
+    ) -> Result<u32, Box<dyn Error>> {
+        for (joint_id, angle_f, speed_f) in joints {
+            self.check_joint(*joint_id)?;
+            self.check_calibrated(JointMask::joint(*joint_id))?;
+            validate_finite(*angle_f, "angle")?;
+            if let Some(speed_f) = speed_f {
+                validate_finite(*speed_f, "speed")?;
+                if *speed_f < 0.0 {
+                    return Err(Box::new(CommsError::InvalidArgument {
+                        field: "speed",
+                        reason: "must be non-negative",
+                    }));
+                }
+            }
+        }
+
+        let mut payload = Vec::new();
+        for (joint_id, angle_f, speed_f) in joints {
+            let angle = (angle_f * 1000.0) as i32;
+            let speed = match speed_f {
+                Some(speed_f) => (speed_f * 1000.0) as i32,
+                None => 0,
+            };
+            payload.extend_from_slice(&joint_id.to_le_bytes());
+            payload.extend_from_slice(&angle.to_le_bytes());
+            payload.extend_from_slice(&speed.to_le_bytes());
+        }
+        let command_id = self.send_request(request_type::MOVE_TO, &payload)?;
+        self.wait_for_ack(command_id)?;
+        if let Some(detection) = self.stall_detection {
+            self.stall_monitor = Some((command_id, StallMonitor::new(detection, joints)));
+        }
+
+        Ok(command_id)
+    }
+
+    /// Move to the next waypoint of a path, sending only the joints that moved by more than
+    /// a threshold since they were last sent. Nothing is sent if no joint moved that far.
+    ///
+    /// Each joint adds 9 bytes to the MOVE_TO payload, on top of 8 bytes of header, request type
+    /// and command ID (9 with protocol version 2). A full 6-joint move is 62 bytes on the wire,
+    /// while a move of a single joint is 17, so a path where one joint moves at a time sends
+    /// about 73% fewer bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `sent` - Joints as last sent, with their angles and speeds. The joints sent now are
+    ///   updated, so a joint creeping by less than the threshold per waypoint is still sent once
+    ///   it has moved far enough.
+    /// * `current` - Joints to move to, with their angles and speeds, as for `move_to`. Must list
+    ///   the same joints in the same order as `sent`.
+    /// * `threshold_deg` - Smallest change in a joint's angle that is sent, in degrees.
+    ///
+    /// # Returns
+    ///
+    /// Ok once the changed joints have finished moving, or an error if the waypoints don't list
+    /// the same joints or the COBOT failed to move.
+    pub fn move_to_delta(
+        &mut self,
+        sent: &mut [(u8, f32, Option<f32>)],
+        current: &[(u8, f32, Option<f32>)],
+        threshold_deg: f32,
+    ) -> Result<(), Box<dyn Error>> {
+        if sent.len() != current.len()
+            || sent
+                .iter()
+                .zip(current)
+                .any(|(sent, current)| sent.0 != current.0)
+        {
+            return Err(Box::new(CommsError::InvalidArgument {
+                field: "current",
+                reason: "must list the same joints as the previous waypoint",
+            }));
+        }
+        let changed = sent
+            .iter_mut()
+            .zip(current)
+            .filter(|(sent, current)| (current.1 - sent.1).abs() > threshold_deg)
+            .collect::<Vec<_>>();
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let joints = changed
+            .iter()
+            .map(|(_, current)| **current)
+            .collect::<Vec<_>>();
+        self.move_to(&joints)?;
+        for (sent, current) in changed {
+            *sent = *current;
+        }
+
+        Ok(())
+    }
+
+    /// Move a joint to the given angle at the speed that takes it there in the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint` - Joint to move.
+    /// * `angle` - Angle to move to, in degrees.
+    /// * `duration` - Time the move should take.
+    /// * `max_speed` - Speed limit of the joint, in degrees per second. If the move would need to
+    ///   be faster, it runs at this speed and takes longer.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT moved successfully, or an error if the COBOT failed to move.
+    pub fn move_to_in_duration(
+        &mut self,
+        joint: u8,
+        angle: f32,
+        duration: Duration,
+        max_speed: Option<f32>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.start_move_to_in_duration(joint, angle, duration, max_speed)? {
+            Some(command_id) => self.wait_for_done(command_id),
+            None => Ok(()),
+        }
+    }
+
+    /// Start moving a joint to the given angle at the speed that takes it there in the given time,
+    /// returning once the COBOT has acknowledged the move. The caller is responsible for waiting
+    /// for the DONE with `wait_for_done`.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint` - Joint to move.
+    /// * `angle` - Angle to move to, in degrees.
+    /// * `duration` - Time the move should take.
+    /// * `max_speed` - Speed limit of the joint, in degrees per second. If the move would need to
+    ///   be faster, it runs at this speed and takes longer.
+    ///
+    /// # Returns
+    ///
+    /// The command ID of the move, `None` if the joint is already at the angle so nothing was
+    /// sent, or an error if the COBOT rejected the move.
+    pub fn start_move_to_in_duration(
+        &mut self,
+        joint: u8,
+        angle: f32,
+        duration: Duration,
+        max_speed: Option<f32>,
+    ) -> Result<Option<u32>, Box<dyn Error>> {
+        self.check_joint(joint)?;
+        let Some(&(current_angle, _)) = self.get_joints()?.get(joint as usize) else {
+            return Err(Box::new(CommsError::InvalidArgument {
+                field: "joint",
+                reason: "not reported by the COBOT",
+            }));
+        };
+
+        let speed = compute_feed_forward_speed(current_angle, angle, duration);
+        if speed == 0.0 {
+            return Ok(None);
+        }
+        let speed = match max_speed {
+            Some(max_speed) => speed.min(max_speed),
+            None if speed.is_finite() => speed,
+            None => {
+                return Err(Box::new(CommsError::InvalidArgument {
+                    field: "duration",
+                    reason: "must be non-zero without a speed limit",
+                }))
+            }
+        };
+
+        self.start_move_to(&[(joint, angle, Some(speed))]).map(Some)
+    }
+
+    /// Move the given joints at the given speeds. The joints keep moving until they are stopped,
+    /// so this only waits for the COBOT to acknowledge the request, not for it to finish.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - List of tuples containing the joint ID and speed to move at.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT started moving, or an error if the COBOT failed to move.
+    pub fn move_speed(&mut self, joints: &[(u8, f32)]) -> Result<(), Box<dyn Error>> {
+        for (joint_id, speed_f) in joints {
+            self.check_joint(*joint_id)?;
+            self.check_calibrated(JointMask::joint(*joint_id))?;
+            validate_finite(*speed_f, "speed")?;
+        }
+
+        let mut payload = Vec::new();
+        for (joint_id, speed_f) in joints {
+            let speed = (speed_f * 1000.0) as i32;
+            payload.extend_from_slice(&joint_id.to_le_bytes());
+            payload.extend_from_slice(&speed.to_le_bytes());
+        }
+        let command_id = self.send_request(request_type::MOVE_SPEED, &payload)?;
+        self.wait_for_ack(command_id)?;
+
+        Ok(())
+    }
+
+    /// Enable or disable the servos of the given joints. A joint with its servo disabled can be
+    /// moved by hand.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints to enable or disable.
+    /// * `enabled` - Whether to enable the servos.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT accepted the request, or an error if it rejected it.
+    pub fn set_servo(&mut self, joints: JointMask, enabled: bool) -> Result<(), Box<dyn Error>> {
+        let mut payload = self.encode_mask(joints)?;
+        payload.push(if enabled { 1 } else { 0 });
+        let command_id = self.send_request(request_type::SET_SERVO, &payload)?;
+        self.wait_for_ack(command_id)?;
+
+        Ok(())
+    }
+
+    /// Get a health readout of the COBOT's controller.
+    ///
+    /// # Returns
+    ///
+    /// The readout, or an error if the response is malformed. Firmware without the request rejects
+    /// it, which gives `CommsError::Unsupported`.
+    pub fn get_device_info(&mut self) -> Result<DeviceInfo, Box<dyn Error>> {
+        let command_id = self.send_request(request_type::GET_INFO, &[])?;
+        let response = self.wait_for_response(command_id, self.timeout)?;
+        match response {
+            Some(response) => match response.response_type {
+                response_type::INFO => parse_device_info(&response.payload),
+                // Firmware that doesn't know the request type reports it as malformed or as
+                // another error.
+                response_type::ERROR if response.payload.first().is_some_and(|code| *code <= 1) => {
+                    Err(Box::new(CommsError::Unsupported {
+                        feature: "Device info",
+                    }))
+                }
+                response_type::ERROR => Err(cobot_error(&response)),
+                actual => Err(unexpected_response(response_type::INFO, actual)),
+            },
+            None => Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "Timed out waiting for response",
+            ))),
+        }
+    }
+
+    /// Get the hardware metadata of the COBOT, which is part of the GET_INFO readout on firmware
+    /// that reports it.
+    ///
+    /// # Returns
+    ///
+    /// The metadata, or `CommsError::Unsupported` if the firmware doesn't report all of it.
+    pub fn get_info(&mut self) -> Result<CobotInfo, Box<dyn Error>> {
+        let info = self.get_device_info()?;
+        match (
+            info.firmware_version,
+            info.joint_count,
+            info.max_speed_deg_s,
+            info.max_angle_deg,
+        ) {
+            (
+                Some(firmware_version),
+                Some(joint_count),
+                Some(max_speed_deg_s),
+                Some(max_angle_deg),
+            ) => Ok(CobotInfo {
+                firmware_version,
+                joint_count,
+                max_speed_deg_s,
+                max_angle_deg,
+            }),
+            _ => Err(Box::new(CommsError::Unsupported {
+                feature: "Hardware metadata",
+            })),
+        }
+    }
+
+    /// Read the joints, error flags and feedback mask in a single round trip.
+    ///
+    /// Firmware without GET_FULL_STATUS rejects it, in which case the joints are read on their own
+    /// and the rest is filled in from what the host knows: no error flags, and the feedback mask
+    /// last set on this connection, if any.
+    ///
+    /// # Returns
+    ///
+    /// The status, or an error if the response is malformed.
+    pub fn get_full_status(&mut self) -> Result<FullStatus, Box<dyn Error>> {
+        let command_id = self.send_request(request_type::GET_FULL_STATUS, &[])?;
+        let response = self.wait_for_response(command_id, self.timeout)?;
+        match response {
+            Some(response) => match response.response_type {
+                response_type::FULL_STATUS => {
+                    let status = parse_full_status(&response.payload)?;
+                    self.joint_count = Some(status.joints.len() as u8);
+                    self.last_successful_joints_at = Some(self.clock.now());
+                    Ok(status)
+                }
+                // Firmware that doesn't know the request type reports it as malformed or as
+                // another error.
+                response_type::ERROR if response.payload.first().is_some_and(|code| *code <= 1) => {
+                    Ok(FullStatus {
+                        joints: self.get_joint_states()?,
+                        error_flags: None,
+                        feedback_mask: self.feedback,
+                    })
+                }
+                response_type::ERROR => Err(cobot_error(&response)),
+                actual => Err(unexpected_response(response_type::FULL_STATUS, actual)),
+            },
+            None => Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "Timed out waiting for response",
+            ))),
+        }
+    }
+
+    /// Download the error log kept by the COBOT's firmware.
+    ///
+    /// # Returns
+    ///
+    /// The entries of the log, oldest first, or an error if the response is malformed.
+    pub fn get_error_log(&mut self) -> Result<Vec<CobotLogEntry>, Box<dyn Error>> {
+        let command_id = self.send_request(request_type::GET_ERROR_LOG, &[])?;
+        let response = self.wait_for_response(command_id, self.timeout)?;
+        match response {
+            Some(response) => match response.response_type {
+                response_type::ERROR_LOG => parse_error_log(&response.payload),
+                response_type::ERROR => Err(cobot_error(&response)),
+                actual => Err(unexpected_response(response_type::ERROR_LOG, actual)),
+            },
+            None => Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "Timed out waiting for response",
+            ))),
+        }
+    }
+
+    /// Move the gripper to the given opening.
+    ///
+    /// # Arguments
+    ///
+    /// * `opening_mm` - Target opening, in mm. Sent with a resolution of 0.1 mm.
+    /// * `force` - Gripping force, or `None` for the firmware's default.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the gripper reached the opening, or an error if the COBOT failed to move it. Firmware
+    /// without a gripper rejects the request, which gives `CommsError::Unsupported`.
+    pub fn set_gripper(
+        &mut self,
+        opening_mm: f32,
+        force: Option<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        let opening = (opening_mm * 10.0).round();
+        if !(0.0..=u16::MAX as f32).contains(&opening) {
+            return Err(Box::new(CommsError::InvalidArgument {
+                field: "opening",
+                reason: "must be between 0 and 6553.5 mm",
+            }));
+        }
+
+        let mut payload = (opening as u16).to_le_bytes().to_vec();
+        payload.push(force.unwrap_or(0));
+        let command_id = self.send_request(request_type::SET_GRIPPER, &payload)?;
+
+        // Firmware that doesn't know the request type reports it as malformed or as another error.
+        if let Err(e) = self.wait_for_ack(command_id) {
+            return match e.downcast_ref::<CobotError>() {
+                Some(CobotError { code: 0 | 1, .. }) => {
+                    Err(Box::new(CommsError::Unsupported { feature: "Gripper" }))
+                }
+                _ => Err(e),
+            };
+        }
+        self.wait_for_done(command_id)?;
+        self.gripper_opening = Some(opening_mm);
+
+        Ok(())
+    }
+
+    /// Move a joint at the given speed for a while, passing on its state as it moves, then stop it.
+    ///
+    /// Feedback is enabled for the joint during the move, then set back to what it was, whether or
+    /// not the move succeeded.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint` - Joint to move.
+    /// * `speed` - Speed to move at, in degrees per second. The sign gives the direction.
+    /// * `duration` - Time to move for.
+    /// * `tx` - Channel to send the joint's state to after each poll. The move ends early once
+    ///   the receiver is dropped.
+    ///
+    /// # Returns
+    ///
+    /// Ok once the joint has been stopped, or an error if the move, a poll, or the stop failed.
+    pub fn move_speed_monitored(
+        &mut self,
+        joint: u8,
+        speed: f32,
+        duration: Duration,
+        tx: &mpsc::UnboundedSender<JointState>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_joint(joint)?;
+        let previous_feedback = self.feedback.unwrap_or_default();
+        let previous_period = self.feedback_period;
+        self.set_feedback(previous_feedback | JointMask::joint(joint), None)?;
+
+        let result = self.monitor_speed_move(joint, speed, duration, tx);
+        let stopped = self.stop(JointMask::joint(joint), false);
+        let restored = self.set_feedback(previous_feedback, previous_period);
+        result?;
+        stopped?;
+        restored
+    }
+
+    /// Start a speed move and poll the joint until the duration is up. The joint is left moving.
+    fn monitor_speed_move(
+        &mut self,
+        joint: u8,
+        speed: f32,
+        duration: Duration,
+        tx: &mpsc::UnboundedSender<JointState>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.move_speed(&[(joint, speed)])?;
+        let start_time = self.clock.now();
+
+        while self.elapsed_since(start_time) < duration {
+            std::thread::sleep(MONITOR_POLL_INTERVAL);
+
+            let joints = self.get_joint_states()?;
+            let Some(&state) = joints.get(joint as usize) else {
+                return Err(Box::new(CommsError::InvalidArgument {
+                    field: "joint",
+                    reason: "not reported by the COBOT",
+                }));
+            };
+            if tx.send(state).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move a joint at the given speed until it stalls against an obstacle, then stop it.
+    ///
+    /// Contact is detected when the joint's reported speed stays below a fraction of the commanded
+    /// speed for several consecutive polls. Detection only starts once the joint has come up to
+    /// speed, or after a short spin-up time, so acceleration isn't mistaken for contact.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint` - Joint to move.
+    /// * `speed` - Speed to move at, in degrees per second. The sign gives the direction.
+    /// * `timeout` - Maximum time to wait for contact.
+    ///
+    /// # Returns
+    ///
+    /// The angle of the joint at contact, in degrees, or an error if no contact was made before the
+    /// timeout.
+    pub fn move_until_contact(
+        &mut self,
+        joint: u8,
+        speed: f32,
+        timeout: Duration,
+    ) -> Result<f32, Box<dyn Error>> {
+        if !speed.is_finite() || speed == 0.0 {
+            return Err(Box::new(CommsError::InvalidArgument {
+                field: "speed",
+                reason: "must be finite and non-zero",
+            }));
+        }
+
+        self.move_speed(&[(joint, speed)])?;
+        let start_time = self.clock.now();
+        let mut armed = false;
+        let mut stalled_samples = 0;
+
+        loop {
+            std::thread::sleep(CONTACT_POLL_INTERVAL);
+
+            let joints = match self.get_joints() {
+                Ok(joints) => joints,
+                Err(e) => {
+                    self.stop(JointMask::joint(joint), true)?;
+                    return Err(e);
+                }
+            };
+            let Some(&(angle, measured_speed)) = joints.get(joint as usize) else {
+                self.stop(JointMask::joint(joint), true)?;
+                return Err(Box::new(CommsError::InvalidArgument {
+                    field: "joint",
+                    reason: "not reported by the COBOT",
+                }));
+            };
+
+            let ratio = measured_speed.abs() / speed.abs();
+            if ratio >= CONTACT_ARM_RATIO || self.elapsed_since(start_time) >= CONTACT_SPIN_UP {
+                armed = true;
+            }
+            if armed && ratio < CONTACT_STALL_RATIO {
+                stalled_samples += 1;
+            } else {
+                stalled_samples = 0;
+            }
+
+            if stalled_samples >= CONTACT_STALL_SAMPLES {
+                self.stop(JointMask::joint(joint), true)?;
+                return Ok(angle);
+            }
+
+            if self.elapsed_since(start_time) >= timeout {
+                self.stop(JointMask::joint(joint), true)?;
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "No contact before the timeout",
+                )));
+            }
+        }
+    }
+
+    /// Stop the given joints.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints to stop.
+    /// * `immediately` - If true, the COBOT will stop immediately. Otherwise, it will decelerate
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT stopped successfully, or an error if the COBOT failed to stop.
+    pub fn stop(&mut self, joints: JointMask, immediately: bool) -> Result<(), Box<dyn Error>> {
+        let command_id = self.start_stop(joints, immediately)?;
+        self.wait_for_done(command_id)?;
+
+        Ok(())
+    }
+
+    /// Start stopping the given joints, returning once the COBOT has acknowledged the request. The
+    /// caller is responsible for waiting for the DONE response.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints to stop.
+    /// * `immediately` - If true, the COBOT will stop immediately. Otherwise, it will decelerate
+    ///
+    /// # Returns
+    ///
+    /// The command ID of the stop, or an error if the COBOT rejected it.
+    pub fn start_stop(
+        &mut self,
+        joints: JointMask,
+        immediately: bool,
+    ) -> Result<u32, Box<dyn Error>> {
+        let mut payload = vec![if immediately { 1 } else { 0 }];
+        payload.extend(self.encode_mask(joints)?);
+        let command_id = self.send_request(request_type::STOP, &payload)?;
+        self.wait_for_ack(command_id)?;
+
+        Ok(command_id)
+    }
+
+    /// Home the given joints.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints to home.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT homed successfully, or an error if the COBOT failed to home.
+    pub fn go_home(&mut self, joints: JointMask) -> Result<(), Box<dyn Error>> {
+        let payload = self.encode_mask(joints)?;
+        self.send_and_complete(request_type::GO_HOME, &payload)?;
+
+        Ok(())
+    }
+
+    /// Reset the COBOT.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT reset successfully, or an error if the COBOT failed to reset.
+    pub fn reset(&mut self) -> Result<(), Box<dyn Error>> {
+        self.initialized = false;
+        self.calibrated = JointMask::default();
+        self.joints_cache = None;
+        self.feedback_period = None;
+        self.send_and_complete(request_type::RESET, &[])?;
+
+        Ok(())
+    }
+
+    /// Reset the COBOT into its bootloader, so new firmware can be flashed. The COBOT stops
+    /// responding once it has acknowledged the request, so the connection should be dropped
+    /// afterwards.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT is entering its bootloader, or an error if it refused or the firmware has
+    /// no bootloader.
+    pub fn enter_bootloader(&mut self) -> Result<(), Box<dyn Error>> {
+        self.initialized = false;
+        self.calibrated = JointMask::default();
+        let command_id = self.send_request(request_type::RESET, &[reset_mode::BOOTLOADER])?;
+        match self.wait_for_ack(command_id) {
+            Err(e) if matches!(e.downcast_ref(), Some(CobotError { code: 0 | 1, .. })) => {
+                Err(Box::new(CommsError::Unsupported {
+                    feature: "Resetting into the bootloader",
+                }))
+            }
+            result => result,
+        }
+    }
+
+    /// Set the log level of the COBOT.
+    ///
+    /// # Arguments
+    ///
+    /// * `log_level` - Log level to set.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT set the log level successfully, or an error if the COBOT failed to set the
+    /// log level.
+    pub fn set_log_level(&mut self, log_level: u8) -> Result<(), Box<dyn Error>> {
+        let payload = [log_level];
+        self.send_and_complete(request_type::SET_LOG_LEVEL, &payload)?;
+
+        Ok(())
+    }
+
+    /// Set the feedback of the given joints.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints to enable feedback for. Feedback is disabled for the others.
+    /// * `period` - Period to stream the joints at, rounded down to 10 ms, or `None` to only
+    ///   report them when asked. Streaming needs firmware version 7.
+    ///
+    /// # Returns
+    ///
+    /// Ok if the COBOT set the feedback successfully, or an error if the COBOT failed to set the
+    /// feedback.
+    pub fn set_feedback(
+        &mut self,
+        joints: JointMask,
+        period: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut payload = self.encode_mask(joints)?;
+        if let Some(period) = period {
+            if self.firmware_version < FEEDBACK_PERIOD_FIRMWARE_VERSION {
+                return Err(Box::new(CommsError::Unsupported {
+                    feature: "Feedback streaming",
+                }));
+            }
+            match u8::try_from(period.as_millis() / FEEDBACK_PERIOD_UNIT.as_millis()) {
+                Ok(units) if units > 0 => payload.push(units),
+                _ => {
+                    return Err(Box::new(CommsError::InvalidArgument {
+                        field: "period",
+                        reason: "must be between 10 ms and 2.55 s",
+                    }))
+                }
+            }
+        }
+        self.send_and_complete(request_type::SET_FEEDBACK, &payload)?;
+        self.feedback = Some(joints);
+        self.feedback_period = period;
+
+        Ok(())
+    }
+
+    /// Reads a response from the serial port and adds it to the list of responses. If log messages
+    /// are received, they will be passed to the standard logger.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum time to wait for the response.
+    ///
+    /// # Returns
+    ///
+    /// The response, or `None` if the response was not received before the timeout.
+    fn read_response(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let start_time = self.clock.now();
+
+        // Wait for a start byte.
+        let mut start_byte = [0];
+        while start_byte[0] != START_BYTE {
+            if !self.read_exact(&mut start_byte, self.remaining_timeout(start_time, timeout))? {
+                // Nothing arrived in time. Waiting callers check their own timeout, so this isn't
+                // an error.
+                return Ok(());
+            }
+            if start_byte[0] != START_BYTE {
+                self.stats.unframed_bytes += 1;
+            }
+        }
+
+        // Read the length and CRC.
+        let mut header = [0; 3];
+        let header = &mut header[..header_len(self.protocol_version)];
+        if !self.read_exact(header, self.remaining_timeout(start_time, timeout))? {
+            return Err("Timed out waiting for length and CRC".into());
+        }
+        let (length, crc) = parse_header(header);
+
+        // Read the payload.
+        let mut payload = vec![0; length];
+        if !self.read_exact(&mut payload, self.remaining_timeout(start_time, timeout))? {
+            return Err("Timed out waiting for payload".into());
+        }
+
+        if self.payload_escaping {
+            payload = match unescape_payload(&payload) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Received invalid message: {}", e);
+                    return Ok(());
+                }
+            };
+        }
+
+        // Check the CRC.
+        if let Err(e) = check_crc(&payload, crc) {
+            warn!("Received message with invalid CRC: {}", e);
+            self.stats.crc_errors += 1;
+            self.record_link_event(LinkEvent::CrcError);
+            self.send_event(CommsEvent::CrcError);
+            return Ok(());
+        }
+        self.record_link_event(LinkEvent::MessageReceived);
+
+        // Handle the message.
+        let message = match parse_message(&payload, self.firmware_version) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Received invalid message: {}", e);
+                return Ok(());
+            }
+        };
+        match message {
+            Message::Log {
+                level: raw_level,
+                tag,
+                message,
+            } => {
+                let level = match raw_level {
+                    log_level::DEBUG => log::Level::Debug,
+                    log_level::INFO => log::Level::Info,
+                    log_level::WARN => log::Level::Warn,
+                    log_level::ERROR => log::Level::Error,
+                    _ => return Ok(()),
+                };
+                // Tagged messages are logged under `cobot::<tag>` so they can be filtered by
+                // subsystem.
+                let target = match tag {
+                    Some(tag) if tag != 0 => format!("cobot::{}", tag),
+                    _ => "cobot".to_string(),
+                };
+                self.stats.logs_received += 1;
+                if self.recent_logs.len() >= RECENT_LOG_CAPACITY {
+                    self.recent_logs.pop_front();
+                }
+                self.recent_logs
+                    .push_back(format!("[{}] {}", level, message));
+                self.send_event(CommsEvent::LogReceived {
+                    level: raw_level,
+                    tag,
+                    message: message.clone(),
+                });
+                if raw_level < self.log_display_level {
+                    return Ok(());
+                }
+                log::logger().log(
+                    &log::Record::builder()
+                        .args(format_args!("{}", message))
+                        .level(level)
+                        .target(&target)
+                        .file(Some("cobot"))
+                        .line(Some(0))
+                        .module_path(Some(&target))
+                        .build(),
+                );
+            }
+            Message::Response(response) if response.command_id == STREAM_COMMAND_ID => {
+                self.session_log.push(
+                    Direction::Received,
+                    response.response_type,
+                    response.command_id,
+                    &response.payload,
+                );
+                // Streamed joints answer no request, so they go straight to the handler instead of
+                // waiting to be claimed.
+                if response.response_type != response_type::JOINTS {
+                    warn!(
+                        "Received {} response with the stream command ID",
+                        response_type_str(response.response_type)
+                    );
+                    return Ok(());
+                }
+                let joints = match parse_joint_states(&response.payload) {
+                    Ok(joints) => joints,
+                    Err(e) => {
+                        warn!("Received invalid streamed joints: {}", e);
+                        return Ok(());
+                    }
+                };
+                let now = self.clock.now();
+                self.stats.responses_received += 1;
+                self.joint_count = Some(joints.len() as u8);
+                self.last_successful_joints_at = Some(now);
+                self.joints_cache = Some((now, joints.clone()));
+                if let Some(handler) = &mut self.joints_handler {
+                    handler(&joints);
+                }
+            }
+            Message::Response(response) => {
+                self.session_log.push(
+                    Direction::Received,
+                    response.response_type,
+                    response.command_id,
+                    &response.payload,
+                );
+                trace!(
+                    "Received {} response to command {}",
+                    response_type_str(response.response_type),
+                    response.command_id
+                );
+
+                // "Not initialized" and "Not calibrated" errors mean the firmware has lost state
+                // this connection thought it had, such as after rebooting.
+                if response.response_type == response_type::ERROR {
+                    match response.payload.first() {
+                        Some(4) => {
+                            self.initialized = false;
+                            self.calibrated = JointMask::default();
+                        }
+                        Some(5) => self.calibrated = JointMask::default(),
+                        _ => {}
+                    }
+                }
+
+                self.stats.responses_received += 1;
+                self.send_event(CommsEvent::ResponseReceived(response.clone()));
+                if self.responses.len() >= RESPONSE_CAPACITY {
+                    // Claimed responses are swap-removed, so the oldest can be anywhere.
+                    let responses = &self.responses;
+                    if let Some(idx) = (0..responses.len()).min_by_key(|&idx| responses[idx].1) {
+                        let (oldest, _) = self.responses.swap_remove(idx);
+                        warn!(
+                            "Response buffer full, discarding unclaimed response to command {}",
+                            oldest.command_id
+                        );
+                    }
+                }
+                self.responses.push((response, self.clock.now()));
+            }
+            Message::Fault(fault) => {
+                let severity = fault.severity;
+                log::error!("{}", fault);
+
+                // A fault may have left the joints anywhere, so they can't be trusted to still
+                // be calibrated.
+                self.calibrated = JointMask::default();
+
+                if self.recent_faults.len() >= RECENT_FAULT_CAPACITY {
+                    self.recent_faults.pop_front();
+                }
+                self.recent_faults.push_back(fault.clone());
+                if let Some(handler) = &mut self.fault_handler {
+                    handler(&fault);
+                }
+
+                // The STOP's responses are left to expire, since whatever request is being waited
+                // for must still be handled normally.
+                if self
+                    .fault_stop_severity
+                    .is_some_and(|threshold| severity >= threshold)
+                {
+                    warn!("Stopping all joints because of the fault");
+                    let mut stop_payload = vec![1];
+                    stop_payload.extend(self.encode_mask(self.all_joints())?);
+                    self.send_request(request_type::STOP, &stop_payload)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Broadcast an event to every subscriber. Events sent while nobody is subscribed are dropped.
+    fn send_event(&self, event: CommsEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Mark the link as lost, telling subscribers the first time.
+    fn lose_link(&mut self, reason: String) {
+        if self.link_lost.is_none() {
+            self.send_event(CommsEvent::Disconnected);
+        }
+        self.link_lost = Some(reason);
+    }
+
+    /// Writes a whole frame to the serial port. Short writes are resumed from the first byte that
+    /// wasn't written, never from the start of the frame, which would corrupt the framing.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to write.
+    ///
+    /// # Returns
+    ///
+    /// Ok once every byte has been written, or `CommsError::WriteTimeout` if the port stopped
+    /// accepting bytes for `WRITE_ATTEMPTS` writes in a row. The connection is lost after any
+    /// error.
+    fn write_frame(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut sent = 0;
+        let mut stalled = 0;
+
+        while sent < frame.len() {
+            if let Err(e) = self.port.set_timeout(WRITE_TIMEOUT) {
+                self.lose_link(e.to_string());
+                return Err(Box::new(e));
+            }
+
+            match self.port.write(&frame[sent..]) {
+                Ok(0) => stalled += 1,
+                Ok(written) => {
+                    sent += written;
+                    stalled = 0;
+                    self.stats.bytes_written += written as u64;
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::TimedOut
+                            | std::io::ErrorKind::Interrupted
+                            | std::io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    stalled += 1
+                }
+                Err(e) => {
+                    self.lose_link(e.to_string());
+                    return Err(Box::new(e));
+                }
+            }
+
+            if stalled >= WRITE_ATTEMPTS {
+                let error = CommsError::WriteTimeout {
+                    sent,
+                    total: frame.len(),
+                };
+                self.lose_link(error.to_string());
+                return Err(Box::new(error));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads enough bytes from the serial port to fill the given buffer. Short reads are
+    /// accumulated until the buffer is full, since some drivers return data a few bytes at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Buffer to fill.
+    /// * `timeout` - Maximum time to wait for the buffer to be filled.
+    ///
+    /// # Returns
+    ///
+    /// True if the buffer was filled, or false if the timeout was reached before the buffer was
+    /// filled. An error is only returned if the port is disconnected or otherwise fails.
+    fn read_exact(&mut self, buffer: &mut [u8], timeout: Duration) -> Result<bool, Box<dyn Error>> {
+        let start_time = self.clock.now();
+        let mut filled = 0;
+
+        while filled < buffer.len() {
+            self.port
+                .set_timeout(self.remaining_timeout(start_time, timeout))?;
+
+            match self.port.read(&mut buffer[filled..]) {
+                // A read that returns nothing without timing out means the port has gone away.
+                Ok(0) => {
+                    self.lose_link("Serial port disconnected".to_string());
+                    return Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "Serial port disconnected",
+                    )));
+                }
+                Ok(read) => {
+                    filled += read;
+                    self.stats.bytes_read += read as u64;
+                }
+                // Transient failures are retried until the deadline.
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::TimedOut
+                            | std::io::ErrorKind::Interrupted
+                            | std::io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    if self.remaining_timeout(start_time, timeout).is_zero() {
+                        return Ok(false);
+                    }
+                }
+                Err(e) => {
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::NotConnected
+                    ) {
+                        self.lose_link(e.to_string());
+                    }
+                    return Err(Box::new(e));
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Determine the remaining time until the timeout is reached. Will return 0 if the timeout has
+    /// already been reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_time` - Time the timeout started.
+    /// * `timeout` - Timeout duration.
+    ///
+    /// # Returns
+    ///
+    /// The remaining time until the timeout is reached.
+    fn remaining_timeout(&self, start_time: Instant, timeout: Duration) -> Duration {
+        timeout.saturating_sub(self.elapsed_since(start_time))
+    }
+
+    /// Enforce the rate limit before sending a request, by waiting until the request is allowed or
+    /// rejecting it.
+    fn throttle(&mut self) -> Result<(), CommsError> {
+        let Some(rate_limit) = self.rate_limit else {
+            return Ok(());
+        };
+
+        if let Some(last_request_at) = self.last_request_at {
+            let wait = Duration::from_millis(rate_limit.min_interval_ms)
+                .saturating_sub(self.elapsed_since(last_request_at));
+            if !wait.is_zero() {
+                self.stats.throttled += 1;
+                if rate_limit.reject {
+                    return Err(CommsError::Throttled);
+                }
+                std::thread::sleep(wait);
+            }
+        }
+        self.last_request_at = Some(self.clock.now());
+
+        Ok(())
+    }
+
+    /// Time since the given instant by the connection's clock, or zero if the clock reads earlier.
+    fn elapsed_since(&self, start_time: Instant) -> Duration {
+        self.clock.now().saturating_duration_since(start_time)
+    }
+}
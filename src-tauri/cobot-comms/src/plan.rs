@@ -0,0 +1,175 @@
+//! Format of scripted test plans, shared by the config tester and cobot-cli, which each run them.
+//!
+//! A test plan is a YAML file with an optional connection and a list of steps, run in order:
+//!
+//! ```yaml
+//! name: Wrist check
+//! connect:
+//!   port_name: /dev/ttyUSB0
+//!   baud_rate: 115200
+//! steps:
+//!   - action: init
+//!   - action: enable_motion
+//!   - action: calibrate
+//!     joints: [0, 1, 2, 3, 4, 5]
+//!   - action: move_joint
+//!     joint: 2
+//!     angle: 45
+//!     speed: 20
+//!   - action: expect_angle
+//!     joint: 2
+//!     angle: 45
+//!     tolerance: 0.5
+//!     within_ms: 10000
+//!   - action: wait
+//!     ms: 500
+//!     continue_on_failure: true
+//! ```
+//!
+//! A plan stops at the first failed step, unless that step has `continue_on_failure` set. Angles
+//! and speeds are in whatever frame and units the runner moves joints in.
+
+use serde::{Deserialize, Serialize};
+
+use crate::JointMask;
+
+/// Test plan, as read from a file.
+#[derive(Clone, Debug)]
+pub struct TestPlan {
+    /// Name of the plan, shown in its report.
+    pub name: Option<String>,
+
+    /// Port to connect to before the first step. If omitted, the runner connects on its own.
+    pub connect: Option<ConnectParams>,
+
+    /// Steps to run, in order.
+    pub steps: Vec<Step>,
+}
+
+/// Serial port to connect to before running a plan.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConnectParams {
+    /// Name of the serial port.
+    pub port_name: String,
+
+    /// Baud rate of the serial port.
+    pub baud_rate: u32,
+}
+
+/// Single step of a test plan.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Step {
+    /// What the step does.
+    #[serde(flatten)]
+    pub action: Action,
+
+    /// Whether the plan carries on if this step fails.
+    #[serde(default)]
+    pub continue_on_failure: bool,
+}
+
+/// What a step of a test plan does.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Initialize the COBOT.
+    Init {
+        #[serde(default)]
+        force: bool,
+    },
+
+    /// Enable motion commands for the configured timeout.
+    EnableMotion,
+
+    /// Calibrate the given joints.
+    Calibrate { joints: Vec<u8> },
+
+    /// Move a joint and wait for the move to finish.
+    MoveJoint {
+        joint: u8,
+        angle: f32,
+        #[serde(default)]
+        speed: Option<f32>,
+    },
+
+    /// Stop every joint.
+    StopAll {
+        #[serde(default)]
+        immediately: bool,
+    },
+
+    /// Do nothing for a while.
+    Wait { ms: u64 },
+
+    /// Check that a joint reaches an angle, within a tolerance, before a deadline, and stays
+    /// there for the settle time.
+    ExpectAngle {
+        joint: u8,
+        angle: f32,
+        tolerance: f32,
+        #[serde(default)]
+        within_ms: u64,
+        #[serde(default)]
+        settle_ms: u64,
+    },
+
+    /// Check that the given joints slow to within a speed threshold before a deadline.
+    ExpectStopped {
+        joints: Vec<u8>,
+        speed_threshold: f32,
+        #[serde(default)]
+        within_ms: u64,
+    },
+}
+
+impl Action {
+    /// Joints the action moves, which are stopped if the plan is paused during it.
+    pub fn joints(&self) -> JointMask {
+        match *self {
+            Action::Calibrate { ref joints } => {
+                joints.iter().fold(JointMask::default(), |mask, &joint| {
+                    mask | JointMask::joint(joint)
+                })
+            }
+            Action::MoveJoint { joint, .. } => JointMask::joint(joint),
+            _ => JointMask::default(),
+        }
+    }
+}
+
+/// Parse a test plan, checking each step on its own so an error names the step at fault.
+///
+/// # Arguments
+///
+/// * `source` - YAML source of the plan.
+///
+/// # Returns
+///
+/// The plan, or a message describing the first problem found.
+pub fn parse(source: &str) -> Result<TestPlan, String> {
+    #[derive(Deserialize)]
+    struct RawPlan {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        connect: Option<ConnectParams>,
+        steps: Vec<serde_yaml::Value>,
+    }
+
+    let raw: RawPlan =
+        serde_yaml::from_str(source).map_err(|e| format!("Invalid test plan: {}", e))?;
+    let steps = raw
+        .steps
+        .into_iter()
+        .enumerate()
+        .map(|(index, step)| {
+            serde_yaml::from_value(step).map_err(|e| format!("Invalid step {}: {}", index + 1, e))
+        })
+        .collect::<Result<Vec<Step>, _>>()?;
+
+    Ok(TestPlan {
+        name: raw.name,
+        connect: raw.connect,
+        steps,
+    })
+}
@@ -0,0 +1,139 @@
+//! Coalescing of move targets sent at a high rate, such as while a slider is dragged.
+//!
+//! Targets are queued per joint in a `TargetQueue`. Whoever queues the first target of a joint
+//! sends it with `CobotConnection::send_queued_targets`, and keeps sending until the joint has no
+//! target left. A target queued while an earlier one is still waiting to be sent replaces it, so
+//! a burst of targets sends far fewer requests and always ends with the latest.
+
+use std::{collections::HashMap, error::Error, sync::Mutex};
+
+use crate::{CobotConnection, JointMask, Transport};
+
+/// Target of a joint, in the firmware frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// Angle and speed to move to with MOVE_TO.
+    Position(f32, Option<f32>),
+
+    /// Speed to move at with MOVE_SPEED.
+    Speed(f32),
+}
+
+/// Latest target of a joint, waiting to be sent.
+#[derive(Clone, Copy, Debug)]
+struct QueuedTarget {
+    /// Target to send.
+    target: Target,
+
+    /// Number of earlier targets this one replaced.
+    superseded: u64,
+}
+
+/// Latest target of each joint not sent yet. Shared between the commands queueing targets and
+/// the one sending them.
+#[derive(Debug, Default)]
+pub struct TargetQueue {
+    /// Target of each joint with one waiting.
+    pending: Mutex<HashMap<u8, QueuedTarget>>,
+}
+
+impl TargetQueue {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the latest target of a joint.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint` - Joint to move.
+    /// * `target` - Target of the joint.
+    ///
+    /// # Returns
+    ///
+    /// True if the caller must send the target with `send_queued_targets`, or false if it
+    /// replaced a target that hasn't been sent yet, and will be sent in its place.
+    pub fn push(&self, joint: u8, target: Target) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get_mut(&joint) {
+            Some(queued) => {
+                queued.target = target;
+                queued.superseded += 1;
+                false
+            }
+            None => {
+                pending.insert(
+                    joint,
+                    QueuedTarget {
+                        target,
+                        superseded: 0,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Drop the targets of the given joints, so they are never sent.
+    pub fn remove(&self, joints: JointMask) {
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|&joint, _| (JointMask::joint(joint) & joints).is_empty());
+    }
+
+    /// Drop every target, so none is sent.
+    pub fn clear(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+
+    /// Take the target of a joint out of the queue.
+    fn take(&self, joint: u8) -> Option<QueuedTarget> {
+        self.pending.lock().unwrap().remove(&joint)
+    }
+}
+
+impl<T: Transport> CobotConnection<T> {
+    /// Send the queued targets of a joint until none is left, then wait for the last move to
+    /// finish. Each target is only sent once the one before it has been acknowledged. If a send
+    /// fails, a target queued since is left in the queue, and the caller should remove it.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue` - Queue the targets are taken from.
+    /// * `joint` - Joint to send the targets of.
+    /// * `on_started` - Called with each target once the COBOT has acknowledged it.
+    ///
+    /// # Returns
+    ///
+    /// Ok once the last target has been reached, or started for a speed, or an error if the
+    /// COBOT rejected a target.
+    pub fn send_queued_targets(
+        &mut self,
+        queue: &TargetQueue,
+        joint: u8,
+        mut on_started: impl FnMut(Target),
+    ) -> Result<(), Box<dyn Error>> {
+        let mut last_move = None;
+        while let Some(queued) = queue.take(joint) {
+            self.stats.coalesced += queued.superseded;
+
+            last_move = match queued.target {
+                Target::Position(angle, speed) => {
+                    Some(self.start_move_to(&[(joint, angle, speed)])?)
+                }
+                Target::Speed(speed) => {
+                    self.move_speed(&[(joint, speed)])?;
+                    None
+                }
+            };
+            on_started(queued.target);
+        }
+
+        match last_move {
+            Some(command_id) => self.wait_for_done(command_id),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,1595 @@
+//! Tests of the codec, and of `CobotConnection` driven over a `MockTransport`.
+
+use super::*;
+
+/// Time to wait for any response in tests, short so tests that time out finish quickly.
+const TEST_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Open a connection over a mock transport, with every joint counted as calibrated so moves
+/// aren't refused locally. Time is measured by the returned clock, which the transport advances
+/// instead of sleeping, so timeouts take no time at all.
+fn connection_with_clock() -> (CobotConnection<MockTransport>, MockClock) {
+    let clock = MockClock::new();
+    let mut port = MockTransport::new();
+    port.clock = Some(clock.clone());
+    let mut cobot = CobotConnection::new(port, FIRMWARE_VERSION, TEST_TIMEOUT);
+    cobot.set_clock(Box::new(clock.clone()));
+    cobot.set_calibration_timeout(TEST_TIMEOUT);
+    cobot.assume_calibrated(JointMask::first(JointMask::MAX_JOINTS));
+    (cobot, clock)
+}
+
+/// Open a connection over a mock transport, as `connection_with_clock`.
+fn connection() -> CobotConnection<MockTransport> {
+    connection_with_clock().0
+}
+
+/// Frame a message as the COBOT would send it with protocol version 1 and no escaping.
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut frame = vec![START_BYTE, message.len() as u8, crc8ccitt(message)];
+    frame.extend_from_slice(message);
+    frame
+}
+
+/// Frame a response to a request.
+fn response_frame(response_type: u8, command_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut message = vec![received_msg_type::RESPONSE, response_type];
+    message.extend_from_slice(&command_id.to_le_bytes());
+    message.extend_from_slice(payload);
+    frame(&message)
+}
+
+/// Check that an error is `CommsError::InvalidArgument` for the given field.
+fn assert_invalid_argument(error: Box<dyn Error>, expected_field: &str) {
+    match error.downcast_ref::<CommsError>() {
+        Some(CommsError::InvalidArgument { field, .. }) => assert_eq!(*field, expected_field),
+        _ => panic!("expected an invalid {}, got {}", expected_field, error),
+    }
+}
+
+#[test]
+fn move_to_rejects_bad_speeds_before_writing() {
+    for speed in [-1.0, -0.001, f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+        let mut cobot = connection();
+        let error = cobot.move_to(&[(0, 10.0, Some(speed))]).unwrap_err();
+        assert_invalid_argument(error, "speed");
+        assert!(cobot.port.written.is_empty(), "speed {} was written", speed);
+    }
+}
+
+#[test]
+fn move_to_rejects_a_bad_speed_on_any_joint() {
+    let mut cobot = connection();
+    let error = cobot
+        .move_to(&[(0, 10.0, Some(5.0)), (1, 20.0, Some(-5.0))])
+        .unwrap_err();
+    assert_invalid_argument(error, "speed");
+    assert!(cobot.port.written.is_empty());
+}
+
+#[test]
+fn move_to_sends_a_valid_speed() {
+    let mut cobot = connection();
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 0, &[]));
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::DONE, 0, &[]));
+    cobot.move_to(&[(0, 10.0, Some(0.0))]).unwrap();
+    assert!(!cobot.port.written.is_empty());
+}
+
+#[test]
+fn response_type_names_include_unknown_values() {
+    assert_eq!(response_type_str(response_type::ACK), "ACK");
+    assert_eq!(response_type_str(response_type::FULL_STATUS), "FULL_STATUS");
+    assert_eq!(response_type_str(0x42), "Unknown(66)");
+    assert_eq!(
+        unexpected_response(response_type::ACK, 0x42).to_string(),
+        "Expected ACK response but received Unknown(66) (0x42)"
+    );
+}
+
+/// Traffic of `messages` messages read, the last `crc_errors` of them with a bad CRC.
+fn traffic(messages: usize, crc_errors: usize) -> Vec<LinkEvent> {
+    let mut events = vec![LinkEvent::MessageReceived; messages - crc_errors];
+    events.extend(vec![LinkEvent::CrcError; crc_errors]);
+    events
+}
+
+#[test]
+fn link_grade_worsens_with_crc_failures() {
+    let thresholds = LinkQualityThresholds::default();
+    assert_eq!(grade_link([], &thresholds), LinkQuality::Good);
+    assert_eq!(grade_link(traffic(200, 0), &thresholds), LinkQuality::Good);
+    assert_eq!(grade_link(traffic(200, 1), &thresholds), LinkQuality::Good);
+    assert_eq!(
+        grade_link(traffic(200, 2), &thresholds),
+        LinkQuality::Degraded
+    );
+    assert_eq!(
+        grade_link(traffic(200, 19), &thresholds),
+        LinkQuality::Degraded
+    );
+    assert_eq!(grade_link(traffic(200, 20), &thresholds), LinkQuality::Bad);
+}
+
+#[test]
+fn link_grade_takes_the_worse_of_crc_and_timeout_rates() {
+    let thresholds = LinkQualityThresholds::default();
+    let mut events = traffic(100, 0);
+    events.extend(vec![LinkEvent::RequestSent; 10]);
+    assert_eq!(grade_link(events.clone(), &thresholds), LinkQuality::Good);
+    events.push(LinkEvent::Timeout);
+    assert_eq!(grade_link(events, &thresholds), LinkQuality::Bad);
+}
+
+#[test]
+fn link_grade_follows_corrupted_frames_on_the_wire() {
+    let thresholds = LinkQualityThresholds::default();
+    let mut cobot = connection();
+    let mut grades = Vec::new();
+
+    // Each round reads 51 frames, of which more and more arrive corrupted. The grade covers every
+    // round so far, since all of them fall within the window.
+    for (round, corrupted) in [0, 2, 20].into_iter().enumerate() {
+        let command_id = round as u32;
+        for i in 0..50 {
+            let mut frame = response_frame(response_type::ACK, STREAM_COMMAND_ID - 1, &[]);
+            if i < corrupted {
+                frame[2] ^= 0xFF;
+            }
+            cobot.port.push_incoming(&frame);
+        }
+        cobot
+            .port
+            .push_incoming(&response_frame(response_type::ACK, command_id, &[]));
+        cobot.wait_for_response(command_id, TEST_TIMEOUT).unwrap();
+        cobot.flush_responses();
+        grades.push(cobot.link_quality(&thresholds));
+    }
+
+    assert_eq!(
+        grades,
+        [LinkQuality::Good, LinkQuality::Degraded, LinkQuality::Bad]
+    );
+    assert_eq!(cobot.stats().crc_errors, 22);
+}
+
+/// Encode an INFO entry.
+fn info_entry(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut entry = vec![tag, value.len() as u8];
+    entry.extend_from_slice(value);
+    entry
+}
+
+#[test]
+fn device_info_parses_every_tag() {
+    let payload = [
+        info_entry(info_tag::UPTIME, &3600u32.to_le_bytes()),
+        info_entry(info_tag::BOARD_TEMPERATURE, &412i16.to_le_bytes()),
+        info_entry(info_tag::SUPPLY_VOLTAGE, &24_100u16.to_le_bytes()),
+        info_entry(info_tag::JOINT_TEMPERATURE, &[2, 0x2C, 0x01]),
+        info_entry(info_tag::FIRMWARE_VERSION, &7u32.to_le_bytes()),
+        info_entry(info_tag::JOINT_COUNT, &[6]),
+        info_entry(info_tag::MAX_SPEED, &90_000i32.to_le_bytes()),
+        info_entry(info_tag::MAX_ANGLE, &170_500i32.to_le_bytes()),
+    ]
+    .concat();
+    let info = parse_device_info(&payload).unwrap();
+
+    assert_eq!(info.uptime_s, Some(3600));
+    assert_eq!(info.board_temperature_c, Some(41.2));
+    assert_eq!(info.supply_voltage_v, Some(24.1));
+    assert_eq!(info.joint_temperatures_c, [None, None, Some(30.0)]);
+    assert_eq!(info.firmware_version, Some(7));
+    assert_eq!(info.joint_count, Some(6));
+    assert_eq!(info.max_speed_deg_s, Some(90.0));
+    assert_eq!(info.max_angle_deg, Some(170.5));
+}
+
+#[test]
+fn device_info_keeps_the_last_of_duplicate_tags() {
+    let payload = [
+        info_entry(info_tag::UPTIME, &10u32.to_le_bytes()),
+        info_entry(info_tag::JOINT_TEMPERATURE, &[0, 100, 0]),
+        info_entry(info_tag::UPTIME, &20u32.to_le_bytes()),
+        info_entry(info_tag::JOINT_TEMPERATURE, &[0, 200, 0]),
+    ]
+    .concat();
+    let info = parse_device_info(&payload).unwrap();
+
+    assert_eq!(info.uptime_s, Some(20));
+    assert_eq!(info.joint_temperatures_c, [Some(20.0)]);
+}
+
+#[test]
+fn device_info_skips_unknown_tags_of_any_length() {
+    let payload = [
+        info_entry(0x7F, &[1, 2, 3, 4, 5, 6, 7]),
+        info_entry(info_tag::JOINT_COUNT, &[4]),
+        info_entry(0x80, &[]),
+    ]
+    .concat();
+    let info = parse_device_info(&payload).unwrap();
+
+    assert_eq!(info.joint_count, Some(4));
+    assert_eq!(info.uptime_s, None);
+}
+
+#[test]
+fn device_info_rejects_truncated_entries() {
+    let uptime = info_entry(info_tag::UPTIME, &3600u32.to_le_bytes());
+
+    // Cut off in the value, then in the header of a following entry.
+    assert!(parse_device_info(&uptime[..uptime.len() - 1]).is_err());
+    assert!(parse_device_info(&[uptime.as_slice(), &[info_tag::JOINT_COUNT]].concat()).is_err());
+    // An unknown tag is skipped by its length, so it must be whole too.
+    assert!(parse_device_info(&[0x7F, 4, 1, 2]).is_err());
+}
+
+#[test]
+fn device_info_rejects_known_tags_of_the_wrong_length() {
+    assert!(parse_device_info(&info_entry(info_tag::UPTIME, &[1, 2])).is_err());
+    assert!(parse_device_info(&info_entry(info_tag::JOINT_COUNT, &[])).is_err());
+    assert!(parse_device_info(&info_entry(info_tag::JOINT_TEMPERATURE, &[0, 1])).is_err());
+}
+
+#[test]
+fn get_device_info_reads_an_info_response() {
+    let mut cobot = connection();
+    let payload = info_entry(info_tag::JOINT_COUNT, &[6]);
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::INFO, 0, &payload));
+
+    assert_eq!(cobot.get_device_info().unwrap().joint_count, Some(6));
+}
+
+#[test]
+fn frames_read_one_byte_at_a_time_are_reassembled() {
+    let mut cobot = connection();
+    cobot.port.read_limit = Some(1);
+    let mut payload = vec![2];
+    for (angle, speed) in [(12_500i32, 0i32), (-90_000, 1_500)] {
+        payload.extend_from_slice(&angle.to_le_bytes());
+        payload.extend_from_slice(&speed.to_le_bytes());
+    }
+    let response = response_frame(response_type::JOINTS, 0, &payload);
+    cobot.port.push_incoming(&response);
+
+    assert_eq!(cobot.get_joints().unwrap(), [(12.5, 0.0), (-90.0, 1.5)]);
+    assert_eq!(cobot.stats().bytes_read, response.len() as u64);
+    assert_eq!(cobot.stats().crc_errors, 0);
+}
+
+#[test]
+fn short_error_responses_are_reported_without_panicking() {
+    let mut cobot = connection();
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ERROR, 0, &[]));
+    let error = cobot.get_joints().unwrap_err();
+    assert!(error.downcast_ref::<CobotError>().is_none());
+
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ERROR, 1, &[3]));
+    let error = cobot.get_full_status().unwrap_err();
+    match error.downcast_ref::<CobotError>() {
+        Some(CobotError { code: 3, message }) => assert!(message.is_empty()),
+        _ => panic!("expected error code 3, got {}", error),
+    }
+}
+
+#[test]
+fn error_responses_carry_their_message() {
+    let mut cobot = connection();
+    cobot.port.push_incoming(&response_frame(
+        response_type::ERROR,
+        0,
+        b"\x04\x00Joint busy",
+    ));
+    let error = cobot.get_joints().unwrap_err();
+    match error.downcast_ref::<CobotError>() {
+        Some(CobotError { code: 4, message }) => assert_eq!(message, "Joint busy"),
+        _ => panic!("expected error code 4, got {}", error),
+    }
+}
+
+/// Build the payload of a JOINTS response.
+fn joints_payload(joints: &[(f32, f32)]) -> Vec<u8> {
+    let mut payload = vec![joints.len() as u8];
+    for (angle, speed) in joints {
+        payload.extend_from_slice(&((angle * 1000.0) as i32).to_le_bytes());
+        payload.extend_from_slice(&((speed * 1000.0) as i32).to_le_bytes());
+    }
+    payload
+}
+
+#[test]
+fn timed_move_returns_once_acknowledged() {
+    let mut cobot = connection();
+    let joints = joints_payload(&[(0.0, 0.0)]);
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::JOINTS, 0, &joints));
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 1, &[]));
+    let command_id = cobot
+        .start_move_to_in_duration(0, 90.0, Duration::from_secs(3), None)
+        .unwrap();
+    assert_eq!(command_id, Some(1));
+
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::DONE, 1, &[]));
+    cobot.wait_for_done(1).unwrap();
+}
+
+#[test]
+fn timed_move_to_the_current_angle_sends_nothing() {
+    let mut cobot = connection();
+    let joints = joints_payload(&[(45.0, 0.0)]);
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::JOINTS, 0, &joints));
+    let command_id = cobot
+        .start_move_to_in_duration(0, 45.0, Duration::from_secs(3), None)
+        .unwrap();
+    assert_eq!(command_id, None);
+    assert_eq!(cobot.stats().requests_sent, 1);
+}
+
+#[test]
+fn a_burst_of_targets_is_coalesced_to_the_latest() {
+    const TARGETS: usize = 100;
+    const ARRIVING_PER_ACK: usize = 11;
+
+    let mut cobot = connection();
+    let queue = TargetQueue::new();
+    let target = |i: usize| Target::Position(i as f32, Some(30.0));
+
+    // The first target is sent straight away. While each move waits for its ACK, the next few
+    // targets arrive, and only the latest of them is sent.
+    let sends = 1 + (TARGETS - 1).div_ceil(ARRIVING_PER_ACK) as u32;
+    for command_id in 0..sends {
+        cobot
+            .port
+            .push_incoming(&response_frame(response_type::ACK, command_id, &[]));
+    }
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::DONE, sends - 1, &[]));
+
+    assert!(queue.push(0, target(0)));
+    let mut queued = 1;
+    let mut started = Vec::new();
+    cobot
+        .send_queued_targets(&queue, 0, |sent| {
+            started.push(sent);
+            for _ in 0..ARRIVING_PER_ACK.min(TARGETS - queued) {
+                queue.push(0, target(queued));
+                queued += 1;
+            }
+        })
+        .unwrap();
+
+    assert_eq!(queued, TARGETS);
+    assert_eq!(started.len(), sends as usize);
+    assert_eq!(started.last(), Some(&target(TARGETS - 1)));
+    let stats = cobot.stats();
+    assert_eq!(stats.requests_sent, sends as u64);
+    assert_eq!(stats.coalesced, (TARGETS - sends as usize) as u64);
+
+    // The last frame on the wire moves the joint to the last target.
+    let mut body = vec![request_type::MOVE_TO];
+    body.extend_from_slice(&(sends - 1).to_le_bytes());
+    body.push(0);
+    body.extend_from_slice(&((TARGETS as i32 - 1) * 1000).to_le_bytes());
+    body.extend_from_slice(&30_000i32.to_le_bytes());
+    assert!(cobot.port.written.ends_with(&frame(&body)));
+}
+
+/// Transport that corrupts the frames queued to be read, as a noisy RS-485 link would. With the
+/// given probability, a frame has one byte flipped or dropped. Faults are kept out of the start
+/// byte and length, so every flip fails the CRC check, and every drop leaves the frame a byte
+/// short, to time out.
+struct FaultyTransport {
+    /// Transport the corrupted frames are read from.
+    inner: MockTransport,
+
+    /// Probability that a frame is corrupted.
+    probability: f64,
+
+    /// State of the xorshift generator choosing the faults, so a seed always injects the same.
+    state: u64,
+
+    /// Number of frames with a byte flipped.
+    flipped: u64,
+
+    /// Number of frames with a byte dropped.
+    dropped: u64,
+}
+
+impl FaultyTransport {
+    /// Create a transport with nothing to read, corrupting frames with the given probability.
+    fn new(probability: f64, seed: u64) -> Self {
+        Self {
+            inner: MockTransport::new(),
+            probability,
+            state: seed,
+            flipped: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Get the next number of the generator.
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Queue a frame to be read, corrupting it with the transport's probability.
+    fn push_frame(&mut self, frame: &[u8]) {
+        let mut frame = frame.to_vec();
+        let chance = (self.next_random() >> 11) as f64 / (1u64 << 53) as f64;
+        if chance < self.probability {
+            let index = 2 + self.next_random() as usize % (frame.len() - 2);
+            if self.next_random().is_multiple_of(2) {
+                frame[index] ^= 1 + (self.next_random() % 255) as u8;
+                self.flipped += 1;
+            } else {
+                frame.remove(index);
+                self.dropped += 1;
+            }
+        }
+        self.inner.push_incoming(&frame);
+    }
+}
+
+impl std::io::Read for FaultyTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl std::io::Write for FaultyTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for FaultyTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn clear(&mut self) -> std::io::Result<()> {
+        self.inner.clear()
+    }
+
+    fn baud_rate(&self) -> std::io::Result<u32> {
+        self.inner.baud_rate()
+    }
+}
+
+#[test]
+fn get_joints_recovers_from_corrupted_frames() {
+    const READS: usize = 40;
+    const ATTEMPTS: usize = 10;
+
+    let clock = MockClock::new();
+    let mut port = FaultyTransport::new(0.3, 0x5EED_C0B0);
+    port.inner.clock = Some(clock.clone());
+    let mut cobot = CobotConnection::new(port, FIRMWARE_VERSION, TEST_TIMEOUT);
+    cobot.set_clock(Box::new(clock));
+
+    for i in 0..READS {
+        let joints = [(i as f32 * 1.5, 10.0), (-(i as f32), 0.0)];
+        let payload = joints_payload(&joints);
+
+        // A corrupted response is lost, so the read is retried, as a caller would.
+        let read = (0..ATTEMPTS)
+            .find_map(|_| {
+                let command_id = cobot.next_command_id;
+                cobot
+                    .port
+                    .push_frame(&response_frame(response_type::JOINTS, command_id, &payload));
+                cobot.get_joints().ok()
+            })
+            .expect("no uncorrupted response");
+        assert_eq!(read, joints);
+    }
+
+    let stats = cobot.stats();
+    assert!(cobot.port.flipped > 0 && cobot.port.dropped > 0);
+    assert_eq!(stats.crc_errors, cobot.port.flipped);
+    assert_eq!(
+        stats.requests_sent,
+        READS as u64 + cobot.port.flipped + cobot.port.dropped
+    );
+}
+
+#[test]
+fn stacked_consumers_share_one_joints_query() {
+    const MAX_AGE: Duration = Duration::from_millis(50);
+
+    let mut cobot = connection();
+    let joints = [(12.0, 0.0), (-45.0, 3.0)];
+    let payload = joints_payload(&joints);
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::JOINTS, 0, &payload));
+
+    // Motion status, stall detection and undo capture each read the joints in turn.
+    for _ in 0..3 {
+        assert_eq!(cobot.get_joints_cached(MAX_AGE).unwrap(), joints);
+    }
+    assert_eq!(cobot.stats().requests_sent, 1);
+
+    // A consumer that needs fresh joints always queries the COBOT.
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::JOINTS, 1, &payload));
+    assert_eq!(cobot.get_joints_cached(Duration::ZERO).unwrap(), joints);
+    assert_eq!(cobot.stats().requests_sent, 2);
+}
+
+#[test]
+fn cached_joints_expire_just_after_their_max_age() {
+    const MAX_AGE: Duration = Duration::from_millis(50);
+
+    let (mut cobot, clock) = connection_with_clock();
+    let payload = joints_payload(&[(10.0, 0.0)]);
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::JOINTS, 0, &payload));
+    cobot.get_joints_cached(MAX_AGE).unwrap();
+
+    // A reading exactly as old as the bound is still reused.
+    clock.advance(MAX_AGE);
+    cobot.get_joints_cached(MAX_AGE).unwrap();
+    assert_eq!(cobot.stats().requests_sent, 1);
+
+    clock.advance(Duration::from_nanos(1));
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::JOINTS, 1, &payload));
+    cobot.get_joints_cached(MAX_AGE).unwrap();
+    assert_eq!(cobot.stats().requests_sent, 2);
+}
+
+#[test]
+fn timeouts_are_measured_by_the_connection_clock() {
+    let (mut cobot, clock) = connection_with_clock();
+    let start = clock.now();
+    let error = cobot.get_joints().unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<std::io::Error>().map(|e| e.kind()),
+        Some(std::io::ErrorKind::TimedOut)
+    );
+    assert_eq!(clock.now() - start, TEST_TIMEOUT);
+    assert_eq!(cobot.stats().timeouts, 1);
+}
+
+#[test]
+fn move_to_delta_only_encodes_changed_joints() {
+    let mut cobot = connection();
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 0, &[]));
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::DONE, 0, &[]));
+    let mut sent = [
+        (0, 10.0, Some(20.0)),
+        (1, 20.0, None),
+        (2, 30.0, Some(20.0)),
+    ];
+    let current = [
+        (0, 10.0, Some(20.0)),
+        (1, 25.0, None),
+        (2, 30.0005, Some(20.0)),
+    ];
+    cobot.move_to_delta(&mut sent, &current, 0.001).unwrap();
+
+    let mut body = vec![request_type::MOVE_TO, 0, 0, 0, 0, 1];
+    body.extend_from_slice(&25_000i32.to_le_bytes());
+    body.extend_from_slice(&0i32.to_le_bytes());
+    assert_eq!(cobot.port.written, frame(&body));
+    assert_eq!(sent[1], current[1]);
+    assert_eq!(sent[2].1, 30.0);
+}
+
+#[test]
+fn move_to_delta_sends_a_creeping_joint_once_it_has_moved_far_enough() {
+    let mut cobot = connection();
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 0, &[]));
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::DONE, 0, &[]));
+    let mut sent = [(0, 0.0, None)];
+    for step in 1..=3 {
+        cobot
+            .move_to_delta(&mut sent, &[(0, step as f32 * 0.0006, None)], 0.001)
+            .unwrap();
+    }
+
+    // Only the second step has moved the joint far enough from where it was sent.
+    assert_eq!(cobot.stats().requests_sent, 1);
+    assert_eq!(sent[0].1, 0.0012);
+}
+
+#[test]
+fn move_to_delta_sends_nothing_without_changes() {
+    let mut cobot = connection();
+    let waypoint = [(0, 10.0, None), (1, 20.0, None)];
+    let mut sent = waypoint;
+    cobot.move_to_delta(&mut sent, &waypoint, 0.001).unwrap();
+    assert!(cobot.port.written.is_empty());
+
+    let error = cobot
+        .move_to_delta(&mut sent, &[(0, 10.0, None), (2, 20.0, None)], 0.001)
+        .unwrap_err();
+    assert_invalid_argument(error, "current");
+}
+
+#[test]
+fn short_writes_resume_where_they_stopped() {
+    let mut body = vec![request_type::MOVE_TO, 0, 0, 0, 0, 2];
+    body.extend_from_slice(&45_000i32.to_le_bytes());
+    body.extend_from_slice(&10_000i32.to_le_bytes());
+    let expected = frame(&body);
+
+    for write_limit in 1..=expected.len() {
+        let mut cobot = connection();
+        cobot.port.write_limit = Some(write_limit);
+        cobot
+            .port
+            .push_incoming(&response_frame(response_type::ACK, 0, &[]));
+        cobot
+            .port
+            .push_incoming(&response_frame(response_type::DONE, 0, &[]));
+        cobot.move_to(&[(2, 45.0, Some(10.0))]).unwrap();
+
+        assert_eq!(
+            cobot.port.written, expected,
+            "{} bytes per write",
+            write_limit
+        );
+        assert_eq!(cobot.stats().bytes_written, expected.len() as u64);
+    }
+}
+
+#[test]
+fn a_port_that_stops_draining_times_out_the_write() {
+    let mut cobot = connection();
+    cobot.port.write_limit = Some(0);
+    let error = cobot.move_to(&[(2, 45.0, Some(10.0))]).unwrap_err();
+
+    match error.downcast_ref::<CommsError>() {
+        Some(CommsError::WriteTimeout { sent: 0, total }) => assert_eq!(*total, 17),
+        _ => panic!("expected a write timeout, got {}", error),
+    }
+    assert!(cobot.port.written.is_empty());
+    assert!(cobot.link_lost().is_some());
+}
+
+#[test]
+fn escaping_round_trips_start_and_escape_bytes_anywhere() {
+    let payloads: [&[u8]; 6] = [
+        &[START_BYTE, 1, 2, 3],
+        &[1, START_BYTE, 2],
+        &[1, 2, 3, START_BYTE],
+        &[ESCAPE_BYTE, 1, ESCAPE_BYTE],
+        &[START_BYTE, ESCAPE_BYTE, START_BYTE, ESCAPE_BYTE],
+        &[],
+    ];
+    for payload in payloads {
+        let escaped = escape_payload(payload);
+        assert!(!escaped.contains(&START_BYTE), "{:02x?}", escaped);
+        assert_eq!(unescape_payload(&escaped).unwrap(), payload);
+    }
+
+    assert_eq!(escape_payload(&[START_BYTE]), [ESCAPE_BYTE, 0x44]);
+    assert_eq!(escape_payload(&[ESCAPE_BYTE]), [ESCAPE_BYTE, 0x45]);
+    assert!(unescape_payload(&[1, ESCAPE_BYTE]).is_err());
+}
+
+#[test]
+fn escaped_frames_round_trip_over_the_wire() {
+    let mut cobot = connection();
+    cobot.set_payload_escaping(true);
+
+    // The command ID of the request is a start byte, which goes out escaped.
+    cobot.next_command_id = START_BYTE as u32;
+    let body = [request_type::GET_INFO, START_BYTE, 0, 0, 0];
+    let escaped = escape_payload(&body);
+    let mut expected = vec![START_BYTE, escaped.len() as u8, crc8ccitt(&body)];
+    expected.extend_from_slice(&escaped);
+
+    // The response has start and escape bytes at the start, middle and end of the uptime.
+    let uptime = u32::from_le_bytes([START_BYTE, ESCAPE_BYTE, 7, START_BYTE]);
+    let mut message = vec![received_msg_type::RESPONSE, response_type::INFO];
+    message.extend_from_slice(&(START_BYTE as u32).to_le_bytes());
+    message.extend(info_entry(info_tag::UPTIME, &uptime.to_le_bytes()));
+    let escaped = escape_payload(&message);
+    let mut response = vec![START_BYTE, escaped.len() as u8, crc8ccitt(&message)];
+    response.extend_from_slice(&escaped);
+    cobot.port.push_incoming(&response);
+
+    assert_eq!(cobot.get_device_info().unwrap().uptime_s, Some(uptime));
+    assert_eq!(cobot.port.written, expected);
+    assert_eq!(cobot.stats().crc_errors, 0);
+}
+
+#[test]
+fn crc_matches_the_crc8_ccitt_check_value() {
+    assert_eq!(crc8ccitt(b"123456789"), 0xF4);
+    assert_eq!(crc8ccitt(&[]), 0);
+    assert!(crc8ccitt_check(b"123456789", 0xF4));
+    assert!(!crc8ccitt_check(b"123456788", 0xF4));
+}
+
+/// Push the ACK and DONE of a request, so it completes.
+fn push_completion(cobot: &mut CobotConnection<MockTransport>, command_id: u32) {
+    push_completion_to(&mut cobot.port, command_id);
+}
+
+/// Push the ACK and DONE of a request to a transport.
+fn push_completion_to(port: &mut MockTransport, command_id: u32) {
+    port.push_incoming(&response_frame(response_type::ACK, command_id, &[]));
+    port.push_incoming(&response_frame(response_type::DONE, command_id, &[]));
+}
+
+#[test]
+fn requests_are_framed_with_their_command_id_and_crc() {
+    let mut cobot = connection();
+    push_completion(&mut cobot, 0);
+    push_completion(&mut cobot, 1);
+    cobot.set_log_level(log_level::WARN).unwrap();
+    cobot
+        .go_home(JointMask::joint(0) | JointMask::joint(2))
+        .unwrap();
+
+    let mut expected = frame(&[request_type::SET_LOG_LEVEL, 0, 0, 0, 0, log_level::WARN]);
+    expected.extend(frame(&[request_type::GO_HOME, 1, 0, 0, 0, 0b101]));
+    assert_eq!(cobot.port.written, expected);
+}
+
+#[test]
+fn frames_decode_to_the_messages_they_carry() {
+    let payload = [1, 2, 3];
+    let frame_bytes = response_frame(response_type::DONE, 0x0102_0304, &payload);
+    let decoded = decode_frame(&frame_bytes, PROTOCOL_V1, FIRMWARE_VERSION).unwrap();
+    let Message::Response(response) = decoded.message else {
+        panic!("not a response: {:?}", decoded.message);
+    };
+    assert_eq!(response.command_id, 0x0102_0304);
+    assert_eq!(response.response_type, response_type::DONE);
+    assert_eq!(response.payload, payload);
+    assert!(decoded.joints.is_none());
+
+    let log = frame(&[
+        received_msg_type::LOG,
+        log_level::ERROR,
+        4,
+        b'o',
+        b'o',
+        b'p',
+        b's',
+    ]);
+    match decode_frame(&log, PROTOCOL_V1, FIRMWARE_VERSION)
+        .unwrap()
+        .message
+    {
+        Message::Log {
+            level,
+            tag,
+            message,
+        } => {
+            assert_eq!(level, log_level::ERROR);
+            assert_eq!(tag, None);
+            assert_eq!(message, "oops");
+        }
+        message => panic!("not a log message: {:?}", message),
+    }
+
+    let fault = frame(&[received_msg_type::FAULT, 3, 2, b'h', b'o', b't']);
+    match decode_frame(&fault, PROTOCOL_V1, FIRMWARE_VERSION)
+        .unwrap()
+        .message
+    {
+        Message::Fault(fault) => {
+            assert_eq!((fault.code, fault.severity), (3, 2));
+            assert_eq!(fault.message, "hot");
+        }
+        message => panic!("not a fault: {:?}", message),
+    }
+
+    let joints = response_frame(
+        response_type::JOINTS,
+        STREAM_COMMAND_ID,
+        &joints_payload(&[(10.0, 1.0), (-20.0, 0.0)]),
+    );
+    let decoded = decode_frame(&joints, PROTOCOL_V1, FIRMWARE_VERSION).unwrap();
+    assert_eq!(decoded.joints.unwrap().len(), 2);
+}
+
+#[test]
+fn protocol_v2_frames_carry_a_two_byte_length() {
+    let payload = vec![0xAB; 300];
+    let mut message = vec![received_msg_type::RESPONSE, response_type::DONE, 9, 0, 0, 0];
+    message.extend_from_slice(&payload);
+    let mut frame_bytes = vec![START_BYTE];
+    frame_bytes.extend_from_slice(&(message.len() as u16).to_le_bytes());
+    frame_bytes.push(crc8ccitt(&message));
+    frame_bytes.extend_from_slice(&message);
+
+    let Message::Response(response) = decode_frame(&frame_bytes, PROTOCOL_V2, FIRMWARE_VERSION)
+        .unwrap()
+        .message
+    else {
+        panic!("not a response");
+    };
+    assert_eq!(response.command_id, 9);
+    assert_eq!(response.payload, payload);
+}
+
+#[test]
+fn corrupted_frames_are_rejected_by_their_crc_and_framing() {
+    let good = response_frame(response_type::ACK, 4, &[]);
+
+    let mut corrupted = good.clone();
+    *corrupted.last_mut().unwrap() ^= 0x01;
+    let expected = good[2];
+    let computed = crc8ccitt(&corrupted[3..]);
+    assert_eq!(
+        decode_frame(&corrupted, PROTOCOL_V1, FIRMWARE_VERSION).unwrap_err(),
+        FrameError::CrcMismatch { expected, computed }
+    );
+
+    let mut bad_start = good.clone();
+    bad_start[0] = 0x23;
+    assert_eq!(
+        decode_frame(&bad_start, PROTOCOL_V1, FIRMWARE_VERSION).unwrap_err(),
+        FrameError::BadStartByte { found: 0x23 }
+    );
+    assert_eq!(
+        decode_frame(&good[..good.len() - 1], PROTOCOL_V1, FIRMWARE_VERSION).unwrap_err(),
+        FrameError::Truncated {
+            expected: good.len(),
+            actual: good.len() - 1
+        }
+    );
+    let trailing = [good.as_slice(), &[0]].concat();
+    assert_eq!(
+        decode_frame(&trailing, PROTOCOL_V1, FIRMWARE_VERSION).unwrap_err(),
+        FrameError::TrailingBytes { count: 1 }
+    );
+
+    // Over a connection, the corrupted frame is counted and skipped, and the next one is read.
+    let mut cobot = connection();
+    cobot.port.push_incoming(&corrupted);
+    cobot.port.push_incoming(&good);
+    assert!(cobot.wait_for_response(4, TEST_TIMEOUT).unwrap().is_some());
+    assert_eq!(cobot.stats().crc_errors, 1);
+}
+
+#[test]
+fn escaped_frames_have_a_single_start_byte() {
+    let mut cobot = connection();
+    cobot.set_payload_escaping(true);
+    push_completion(&mut cobot, START_BYTE as u32);
+    cobot.next_command_id = START_BYTE as u32;
+    cobot.set_log_level(ESCAPE_BYTE).unwrap();
+
+    let written = &cobot.port.written;
+    assert_eq!(written.iter().filter(|&&b| b == START_BYTE).count(), 1);
+    let body = [
+        request_type::SET_LOG_LEVEL,
+        START_BYTE,
+        0,
+        0,
+        0,
+        ESCAPE_BYTE,
+    ];
+    assert_eq!(written[1] as usize, body.len() + 2);
+    assert_eq!(written[2], crc8ccitt(&body));
+    assert_eq!(unescape_payload(&written[3..]).unwrap(), body);
+}
+
+#[test]
+fn requests_complete_on_their_own_responses_among_streamed_feedback() {
+    let mut cobot = connection();
+    let streamed = Arc::new(Mutex::new(0));
+    let counter = streamed.clone();
+    cobot.set_joints_handler(Box::new(move |_| *counter.lock().unwrap() += 1));
+
+    // Streamed joints, and responses to an earlier and a later request, arrive between the
+    // responses to the request.
+    cobot.next_command_id = 5;
+    let feedback = response_frame(
+        response_type::JOINTS,
+        STREAM_COMMAND_ID,
+        &joints_payload(&[(1.0, 0.0), (2.0, 0.0)]),
+    );
+    for frame_bytes in [
+        feedback.clone(),
+        response_frame(response_type::ACK, 4, &[]),
+        response_frame(response_type::DONE, 6, &[]),
+        feedback.clone(),
+        response_frame(response_type::ACK, 5, &[]),
+        feedback.clone(),
+        frame(&[received_msg_type::LOG, log_level::INFO, 2, b'h', b'i']),
+        response_frame(response_type::ERROR, 4, b"\x01\x00Unknown"),
+        feedback,
+        response_frame(response_type::DONE, 5, &[]),
+    ] {
+        cobot.port.push_incoming(&frame_bytes);
+    }
+
+    cobot.go_home(JointMask::joint(1)).unwrap();
+    assert_eq!(
+        cobot.port.written,
+        frame(&[request_type::GO_HOME, 5, 0, 0, 0, 0b10])
+    );
+    assert_eq!(*streamed.lock().unwrap(), 4);
+
+    // The other requests' responses are left for them, and no streamed joints are buffered.
+    let mut left = cobot
+        .responses
+        .iter()
+        .map(|(response, _)| (response.command_id, response.response_type))
+        .collect::<Vec<_>>();
+    left.sort();
+    assert_eq!(
+        left,
+        [
+            (4, response_type::ACK),
+            (4, response_type::ERROR),
+            (6, response_type::DONE)
+        ]
+    );
+
+    // Without its own DONE, the request times out whatever else arrives.
+    cobot.flush_responses();
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 8, &[]));
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::DONE, 9, &[]));
+    cobot.next_command_id = 8;
+    let error = cobot.go_home(JointMask::joint(1)).unwrap_err();
+    match error.downcast_ref::<std::io::Error>() {
+        Some(error) => assert_eq!(error.kind(), std::io::ErrorKind::TimedOut),
+        None => panic!("expected a timeout, got {}", error),
+    }
+    assert_eq!(cobot.buffered_responses(), 1);
+}
+
+#[test]
+fn joint_masks_encode_to_one_byte_up_to_eight_joints() {
+    let mask = JointMask::joint(0) | JointMask::joint(7);
+    assert_eq!(mask.encode(false), [0b1000_0001]);
+    assert_eq!(mask.encode(true), [0b1000_0001, 0]);
+    assert_eq!(JointMask::joint(9).encode(true), [0, 0b10]);
+    assert_eq!(JointMask::first(3).encode(false), [0b111]);
+    assert_eq!(
+        JointMask::first(JointMask::MAX_JOINTS).encode(true),
+        [0xFF, 0xFF]
+    );
+    assert!(JointMask::joint(JointMask::MAX_JOINTS).is_empty());
+    assert_eq!(mask.to_string(), "[0, 7]");
+
+    // A COBOT of up to eight joints takes one byte, and a larger one takes two.
+    let mut cobot = connection();
+    cobot.joint_count = Some(6);
+    push_completion(&mut cobot, 0);
+    cobot.go_home(JointMask::first(6)).unwrap();
+    assert_eq!(
+        cobot.port.written,
+        frame(&[request_type::GO_HOME, 0, 0, 0, 0, 0b11_1111])
+    );
+    assert_invalid_argument(cobot.go_home(JointMask::joint(6)).unwrap_err(), "joint");
+
+    let mut cobot = connection();
+    cobot.joint_count = Some(12);
+    push_completion(&mut cobot, 0);
+    cobot.go_home(JointMask::joint(9)).unwrap();
+    assert_eq!(
+        cobot.port.written,
+        frame(&[request_type::GO_HOME, 0, 0, 0, 0, 0, 0b10])
+    );
+
+    // Without a known joint count, joints past the eighth can't be encoded.
+    let mut cobot = connection();
+    assert_invalid_argument(cobot.go_home(JointMask::joint(9)).unwrap_err(), "joints");
+    assert!(cobot.port.written.is_empty());
+}
+
+#[test]
+fn wait_for_response_gives_up_exactly_at_the_timeout() {
+    let (mut cobot, clock) = connection_with_clock();
+    let start = clock.now();
+    assert!(cobot.wait_for_response(0, TEST_TIMEOUT).unwrap().is_none());
+    assert_eq!(clock.now() - start, TEST_TIMEOUT);
+    assert_eq!(cobot.stats().timeouts, 1);
+
+    // A response that has arrived is taken however little time is left.
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 1, &[]));
+    let response = cobot.wait_for_response(1, Duration::from_nanos(1));
+    assert_eq!(response.unwrap().unwrap().response_type, response_type::ACK);
+
+    // With no time left, nothing is read at all.
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 2, &[]));
+    assert!(cobot
+        .wait_for_response(2, Duration::ZERO)
+        .unwrap()
+        .is_none());
+    assert!(!cobot.port.incoming.is_empty());
+}
+
+#[test]
+fn buffered_responses_expire_exactly_after_their_expiry() {
+    let (mut cobot, clock) = connection_with_clock();
+
+    // Responses to requests nobody is waiting for yet are kept from when they arrive.
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 7, &[]));
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 8, &[]));
+    let arrived = clock.now();
+    assert!(cobot.wait_for_response(0, TEST_TIMEOUT).unwrap().is_none());
+    assert_eq!(cobot.responses.len(), 2);
+
+    clock.advance(RESPONSE_EXPIRY - (clock.now() - arrived) - Duration::from_nanos(1));
+    assert!(cobot
+        .wait_for_response(7, Duration::ZERO)
+        .unwrap()
+        .is_some());
+
+    clock.advance(Duration::from_nanos(1));
+    assert!(cobot
+        .wait_for_response(8, Duration::ZERO)
+        .unwrap()
+        .is_none());
+    assert!(cobot.responses.is_empty());
+}
+
+#[test]
+fn full_response_buffer_discards_the_oldest_response() {
+    let mut cobot = connection();
+    for command_id in 100..=100 + RESPONSE_CAPACITY as u32 {
+        cobot
+            .port
+            .push_incoming(&response_frame(response_type::ACK, command_id, &[]));
+    }
+    assert!(cobot.wait_for_response(0, TEST_TIMEOUT).unwrap().is_none());
+    assert_eq!(cobot.buffered_responses(), RESPONSE_CAPACITY);
+
+    assert!(cobot
+        .wait_for_response(100, Duration::ZERO)
+        .unwrap()
+        .is_none());
+    let last = 100 + RESPONSE_CAPACITY as u32;
+    assert!(cobot
+        .wait_for_response(last, Duration::ZERO)
+        .unwrap()
+        .is_some());
+}
+
+/// Values that must never be encoded, since casting them to an integer silently gives 0 or the
+/// integer's limit.
+const NON_FINITE: [f32; 3] = [f32::NAN, f32::INFINITY, f32::NEG_INFINITY];
+
+/// Check that an error is `CommsError::InvalidArgument` for a value of the field that isn't
+/// finite.
+fn assert_not_finite(error: Box<dyn Error>, expected_field: &str) {
+    match error.downcast_ref::<CommsError>() {
+        Some(CommsError::InvalidArgument {
+            field,
+            reason: "not finite",
+        }) => assert_eq!(*field, expected_field),
+        _ => panic!("expected a non-finite {}, got {}", expected_field, error),
+    }
+}
+
+#[test]
+fn move_to_rejects_non_finite_values() {
+    for value in NON_FINITE {
+        let mut cobot = connection();
+        let error = cobot.move_to(&[(0, value, None)]).unwrap_err();
+        assert_not_finite(error, "angle");
+        let error = cobot.move_to(&[(0, 10.0, Some(value))]).unwrap_err();
+        assert_not_finite(error, "speed");
+        assert!(cobot.port.written.is_empty());
+    }
+}
+
+#[test]
+fn override_angles_rejects_non_finite_angles() {
+    for value in NON_FINITE {
+        let mut cobot = connection();
+        let error = cobot.override_angles(&[(0, 0.0), (1, value)]).unwrap_err();
+        assert_not_finite(error, "angle");
+        assert!(cobot.port.written.is_empty());
+    }
+}
+
+#[test]
+fn move_speed_rejects_non_finite_speeds() {
+    for value in NON_FINITE {
+        let mut cobot = connection();
+        let error = cobot.move_speed(&[(0, value)]).unwrap_err();
+        assert_not_finite(error, "speed");
+        assert!(cobot.port.written.is_empty());
+    }
+}
+
+/// Transport that answers the first request written to it, as a device on the port would, so the
+/// answer survives the buffers being cleared before the request.
+struct AnsweringTransport {
+    /// Transport the answer is read from.
+    inner: MockTransport,
+
+    /// Bytes sent back once the first request is written.
+    answer: Option<Vec<u8>>,
+}
+
+impl std::io::Read for AnsweringTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl std::io::Write for AnsweringTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(answer) = self.answer.take() {
+            self.inner.push_incoming(&answer);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for AnsweringTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn clear(&mut self) -> std::io::Result<()> {
+        self.inner.clear()
+    }
+
+    fn baud_rate(&self) -> std::io::Result<u32> {
+        self.inner.baud_rate()
+    }
+}
+
+/// Probe a device that answers the probe with the given bytes, after sending some stale bytes
+/// that the probe must discard.
+///
+/// # Returns
+///
+/// The result of the probe, and how long it took.
+fn probe_device(answer: &[u8]) -> (Result<(), Box<dyn Error>>, Duration) {
+    let clock = MockClock::new();
+    let mut port = AnsweringTransport {
+        inner: MockTransport::new(),
+        answer: Some(answer.to_vec()),
+    };
+    port.inner.clock = Some(clock.clone());
+    port.inner.push_incoming(b"stale\r\n");
+    let mut cobot = CobotConnection::new(port, FIRMWARE_VERSION, TEST_TIMEOUT);
+    cobot.set_clock(Box::new(clock.clone()));
+
+    let start = clock.now();
+    let result = cobot.probe();
+    (result, clock.now() - start)
+}
+
+#[test]
+fn probe_reports_a_silent_device() {
+    let (result, elapsed) = probe_device(&[]);
+    let error = result.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref(),
+        Some(CommsError::NotACobot { garbage: false })
+    ));
+    assert_eq!(elapsed, PROBE_TIMEOUT);
+}
+
+#[test]
+fn probe_reports_a_device_sending_garbage() {
+    let (result, _) = probe_device(b"Arduino ready\r\nvalue=$12\r\n");
+    let error = result.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref(),
+        Some(CommsError::NotACobot { garbage: true })
+    ));
+}
+
+#[test]
+fn probe_accepts_a_valid_frame() {
+    let joints = response_frame(response_type::JOINTS, 0, &joints_payload(&[(0.0, 0.0)]));
+    let (result, elapsed) = probe_device(&joints);
+    result.unwrap();
+    assert!(elapsed < PROBE_TIMEOUT);
+
+    // Any valid response shows a COBOT is there, even after noise on the line.
+    let error = response_frame(response_type::ERROR, 0, b"\x01\x00Malformed");
+    let (result, _) = probe_device(&[b"\x00\xff".as_slice(), &error].concat());
+    result.unwrap();
+}
+
+#[cfg(feature = "plan")]
+#[test]
+fn test_plans_parse_with_errors_naming_the_step() {
+    let source = "
+name: Wrist check
+connect:
+  port_name: /dev/ttyUSB0
+  baud_rate: 115200
+steps:
+  - action: calibrate
+    joints: [0, 2]
+  - action: move_joint
+    joint: 2
+    angle: 45
+    continue_on_failure: true
+";
+    let plan = plan::parse(source).unwrap();
+    assert_eq!(plan.name.as_deref(), Some("Wrist check"));
+    assert_eq!(plan.connect.unwrap().baud_rate, 115200);
+    assert_eq!(plan.steps.len(), 2);
+    assert_eq!(
+        plan.steps[0].action.joints(),
+        JointMask::joint(0) | JointMask::joint(2)
+    );
+    assert!(!plan.steps[0].continue_on_failure);
+    assert!(matches!(
+        plan.steps[1].action,
+        plan::Action::MoveJoint {
+            joint: 2,
+            speed: None,
+            ..
+        }
+    ));
+    assert!(plan.steps[1].continue_on_failure);
+
+    let error =
+        plan::parse("steps:\n  - action: wait\n    ms: 5\n  - action: dance\n").unwrap_err();
+    assert!(error.starts_with("Invalid step 2:"), "{}", error);
+}
+
+/// Start a move of joint 0 as command 0, and push a fault and the move's DONE after its ACK.
+fn start_move_with_fault(cobot: &mut CobotConnection<MockTransport>) -> u32 {
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 0, &[]));
+    let command_id = cobot.start_move_to(&[(0, 10.0, None)]).unwrap();
+    cobot
+        .port
+        .push_incoming(&frame(&[received_msg_type::FAULT, 3, 2, b'h', b'o', b't']));
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::DONE, command_id, &[]));
+    command_id
+}
+
+#[test]
+fn faults_received_while_waiting_for_done_are_reported() {
+    let mut cobot = connection();
+    let faults = Arc::new(Mutex::new(Vec::new()));
+    let reported = faults.clone();
+    cobot.set_fault_handler(Box::new(move |fault| {
+        reported
+            .lock()
+            .unwrap()
+            .push((fault.code, fault.severity, fault.message.clone()));
+    }));
+
+    let command_id = start_move_with_fault(&mut cobot);
+    let move_frame = cobot.port.written.clone();
+    cobot.wait_for_done(command_id).unwrap();
+
+    assert_eq!(*faults.lock().unwrap(), [(3, 2, "hot".to_string())]);
+    let recent = cobot.recent_faults().collect::<Vec<_>>();
+    assert_eq!(recent.len(), 1);
+    assert_eq!((recent[0].code, recent[0].severity), (3, 2));
+
+    // The move completes on its DONE, nothing else is sent, and nothing is left buffered.
+    assert_eq!(cobot.port.written, move_frame);
+    assert_eq!(cobot.buffered_responses(), 0);
+
+    // The fault may have moved the joints, so they must be calibrated again.
+    assert!(cobot.calibrated_joints().is_empty());
+}
+
+#[test]
+fn severe_faults_stop_every_joint_without_failing_the_wait() {
+    let mut cobot = connection();
+    cobot.set_fault_stop_severity(Some(2));
+
+    let command_id = start_move_with_fault(&mut cobot);
+    let move_len = cobot.port.written.len();
+    cobot.wait_for_done(command_id).unwrap();
+
+    // An immediate STOP of every joint as command 1, whose responses are never waited for.
+    assert_eq!(
+        cobot.port.written[move_len..],
+        frame(&[request_type::STOP, 1, 0, 0, 0, 1, 0xFF])
+    );
+    assert_eq!(cobot.buffered_responses(), 0);
+}
+
+/// Transport that replays a trace of joint angles, one sample for each GET_JOINTS, and finishes
+/// the move once the trace runs out. Moves and stops are acknowledged, and stops finished, as
+/// soon as they're written.
+struct TraceTransport {
+    /// Transport the answers are read from.
+    inner: MockTransport,
+
+    /// Angles of every joint still to be reported, oldest first.
+    trace: VecDeque<Vec<f32>>,
+
+    /// Angles last reported, repeated once the trace runs out.
+    last: Vec<f32>,
+
+    /// Request type of each request written, in order.
+    requests: Vec<u8>,
+}
+
+impl std::io::Read for TraceTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl std::io::Write for TraceTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let (request, command_id) = (buf[3], u32::from_le_bytes(buf[4..8].try_into().unwrap()));
+        self.requests.push(request);
+        match request {
+            request_type::MOVE_TO => {
+                self.inner
+                    .push_incoming(&response_frame(response_type::ACK, command_id, &[]));
+            }
+            request_type::GET_JOINTS => {
+                match self.trace.pop_front() {
+                    Some(angles) => self.last = angles,
+                    None => self
+                        .inner
+                        .push_incoming(&response_frame(response_type::DONE, 0, &[])),
+                }
+                let joints = self
+                    .last
+                    .iter()
+                    .map(|&angle| (angle, 0.0))
+                    .collect::<Vec<_>>();
+                self.inner.push_incoming(&response_frame(
+                    response_type::JOINTS,
+                    command_id,
+                    &joints_payload(&joints),
+                ));
+            }
+            request_type::STOP => push_completion_to(&mut self.inner, command_id),
+            _ => {}
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for TraceTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn clear(&mut self) -> std::io::Result<()> {
+        self.inner.clear()
+    }
+
+    fn baud_rate(&self) -> std::io::Result<u32> {
+        self.inner.baud_rate()
+    }
+}
+
+/// Move joints to 90° as command 0 while the COBOT reports the angles of a trace sampled every
+/// `STALL_POLL_INTERVAL`, watching for stalls with a window of 300 ms and a minimum progress of
+/// 1°.
+///
+/// # Returns
+///
+/// Each joint suspected to have stalled and when, from the start of the move, and the connection.
+fn replay_move(
+    joints: &[u8],
+    trace: &[&[f32]],
+    auto_stop: bool,
+) -> (Vec<(u8, Duration)>, CobotConnection<TraceTransport>) {
+    let clock = MockClock::new();
+    let mut port = TraceTransport {
+        inner: MockTransport::new(),
+        trace: trace.iter().map(|angles| angles.to_vec()).collect(),
+        last: trace[0].to_vec(),
+        requests: Vec::new(),
+    };
+    port.inner.clock = Some(clock.clone());
+    let mut cobot = CobotConnection::new(port, FIRMWARE_VERSION, TEST_TIMEOUT);
+    cobot.set_clock(Box::new(clock.clone()));
+    cobot.assume_calibrated(JointMask::first(JointMask::MAX_JOINTS));
+    cobot.set_stall_detection(Some(StallDetection {
+        window: Duration::from_millis(300),
+        min_progress: 1.0,
+        auto_stop,
+    }));
+    let stalls = Arc::new(Mutex::new(Vec::new()));
+    let reported = stalls.clone();
+    let start = clock.now();
+    cobot.set_stall_handler(Box::new(move |joint| {
+        reported.lock().unwrap().push((joint, clock.now() - start));
+    }));
+
+    let targets = joints
+        .iter()
+        .map(|&joint| (joint, 90.0, None))
+        .collect::<Vec<_>>();
+    let command_id = cobot.start_move_to(&targets).unwrap();
+    cobot.wait_for_done(command_id).unwrap();
+
+    // The monitor disarms once the move is done.
+    assert!(cobot.stall_monitor.is_none());
+    let stalls = stalls.lock().unwrap().clone();
+    (stalls, cobot)
+}
+
+#[test]
+fn healthy_moves_are_not_suspected_of_stalling() {
+    // A joint moving steadily, then slowing right down as it nears its target.
+    let decelerating: &[&[f32]] = &[
+        &[15.0, 0.0],
+        &[30.0, 0.0],
+        &[45.0, 0.0],
+        &[60.0, 0.0],
+        &[75.0, 0.0],
+        &[85.0, 0.0],
+        &[88.5, 0.0],
+        &[88.9, 0.0],
+        &[89.2, 0.0],
+        &[89.4, 0.0],
+        &[89.6, 0.0],
+        &[89.7, 0.0],
+    ];
+    // A joint creeping along at just over the minimum progress.
+    let creeping: &[&[f32]] = &[
+        &[10.0, 0.0],
+        &[10.4, 0.0],
+        &[10.8, 0.0],
+        &[11.2, 0.0],
+        &[11.6, 0.0],
+        &[12.0, 0.0],
+        &[12.4, 0.0],
+        &[12.8, 0.0],
+    ];
+    for trace in [decelerating, creeping] {
+        let (stalls, cobot) = replay_move(&[0], trace, true);
+        assert!(stalls.is_empty(), "{:?} stalled in {:?}", stalls, trace);
+        assert!(!cobot.into_port().requests.contains(&request_type::STOP));
+    }
+}
+
+#[test]
+fn stalled_joints_are_reported_once_the_window_passes_and_stopped() {
+    // Progress stops at 20° after 200 ms.
+    let trace: &[&[f32]] = &[
+        &[10.0, 0.0],
+        &[20.0, 0.0],
+        &[20.3, 0.0],
+        &[20.5, 0.0],
+        &[20.6, 0.0],
+        &[20.6, 0.0],
+        &[20.6, 0.0],
+    ];
+    let (stalls, cobot) = replay_move(&[0], trace, true);
+    assert_eq!(stalls, [(0, Duration::from_millis(500))]);
+
+    // The joint is stopped straight after the sample that showed the stall, and only once.
+    let port = cobot.into_port();
+    let mut expected = vec![request_type::MOVE_TO];
+    expected.extend([request_type::GET_JOINTS; 5]);
+    expected.push(request_type::STOP);
+    assert_eq!(port.requests[..expected.len()], expected);
+    assert_eq!(
+        port.requests
+            .iter()
+            .filter(|&&request| request == request_type::STOP)
+            .count(),
+        1
+    );
+    let stop = frame(&[request_type::STOP, 6, 0, 0, 0, 1, 0b01]);
+    assert!(port
+        .inner
+        .written
+        .windows(stop.len())
+        .any(|written| written == stop));
+}
+
+#[test]
+fn only_the_stalled_joint_of_a_move_is_reported() {
+    // Joint 0 moves on while joint 1 never leaves 5°.
+    let trace: &[&[f32]] = &[
+        &[10.0, 5.0],
+        &[20.0, 5.0],
+        &[30.0, 5.0],
+        &[40.0, 5.0],
+        &[50.0, 5.0],
+        &[60.0, 5.0],
+    ];
+    let (stalls, cobot) = replay_move(&[0, 1], trace, false);
+    assert_eq!(stalls, [(1, Duration::from_millis(400))]);
+    assert!(!cobot.into_port().requests.contains(&request_type::STOP));
+}
+
+#[test]
+fn a_late_done_for_a_cancelled_wait_is_left_unclaimed() {
+    let mut cobot = connection();
+    let cancel = CancelHandle::default();
+    cobot.set_cancel_handle(cancel.clone());
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::ACK, 0, &[]));
+    let command_id = cobot.start_move_to(&[(0, 10.0, None)]).unwrap();
+
+    cancel.cancel();
+    let error = cobot.wait_for_done(command_id).unwrap_err();
+    assert!(
+        matches!(error.downcast_ref(), Some(CommsError::Cancelled)),
+        "expected a cancellation, got {}",
+        error
+    );
+
+    // The cancelled move's DONE arrives ahead of the responses to the next request.
+    cobot
+        .port
+        .push_incoming(&response_frame(response_type::DONE, command_id, &[]));
+    push_completion(&mut cobot, 1);
+    cobot.go_home(JointMask::joint(1)).unwrap();
+    assert!(cobot
+        .port
+        .written
+        .ends_with(&frame(&[request_type::GO_HOME, 1, 0, 0, 0, 0b10])));
+
+    let unclaimed = cobot
+        .responses
+        .iter()
+        .map(|(response, _)| (response.command_id, response.response_type))
+        .collect::<Vec<_>>();
+    assert_eq!(unclaimed, [(command_id, response_type::DONE)]);
+}
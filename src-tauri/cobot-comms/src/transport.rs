@@ -0,0 +1,154 @@
+//! Byte streams a connection can run over.
+//!
+//! A `CobotConnection` only needs to read and write bytes with a timeout, so it runs over anything
+//! implementing `Transport`. With the `serialport` feature, every serial port is a transport.
+//! `MockTransport` stands in for a COBOT where there is none, such as when checking the frames a
+//! connection sends.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use crate::MockClock;
+
+/// Byte stream to and from the COBOT.
+///
+/// Reads block for at most the timeout last set, then fail with `io::ErrorKind::TimedOut`. A read
+/// that returns 0 bytes means the stream has closed.
+pub trait Transport: Read + Write + Send {
+    /// Set the longest time a read or write blocks.
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Discard any bytes received but not yet read, and any bytes written but not yet sent.
+    fn clear(&mut self) -> io::Result<()>;
+
+    /// Get the name of the underlying device, if it has one.
+    fn name(&self) -> Option<String> {
+        None
+    }
+
+    /// Get the baud rate of the underlying device.
+    fn baud_rate(&self) -> io::Result<u32>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        (**self).set_timeout(timeout)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        (**self).clear()
+    }
+
+    fn name(&self) -> Option<String> {
+        (**self).name()
+    }
+
+    fn baud_rate(&self) -> io::Result<u32> {
+        (**self).baud_rate()
+    }
+}
+
+#[cfg(feature = "serialport")]
+impl Transport for dyn serialport::SerialPort {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        Ok(serialport::SerialPort::set_timeout(self, timeout)?)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        Ok(serialport::SerialPort::clear(
+            self,
+            serialport::ClearBuffer::All,
+        )?)
+    }
+
+    fn name(&self) -> Option<String> {
+        serialport::SerialPort::name(self)
+    }
+
+    fn baud_rate(&self) -> io::Result<u32> {
+        Ok(serialport::SerialPort::baud_rate(self)?)
+    }
+}
+
+/// Transport that reads from a buffer of bytes and records every byte written, so a connection
+/// can be driven without a COBOT. Reading from an empty buffer waits out the timeout, then times
+/// out, as a serial port with nothing on the other end would. Given a `MockClock`, it advances the
+/// clock by the timeout instead of sleeping.
+#[derive(Clone, Debug, Default)]
+pub struct MockTransport {
+    /// Bytes still to be read, as if sent by the COBOT.
+    pub incoming: VecDeque<u8>,
+
+    /// Bytes written so far, as if sent to the COBOT.
+    pub written: Vec<u8>,
+
+    /// Most bytes a single read returns, as a driver that delivers data a few bytes at a time
+    /// would, or `None` to fill as much of the buffer as possible.
+    pub read_limit: Option<usize>,
+
+    /// Most bytes a single write accepts, as a port whose buffer is nearly full would, or `None`
+    /// to accept every byte. `Some(0)` accepts nothing, as a port that stopped draining.
+    pub write_limit: Option<usize>,
+
+    /// Clock to advance when a read times out, or `None` to sleep for the timeout.
+    pub clock: Option<MockClock>,
+
+    /// Longest time a read blocks, as last set.
+    timeout: Duration,
+}
+
+impl MockTransport {
+    /// Create a transport with nothing to read.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue bytes to be read, such as a frame encoding a response.
+    pub fn push_incoming(&mut self, bytes: &[u8]) {
+        self.incoming.extend(bytes);
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.incoming.is_empty() {
+            match &self.clock {
+                Some(clock) => clock.advance(self.timeout),
+                None => std::thread::sleep(self.timeout),
+            }
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let limit = self.read_limit.unwrap_or(buf.len()).min(buf.len());
+        self.incoming.read(&mut buf[..limit])
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let limit = self.write_limit.unwrap_or(buf.len()).min(buf.len());
+        self.written.write(&buf[..limit])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.incoming.clear();
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> io::Result<u32> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,156 @@
+//! Measurement of a joint's backlash, by approaching the same angle from either side.
+//!
+//! Each cycle moves the joint past the center angle on one side, returns to the center, waits for
+//! it to settle and reads where it stopped, then does the same from the other side. The
+//! difference between the two readings is the backlash of that cycle. Angles and speeds are in the
+//! display frame and the active units, as in the Tauri commands.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::{checks, AppState};
+
+/// Time to let the joint settle at the center before reading it.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Readings of a single cycle.
+#[derive(Clone, Debug, Serialize)]
+pub struct BacklashCycle {
+    /// Angle the joint stopped at when approaching the center from below.
+    pub from_below: f32,
+
+    /// Angle the joint stopped at when approaching the center from above.
+    pub from_above: f32,
+
+    /// Difference between the two readings, `from_above - from_below`.
+    pub difference: f32,
+}
+
+/// Outcome of a backlash measurement.
+#[derive(Clone, Debug, Serialize)]
+pub struct BacklashReport {
+    /// Joint measured.
+    pub joint: u8,
+
+    /// Angle approached from either side.
+    pub center: f32,
+
+    /// Distance the joint was moved past the center on either side.
+    pub amplitude: f32,
+
+    /// Readings of each cycle.
+    pub cycles: Vec<BacklashCycle>,
+
+    /// Mean of the absolute differences, the estimate of the backlash.
+    pub mean: f32,
+
+    /// Largest absolute difference.
+    pub max: f32,
+}
+
+/// Move the joint to an angle and wait for the move to finish.
+async fn move_to(app: &AppHandle, joint: u8, angle: f32, speed: f32) -> Result<(), String> {
+    crate::move_joint(
+        app.clone(),
+        app.state::<AppState>(),
+        joint,
+        angle,
+        Some(speed),
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Approach the center from the given side, let the joint settle and read where it stopped.
+async fn approach(
+    app: &AppHandle,
+    joint: u8,
+    start: f32,
+    center: f32,
+    speed: f32,
+) -> Result<f32, String> {
+    move_to(app, joint, start, speed).await?;
+    move_to(app, joint, center, speed).await?;
+    tokio::time::sleep(SETTLE_TIME).await;
+
+    let sample = checks::read_joints(app).await?;
+    sample
+        .angles
+        .get(joint as usize)
+        .copied()
+        .ok_or_else(|| format!("Joint {} doesn't exist", joint))
+}
+
+/// Measure the backlash of a joint.
+///
+/// # Arguments
+///
+/// * `joint` - Joint to measure.
+/// * `center` - Angle to approach from either side.
+/// * `amplitude` - Distance to move past the center on either side. Must be positive, and keep
+///   the joint within its soft limits.
+/// * `speed` - Speed of every move. Must be positive.
+/// * `cycles` - Number of times to approach from both sides. Must be at least 1.
+///
+/// # Returns
+///
+/// The readings and the backlash estimate, or an error if the arguments are invalid or a move
+/// fails.
+pub async fn run(
+    app: &AppHandle,
+    joint: u8,
+    center: f32,
+    amplitude: f32,
+    speed: f32,
+    cycles: u32,
+) -> Result<BacklashReport, String> {
+    if amplitude.is_nan() || speed.is_nan() || amplitude <= 0.0 || speed <= 0.0 {
+        return Err("Amplitude and speed must be positive".into());
+    }
+    if cycles == 0 {
+        return Err("At least one cycle is required".into());
+    }
+
+    let below = center - amplitude;
+    let above = center + amplitude;
+    let settings = app.state::<AppState>().settings.lock().await.clone();
+    if let Some(limits) = settings.soft_limits(joint) {
+        let below = settings
+            .angle_to_degrees(below)
+            .map_err(|e| e.to_string())?;
+        let above = settings
+            .angle_to_degrees(above)
+            .map_err(|e| e.to_string())?;
+        if below < limits.min || above > limits.max {
+            return Err(format!(
+                "Moving joint {} {} either side of {} would pass its soft limits",
+                joint, amplitude, center
+            ));
+        }
+    }
+
+    let mut readings = Vec::new();
+    for _ in 0..cycles {
+        let from_below = approach(app, joint, below, center, speed).await?;
+        let from_above = approach(app, joint, above, center, speed).await?;
+        readings.push(BacklashCycle {
+            from_below,
+            from_above,
+            difference: from_above - from_below,
+        });
+    }
+
+    let differences = readings.iter().map(|cycle| cycle.difference.abs());
+    let mean = differences.clone().sum::<f32>() / readings.len() as f32;
+    let max = differences.fold(0.0, f32::max);
+    Ok(BacklashReport {
+        joint,
+        center,
+        amplitude,
+        cycles: readings,
+        mean,
+        max,
+    })
+}
@@ -0,0 +1,440 @@
+//! WebSocket bridge for driving the COBOT from another machine.
+//!
+//! Clients exchange JSON text messages with the server. The first message on every connection
+//! must authenticate with the shared token, otherwise the connection is closed:
+//!
+//! ```json
+//! { "op": "auth", "token": "..." }
+//! ```
+//!
+//! After that, each request names an operation and may carry an `id` which is echoed back in the
+//! reply:
+//!
+//! ```json
+//! { "id": 1, "op": "move_joint", "joint": 2, "angle": 45.0, "speed": 20.0 }
+//! { "id": 1, "ok": true, "result": null }
+//! ```
+//!
+//! Sending `{ "op": "subscribe", "interval_ms": 100 }` starts streaming joint samples as
+//! `{ "event": "joints", "angles": [...], "speeds": [...] }` messages, at most one per interval,
+//! along with the protocol events of the connection as `{ "event": "comms", "comms": {...} }`.
+//! Samples are only sent as the app reads or the COBOT streams the joints, so subscribers never add
+//! traffic on the serial link.
+//!
+//! Requests are handled by calling the same functions as the Tauri commands, so the bridge and the
+//! local UI share the connection and its state.
+
+use std::time::{Duration, Instant};
+
+use cobot_comms::{CommsEvent, JointMask};
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tauri::{async_runtime::Mutex, AppHandle, Manager};
+use tokio::{
+    net::TcpListener,
+    net::TcpStream,
+    sync::{broadcast, broadcast::error::RecvError, mpsc},
+};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{AppError, AppState, ConnectionEvent};
+
+/// Shortest allowed interval between streamed joint updates.
+const MIN_STREAM_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Running bridge, if any.
+pub struct BridgeState {
+    server: Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+}
+
+impl BridgeState {
+    pub fn new() -> Self {
+        BridgeState {
+            server: Mutex::new(None),
+        }
+    }
+}
+
+/// Operations that control the bridge connection itself rather than the COBOT.
+const CONTROL_OPS: [&str; 3] = ["auth", "subscribe", "unsubscribe"];
+
+/// Request sent by a bridge client.
+enum Request {
+    /// Control of the bridge connection, handled by the connection loop.
+    Control(Control),
+
+    /// Command run through the matching Tauri command.
+    Command(Command),
+}
+
+impl<'de> Deserialize<'de> for Request {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let op = value.get("op").and_then(Value::as_str);
+        let request = if op.is_some_and(|op| CONTROL_OPS.contains(&op)) {
+            Control::deserialize(value).map(Request::Control)
+        } else {
+            Command::deserialize(value).map(Request::Command)
+        };
+        request.map_err(serde::de::Error::custom)
+    }
+}
+
+/// Request controlling the bridge connection.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Control {
+    Auth { token: String },
+    Subscribe { interval_ms: u64 },
+    Unsubscribe,
+}
+
+/// Request run against the COBOT.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Command {
+    IsConnected,
+    Connect {
+        port_name: String,
+        baud_rate: u32,
+        verify: Option<bool>,
+        startup: Option<bool>,
+    },
+    Disconnect,
+    Init {
+        #[serde(default)]
+        force: bool,
+    },
+    Calibrate {
+        joints: JointMask,
+    },
+    GetJoints,
+    MoveJoint {
+        joint: u8,
+        angle: f32,
+        speed: Option<f32>,
+    },
+    StopJoint {
+        joint: u8,
+        #[serde(default)]
+        immediately: bool,
+    },
+    StopAllJoints {
+        #[serde(default)]
+        immediately: bool,
+    },
+}
+
+/// Request along with the client-chosen ID used to match it to its reply.
+#[derive(Deserialize)]
+struct Envelope {
+    id: Option<Value>,
+
+    #[serde(flatten)]
+    request: Request,
+}
+
+/// Start the WebSocket bridge on the given address. Clients must authenticate with `token` before
+/// any other request is accepted.
+#[tauri::command]
+pub async fn start_ws_bridge(
+    app: AppHandle,
+    bridge: tauri::State<'_, BridgeState>,
+    address: String,
+    token: String,
+) -> Result<(), String> {
+    if token.is_empty() {
+        return Err("Token must not be empty".to_string());
+    }
+
+    let mut server = bridge.server.lock().await;
+    if server.is_some() {
+        return Err("Bridge already running".to_string());
+    }
+
+    let listener = TcpListener::bind(&address)
+        .await
+        .map_err(|e| format!("Failed to bind {}: {}", address, e))?;
+    info!("WebSocket bridge listening on {}", address);
+
+    *server = Some(tauri::async_runtime::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    info!("Bridge client connected from {}", peer);
+                    tauri::async_runtime::spawn(handle_client(app.clone(), stream, token.clone()));
+                }
+                Err(e) => warn!("Failed to accept bridge client: {}", e),
+            }
+        }
+    }));
+
+    Ok(())
+}
+
+/// Stop the WebSocket bridge. Clients that are already connected stay connected until they
+/// disconnect.
+#[tauri::command]
+pub async fn stop_ws_bridge(bridge: tauri::State<'_, BridgeState>) -> Result<(), String> {
+    if let Some(server) = bridge.server.lock().await.take() {
+        server.abort();
+    }
+    Ok(())
+}
+
+/// Serve a single bridge client until it disconnects.
+async fn handle_client(app: AppHandle, stream: TcpStream, token: String) {
+    let socket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("Bridge handshake failed: {}", e);
+            return;
+        }
+    };
+    let (mut sink, mut source) = socket.split();
+
+    // All outgoing messages go through a single writer so replies and streamed events can't
+    // interleave mid-frame.
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let writer = tauri::async_runtime::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if sink.send(Message::Text(message.to_string())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut authenticated = false;
+    let mut stream_task: Option<tauri::async_runtime::JoinHandle<()>> = None;
+
+    while let Some(Ok(message)) = source.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let Envelope { id, request } = match serde_json::from_str::<Envelope>(&text) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                let _ = tx.send(json!({ "ok": false, "error": format!("Bad request: {}", e) }));
+                if !authenticated {
+                    warn!("Bridge client sent a bad request before authenticating");
+                    break;
+                }
+                continue;
+            }
+        };
+
+        if !authenticated {
+            match request {
+                Request::Control(Control::Auth { token: given })
+                    if tokens_match(&given, &token) =>
+                {
+                    authenticated = true;
+                    let _ = tx.send(json!({ "id": id, "ok": true, "result": null }));
+                    continue;
+                }
+                _ => {
+                    warn!("Bridge client failed to authenticate");
+                    let _ = tx.send(json!({ "id": id, "ok": false, "error": "Unauthorized" }));
+                    break;
+                }
+            }
+        }
+
+        let result = match request {
+            Request::Control(Control::Auth { .. }) => Ok(Value::Null),
+            Request::Control(Control::Subscribe { interval_ms }) => {
+                if let Some(task) = stream_task.take() {
+                    task.abort();
+                }
+                let interval = Duration::from_millis(interval_ms).max(MIN_STREAM_INTERVAL);
+                stream_task = Some(tauri::async_runtime::spawn(stream_joints(
+                    app.clone(),
+                    tx.clone(),
+                    interval,
+                )));
+                Ok(Value::Null)
+            }
+            Request::Control(Control::Unsubscribe) => {
+                if let Some(task) = stream_task.take() {
+                    task.abort();
+                }
+                Ok(Value::Null)
+            }
+            Request::Command(command) => dispatch(&app, command).await,
+        };
+
+        let reply = match result {
+            Ok(result) => json!({ "id": id, "ok": true, "result": result }),
+            Err(error) => json!({ "id": id, "ok": false, "error": error }),
+        };
+        if tx.send(reply).is_err() {
+            break;
+        }
+    }
+
+    if let Some(task) = stream_task {
+        task.abort();
+    }
+    drop(tx);
+    let _ = writer.await;
+    info!("Bridge client disconnected");
+}
+
+/// Compare the token a client gave with the bridge's, in a time that doesn't depend on where they
+/// differ, so the token can't be guessed a byte at a time.
+fn tokens_match(given: &str, token: &str) -> bool {
+    let (given, token) = (given.as_bytes(), token.as_bytes());
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (given, token)| diff | (given ^ token))
+            == 0
+}
+
+/// Run a command through the matching Tauri command.
+async fn dispatch(app: &AppHandle, command: Command) -> Result<Value, String> {
+    let state = app.state::<AppState>();
+    let result = match command {
+        Command::IsConnected => crate::is_connected(state).await.map(|r| json!(r)),
+        Command::Connect {
+            port_name,
+            baud_rate,
+            verify,
+            startup,
+        } => crate::connect(app.clone(), state, port_name, baud_rate, verify, startup)
+            .await
+            .map(|r| json!(r)),
+        Command::Disconnect => crate::disconnect(state).await.map(|r| json!(r)),
+        Command::Init { force } => crate::init(app.clone(), state, Some(force))
+            .await
+            .map(|r| json!(r)),
+        Command::Calibrate { joints } => crate::calibrate(app.clone(), state, joints)
+            .await
+            .map(|r| json!(r)),
+        Command::GetJoints => crate::get_angles(state).await.map(|r| json!(r)),
+        Command::MoveJoint {
+            joint,
+            angle,
+            speed,
+        } => crate::move_joint(app.clone(), state, joint, angle, speed)
+            .await
+            .map(|r| json!(r)),
+        Command::StopJoint { joint, immediately } => crate::stop_joint(state, joint, immediately)
+            .await
+            .map(|r| json!(r)),
+        Command::StopAllJoints { immediately } => crate::stop_all_joints(state, immediately)
+            .await
+            .map(|r| json!(r)),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+/// Send the joint samples published by the app and the protocol events of the connection to a
+/// client, with at most one joint sample per interval. Events are followed across reconnections.
+async fn stream_joints(app: AppHandle, tx: mpsc::UnboundedSender<Value>, interval: Duration) {
+    let state = app.state::<AppState>();
+    let mut samples = state.joint_samples.subscribe();
+    let mut connections = state.connection_events.subscribe();
+    let mut comms = subscribe_to_comms(&state).await;
+    let mut last_sent: Option<Instant> = None;
+
+    loop {
+        let message = tokio::select! {
+            sample = samples.recv() => match sample {
+                Ok(sample) => {
+                    if last_sent.is_some_and(|last| last.elapsed() < interval) {
+                        continue;
+                    }
+                    last_sent = Some(Instant::now());
+                    json!({
+                        "event": "joints",
+                        "angles": sample.angles,
+                        "speeds": sample.speeds,
+                        "units": sample.units,
+                    })
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            event = next_comms_event(&mut comms) => match event {
+                Ok(event) => json!({ "event": "comms", "comms": event }),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => {
+                    // The connection was dropped; the next one is picked up when it connects.
+                    comms = None;
+                    continue;
+                }
+            },
+            event = connections.recv() => {
+                if let Ok(ConnectionEvent::Connected { .. }) = event {
+                    comms = subscribe_to_comms(&state).await;
+                }
+                continue;
+            }
+        };
+        if tx.send(message).is_err() {
+            return;
+        }
+    }
+}
+
+/// Subscribe to the protocol events of the connected COBOT, or `None` if it isn't connected.
+async fn subscribe_to_comms(state: &AppState) -> Option<broadcast::Receiver<CommsEvent>> {
+    state
+        .with_cobot_background(|cobot| Ok::<_, AppError>(cobot.subscribe()))
+        .await
+        .ok()
+}
+
+/// Wait for the next protocol event, or forever if there's no connection to get events from.
+async fn next_comms_event(
+    events: &mut Option<broadcast::Receiver<CommsEvent>>,
+) -> Result<CommsEvent, RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_match_only_when_equal() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3creT", "s3cret"));
+        assert!(!tokens_match("s3cre", "s3cret"));
+        assert!(!tokens_match("", "s3cret"));
+    }
+
+    #[test]
+    fn requests_are_split_into_control_and_commands() {
+        let parse = |text| serde_json::from_str::<Envelope>(text).map(|envelope| envelope.request);
+        assert!(matches!(
+            parse(r#"{ "id": 1, "op": "auth", "token": "t" }"#),
+            Ok(Request::Control(Control::Auth { .. }))
+        ));
+        assert!(matches!(
+            parse(r#"{ "op": "unsubscribe" }"#),
+            Ok(Request::Control(Control::Unsubscribe))
+        ));
+        assert!(matches!(
+            parse(r#"{ "id": "a", "op": "move_joint", "joint": 2, "angle": 45.0 }"#),
+            Ok(Request::Command(Command::MoveJoint {
+                joint: 2,
+                speed: None,
+                ..
+            }))
+        ));
+        assert!(parse(r#"{ "op": "auth" }"#).is_err());
+        assert!(parse(r#"{ "op": "dance" }"#).is_err());
+        assert!(parse(r#"{ "joint": 2 }"#).is_err());
+    }
+}
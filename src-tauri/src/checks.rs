@@ -0,0 +1,238 @@
+//! Checks of the joints against expected values, for test plans and checks from the UI.
+//!
+//! Each check reads the joints until it passes or times out, and reports what it saw rather than
+//! a bare pass or fail. Angles, speeds and tolerances are in the display frame and the active
+//! units, as in the Tauri commands. Joints read recently enough, whether polled by another command
+//! or streamed by the COBOT, are used instead of reading them again.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::{AppState, JointSample};
+
+/// Interval between reads of the joints while a check hasn't passed yet. Joints read more
+/// recently than this are reused.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Outcome of `wait_for_angle`.
+#[derive(Clone, Debug, Serialize)]
+pub struct AngleCheck {
+    /// Whether the joint reached the target and stayed there for the settle time.
+    pub passed: bool,
+
+    /// Angle of the joint when the check passed, or when it timed out.
+    pub achieved: f32,
+
+    /// Time from the start of the check until it passed or timed out, in milliseconds.
+    pub elapsed_ms: u64,
+
+    /// Number of times the joints were read.
+    pub samples: u32,
+}
+
+/// Outcome of `wait_for_stopped`.
+#[derive(Clone, Debug, Serialize)]
+pub struct StoppedCheck {
+    /// Whether every joint slowed to within the threshold.
+    pub passed: bool,
+
+    /// Speed of each checked joint when the check passed, or when it timed out.
+    pub speeds: Vec<f32>,
+
+    /// Time from the start of the check until it passed or timed out, in milliseconds.
+    pub elapsed_ms: u64,
+
+    /// Number of times the joints were read.
+    pub samples: u32,
+}
+
+/// Outcome of `assert_pose`.
+#[derive(Clone, Debug, Serialize)]
+pub struct PoseCheck {
+    /// Whether every joint is within the tolerance of its expected angle.
+    pub passed: bool,
+
+    /// Angle of each joint.
+    pub angles: Vec<f32>,
+
+    /// Difference between each joint's angle and its expected angle.
+    pub deltas: Vec<f32>,
+}
+
+/// Read the joints, or reuse joints read within the last `POLL_INTERVAL`.
+pub async fn read_joints(app: &AppHandle) -> Result<JointSample, String> {
+    let state = app.state::<AppState>();
+    let cached = state
+        .cached_joint_states
+        .lock()
+        .unwrap()
+        .clone()
+        .filter(|(_, read_at)| read_at.elapsed() < POLL_INTERVAL);
+    let joint_states = match cached {
+        Some((joint_states, _)) => joint_states,
+        None => {
+            let joint_states = state
+                .with_cobot(|cobot| {
+                    cobot
+                        .get_joint_states()
+                        .map_err(|e| format!("Failed to get joint states: {}", e))
+                })
+                .await
+                .map_err(|e| e.to_string())?;
+            *state.cached_joint_states.lock().unwrap() =
+                Some((joint_states.clone(), Instant::now()));
+            joint_states
+        }
+    };
+
+    let settings = state.settings.lock().await;
+    Ok(crate::joint_sample(&settings, &joint_states))
+}
+
+/// Wait for a joint to reach an angle. A joint that passes through the tolerance band while
+/// overshooting only passes the check if it stays in the band for the settle time.
+///
+/// # Arguments
+///
+/// * `joint` - Joint to check.
+/// * `target` - Angle the joint should reach.
+/// * `tolerance` - Largest allowed difference from the target.
+/// * `timeout` - Time the joint has to reach the target.
+/// * `settle` - Time the joint must stay within the tolerance. Zero passes on the first sample
+///   within the tolerance.
+///
+/// # Returns
+///
+/// The outcome, or an error if the joints can't be read or the joint doesn't exist.
+pub async fn wait_for_angle(
+    app: &AppHandle,
+    joint: u8,
+    target: f32,
+    tolerance: f32,
+    timeout: Duration,
+    settle: Duration,
+) -> Result<AngleCheck, String> {
+    let start = Instant::now();
+    let mut samples = 0;
+    let mut in_band_since = None;
+    loop {
+        let sample = read_joints(app).await?;
+        samples += 1;
+        let Some(&achieved) = sample.angles.get(joint as usize) else {
+            return Err(format!("Joint {} doesn't exist", joint));
+        };
+
+        let now = Instant::now();
+        if (achieved - target).abs() <= tolerance {
+            let since = *in_band_since.get_or_insert(now);
+            if now - since >= settle {
+                return Ok(AngleCheck {
+                    passed: true,
+                    achieved,
+                    elapsed_ms: (now - start).as_millis() as u64,
+                    samples,
+                });
+            }
+        } else {
+            in_band_since = None;
+        }
+
+        if now - start >= timeout {
+            return Ok(AngleCheck {
+                passed: false,
+                achieved,
+                elapsed_ms: (now - start).as_millis() as u64,
+                samples,
+            });
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Wait for the given joints to stop moving.
+///
+/// # Arguments
+///
+/// * `joints` - Joints to check.
+/// * `speed_threshold` - Highest speed, per second, a joint may have and still count as stopped.
+/// * `timeout` - Time the joints have to stop.
+///
+/// # Returns
+///
+/// The outcome, or an error if the joints can't be read or one of them doesn't exist.
+pub async fn wait_for_stopped(
+    app: &AppHandle,
+    joints: &[u8],
+    speed_threshold: f32,
+    timeout: Duration,
+) -> Result<StoppedCheck, String> {
+    let start = Instant::now();
+    let mut samples = 0;
+    loop {
+        let sample = read_joints(app).await?;
+        samples += 1;
+        let speeds = joints
+            .iter()
+            .map(|&joint| {
+                sample
+                    .speeds
+                    .get(joint as usize)
+                    .copied()
+                    .ok_or_else(|| format!("Joint {} doesn't exist", joint))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let elapsed = start.elapsed();
+        let passed = speeds.iter().all(|speed| speed.abs() <= speed_threshold);
+        if passed || elapsed >= timeout {
+            return Ok(StoppedCheck {
+                passed,
+                speeds,
+                elapsed_ms: elapsed.as_millis() as u64,
+                samples,
+            });
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Check that the joints are at a pose, from a single reading.
+///
+/// # Arguments
+///
+/// * `pose` - Expected angle of each joint, from the first joint. Joints beyond the end of the
+///   pose aren't checked.
+/// * `tolerance` - Largest allowed difference from each expected angle.
+///
+/// # Returns
+///
+/// The outcome, or an error if the joints can't be read or the pose has more joints than the
+/// COBOT.
+pub async fn assert_pose(
+    app: &AppHandle,
+    pose: &[f32],
+    tolerance: f32,
+) -> Result<PoseCheck, String> {
+    let sample = read_joints(app).await?;
+    if pose.len() > sample.angles.len() {
+        return Err(format!(
+            "Pose has {} joints, but the COBOT has {}",
+            pose.len(),
+            sample.angles.len()
+        ));
+    }
+
+    let angles = sample.angles[..pose.len()].to_vec();
+    let deltas = angles
+        .iter()
+        .zip(pose)
+        .map(|(angle, expected)| angle - expected)
+        .collect::<Vec<_>>();
+    Ok(PoseCheck {
+        passed: deltas.iter().all(|delta| delta.abs() <= tolerance),
+        angles,
+        deltas,
+    })
+}
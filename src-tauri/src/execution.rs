@@ -0,0 +1,319 @@
+//! State of the test plan executor, shared with the commands that pause and resume it.
+//!
+//! Pausing stops the joints moved by the step in progress and freezes the executor before its next
+//! step. A step interrupted by the pause is run again from its start once resumed, so a move is
+//! re-issued to its original target from wherever the joint is, even if the arm was pushed while
+//! paused. A wait step only waits out the time it had left.
+
+use std::time::Duration;
+
+use cobot_comms::JointMask;
+use serde::Serialize;
+use tokio::{sync::watch, time::Instant};
+
+/// State of the executor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionState {
+    /// Nothing has run yet, or the last plan finished.
+    Idle,
+
+    /// A plan is running.
+    Running,
+
+    /// A plan is paused by the operator, waiting to be resumed.
+    Paused,
+
+    /// The last plan stopped early, at a failed step or because the operator aborted it.
+    Aborted,
+}
+
+/// Function called with the state of the executor whenever it changes.
+pub type StateHandler = Box<dyn Fn(ExecutionState) + Send + Sync>;
+
+/// Executor state shared between the running plan and the commands controlling it.
+pub struct Execution {
+    /// Current state, watched by the running plan.
+    state: watch::Sender<ExecutionState>,
+
+    /// Called with every change of state.
+    on_change: StateHandler,
+
+    /// Joints moved by the step in progress, stopped when the plan is paused.
+    step_joints: std::sync::Mutex<JointMask>,
+}
+
+impl Execution {
+    /// Create an idle executor.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_change` - Function to call with every change of state, such as to tell the frontend.
+    pub fn new(on_change: StateHandler) -> Self {
+        Execution {
+            state: watch::channel(ExecutionState::Idle).0,
+            on_change,
+            step_joints: std::sync::Mutex::new(JointMask::default()),
+        }
+    }
+
+    /// Get the current state.
+    pub fn state(&self) -> ExecutionState {
+        *self.state.borrow()
+    }
+
+    /// Change the state and pass it to the state handler.
+    pub fn set_state(&self, state: ExecutionState) {
+        self.state.send_replace(state);
+        (self.on_change)(state);
+    }
+
+    /// Mark a plan as running, unless one already is.
+    pub fn start(&self) -> Result<(), String> {
+        if matches!(
+            self.state(),
+            ExecutionState::Running | ExecutionState::Paused
+        ) {
+            return Err("A test plan is already running".into());
+        }
+        self.set_state(ExecutionState::Running);
+        Ok(())
+    }
+
+    /// Get the joints moved by the step in progress.
+    pub fn step_joints(&self) -> JointMask {
+        *self.step_joints.lock().unwrap()
+    }
+
+    /// Set the joints moved by the step about to run.
+    pub fn set_step_joints(&self, joints: JointMask) {
+        *self.step_joints.lock().unwrap() = joints;
+    }
+
+    /// Pause the running plan.
+    ///
+    /// # Returns
+    ///
+    /// The joints moved by the step in progress, for the caller to stop, or an error if no plan is
+    /// running.
+    pub fn pause(&self) -> Result<JointMask, String> {
+        if self.state() != ExecutionState::Running {
+            return Err("No test plan is running".into());
+        }
+        self.set_state(ExecutionState::Paused);
+        Ok(self.step_joints())
+    }
+
+    /// Resume the paused plan, or return an error if no plan is paused.
+    pub fn resume(&self) -> Result<(), String> {
+        if self.state() != ExecutionState::Paused {
+            return Err("No test plan is paused".into());
+        }
+        self.set_state(ExecutionState::Running);
+        Ok(())
+    }
+
+    /// Abort the running or paused plan before its next step, or return an error if there is none.
+    pub fn abort(&self) -> Result<(), String> {
+        if !matches!(
+            self.state(),
+            ExecutionState::Running | ExecutionState::Paused
+        ) {
+            return Err("No test plan is running".into());
+        }
+        self.set_state(ExecutionState::Aborted);
+        Ok(())
+    }
+
+    /// Wait until the plan isn't paused.
+    ///
+    /// # Returns
+    ///
+    /// The state the plan left the pause in.
+    pub async fn wait_while_paused(&self) -> ExecutionState {
+        let mut state = self.state.subscribe();
+        let resumed = state
+            .wait_for(|state| *state != ExecutionState::Paused)
+            .await;
+        resumed.map_or(ExecutionState::Aborted, |state| *state)
+    }
+
+    /// Wait for a while, not counting the time spent paused.
+    ///
+    /// # Returns
+    ///
+    /// The state once the time is up, or `Aborted` as soon as the plan is aborted, paused or not.
+    pub async fn dwell(&self, duration: Duration) -> ExecutionState {
+        let mut remaining = duration;
+        let mut state = self.state.subscribe();
+        loop {
+            let resumed = self.wait_while_paused().await;
+            if resumed == ExecutionState::Aborted {
+                return resumed;
+            }
+
+            let start = Instant::now();
+            let interrupted = state.wait_for(|state| {
+                matches!(state, ExecutionState::Paused | ExecutionState::Aborted)
+            });
+            let interruption = match tokio::time::timeout(remaining, interrupted).await {
+                Ok(interruption) => interruption.map_or(ExecutionState::Aborted, |state| *state),
+                Err(_) => return self.state(),
+            };
+            if interruption == ExecutionState::Aborted {
+                return interruption;
+            }
+            remaining = remaining.saturating_sub(start.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Executor recording every state passed to its handler.
+    fn recorded_execution() -> (Arc<Execution>, Arc<Mutex<Vec<ExecutionState>>>) {
+        let states = Arc::new(Mutex::new(Vec::new()));
+        let recorded = states.clone();
+        let execution = Execution::new(Box::new(move |state| {
+            recorded.lock().unwrap().push(state);
+        }));
+        (Arc::new(execution), states)
+    }
+
+    /// Start dwelling for `duration` on another task.
+    ///
+    /// # Returns
+    ///
+    /// The state the dwell ended in and how long it took.
+    fn spawn_dwell(
+        execution: &Arc<Execution>,
+        duration: Duration,
+    ) -> tokio::task::JoinHandle<(ExecutionState, Duration)> {
+        let execution = execution.clone();
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let state = execution.dwell(duration).await;
+            (state, start.elapsed())
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dwell_does_not_count_time_spent_paused() {
+        let (execution, states) = recorded_execution();
+        execution.start().unwrap();
+        let dwell = spawn_dwell(&execution, Duration::from_millis(1000));
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        execution.pause().unwrap();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(!dwell.is_finished());
+        execution.resume().unwrap();
+
+        // 400 ms before the pause, 5 s paused, then the 600 ms left.
+        assert_eq!(
+            dwell.await.unwrap(),
+            (ExecutionState::Running, Duration::from_millis(6000))
+        );
+        assert_eq!(
+            *states.lock().unwrap(),
+            [
+                ExecutionState::Running,
+                ExecutionState::Paused,
+                ExecutionState::Running
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abort_ends_a_dwell_at_once() {
+        let (execution, _) = recorded_execution();
+        execution.start().unwrap();
+        let dwell = spawn_dwell(&execution, Duration::from_secs(10));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        execution.abort().unwrap();
+        assert_eq!(
+            dwell.await.unwrap(),
+            (ExecutionState::Aborted, Duration::from_millis(100))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abort_ends_a_paused_dwell() {
+        let (execution, _) = recorded_execution();
+        execution.start().unwrap();
+        let dwell = spawn_dwell(&execution, Duration::from_secs(10));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        execution.pause().unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        execution.abort().unwrap();
+        assert_eq!(
+            dwell.await.unwrap(),
+            (ExecutionState::Aborted, Duration::from_millis(300))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pause_between_steps_holds_the_plan_until_resumed() {
+        let (execution, states) = recorded_execution();
+        execution.start().unwrap();
+
+        // The step that just finished moved joint 2, which a pause hands back to be stopped.
+        execution.set_step_joints(JointMask::joint(2));
+        assert_eq!(execution.pause().unwrap(), JointMask::joint(2));
+        assert!(execution.pause().is_err());
+
+        let waiting = {
+            let execution = execution.clone();
+            tokio::spawn(async move { execution.wait_while_paused().await })
+        };
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert!(!waiting.is_finished());
+
+        execution.resume().unwrap();
+        assert_eq!(waiting.await.unwrap(), ExecutionState::Running);
+        assert!(execution.resume().is_err());
+        assert_eq!(
+            *states.lock().unwrap(),
+            [
+                ExecutionState::Running,
+                ExecutionState::Paused,
+                ExecutionState::Running
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abort_between_steps_releases_the_plan() {
+        let (execution, states) = recorded_execution();
+        execution.start().unwrap();
+        execution.pause().unwrap();
+
+        let waiting = {
+            let execution = execution.clone();
+            tokio::spawn(async move { execution.wait_while_paused().await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        execution.abort().unwrap();
+        assert_eq!(waiting.await.unwrap(), ExecutionState::Aborted);
+
+        // Nothing is left to pause, resume or abort, but a new plan can start.
+        assert!(execution.pause().is_err());
+        assert!(execution.resume().is_err());
+        assert!(execution.abort().is_err());
+        execution.start().unwrap();
+        assert_eq!(
+            *states.lock().unwrap(),
+            [
+                ExecutionState::Running,
+                ExecutionState::Paused,
+                ExecutionState::Aborted,
+                ExecutionState::Running
+            ]
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Flashing new firmware through the COBOT's bootloader, over the same serial port.
+//!
+//! # Bootloader Protocol
+//!
+//! The bootloader frames its packets like protocol version 2, with a 2-byte payload length.
+//!
+//! | Byte | Description                |
+//! | ---- | -------------------------- |
+//! | 0    | Start byte (0x24)          |
+//! | 1-2  | Payload length (uint16)    |
+//! | 3    | CRC of payload (crc8ccitt) |
+//! | 4... | Payload                    |
+//!
+//! ## Packets
+//!
+//! Every packet is answered with a reply before the next is sent.
+//!
+//! | Byte | Description |
+//! | ---- | ----------- |
+//! | 0    | Packet type |
+//! | 1... | Body        |
+//!
+//! ### Begin (0x00)
+//!
+//! Erases the application area. Can take several seconds.
+//!
+//! | Byte | Description                        |
+//! | ---- | ---------------------------------- |
+//! | 1-4  | Image size (uint32) (bytes)        |
+//! | 5    | CRC of the whole image (crc8ccitt) |
+//!
+//! ### Chunk (0x01)
+//!
+//! | Byte | Description                  |
+//! | ---- | ---------------------------- |
+//! | 1-4  | Offset in the image (uint32) |
+//! | 5... | Data, up to 256 bytes        |
+//!
+//! ### End (0x02)
+//!
+//! No body. The bootloader checks the image against the CRC from Begin before accepting it.
+//!
+//! ### Reset (0x03)
+//!
+//! No body. The bootloader replies, then boots the new firmware.
+//!
+//! ## Reply
+//!
+//! | Byte | Description                                |
+//! | ---- | ------------------------------------------ |
+//! | 0    | 0x00 (ACK) or 0x01 (NAK)                   |
+//! | 1-4  | Offset of the chunk (uint32), 0 for others |
+//! | 5    | Error code, NAK only                       |
+
+use std::{error::Error, time::Duration};
+
+use cobot_comms::checksum::{crc8ccitt, crc8ccitt_check};
+use serialport::{ClearBuffer, SerialPort};
+
+/// Byte every frame begins with.
+const START_BYTE: u8 = 0x24;
+
+/// Largest amount of the image sent in one chunk, in bytes.
+pub const CHUNK_SIZE: usize = 256;
+
+/// Time to wait for a reply to Begin or End, which erase or check the whole image.
+const LONG_REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time to wait for a reply to any other packet.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Number of times a chunk is sent before flashing is abandoned.
+const CHUNK_ATTEMPTS: u32 = 3;
+
+/// Types of packets sent to the bootloader.
+mod packet_type {
+    pub const BEGIN: u8 = 0x00;
+    pub const CHUNK: u8 = 0x01;
+    pub const END: u8 = 0x02;
+    pub const RESET: u8 = 0x03;
+}
+
+/// Types of replies sent by the bootloader.
+mod reply_type {
+    pub const ACK: u8 = 0x00;
+    pub const NAK: u8 = 0x01;
+}
+
+/// Packet sent to the bootloader.
+pub struct FlashPacket {
+    /// Type of the packet, as in `packet_type`.
+    packet_type: u8,
+
+    /// Body of the packet.
+    body: Vec<u8>,
+}
+
+impl FlashPacket {
+    /// Start flashing an image.
+    fn begin(image: &[u8]) -> Self {
+        let mut body = (image.len() as u32).to_le_bytes().to_vec();
+        body.push(crc8ccitt(image));
+        FlashPacket {
+            packet_type: packet_type::BEGIN,
+            body,
+        }
+    }
+
+    /// Write part of the image at the given offset.
+    fn chunk(offset: u32, data: &[u8]) -> Self {
+        let mut body = offset.to_le_bytes().to_vec();
+        body.extend_from_slice(data);
+        FlashPacket {
+            packet_type: packet_type::CHUNK,
+            body,
+        }
+    }
+
+    /// Finish flashing the image.
+    fn end() -> Self {
+        FlashPacket {
+            packet_type: packet_type::END,
+            body: Vec::new(),
+        }
+    }
+
+    /// Boot the new firmware.
+    fn reset() -> Self {
+        FlashPacket {
+            packet_type: packet_type::RESET,
+            body: Vec::new(),
+        }
+    }
+
+    /// Encode the packet as a frame.
+    fn encode(&self) -> Vec<u8> {
+        let mut payload = vec![self.packet_type];
+        payload.extend_from_slice(&self.body);
+
+        let mut frame = vec![START_BYTE];
+        frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        frame.push(crc8ccitt(&payload));
+        frame.extend(payload);
+        frame
+    }
+}
+
+/// Reply from the bootloader to a packet.
+struct Reply {
+    /// Whether the packet was accepted.
+    ack: bool,
+
+    /// Offset of the chunk the reply is for, or 0 for other packets.
+    offset: u32,
+
+    /// Why the packet was rejected, if it was.
+    error_code: u8,
+}
+
+/// Send a packet to the bootloader and wait for its reply.
+///
+/// # Arguments
+///
+/// * `port` - Serial port the bootloader is on.
+/// * `packet` - Packet to send.
+/// * `timeout` - Maximum time to wait for the reply.
+fn send(
+    port: &mut dyn SerialPort,
+    packet: &FlashPacket,
+    timeout: Duration,
+) -> Result<Reply, Box<dyn Error>> {
+    port.write_all(&packet.encode())?;
+    port.set_timeout(timeout)?;
+
+    let mut start_byte = [0];
+    while start_byte[0] != START_BYTE {
+        port.read_exact(&mut start_byte)?;
+    }
+    let mut header = [0; 3];
+    port.read_exact(&mut header)?;
+    let mut payload = vec![0; u16::from_le_bytes([header[0], header[1]]) as usize];
+    port.read_exact(&mut payload)?;
+    if !crc8ccitt_check(&payload, header[2]) {
+        return Err("Reply with invalid CRC".into());
+    }
+
+    match payload[..] {
+        [reply_type::ACK, a, b, c, d, ..] => Ok(Reply {
+            ack: true,
+            offset: u32::from_le_bytes([a, b, c, d]),
+            error_code: 0,
+        }),
+        [reply_type::NAK, a, b, c, d, error_code, ..] => Ok(Reply {
+            ack: false,
+            offset: u32::from_le_bytes([a, b, c, d]),
+            error_code,
+        }),
+        _ => Err("Malformed reply".into()),
+    }
+}
+
+/// Send a packet other than a chunk, failing if the bootloader rejects it.
+fn send_expecting_ack(
+    port: &mut dyn SerialPort,
+    packet: &FlashPacket,
+    timeout: Duration,
+    name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let reply = send(port, packet, timeout)?;
+    if !reply.ack {
+        return Err(format!("{} rejected with error code {}", name, reply.error_code).into());
+    }
+    Ok(())
+}
+
+/// Flash an image through the bootloader, then boot it. The COBOT must already be in its
+/// bootloader.
+///
+/// # Arguments
+///
+/// * `port` - Serial port the bootloader is on.
+/// * `image` - Firmware image to flash.
+/// * `progress` - Called after each chunk with the number of bytes written and the image size.
+///
+/// # Returns
+///
+/// Ok once the new firmware is booting, or an error if any packet was rejected or went
+/// unanswered.
+pub fn flash(
+    port: &mut dyn SerialPort,
+    image: &[u8],
+    mut progress: impl FnMut(usize, usize),
+) -> Result<(), Box<dyn Error>> {
+    if image.len() > u32::MAX as usize {
+        return Err("Firmware image too large".into());
+    }
+
+    // Drop anything the firmware sent before it reset.
+    port.clear(ClearBuffer::Input)?;
+    send_expecting_ack(
+        port,
+        &FlashPacket::begin(image),
+        LONG_REPLY_TIMEOUT,
+        "Begin",
+    )?;
+
+    for (index, data) in image.chunks(CHUNK_SIZE).enumerate() {
+        let offset = (index * CHUNK_SIZE) as u32;
+        let packet = FlashPacket::chunk(offset, data);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match send(port, &packet, REPLY_TIMEOUT) {
+                Ok(reply) if reply.ack && reply.offset == offset => break,
+                Ok(reply) if reply.ack => format!("ACK for offset {}", reply.offset),
+                Ok(reply) => format!("rejected with error code {}", reply.error_code),
+                Err(e) => e.to_string(),
+            };
+            if attempts >= CHUNK_ATTEMPTS {
+                return Err(format!("Chunk at offset {} failed: {}", offset, error).into());
+            }
+            // A late reply to the failed attempt must not be taken for the retry's.
+            port.clear(ClearBuffer::Input)?;
+        }
+        progress(offset as usize + data.len(), image.len());
+    }
+
+    send_expecting_ack(port, &FlashPacket::end(), LONG_REPLY_TIMEOUT, "End")?;
+    send_expecting_ack(port, &FlashPacket::reset(), REPLY_TIMEOUT, "Reset")
+}
@@ -0,0 +1,126 @@
+//! Timer of how long the COBOT has gone without a command from the operator, after which it's
+//! disconnected to release its port.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use cobot_comms::{Clock, SystemClock};
+
+/// Time since the connection was last used on the operator's behalf, which can be paused to keep
+/// an idle COBOT connected.
+pub struct IdleTimer {
+    /// Clock the idle time is measured by.
+    clock: Box<dyn Clock + Sync>,
+
+    /// Time of the last activity.
+    last_activity: Mutex<Instant>,
+
+    /// Set while the timer is paused.
+    keep_alive: AtomicBool,
+}
+
+impl IdleTimer {
+    /// Create a timer that starts counting from now.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock the idle time is measured by.
+    pub fn new(clock: Box<dyn Clock + Sync>) -> Self {
+        let last_activity = Mutex::new(clock.now());
+        Self {
+            clock,
+            last_activity,
+            keep_alive: AtomicBool::new(false),
+        }
+    }
+
+    /// Record activity, starting the idle period again.
+    pub fn touch(&self) {
+        *self.last_activity.lock().unwrap() = self.clock.now();
+    }
+
+    /// Pause the timer, or resume it with the idle period starting again from now.
+    pub fn set_keep_alive(&self, enabled: bool) {
+        if !enabled {
+            self.touch();
+        }
+        self.keep_alive.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Check whether the connection has been idle for at least the given time.
+    ///
+    /// # Returns
+    ///
+    /// How long the connection has been idle, or `None` if that's less than `timeout` or the timer
+    /// is paused.
+    pub fn expired(&self, timeout: Duration) -> Option<Duration> {
+        if self.keep_alive.load(Ordering::Relaxed) {
+            return None;
+        }
+        let idle = self.clock.now() - *self.last_activity.lock().unwrap();
+        (idle >= timeout).then_some(idle)
+    }
+}
+
+impl Default for IdleTimer {
+    fn default() -> Self {
+        Self::new(Box::new(SystemClock))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cobot_comms::MockClock;
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn timer() -> (IdleTimer, MockClock) {
+        let clock = MockClock::new();
+        (IdleTimer::new(Box::new(clock.clone())), clock)
+    }
+
+    #[test]
+    fn expires_once_the_timeout_passes_without_activity() {
+        let (timer, clock) = timer();
+        clock.advance(TIMEOUT - Duration::from_millis(1));
+        assert_eq!(timer.expired(TIMEOUT), None);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(timer.expired(TIMEOUT), Some(TIMEOUT));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(
+            timer.expired(TIMEOUT),
+            Some(TIMEOUT + Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn activity_starts_the_idle_period_again() {
+        let (timer, clock) = timer();
+        clock.advance(TIMEOUT - Duration::from_secs(1));
+        timer.touch();
+        clock.advance(TIMEOUT - Duration::from_secs(1));
+        assert_eq!(timer.expired(TIMEOUT), None);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(timer.expired(TIMEOUT), Some(TIMEOUT));
+    }
+
+    #[test]
+    fn keep_alive_pauses_the_timer_until_turned_off() {
+        let (timer, clock) = timer();
+        timer.set_keep_alive(true);
+        clock.advance(TIMEOUT * 3);
+        assert_eq!(timer.expired(TIMEOUT), None);
+
+        // The time spent kept alive doesn't count once the timer resumes.
+        timer.set_keep_alive(false);
+        assert_eq!(timer.expired(TIMEOUT), None);
+        clock.advance(TIMEOUT);
+        assert_eq!(timer.expired(TIMEOUT), Some(TIMEOUT));
+    }
+}
@@ -0,0 +1,464 @@
+//! Forward kinematics of the arm, from Denavit-Hartenberg parameters.
+
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// Denavit-Hartenberg parameters of a single joint, using the standard convention.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DhParameters {
+    /// Distance along the previous z axis to the common normal, in mm.
+    pub d: f32,
+
+    /// Angle of the joint, in degrees, when the firmware reports `0`.
+    pub theta_offset: f32,
+
+    /// Length of the common normal, in mm.
+    pub a: f32,
+
+    /// Angle about the common normal from the previous z axis to the new one, in degrees.
+    pub alpha: f32,
+}
+
+/// Position and orientation of the end effector relative to the base.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Pose {
+    /// Position, in mm.
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+
+    /// Orientation as a unit quaternion.
+    pub qw: f32,
+    pub qx: f32,
+    pub qy: f32,
+    pub qz: f32,
+}
+
+/// Error computing a pose.
+#[derive(Debug)]
+pub enum KinematicsError {
+    /// No kinematic parameters are configured.
+    NotConfigured,
+
+    /// The number of joint angles doesn't match the number of configured joints.
+    JointCountMismatch { expected: usize, actual: usize },
+}
+impl fmt::Display for KinematicsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KinematicsError::NotConfigured => write!(f, "Kinematics not configured"),
+            KinematicsError::JointCountMismatch { expected, actual } => write!(
+                f,
+                "Kinematics configured for {} joints but the COBOT reported {}",
+                expected, actual
+            ),
+        }
+    }
+}
+impl Error for KinematicsError {}
+
+/// Homogeneous transform, row-major.
+type Transform = [[f64; 4]; 4];
+
+const IDENTITY: Transform = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Transform from one joint's frame to the next.
+fn joint_transform(params: &DhParameters, angle: f32) -> Transform {
+    let theta = (angle as f64 + params.theta_offset as f64).to_radians();
+    let alpha = (params.alpha as f64).to_radians();
+    let (st, ct) = theta.sin_cos();
+    let (sa, ca) = alpha.sin_cos();
+    let a = params.a as f64;
+    let d = params.d as f64;
+
+    [
+        [ct, -st * ca, st * sa, a * ct],
+        [st, ct * ca, -ct * sa, a * st],
+        [0.0, sa, ca, d],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn multiply(lhs: &Transform, rhs: &Transform) -> Transform {
+    let mut result = [[0.0; 4]; 4];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| lhs[i][k] * rhs[k][j]).sum();
+        }
+    }
+    result
+}
+
+/// Convert a rotation matrix to a unit quaternion `(w, x, y, z)`, with `w` non-negative.
+fn rotation_to_quaternion(m: &Transform) -> (f64, f64, f64, f64) {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let (w, x, y, z) = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        (
+            0.25 * s,
+            (m[2][1] - m[1][2]) / s,
+            (m[0][2] - m[2][0]) / s,
+            (m[1][0] - m[0][1]) / s,
+        )
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+        (
+            (m[2][1] - m[1][2]) / s,
+            0.25 * s,
+            (m[0][1] + m[1][0]) / s,
+            (m[0][2] + m[2][0]) / s,
+        )
+    } else if m[1][1] > m[2][2] {
+        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+        (
+            (m[0][2] - m[2][0]) / s,
+            (m[0][1] + m[1][0]) / s,
+            0.25 * s,
+            (m[1][2] + m[2][1]) / s,
+        )
+    } else {
+        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+        (
+            (m[1][0] - m[0][1]) / s,
+            (m[0][2] + m[2][0]) / s,
+            (m[1][2] + m[2][1]) / s,
+            0.25 * s,
+        )
+    };
+
+    if w < 0.0 {
+        (-w, -x, -y, -z)
+    } else {
+        (w, x, y, z)
+    }
+}
+
+/// Kinematics of a six-joint arm, for computing the full transform of the end effector.
+#[cfg(feature = "nalgebra")]
+#[derive(Clone, Copy, Debug)]
+pub struct CoordFrame {
+    /// `(d, theta, a, alpha)` of each joint, from the base outwards, using the standard
+    /// Denavit-Hartenberg convention. Lengths are in mm and angles in degrees, with `theta` the
+    /// angle of the joint when the firmware reports `0`.
+    pub dh_params: [(f64, f64, f64, f64); 6],
+}
+
+#[cfg(feature = "nalgebra")]
+impl CoordFrame {
+    /// Build the frame from configured parameters, which must cover exactly six joints.
+    pub fn from_parameters(params: &[DhParameters]) -> Result<Self, KinematicsError> {
+        if params.is_empty() {
+            return Err(KinematicsError::NotConfigured);
+        }
+        let params: &[DhParameters; 6] =
+            params
+                .try_into()
+                .map_err(|_| KinematicsError::JointCountMismatch {
+                    expected: 6,
+                    actual: params.len(),
+                })?;
+
+        Ok(CoordFrame {
+            dh_params: params.map(|p| {
+                (
+                    p.d as f64,
+                    p.theta_offset as f64,
+                    p.a as f64,
+                    p.alpha as f64,
+                )
+            }),
+        })
+    }
+
+    /// Compute the transform from the base to the end effector.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint_angles_deg` - Angle of each joint as reported by the firmware, in degrees.
+    pub fn forward_kinematics(&self, joint_angles_deg: &[f64; 6]) -> nalgebra::Isometry3<f64> {
+        use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+
+        self.dh_params.iter().zip(joint_angles_deg).fold(
+            Isometry3::identity(),
+            |transform, (&(d, theta, a, alpha), angle)| {
+                let rotate_z = Isometry3::from_parts(
+                    Translation3::new(0.0, 0.0, d),
+                    UnitQuaternion::from_axis_angle(
+                        &Vector3::z_axis(),
+                        (angle + theta).to_radians(),
+                    ),
+                );
+                let rotate_x = Isometry3::from_parts(
+                    Translation3::new(a, 0.0, 0.0),
+                    UnitQuaternion::from_axis_angle(&Vector3::x_axis(), alpha.to_radians()),
+                );
+                transform * rotate_z * rotate_x
+            },
+        )
+    }
+}
+
+/// Compute the pose of the end effector.
+///
+/// # Arguments
+///
+/// * `params` - Parameters of each joint, from the base outwards.
+/// * `angles` - Angle of each joint as reported by the firmware, in degrees.
+///
+/// # Returns
+///
+/// The pose of the end effector, or an error if no parameters are configured or their number
+/// doesn't match the number of angles.
+pub fn forward(params: &[DhParameters], angles: &[f32]) -> Result<Pose, KinematicsError> {
+    if params.is_empty() {
+        return Err(KinematicsError::NotConfigured);
+    }
+    if params.len() != angles.len() {
+        return Err(KinematicsError::JointCountMismatch {
+            expected: params.len(),
+            actual: angles.len(),
+        });
+    }
+
+    let transform = params
+        .iter()
+        .zip(angles)
+        .fold(IDENTITY, |transform, (params, &angle)| {
+            multiply(&transform, &joint_transform(params, angle))
+        });
+    let (qw, qx, qy, qz) = rotation_to_quaternion(&transform);
+
+    Ok(Pose {
+        x: transform[0][3] as f32,
+        y: transform[1][3] as f32,
+        z: transform[2][3] as f32,
+        qw: qw as f32,
+        qx: qx as f32,
+        qy: qy as f32,
+        qz: qz as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    /// Two links in the xy plane, 100 mm and 50 mm long, with the first raised 80 mm off the base.
+    fn planar_arm() -> Vec<DhParameters> {
+        vec![
+            DhParameters {
+                d: 80.0,
+                a: 100.0,
+                ..DhParameters::default()
+            },
+            DhParameters {
+                a: 50.0,
+                ..DhParameters::default()
+            },
+        ]
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn assert_pose(pose: Pose, position: [f32; 3], orientation: [f32; 4]) {
+        assert_close(pose.x, position[0]);
+        assert_close(pose.y, position[1]);
+        assert_close(pose.z, position[2]);
+        assert_close(pose.qw, orientation[0]);
+        assert_close(pose.qx, orientation[1]);
+        assert_close(pose.qy, orientation[2]);
+        assert_close(pose.qz, orientation[3]);
+    }
+
+    /// Rotation matrix of `angle` degrees about a unit axis.
+    fn rotation(axis: [f64; 3], angle: f64) -> Transform {
+        let [x, y, z] = axis;
+        let (s, c) = angle.to_radians().sin_cos();
+        let t = 1.0 - c;
+        [
+            [t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.0],
+            [t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.0],
+            [t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
+
+    #[test]
+    fn all_joints_at_zero_stretch_the_arm_along_x() {
+        let pose = forward(&planar_arm(), &[0.0, 0.0]).unwrap();
+        assert_pose(pose, [150.0, 0.0, 80.0], [1.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn single_joint_at_90_degrees_swings_the_arm_to_y() {
+        let pose = forward(&planar_arm(), &[90.0, 0.0]).unwrap();
+        assert_pose(
+            pose,
+            [0.0, 150.0, 80.0],
+            [FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2],
+        );
+
+        let pose = forward(&planar_arm(), &[0.0, 90.0]).unwrap();
+        assert_pose(
+            pose,
+            [100.0, 50.0, 80.0],
+            [FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2],
+        );
+    }
+
+    #[test]
+    fn theta_offset_and_alpha_are_applied() {
+        let params = [DhParameters {
+            theta_offset: 90.0,
+            a: 10.0,
+            alpha: 90.0,
+            ..DhParameters::default()
+        }];
+        // Rotating 90° about z then 90° about the new x is 120° about (1, 1, 1).
+        let pose = forward(&params, &[0.0]).unwrap();
+        assert_pose(pose, [0.0, 10.0, 0.0], [0.5, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn rotations_convert_to_unit_quaternions() {
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        // The identity and half turns about each axis take each branch of the conversion.
+        let cases = [
+            ([0.0, 0.0, 1.0], 0.0, (1.0, 0.0, 0.0, 0.0)),
+            ([1.0, 0.0, 0.0], 90.0, (half, half, 0.0, 0.0)),
+            ([0.0, 1.0, 0.0], -90.0, (half, 0.0, -half, 0.0)),
+            ([1.0, 0.0, 0.0], 180.0, (0.0, 1.0, 0.0, 0.0)),
+            ([0.0, 1.0, 0.0], 180.0, (0.0, 0.0, 1.0, 0.0)),
+            ([0.0, 0.0, 1.0], 180.0, (0.0, 0.0, 0.0, 1.0)),
+            ([0.0, 0.0, 1.0], 270.0, (half, 0.0, 0.0, -half)),
+        ];
+        for (axis, angle, expected) in cases {
+            let (w, x, y, z) = rotation_to_quaternion(&rotation(axis, angle));
+            for (actual, expected) in [
+                (w, expected.0),
+                (x, expected.1),
+                (y, expected.2),
+                (z, expected.3),
+            ] {
+                assert!(
+                    (actual - expected).abs() < 1e-9,
+                    "rotation of {}° about {:?}: expected {:?}, got {:?}",
+                    angle,
+                    axis,
+                    expected,
+                    (w, x, y, z)
+                );
+            }
+            assert!((w * w + x * x + y * y + z * z - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn missing_parameters_are_reported_as_not_configured() {
+        let error = forward(&[], &[0.0; 6]).unwrap_err();
+        assert!(matches!(error, KinematicsError::NotConfigured));
+        assert_eq!(error.to_string(), "Kinematics not configured");
+    }
+
+    #[test]
+    fn joint_count_must_match_the_parameters() {
+        assert!(matches!(
+            forward(&planar_arm(), &[0.0; 6]),
+            Err(KinematicsError::JointCountMismatch {
+                expected: 2,
+                actual: 6
+            })
+        ));
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn full_transform_matches_the_pose() {
+        let params = [
+            DhParameters {
+                d: 89.2,
+                alpha: 90.0,
+                ..DhParameters::default()
+            },
+            DhParameters {
+                a: -425.0,
+                ..DhParameters::default()
+            },
+            DhParameters {
+                a: -392.0,
+                ..DhParameters::default()
+            },
+            DhParameters {
+                d: 109.3,
+                alpha: 90.0,
+                ..DhParameters::default()
+            },
+            DhParameters {
+                d: 94.75,
+                alpha: -90.0,
+                ..DhParameters::default()
+            },
+            DhParameters {
+                d: 82.5,
+                theta_offset: 15.0,
+                ..DhParameters::default()
+            },
+        ];
+        let angles = [10.0, -45.0, 30.0, 120.0, -60.0, 5.0];
+
+        let frame = CoordFrame::from_parameters(&params).unwrap();
+        let transform = frame.forward_kinematics(&angles.map(|angle| angle as f64));
+        let pose = forward(&params, &angles).unwrap();
+
+        let translation = transform.translation.vector;
+        let mut rotation = transform.rotation.into_inner();
+        if rotation.w < 0.0 {
+            rotation = -rotation;
+        }
+        assert_pose(
+            pose,
+            [
+                translation.x as f32,
+                translation.y as f32,
+                translation.z as f32,
+            ],
+            [
+                rotation.w as f32,
+                rotation.i as f32,
+                rotation.j as f32,
+                rotation.k as f32,
+            ],
+        );
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn full_transform_needs_six_joints() {
+        assert!(matches!(
+            CoordFrame::from_parameters(&[]),
+            Err(KinematicsError::NotConfigured)
+        ));
+        assert!(matches!(
+            CoordFrame::from_parameters(&planar_arm()),
+            Err(KinematicsError::JointCountMismatch {
+                expected: 6,
+                actual: 2
+            })
+        ));
+    }
+}
@@ -1,172 +1,4042 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::time::Duration;
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use comms::CobotConnection;
-use tauri::async_runtime::Mutex;
+use backlash::BacklashReport;
+use checks::{AngleCheck, PoseCheck, StoppedCheck};
+use cobot_comms::{
+    CancelHandle, CobotConnection, CobotError, CobotInfo, CobotLogEntry, CommsError, CommsEvent,
+    CommsStats, DecodedFrame, DeviceInfo, JointMask, JointState, LinkQualityThresholds,
+    LoopbackStats, ProtocolInfo, RateLimit, SessionEntry, Target, TargetQueue, FIRMWARE_VERSION,
+};
+use execution::{Execution, ExecutionState};
+use idle::IdleTimer;
+use kinematics::{DhParameters, Pose};
+use log::{error, warn};
+use priority::{Priority, PriorityGate};
+use serde::Serialize;
+use serde_json::json;
+use settings::{AngleUnits, JointCorrection, JointDisplay, JointLimits, Settings, TABLE_JOINTS};
+use speed_test::SpeedTestReport;
+use tauri::{async_runtime::Mutex, AppHandle, Manager};
+use test_plan::TestPlanReport;
+use tokio::sync::{broadcast, mpsc};
+use watchdog::{ActiveMotions, MotionGuard, WatchdogIncident};
 
-mod checksum;
-mod comms;
+mod backlash;
+#[cfg(feature = "ws-bridge")]
+mod bridge;
+mod checks;
+mod execution;
+mod flash;
+mod idle;
+mod kinematics;
+mod motion;
+mod priority;
+mod recovery;
+mod settings;
+mod speed_test;
+mod test_plan;
+mod trajectory;
+mod watchdog;
 
-const FIRMWARE_VERSION: u32 = 5;
+#[cfg(feature = "mqtt")]
+mod telemetry;
+
+/// Stand-ins for the telemetry commands when built without the `mqtt` feature.
+#[cfg(not(feature = "mqtt"))]
+mod telemetry {
+    #[tauri::command]
+    pub async fn start_telemetry(
+        _broker_url: String,
+        _topic: String,
+        _interval_ms: u64,
+    ) -> Result<(), String> {
+        Err("Built without MQTT telemetry support".to_string())
+    }
+
+    #[tauri::command]
+    pub async fn stop_telemetry() -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Stand-ins for the bridge commands when built without the `ws-bridge` feature.
+#[cfg(not(feature = "ws-bridge"))]
+mod bridge {
+    #[tauri::command]
+    pub async fn start_ws_bridge(_address: String, _token: String) -> Result<(), String> {
+        Err("Built without WebSocket bridge support".to_string())
+    }
+
+    #[tauri::command]
+    pub async fn stop_ws_bridge() -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Maximum number of poses kept on the undo stack.
+const UNDO_DEPTH: usize = 20;
+
+/// Number of watchdog incidents kept for debug reports and autosaves.
+const WATCHDOG_INCIDENT_CAPACITY: usize = 20;
+
+/// Event emitted periodically while calibrating, with the seconds elapsed so far.
+const CALIBRATION_PROGRESS_EVENT: &str = "cobot://calibration-progress";
+
+/// Interval between calibration progress events.
+const CALIBRATION_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Event emitted as each joint of a sequential calibration finishes.
+const CALIBRATION_JOINT_EVENT: &str = "cobot://calibration-joint";
+
+/// Event emitted as each step of `reset_and_reinit` starts.
+const REINIT_STEP_EVENT: &str = "cobot://reinit-step";
+
+/// Time the firmware is given to restart after a reset, before it's initialized again.
+const RESET_RESTART_DELAY: Duration = Duration::from_millis(500);
+
+/// Oldest joint reading reused for undo capture and forward kinematics, so consumers reading the
+/// joints back to back share one query.
+const JOINT_CACHE_MAX_AGE: Duration = Duration::from_millis(50);
+
+/// Smallest change in a joint's angle between waypoints of a path that is sent, in degrees. Angles
+/// are sent in thousandths of a degree, so smaller changes couldn't move the joint anyway.
+const PATH_ANGLE_RESOLUTION_DEG: f32 = 0.001;
+
+/// Event emitted as each chunk of a firmware image is flashed.
+const FLASH_PROGRESS_EVENT: &str = "cobot://flash-progress";
+
+/// Time the bootloader takes to start after the firmware resets into it.
+const BOOTLOADER_STARTUP: Duration = Duration::from_millis(500);
+
+/// Interval between attempts to reconnect after flashing, while the new firmware boots.
+const FLASH_RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Number of attempts to reconnect after flashing.
+const FLASH_RECONNECT_ATTEMPTS: u32 = 10;
+
+/// Event emitted whenever the COBOT is connected, disconnected or lost.
+const CONNECTION_EVENT: &str = "cobot://connection";
+
+/// Number of connection events buffered before the oldest are dropped.
+const CONNECTION_EVENT_CAPACITY: usize = 16;
+
+/// Event emitted with every protocol event of the connection, once the frontend has subscribed.
+const COMMS_EVENT: &str = "cobot://comms";
+
+/// Baud rates tried by `probe_baud_rates` when none are given, and by `connect` with a baud rate
+/// of 0.
+const DEFAULT_PROBE_BAUD_RATES: [u32; 6] = [9600, 57600, 115200, 230400, 250000, 1000000];
+
+/// Event emitted when an idle COBOT is disconnected to release its port.
+const AUTO_DISCONNECTED_EVENT: &str = "cobot://auto-disconnected";
+
+/// Interval between checks of whether the COBOT has been idle long enough to disconnect.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Event emitted after each attempt of `connect_with_retry`.
+const CONNECT_ATTEMPT_EVENT: &str = "cobot://connect-attempt";
+
+/// Event emitted when `init` is rejected because the firmware expects another version.
+const FIRMWARE_VERSION_MISMATCH_EVENT: &str = "cobot://firmware-version-mismatch";
+
+/// Event emitted when a joint is moved to within its warning margin of a soft limit.
+const NEAR_LIMIT_EVENT: &str = "cobot://near-limit";
+
+/// Fastest speed allowed when driving a joint to its soft limit, in degrees per second.
+const LIMIT_TEST_MAX_SPEED: f32 = 20.0;
+
+/// Range of allowed calibration timeouts, in milliseconds.
+const CALIBRATION_TIMEOUT_RANGE_MS: std::ops::RangeInclusive<u64> = 10_000..=300_000;
+
+/// Range of allowed response timeouts, in milliseconds.
+const RESPONSE_TIMEOUT_RANGE_MS: std::ops::RangeInclusive<u64> = 10..=10_000;
+
+/// Event emitted with every fault the COBOT reports.
+const FAULT_EVENT: &str = "cobot://fault";
+
+/// Event emitted with the joint when a joint is suspected to have stalled during a move.
+const STALL_SUSPECTED_EVENT: &str = "cobot://stall-suspected";
+
+/// Event emitted when the watchdog stops the COBOT.
+const WATCHDOG_TRIGGERED_EVENT: &str = "cobot://watchdog-triggered";
+
+/// Event emitted with the state of the test plan executor whenever it changes.
+const EXECUTION_STATE_EVENT: &str = "cobot://execution-state";
+
+/// Interval between link quality events.
+const LINK_QUALITY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Event emitted with the joint's state after each poll of `move_speed_monitored`.
+const MONITORED_JOINT_EVENT: &str = "cobot://monitored-joint";
+
+/// Event emitted periodically with the quality of the link to the COBOT while connected.
+const LINK_QUALITY_EVENT: &str = "cobot://link-quality";
+
+/// Number of joint samples buffered for each observer before the oldest are dropped.
+const JOINT_SAMPLE_CAPACITY: usize = 64;
+
+/// Joint states read from the COBOT, in the display frame.
+#[derive(Clone, Debug, Serialize)]
+struct JointSample {
+    /// Time the sample was read, in milliseconds since the Unix epoch.
+    timestamp: u64,
+
+    /// Units of the angles and speeds.
+    units: AngleUnits,
+
+    /// Angle of each joint.
+    angles: Vec<f32>,
+
+    /// Speed of each joint, per second.
+    speeds: Vec<f32>,
+
+    /// Motor current of each joint, in mA, if the firmware reports it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currents_ma: Option<Vec<i16>>,
+
+    /// Pose of the end effector, if the kinematics are configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pose: Option<Pose>,
+}
+
+/// Status of the COBOT read in a single round trip, with the joints in the display frame.
+#[derive(Clone, Debug, Serialize)]
+struct StatusSample {
+    /// Joint states.
+    joints: JointSample,
+
+    /// Error flags of the controller, or `None` if the firmware can't report them.
+    error_flags: Option<u8>,
+
+    /// Joints with feedback enabled, or `None` if unknown.
+    feedback_mask: Option<JointMask>,
+}
+
+/// Times the COBOT was last known to be responsive, in milliseconds since the Unix epoch.
+#[derive(Clone, Debug, Serialize)]
+struct LivenessInfo {
+    /// Time of the last request the COBOT acknowledged, if any.
+    last_command_ms: Option<u64>,
+
+    /// Time of the last successful read of the joints, if any.
+    last_joints_ms: Option<u64>,
+}
+
+/// Warning that a joint is being moved close to one of its soft limits.
+#[derive(Clone, Debug, Serialize)]
+struct NearLimitWarning {
+    /// Joint being moved.
+    joint: u8,
+
+    /// Angle the joint is moving to, in the display frame and the active units.
+    angle: f32,
+
+    /// Soft limit the angle is close to, in the display frame and the active units.
+    limit: f32,
+}
+
+/// Rejection of `init` because of the firmware version, emitted as
+/// `cobot://firmware-version-mismatch`.
+#[derive(Clone, Debug, Serialize)]
+struct FirmwareVersionMismatch {
+    /// Firmware version this host is written against.
+    expected: u32,
+
+    /// Message the COBOT rejected the version with.
+    message: String,
+}
+
+/// State of a joint during `move_speed_monitored`, emitted as `cobot://monitored-joint`.
+#[derive(Clone, Debug, Serialize)]
+struct MonitoredJoint {
+    /// Joint being moved.
+    joint: u8,
+
+    /// Angle of the joint, in the display frame and the active units.
+    angle: f32,
+
+    /// Speed of the joint, in the active units per second.
+    speed: f32,
+}
+
+/// Change in the connection to the COBOT, emitted as `cobot://connection`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind")]
+enum ConnectionEvent {
+    /// A COBOT was connected.
+    Connected {
+        /// Name of the serial port.
+        port: String,
+
+        /// Baud rate of the serial port.
+        baud: u32,
+    },
+
+    /// The COBOT was disconnected on request, or by the app shutting down.
+    Disconnected,
+
+    /// The serial link failed, such as when the cable is pulled, and the COBOT was disconnected.
+    Lost {
+        /// What failed.
+        reason: String,
+    },
+
+    /// The startup sequence failed. The COBOT is still connected, but needs the operator's
+    /// attention before it's used.
+    Faulted {
+        /// Why the sequence failed.
+        reason: String,
+    },
+}
+
+/// COBOT disconnected for being idle, emitted as `cobot://auto-disconnected` and kept so it can be
+/// reconnected with `reconnect_after_idle`.
+#[derive(Clone, Debug, Serialize)]
+struct AutoDisconnected {
+    /// Name of the serial port released, if known.
+    port_name: Option<String>,
+
+    /// Baud rate the COBOT was connected at, if known.
+    baud_rate: Option<u32>,
+
+    /// Time without a command from the operator before disconnecting, in seconds.
+    idle_s: u64,
+}
+
+/// Step of `reset_and_reinit`, emitted as `cobot://reinit-step` as it starts.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ReinitStep {
+    Reset,
+    Init,
+    Calibrate,
+}
+
+/// Payload of `cobot://reinit-step`.
+#[derive(Clone, Copy, Debug, Serialize)]
+struct ReinitProgress {
+    /// Step starting.
+    step: ReinitStep,
+}
+
+/// Outcome of an attempt to connect, emitted by `connect_with_retry`.
+#[derive(Clone, Debug, Serialize)]
+struct ConnectAttempt {
+    /// Number of the attempt, starting at 1.
+    attempt: u32,
+
+    /// Number of attempts that will be made at most.
+    max_attempts: u32,
+
+    /// Why the attempt failed, or `None` if it succeeded.
+    error: Option<String>,
+}
+
+/// Details of the serial port the COBOT is connected over.
+#[derive(Clone, Debug, Serialize)]
+struct PortInfo {
+    /// Name of the serial port.
+    port_name: String,
+
+    /// Baud rate of the serial port.
+    baud_rate: u32,
+
+    /// Time to wait for a response other than a DONE, in milliseconds.
+    timeout_ms: u64,
+
+    /// Number of bytes written to the port since connecting or last resetting the traffic
+    /// counters.
+    bytes_written: u64,
+
+    /// Number of bytes read from the port since connecting or last resetting the traffic counters.
+    bytes_read: u64,
+}
+
+/// Outcome of trying a single baud rate in `probe_baud_rates`.
+#[derive(Clone, Debug, Serialize)]
+struct BaudRateAttempt {
+    /// Baud rate tried.
+    baud_rate: u32,
+
+    /// Why no COBOT answered at this rate, or `None` if one did.
+    error: Option<String>,
+}
+
+/// Outcome of `probe_baud_rates`.
+#[derive(Clone, Debug, Serialize)]
+struct BaudRateProbe {
+    /// First baud rate a COBOT answered at, or `None` if it didn't answer at any.
+    baud_rate: Option<u32>,
+
+    /// Each rate tried, in order, up to and including the one that was found.
+    attempts: Vec<BaudRateAttempt>,
+}
+
+/// Progress of a firmware flash, emitted as each chunk is written.
+#[derive(Clone, Debug, Serialize)]
+struct FlashProgress {
+    /// Number of bytes of the image written so far.
+    written: usize,
+
+    /// Size of the image, in bytes.
+    total: usize,
+}
+
+/// Progress of a sequential calibration, emitted as each joint finishes.
+#[derive(Clone, Debug, Serialize)]
+struct CalibrationJointProgress {
+    /// Joint that finished.
+    joint: u8,
+
+    /// Position of the joint in the calibration order, from 0.
+    index: usize,
+
+    /// Number of joints being calibrated.
+    total: usize,
+
+    /// Whether the joint calibrated successfully.
+    ok: bool,
+}
+
+/// Joint that failed to calibrate during a sequential calibration.
+#[derive(Clone, Debug, Serialize)]
+struct CalibrationFailure {
+    joint: u8,
+    error: String,
+}
+
+/// Outcome of a sequential calibration.
+#[derive(Clone, Debug, Default, Serialize)]
+struct CalibrationResult {
+    /// Joints that calibrated successfully, in order.
+    calibrated: Vec<u8>,
+
+    /// Joints that failed to calibrate, in order.
+    failed: Vec<CalibrationFailure>,
+
+    /// Joints that weren't attempted because the calibration was aborted.
+    skipped: Vec<u8>,
+}
+
+/// Setup state of the cobot, so the UI can show which steps are done.
+#[derive(Clone, Debug, Serialize)]
+struct CobotState {
+    /// Whether the cobot is connected.
+    connected: bool,
+
+    /// Whether the cobot has been initialized since it was connected or last reset.
+    initialized: bool,
+
+    /// Whether every joint has been calibrated since the cobot was last initialized.
+    calibrated: bool,
+
+    /// Joints calibrated since the cobot was last initialized.
+    calibrated_joints: JointMask,
+}
+
+/// Timeouts used when waiting for the COBOT, in milliseconds.
+#[derive(Clone, Debug, Serialize)]
+struct Timeouts {
+    /// Time to wait for a response other than a DONE, such as an ACK.
+    response_ms: u64,
+
+    /// Time to wait for a DONE response, which bounds calibration and other long operations.
+    calibration_ms: u64,
+
+    /// Time to wait for each joint during a sequential calibration.
+    joint_calibration_ms: u64,
+}
+
+/// Error returned by the Tauri commands. Serialized as its message so the frontend receives a
+/// plain string.
+#[derive(Debug)]
+enum AppError {
+    /// No COBOT is connected.
+    NotConnected,
+
+    /// A motion command was sent while motion isn't enabled.
+    MotionDisabled,
+
+    /// A command was sent after the COBOT was reset into its bootloader.
+    InBootloader,
+
+    /// Any other failure, described for the user.
+    Other(String),
+}
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::NotConnected => write!(f, "Not connected"),
+            AppError::MotionDisabled => write!(f, "Motion disabled"),
+            AppError::InBootloader => {
+                write!(f, "COBOT is in its bootloader, disconnect before flashing")
+            }
+            AppError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+impl std::error::Error for AppError {}
+impl Serialize for AppError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Other(message)
+    }
+}
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Other(message.to_string())
+    }
+}
+impl From<Box<dyn Error>> for AppError {
+    fn from(error: Box<dyn Error>) -> Self {
+        AppError::Other(error.to_string())
+    }
+}
 
 struct AppState {
     cobot: Mutex<Option<Box<CobotConnection>>>,
+
+    /// Joint angles captured before each motion command, most recent last.
+    undo_stack: std::sync::Mutex<VecDeque<Vec<f32>>>,
+
+    /// Host-side settings.
+    settings: Mutex<Settings>,
+
+    /// File the settings are saved to, if the app config directory is known.
+    settings_path: Option<PathBuf>,
+
+    /// Path of the file the session is autosaved to, if there is an app data directory.
+    recovery_path: Option<PathBuf>,
+
+    /// Whether the session is still autosaved, until a clean shutdown. Held while a save is
+    /// written, so shutting down waits for the save in flight.
+    autosave_enabled: std::sync::Mutex<bool>,
+
+    /// Session left by a crash, found at startup, until it's restored or discarded.
+    recovered_session: std::sync::Mutex<Option<serde_json::Value>>,
+
+    /// Session restored from a crash, kept in debug reports and autosaves.
+    restored_session: std::sync::Mutex<Option<serde_json::Value>>,
+
+    /// Every set of joint states read from the COBOT is published here for observers such as
+    /// telemetry.
+    joint_samples: broadcast::Sender<JointSample>,
+
+    /// Joint states last read by `get_angles` and when they were read, so they can be reused
+    /// without waiting for the connection.
+    cached_joint_states: std::sync::Mutex<Option<(Vec<JointState>, Instant)>>,
+
+    /// Joints moving under `move_joint_continuous` that haven't been told to stop.
+    jogging: std::sync::Mutex<JointMask>,
+
+    /// Joints held at their angles by `hold_position` until released or stopped.
+    holding: std::sync::Mutex<JointMask>,
+
+    /// Motions other than jogging that are in progress, supervised by the watchdog.
+    active_motions: ActiveMotions,
+
+    /// Time of the last heartbeat from the frontend.
+    last_heartbeat: std::sync::Mutex<Instant>,
+
+    /// Times the watchdog stopped the COBOT, oldest first, included in debug reports.
+    watchdog_incidents: std::sync::Mutex<VecDeque<WatchdogIncident>>,
+
+    /// Time since the connection was last used on the operator's behalf. Background polling
+    /// doesn't count.
+    idle: IdleTimer,
+
+    /// COBOT last disconnected for being idle, until it's reconnected.
+    auto_disconnected: std::sync::Mutex<Option<AutoDisconnected>>,
+
+    /// Time until which motion commands are accepted, if motion is enabled.
+    motion_enabled_until: std::sync::Mutex<Option<Instant>>,
+
+    /// Joints whose servos have been disabled for free-drive. Kept across reconnects and restored
+    /// on init.
+    servos_disabled: std::sync::Mutex<JointMask>,
+
+    /// Set to abort a sequential calibration.
+    calibration_abort: AtomicBool,
+
+    /// Releases whoever is waiting on the COBOT, so stops and disconnects aren't stuck behind a
+    /// long move.
+    cancel: CancelHandle,
+
+    /// Latest target of each joint that hasn't been sent yet. A target queued for a joint that
+    /// already has one replaces it, so bursts of moves from a slider don't back up behind the
+    /// connection.
+    pending_targets: TargetQueue,
+
+    /// Set once the COBOT has been reset into its bootloader, until it's disconnected or flashed.
+    in_bootloader: AtomicBool,
+
+    /// Task relaying the protocol events of the connection to the frontend, if subscribed.
+    comms_relay: std::sync::Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+
+    /// Latest backlash measurement of each joint, included in debug reports.
+    backlash_reports: std::sync::Mutex<HashMap<u8, BacklashReport>>,
+
+    /// Latest speed accuracy test of each joint, included in debug reports.
+    speed_test_reports: std::sync::Mutex<HashMap<u8, SpeedTestReport>>,
+
+    /// State of the test plan executor, paused and resumed by the operator.
+    execution: Execution,
+
+    /// Report of the last test plan run, included in debug reports.
+    last_test_plan: std::sync::Mutex<Option<TestPlanReport>>,
+
+    /// Changes in the connection, passed on to the frontend by `forward_connection_events`.
+    connection_events: broadcast::Sender<ConnectionEvent>,
+
+    /// Hands the connection to `Emergency` and `Control` commands ahead of `Normal` ones.
+    priority_gate: PriorityGate,
+}
+
+impl AppState {
+    /// Create the state of an app with no COBOT connected.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Settings loaded at startup.
+    /// * `settings_path` - File the settings are saved to, if the app config directory is known.
+    /// * `recovery_path` - File the session is autosaved to, if there is an app data directory.
+    /// * `recovered_session` - Session left by a crash, if one was found.
+    /// * `execution` - Test plan executor, idle.
+    fn new(
+        settings: Settings,
+        settings_path: Option<PathBuf>,
+        recovery_path: Option<PathBuf>,
+        recovered_session: Option<serde_json::Value>,
+        execution: Execution,
+    ) -> AppState {
+        AppState {
+            cobot: Mutex::new(None),
+            undo_stack: std::sync::Mutex::new(VecDeque::new()),
+            settings: Mutex::new(settings),
+            settings_path,
+            recovery_path,
+            autosave_enabled: std::sync::Mutex::new(true),
+            recovered_session: std::sync::Mutex::new(recovered_session),
+            restored_session: std::sync::Mutex::new(None),
+            joint_samples: broadcast::channel(JOINT_SAMPLE_CAPACITY).0,
+            cached_joint_states: std::sync::Mutex::new(None),
+            jogging: std::sync::Mutex::new(JointMask::default()),
+            holding: std::sync::Mutex::new(JointMask::default()),
+            active_motions: ActiveMotions::default(),
+            last_heartbeat: std::sync::Mutex::new(Instant::now()),
+            watchdog_incidents: std::sync::Mutex::new(VecDeque::new()),
+            idle: IdleTimer::default(),
+            auto_disconnected: std::sync::Mutex::new(None),
+            motion_enabled_until: std::sync::Mutex::new(None),
+            servos_disabled: std::sync::Mutex::new(JointMask::default()),
+            calibration_abort: AtomicBool::new(false),
+            cancel: CancelHandle::default(),
+            pending_targets: TargetQueue::new(),
+            in_bootloader: AtomicBool::new(false),
+            comms_relay: std::sync::Mutex::new(None),
+            connection_events: broadcast::channel(CONNECTION_EVENT_CAPACITY).0,
+            last_test_plan: std::sync::Mutex::new(None),
+            execution,
+            backlash_reports: std::sync::Mutex::new(HashMap::new()),
+            speed_test_reports: std::sync::Mutex::new(HashMap::new()),
+            priority_gate: PriorityGate::default(),
+        }
+    }
+
+    /// Run a function with exclusive access to the connected COBOT.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Function to run with the connection.
+    ///
+    /// # Returns
+    ///
+    /// The result of `f`, `AppError::NotConnected` if no COBOT is connected, or
+    /// `AppError::InBootloader` if the COBOT has been reset into its bootloader.
+    async fn with_cobot<F, T, E>(&self, f: F) -> Result<T, AppError>
+    where
+        F: FnOnce(&mut CobotConnection) -> Result<T, E>,
+        E: Into<AppError>,
+    {
+        self.with_cobot_priority(Priority::Normal, f).await
+    }
+
+    /// Run a function with exclusive access to the connected COBOT, ahead of any command of a
+    /// lower priority class waiting for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `priority` - Priority class of the command.
+    /// * `f` - Function to run with the connection.
+    ///
+    /// # Returns
+    ///
+    /// As `with_cobot`. An `Emergency` command also makes whoever holds the connection fail with
+    /// `CommsError::Cancelled` if it's waiting on the COBOT.
+    async fn with_cobot_priority<F, T, E>(&self, priority: Priority, f: F) -> Result<T, AppError>
+    where
+        F: FnOnce(&mut CobotConnection) -> Result<T, E>,
+        E: Into<AppError>,
+    {
+        self.run_with_cobot(priority, true, f).await
+    }
+
+    /// Run a function with exclusive access to the connected COBOT on behalf of background
+    /// polling, which doesn't keep an idle COBOT connected.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Function to run with the connection.
+    ///
+    /// # Returns
+    ///
+    /// As `with_cobot`.
+    async fn with_cobot_background<F, T, E>(&self, f: F) -> Result<T, AppError>
+    where
+        F: FnOnce(&mut CobotConnection) -> Result<T, E>,
+        E: Into<AppError>,
+    {
+        self.run_with_cobot(Priority::Normal, false, f).await
+    }
+
+    /// Run a function with exclusive access to the connected COBOT, as `with_cobot_priority`.
+    /// `activity` says whether the command counts as the operator using the COBOT.
+    async fn run_with_cobot<F, T, E>(
+        &self,
+        priority: Priority,
+        activity: bool,
+        f: F,
+    ) -> Result<T, AppError>
+    where
+        F: FnOnce(&mut CobotConnection) -> Result<T, E>,
+        E: Into<AppError>,
+    {
+        let mut guard = self
+            .priority_gate
+            .lock(&self.cobot, priority, &self.cancel)
+            .await;
+        let cobot = guard.as_mut().ok_or(AppError::NotConnected)?;
+        if self.in_bootloader.load(Ordering::Relaxed) {
+            return Err(AppError::InBootloader);
+        }
+        // Any cancellation was meant for whoever held the connection before.
+        self.cancel.reset();
+        if activity {
+            self.idle.touch();
+        }
+        let result = f(cobot).map_err(Into::into);
+
+        // The port is gone, or a frame may have been left half-written, so nothing more can be
+        // sent on this port.
+        if let Some(reason) = cobot.link_lost() {
+            warn!(
+                "Lost the serial link to the COBOT, disconnecting: {}",
+                reason
+            );
+            let _ = self.connection_events.send(ConnectionEvent::Lost {
+                reason: reason.to_string(),
+            });
+            *guard = None;
+            *self.cached_joint_states.lock().unwrap() = None;
+            self.pending_targets.clear();
+            *self.jogging.lock().unwrap() = JointMask::default();
+            *self.holding.lock().unwrap() = JointMask::default();
+        }
+        result
+    }
+
+    /// Save the current settings to disk.
+    async fn save_settings(&self) -> Result<(), AppError> {
+        let Some(path) = &self.settings_path else {
+            return Err("No settings directory available".into());
+        };
+        self.settings
+            .lock()
+            .await
+            .save(path)
+            .map_err(|e| format!("Failed to save settings: {}", e).into())
+    }
+
+    /// Check that motion is enabled and that none of the given joints has its servo disabled.
+    /// Every command that moves the arm calls this first; stopping never does.
+    ///
+    /// # Arguments
+    ///
+    /// * `joints` - Joints the command moves.
+    fn check_motion_enabled(&self, joints: JointMask) -> Result<(), AppError> {
+        match *self.motion_enabled_until.lock().unwrap() {
+            Some(until) if Instant::now() < until => {}
+            _ => return Err(AppError::MotionDisabled),
+        }
+
+        let released = joints & *self.servos_disabled.lock().unwrap();
+        if !released.is_empty() {
+            return Err(format!("Servos of joints {} are disabled", released).into());
+        }
+
+        Ok(())
+    }
+
+    /// Count a motion as in progress until the returned guard is dropped, so the watchdog stops it
+    /// if the frontend stops sending heartbeats. The command starting the motion counts as a
+    /// heartbeat.
+    fn start_motion(&self) -> MotionGuard<'_> {
+        *self.last_heartbeat.lock().unwrap() = Instant::now();
+        self.active_motions.start()
+    }
+
+    /// Push a pose onto the undo stack, discarding the oldest pose if the stack is full.
+    fn push_undo(&self, pose: Vec<f32>) {
+        let mut undo_stack = self.undo_stack.lock().unwrap();
+        if undo_stack.len() >= UNDO_DEPTH {
+            undo_stack.pop_front();
+        }
+        undo_stack.push_back(pose);
+    }
+
+    /// Stop every joint and disconnect from the COBOT, if connected. Used when the app can no
+    /// longer supervise the arm.
+    async fn stop_and_disconnect(&self) {
+        *self.jogging.lock().unwrap() = JointMask::default();
+        *self.holding.lock().unwrap() = JointMask::default();
+        self.pending_targets.clear();
+        self.cancel.cancel();
+        if let Some(mut cobot) = self.cobot.lock().await.take() {
+            self.cancel.reset();
+            let _ = self.connection_events.send(ConnectionEvent::Disconnected);
+            // The bootloader can't move the arm, and doesn't understand STOP.
+            if self.in_bootloader.swap(false, Ordering::Relaxed) {
+                return;
+            }
+            let all_joints = cobot.all_joints();
+            if let Err(e) = cobot.stop(all_joints, false) {
+                error!("Failed to stop the COBOT: {}", e);
+            }
+        }
+    }
+}
+
+/// Emit every change in the connection to the frontend, for as long as the app runs.
+async fn forward_connection_events(app: AppHandle) {
+    let mut events = app.state::<AppState>().connection_events.subscribe();
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let _ = app.emit_all(CONNECTION_EVENT, event);
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Emit the protocol events of a connection to the frontend until the connection is dropped.
+async fn relay_comms_events(app: AppHandle, mut events: broadcast::Receiver<CommsEvent>) {
+    let mut disconnected = false;
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                disconnected = matches!(event, CommsEvent::Disconnected);
+                let _ = app.emit_all(COMMS_EVENT, event);
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => {
+                // A connection dropped by `disconnect` closes the channel without losing the link.
+                if !disconnected {
+                    let _ = app.emit_all(COMMS_EVENT, CommsEvent::Disconnected);
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Stop all joints whenever motion is in progress and the frontend has stopped sending
+/// heartbeats, aborting any test plan, and record the incident.
+async fn watchdog(app: AppHandle) {
+    loop {
+        tokio::time::sleep(watchdog::POLL_INTERVAL).await;
+
+        let state = app.state::<AppState>();
+        let timeout = state
+            .settings
+            .lock()
+            .await
+            .watchdog_timeout_ms
+            .unwrap_or(settings::DEFAULT_WATCHDOG_TIMEOUT_MS);
+        let jogging = *state.jogging.lock().unwrap();
+        let active_motions = state.active_motions.count();
+        let since_heartbeat = state.last_heartbeat.lock().unwrap().elapsed();
+        if !watchdog::heartbeat_missed(
+            jogging,
+            active_motions,
+            since_heartbeat,
+            Duration::from_millis(timeout),
+        ) {
+            continue;
+        }
+
+        warn!(
+            "No heartbeat for {} ms while joints {} were jogging and {} other motions were in \
+             progress, stopping all joints",
+            since_heartbeat.as_millis(),
+            jogging,
+            active_motions
+        );
+        *state.jogging.lock().unwrap() = JointMask::default();
+        state.pending_targets.clear();
+        // A test plan would otherwise carry on with its next step once the current one is
+        // cancelled.
+        let _ = state.execution.abort();
+        // The emergency class cancels the move being waited on, which would otherwise hold the
+        // connection until it finished.
+        let result = state
+            .with_cobot_priority(Priority::Emergency, watchdog::stop_all)
+            .await;
+        if let Err(e) = &result {
+            error!("Watchdog failed to stop the COBOT: {}", e);
+        }
+
+        let incident = WatchdogIncident {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64),
+            since_heartbeat_ms: since_heartbeat.as_millis() as u64,
+            jogging,
+            active_motions,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        let mut incidents = state.watchdog_incidents.lock().unwrap();
+        if incidents.len() >= WATCHDOG_INCIDENT_CAPACITY {
+            incidents.pop_front();
+        }
+        incidents.push_back(incident);
+        drop(incidents);
+
+        // Give the cancelled motions a full timeout to wind down before firing again.
+        *state.last_heartbeat.lock().unwrap() = Instant::now();
+        let _ = app.emit_all(WATCHDOG_TRIGGERED_EVENT, result.is_ok());
+    }
+}
+
+/// Disconnect the COBOT once it has gone `idle_disconnect_s` without a command from the operator,
+/// so another machine can use its port, for as long as the app runs. Does nothing while
+/// `keep_alive` is set.
+async fn idle_monitor(app: AppHandle) {
+    loop {
+        tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+
+        let state = app.state::<AppState>();
+        let Some(idle_s) = state.settings.lock().await.idle_disconnect_s else {
+            continue;
+        };
+        let Some(idle) = state.idle.expired(Duration::from_secs(idle_s)) else {
+            continue;
+        };
+
+        // Streaming is stopped first, so the COBOT isn't left sending to a released port.
+        let Ok((port_name, baud_rate)) = state
+            .with_cobot_background(|cobot| {
+                if let Err(e) = cobot.stop_streaming() {
+                    warn!("Failed to stop streaming before disconnecting: {}", e);
+                }
+                Ok::<_, String>((cobot.port_name(), cobot.baud_rate().ok()))
+            })
+            .await
+        else {
+            continue;
+        };
+        warn!(
+            "No commands for {} s, disconnecting the COBOT",
+            idle.as_secs()
+        );
+        state.stop_and_disconnect().await;
+
+        let disconnected = AutoDisconnected {
+            port_name,
+            baud_rate,
+            idle_s,
+        };
+        *state.auto_disconnected.lock().unwrap() = Some(disconnected.clone());
+        let _ = app.emit_all(AUTO_DISCONNECTED_EVENT, disconnected);
+    }
+}
+
+/// Periodically grade the link to the COBOT and emit the result. A grade is skipped while the
+/// connection is busy with a long operation such as a move.
+async fn link_quality_monitor(app: AppHandle) {
+    loop {
+        tokio::time::sleep(LINK_QUALITY_INTERVAL).await;
+
+        let state = app.state::<AppState>();
+        let thresholds = state
+            .settings
+            .lock()
+            .await
+            .link_quality_thresholds
+            .unwrap_or_default();
+        let Ok(mut cobot) = state.cobot.try_lock() else {
+            continue;
+        };
+        if let Some(cobot) = cobot.as_mut() {
+            let _ = app.emit_all(LINK_QUALITY_EVENT, cobot.link_quality(&thresholds));
+        }
+    }
+}
+
+/// Move the given joints, recording the pose from before the move on the undo stack. The pose is
+/// only recorded once the COBOT has acknowledged the move, so rejected moves can't be undone.
+fn move_with_undo(
+    state: &AppState,
+    cobot: &mut CobotConnection,
+    joints: &[(u8, f32, Option<f32>)],
+) -> Result<(), Box<dyn Error>> {
+    let _motion = state.start_motion();
+    let pose = cobot
+        .get_joints_cached(JOINT_CACHE_MAX_AGE)?
+        .into_iter()
+        .map(|joint| joint.0)
+        .collect();
+    let command_id = cobot.start_move_to(joints)?;
+    state.push_undo(pose);
+    cobot.wait_for_done(command_id)
+}
+
+/// Move through each waypoint of a path in turn, recording the pose from before the path on the
+/// undo stack. After the first waypoint, only the joints that moved since they were last sent
+/// are sent.
+///
+/// # Arguments
+///
+/// * `path` - Joints to move at each waypoint, with their angles and speeds.
+fn follow_waypoints(
+    state: &AppState,
+    cobot: &mut CobotConnection,
+    path: &[Vec<(u8, f32, Option<f32>)>],
+) -> Result<(), Box<dyn Error>> {
+    let Some((first, rest)) = path.split_first() else {
+        return Ok(());
+    };
+    move_with_undo(state, cobot, first)?;
+    let mut sent = first.clone();
+    for waypoint in rest {
+        cobot.move_to_delta(&mut sent, waypoint, PATH_ANGLE_RESOLUTION_DEG)?;
+    }
+
+    Ok(())
+}
+
+/// Emit `cobot://near-limit` if an angle is within the joint's warning margin of one of its soft
+/// limits. The move goes ahead either way.
+///
+/// # Arguments
+///
+/// * `angle` - Angle the joint is moving to, in the display frame and degrees.
+fn warn_near_limit(app: &AppHandle, settings: &Settings, joint: u8, angle: f32) {
+    let (Some(limits), Some(margin)) = (
+        settings.soft_limits(joint),
+        settings.limit_warning_margin(joint),
+    ) else {
+        return;
+    };
+    let limit = if angle >= limits.max - margin {
+        limits.max
+    } else if angle <= limits.min + margin {
+        limits.min
+    } else {
+        return;
+    };
+
+    let _ = app.emit_all(
+        NEAR_LIMIT_EVENT,
+        NearLimitWarning {
+            joint,
+            angle: settings.degrees_to_units(angle),
+            limit: settings.degrees_to_units(limit),
+        },
+    );
+}
+
+/// Send the queued targets of a joint until none is left, then wait for the last move to finish.
+/// Jogging joints are tracked for the watchdog.
+///
+/// # Arguments
+///
+/// * `record_undo` - Whether to record the pose from before the first move to a position for
+///   undo.
+fn send_queued_targets(
+    state: &AppState,
+    cobot: &mut CobotConnection,
+    joint: u8,
+    record_undo: bool,
+) -> Result<(), Box<dyn Error>> {
+    let _motion = state.start_motion();
+    let mut pose = if record_undo {
+        Some(
+            cobot
+                .get_joints_cached(JOINT_CACHE_MAX_AGE)?
+                .into_iter()
+                .map(|joint| joint.0)
+                .collect(),
+        )
+    } else {
+        None
+    };
+    cobot.send_queued_targets(&state.pending_targets, joint, |target| match target {
+        Target::Position(..) => {
+            if let Some(pose) = pose.take() {
+                state.push_undo(pose);
+            }
+        }
+        Target::Speed(speed) => {
+            // Give the frontend a full heartbeat window from the start of the motion.
+            *state.last_heartbeat.lock().unwrap() = Instant::now();
+            let mut jogging = state.jogging.lock().unwrap();
+            if speed == 0.0 {
+                *jogging = *jogging & !JointMask::joint(joint);
+            } else {
+                *jogging = *jogging | JointMask::joint(joint);
+            }
+        }
+    })
+}
+
+/// Check whether the cobot is connected.
+#[tauri::command]
+async fn is_connected(state: tauri::State<'_, AppState>) -> Result<bool, AppError> {
+    Ok(state.cobot.lock().await.is_some())
+}
+
+/// Connect to the cobot over the given serial port.
+///
+/// Unless `verify` is false, the device on the port must answer a probe before the connection is
+/// kept, so a port with some other device on it is closed again with an error saying whether the
+/// device stayed silent or sent unreadable data. A baud rate of 0 tries each rate in
+/// `DEFAULT_PROBE_BAUD_RATES` and connects at the first one the COBOT answers at.
+///
+/// If `startup` is true, the configured startup sequence is then run as a test plan, emitting
+/// `cobot://test-plan-step` for each step. A failed step stops the sequence and returns an error,
+/// leaving the COBOT connected but faulted.
+#[tauri::command]
+async fn connect(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    port_name: String,
+    baud_rate: u32,
+    verify: Option<bool>,
+    startup: Option<bool>,
+) -> Result<(), AppError> {
+    let mut cobot = state.cobot.lock().await;
+    if cobot.is_some() {
+        return Ok(());
+    }
+
+    let baud_rate = if baud_rate == 0 {
+        let payload_escaping = state.settings.lock().await.payload_escaping;
+        let probe = find_baud_rate(
+            port_name.clone(),
+            DEFAULT_PROBE_BAUD_RATES.to_vec(),
+            payload_escaping,
+        )
+        .await?;
+        probe
+            .baud_rate
+            .ok_or_else(|| format!("No COBOT answered on {} at any baud rate", port_name))?
+    } else {
+        baud_rate
+    };
+
+    let port = serialport::new(&port_name, baud_rate)
+        .timeout(std::time::Duration::from_millis(1000))
+        .open()
+        .map_err(|e| format!("Failed to open port: {}", e))?;
+
+    let settings = state.settings.lock().await;
+    let response_timeout = Duration::from_millis(
+        settings
+            .response_timeout_ms
+            .unwrap_or(settings::DEFAULT_RESPONSE_TIMEOUT_MS),
+    );
+    let mut connection = CobotConnection::new(port, FIRMWARE_VERSION, response_timeout);
+    if let Some(timeout_ms) = settings.calibration_timeout_ms {
+        connection.set_calibration_timeout(Duration::from_millis(timeout_ms));
+    }
+    connection.set_fault_stop_severity(settings.fault_stop_severity);
+    connection.set_rate_limit(settings.rate_limit);
+    connection.set_payload_escaping(settings.payload_escaping);
+    connection.set_stall_detection(settings.stall_detection());
+    if let Some(level) = settings.log_display_level {
+        connection
+            .set_log_display_level(level)
+            .map_err(|e| format!("Failed to set log display level: {}", e))?;
+    }
+    drop(settings);
+    if verify.unwrap_or(true) {
+        // Dropping the connection on failure closes the port.
+        connection
+            .probe()
+            .map_err(|e| format!("Failed to connect: {}", e))?;
+    }
+    let fault_app = app.clone();
+    connection.set_fault_handler(Box::new(move |fault| {
+        let _ = fault_app.emit_all(FAULT_EVENT, fault.clone());
+    }));
+    let stall_app = app.clone();
+    connection.set_stall_handler(Box::new(move |joint| {
+        let _ = stall_app.emit_all(STALL_SUSPECTED_EVENT, joint);
+    }));
+    let (streamed_sender, streamed) = mpsc::unbounded_channel();
+    connection.set_joints_handler(Box::new(move |joint_states| {
+        let _ = streamed_sender.send(joint_states.to_vec());
+    }));
+    tauri::async_runtime::spawn(publish_streamed_joints(app.clone(), streamed));
+    connection.set_cancel_handle(state.cancel.clone());
+    *cobot = Some(Box::new(connection));
+    state.idle.touch();
+    *state.auto_disconnected.lock().unwrap() = None;
+    *state.cached_joint_states.lock().unwrap() = None;
+    let _ = state.connection_events.send(ConnectionEvent::Connected {
+        port: port_name,
+        baud: baud_rate,
+    });
+    drop(cobot);
+
+    if startup.unwrap_or(false) {
+        run_startup_sequence(&app, &state).await?;
+    }
+    Ok(())
+}
+
+/// Run the configured startup sequence, keeping its report for debug reports.
+///
+/// # Returns
+///
+/// Ok if every step passed, or an error naming the first step that failed, once
+/// `ConnectionEvent::Faulted` has been sent.
+async fn run_startup_sequence(app: &AppHandle, state: &AppState) -> Result<(), AppError> {
+    let steps = state.settings.lock().await.startup_sequence.clone();
+    if steps.is_empty() {
+        return Ok(());
+    }
+
+    let report = test_plan::run(app, Some("Startup sequence".into()), steps).await?;
+    *state.last_test_plan.lock().unwrap() = Some(report.clone());
+    if report.passed {
+        return Ok(());
+    }
+
+    let reason = match report.steps.iter().position(|step| !step.passed) {
+        Some(index) => format!(
+            "startup step {} failed: {}",
+            index + 1,
+            report.steps[index]
+                .error
+                .as_deref()
+                .unwrap_or("unknown error")
+        ),
+        None => "startup sequence aborted".to_string(),
+    };
+    let _ = state.connection_events.send(ConnectionEvent::Faulted {
+        reason: reason.clone(),
+    });
+    Err(format!("Connected, but {}", reason).into())
+}
+
+/// Check whether a COBOT answers the connection probe on the port at the given baud rate. The
+/// port is closed again either way.
+fn probe_baud_rate(port_name: &str, baud_rate: u32, payload_escaping: bool) -> Result<(), String> {
+    let port = serialport::new(port_name, baud_rate)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .map_err(|e| format!("Failed to open port: {}", e))?;
+    let mut connection = CobotConnection::new(port, FIRMWARE_VERSION, Duration::from_millis(100));
+    connection.set_payload_escaping(payload_escaping);
+    connection.probe().map_err(|e| e.to_string())
+}
+
+/// Try each baud rate in turn until a COBOT answers the connection probe. Each rate takes at most
+/// the probe's timeout, so the default rates are all tried within a few seconds.
+async fn find_baud_rate(
+    port_name: String,
+    candidates: Vec<u32>,
+    payload_escaping: bool,
+) -> Result<BaudRateProbe, AppError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut attempts = Vec::new();
+        for baud_rate in candidates {
+            let error = probe_baud_rate(&port_name, baud_rate, payload_escaping).err();
+            let found = error.is_none();
+            attempts.push(BaudRateAttempt { baud_rate, error });
+            if found {
+                return BaudRateProbe {
+                    baud_rate: Some(baud_rate),
+                    attempts,
+                };
+            }
+        }
+        BaudRateProbe {
+            baud_rate: None,
+            attempts,
+        }
+    })
+    .await
+    .map_err(|e| AppError::from(format!("Baud rate probe failed: {}", e)))
+}
+
+/// Find the baud rate the COBOT on a port is configured for, by trying each candidate rate until
+/// it answers. Must be called while disconnected, since probing opens the port.
+///
+/// # Arguments
+///
+/// * `port_name` - Serial port to probe.
+/// * `candidates` - Baud rates to try, in order, or `None` for `DEFAULT_PROBE_BAUD_RATES`.
+///
+/// # Returns
+///
+/// The first rate the COBOT answered at, with why each earlier rate failed.
+#[tauri::command]
+async fn probe_baud_rates(
+    state: tauri::State<'_, AppState>,
+    port_name: String,
+    candidates: Option<Vec<u32>>,
+) -> Result<BaudRateProbe, AppError> {
+    let cobot = state.cobot.lock().await;
+    if cobot.is_some() {
+        return Err("Disconnect before probing baud rates".into());
+    }
+    let candidates = candidates.unwrap_or_else(|| DEFAULT_PROBE_BAUD_RATES.to_vec());
+    if candidates.is_empty() {
+        return Err("At least one baud rate is required".into());
+    }
+
+    let payload_escaping = state.settings.lock().await.payload_escaping;
+    find_baud_rate(port_name, candidates, payload_escaping).await
+}
+
+/// Connect to the cobot, retrying if the port can't be opened yet, as happens for a moment after a
+/// USB adapter is plugged in on some systems.
+///
+/// # Arguments
+///
+/// * `max_attempts` - Number of attempts to make before giving up.
+/// * `retry_delay_ms` - Time to wait between attempts, in milliseconds.
+#[tauri::command]
+async fn connect_with_retry(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    port_name: String,
+    baud_rate: u32,
+    max_attempts: u32,
+    retry_delay_ms: u64,
+) -> Result<(), AppError> {
+    if max_attempts == 0 {
+        return Err("At least one attempt is required".into());
+    }
+
+    let mut last_error = AppError::NotConnected;
+    for attempt in 1..=max_attempts {
+        if attempt > 1 {
+            tokio::time::sleep(Duration::from_millis(retry_delay_ms)).await;
+        }
+        let result = connect(
+            app.clone(),
+            state.clone(),
+            port_name.clone(),
+            baud_rate,
+            None,
+            None,
+        )
+        .await;
+        let error = result.as_ref().err().map(ToString::to_string);
+        let _ = app.emit_all(
+            CONNECT_ATTEMPT_EVENT,
+            ConnectAttempt {
+                attempt,
+                max_attempts,
+                error,
+            },
+        );
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(format!(
+        "Failed to connect after {} attempts: {}",
+        max_attempts, last_error
+    )
+    .into())
+}
+
+/// Disconnect from the cobot.
+#[tauri::command]
+async fn disconnect(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    state.cancel.cancel();
+    let mut cobot = state.cobot.lock().await;
+    state.cancel.reset();
+    if cobot.take().is_some() {
+        let _ = state.connection_events.send(ConnectionEvent::Disconnected);
+    }
+    *state.cached_joint_states.lock().unwrap() = None;
+    state.pending_targets.clear();
+    state.in_bootloader.store(false, Ordering::Relaxed);
+    state.undo_stack.lock().unwrap().clear();
+    *state.jogging.lock().unwrap() = JointMask::default();
+    Ok(())
+}
+
+/// Initialize the cobot. Does nothing if it's already initialized, unless `force` is set. If the
+/// firmware rejects this host's version, `cobot://firmware-version-mismatch` is emitted before the
+/// error is returned.
+///
+/// # Returns
+///
+/// Whether the cobot was initialized, or `false` if it already was.
+#[tauri::command]
+async fn init(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    force: Option<bool>,
+) -> Result<bool, AppError> {
+    let servos_disabled = *state.servos_disabled.lock().unwrap();
+    state
+        .with_cobot(|cobot| {
+            if cobot.is_initialized() && !force.unwrap_or(false) {
+                return Ok(false);
+            }
+
+            cobot.init().map_err(|e| {
+                // Error code 7 is "Invalid firmware version", in `cobot_comms::ERROR_CODES`.
+                if let Some(CobotError { code: 7, message }) = e.downcast_ref() {
+                    let _ = app.emit_all(
+                        FIRMWARE_VERSION_MISMATCH_EVENT,
+                        FirmwareVersionMismatch {
+                            expected: FIRMWARE_VERSION,
+                            message: message.clone(),
+                        },
+                    );
+                }
+                format!("Failed to initialize: {}", e)
+            })?;
+            if !servos_disabled.is_empty() {
+                cobot
+                    .set_servo(servos_disabled, false)
+                    .map_err(|e| format!("Failed to restore disabled servos: {}", e))?;
+            }
+            Ok::<_, String>(true)
+        })
+        .await
+}
+
+/// Reset the cobot into its bootloader so new firmware can be flashed. Every other command is
+/// rejected until the cobot is disconnected, which frees the serial port for the flasher.
+#[tauri::command]
+async fn enter_bootloader(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .enter_bootloader()
+                .map_err(|e| format!("Failed to enter the bootloader: {}", e))
+        })
+        .await?;
+    state.in_bootloader.store(true, Ordering::Relaxed);
+    state.undo_stack.lock().unwrap().clear();
+    *state.jogging.lock().unwrap() = JointMask::default();
+
+    Ok(())
+}
+
+/// Flash a firmware image and reconnect once it has booted. The cobot is reset into its
+/// bootloader first, unless `enter_bootloader` has already done so.
+///
+/// # Arguments
+///
+/// * `path` - Path of the firmware binary.
+#[tauri::command]
+async fn flash_firmware(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<(), AppError> {
+    let image = std::fs::read(&path).map_err(|e| format!("Failed to read firmware: {}", e))?;
+    if image.is_empty() {
+        return Err("Firmware image is empty".into());
+    }
+
+    state.cancel.cancel();
+    let mut cobot = state.cobot.lock().await;
+    state.cancel.reset();
+    let Some(connection) = cobot.as_mut() else {
+        return Err(AppError::NotConnected);
+    };
+    let (Some(port_name), Ok(baud_rate)) = (connection.port_name(), connection.baud_rate()) else {
+        return Err("Can't reconnect to this serial port after flashing".into());
+    };
+    if !state.in_bootloader.load(Ordering::Relaxed) {
+        connection
+            .enter_bootloader()
+            .map_err(|e| format!("Failed to enter the bootloader: {}", e))?;
+        state.in_bootloader.store(true, Ordering::Relaxed);
+        tokio::time::sleep(BOOTLOADER_STARTUP).await;
+    }
+
+    let mut port = cobot.take().ok_or(AppError::NotConnected)?.into_port();
+    let _ = state.connection_events.send(ConnectionEvent::Disconnected);
+    state.in_bootloader.store(false, Ordering::Relaxed);
+    state.undo_stack.lock().unwrap().clear();
+    *state.jogging.lock().unwrap() = JointMask::default();
+    let result = flash::flash(port.as_mut(), &image, |written, total| {
+        let _ = app.emit_all(FLASH_PROGRESS_EVENT, FlashProgress { written, total });
+    });
+    drop(port);
+    drop(cobot);
+    result.map_err(|e| format!("Failed to flash firmware: {}", e))?;
+
+    // The new firmware takes a moment to boot, and USB adapters may disappear while it does.
+    let mut last_error = AppError::NotConnected;
+    for _ in 0..FLASH_RECONNECT_ATTEMPTS {
+        tokio::time::sleep(FLASH_RECONNECT_INTERVAL).await;
+        match connect(
+            app.clone(),
+            state.clone(),
+            port_name.clone(),
+            baud_rate,
+            None,
+            None,
+        )
+        .await
+        {
+            Ok(()) => return Ok(()),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(format!("Flashed, but failed to reconnect: {}", last_error).into())
+}
+
+/// Compute the CRC of the given bytes, as used in frame headers, so a hand-crafted frame can be
+/// checked against the host's checksum without a COBOT.
+#[tauri::command]
+async fn compute_crc8(data: Vec<u8>) -> Result<u8, AppError> {
+    Ok(cobot_comms::checksum::crc8ccitt(&data))
+}
+
+/// Check the given bytes against an expected CRC, as the host does for every received frame.
+#[tauri::command]
+async fn check_crc8(data: Vec<u8>, expected: u8) -> Result<bool, AppError> {
+    Ok(cobot_comms::checksum::crc8ccitt_check(&data, expected))
+}
+
+/// Parse a hex dump into bytes. Bytes may be separated by whitespace or commas, and prefixed with
+/// `0x`. Unseparated runs of hex digits are read two digits at a time.
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for token in hex.split(|c: char| c.is_whitespace() || c == ',') {
+        let token = token.trim_start_matches("0x").trim_start_matches("0X");
+        if token.len() % 2 != 0 {
+            return Err(format!("Odd number of hex digits in \"{}\"", token));
+        }
+        for pair in token.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            let byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| format!("Invalid hex \"{}\" at byte {}", pair, bytes.len()))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decode a frame from a hex dump, such as one captured with a logic analyzer, without a COBOT.
+///
+/// # Arguments
+///
+/// * `hex` - Bytes of the frame, from the start byte to the end of the payload.
+/// * `protocol_version` - Framing the frame uses. Defaults to that of the connected cobot, or
+///   version 1 if none is connected.
+///
+/// # Returns
+///
+/// The decoded message, or where decoding failed.
+#[tauri::command]
+async fn decode_frame(
+    state: tauri::State<'_, AppState>,
+    hex: String,
+    protocol_version: Option<u8>,
+) -> Result<DecodedFrame, AppError> {
+    let frame = parse_hex(&hex)?;
+    let protocol_version = match protocol_version {
+        Some(version) => version,
+        None => state
+            .cobot
+            .lock()
+            .await
+            .as_ref()
+            .map_or(cobot_comms::PROTOCOL_V1, |cobot| cobot.protocol_version()),
+    };
+    cobot_comms::decode_frame(&frame, protocol_version, FIRMWARE_VERSION)
+        .map_err(|e| e.to_string().into())
+}
+
+/// Get whether each joint has been calibrated since the cobot was last initialized, reset, or
+/// reported a fault. The firmware can't report this, so it's tracked by the host.
+#[tauri::command]
+async fn get_calibration_status(state: tauri::State<'_, AppState>) -> Result<Vec<bool>, AppError> {
+    state
+        .with_cobot(|cobot| {
+            let joint_count = match cobot.joint_count() {
+                Some(joint_count) => joint_count,
+                None => cobot
+                    .get_joints()
+                    .map_err(|e| format!("Failed to get joint states: {}", e))?
+                    .len() as u8,
+            };
+            let calibrated = cobot.calibrated_joints();
+            Ok::<_, String>(
+                (0..joint_count)
+                    .map(|joint| calibrated.contains(joint))
+                    .collect(),
+            )
+        })
+        .await
+}
+
+/// Get whether the cobot is connected, initialized and calibrated.
+#[tauri::command]
+async fn get_state(state: tauri::State<'_, AppState>) -> Result<CobotState, AppError> {
+    let cobot = state.cobot.lock().await;
+    Ok(match cobot.as_ref() {
+        Some(cobot) => CobotState {
+            connected: true,
+            initialized: cobot.is_initialized(),
+            calibrated: cobot.is_initialized()
+                && (cobot.all_joints() & !cobot.calibrated_joints()).is_empty(),
+            calibrated_joints: cobot.calibrated_joints(),
+        },
+        None => CobotState {
+            connected: false,
+            initialized: false,
+            calibrated: false,
+            calibrated_joints: JointMask::default(),
+        },
+    })
+}
+
+/// Calibrate the cobot.
+#[tauri::command]
+async fn calibrate(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joints: JointMask,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(joints)?;
+    let _motion = state.start_motion();
+
+    // Calibration can take minutes, so report that it's still running.
+    let progress = tauri::async_runtime::spawn(async move {
+        let start_time = Instant::now();
+        loop {
+            tokio::time::sleep(CALIBRATION_PROGRESS_INTERVAL).await;
+            let _ = app.emit_all(CALIBRATION_PROGRESS_EVENT, start_time.elapsed().as_secs());
+        }
+    });
+    let result = state
+        .with_cobot(|cobot| {
+            cobot
+                .calibrate(joints)
+                .map_err(|e| format!("Failed to calibrate: {}", e))
+        })
+        .await;
+    progress.abort();
+    result?;
+    state.undo_stack.lock().unwrap().clear();
+
+    Ok(())
+}
+
+/// Recover from a fault by resetting the COBOT, initializing it again once the firmware has
+/// restarted, and calibrating the given joints, emitting `cobot://reinit-step` as each step
+/// starts. Motion must be enabled for the joints, which is checked before anything is reset.
+///
+/// # Returns
+///
+/// Ok once the joints are calibrated, or the error of the first step that failed, which stops the
+/// sequence.
+#[tauri::command]
+async fn reset_and_reinit(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joints: JointMask,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(joints)?;
+    let _motion = state.start_motion();
+
+    let emit_step = |step| {
+        let _ = app.emit_all(REINIT_STEP_EVENT, ReinitProgress { step });
+    };
+    emit_step(ReinitStep::Reset);
+    state
+        .with_cobot(|cobot| cobot.reset().map_err(|e| format!("Failed to reset: {}", e)))
+        .await?;
+    *state.cached_joint_states.lock().unwrap() = None;
+    tokio::time::sleep(RESET_RESTART_DELAY).await;
+
+    emit_step(ReinitStep::Init);
+    init(app.clone(), state.clone(), None).await?;
+
+    emit_step(ReinitStep::Calibrate);
+    calibrate(app.clone(), state, joints).await
+}
+
+/// Calibrate the given joints one at a time, in order, emitting a progress event as each
+/// finishes. A joint that fails doesn't stop the others from being calibrated.
+///
+/// # Returns
+///
+/// Which joints calibrated, which failed and why, and which were skipped because the calibration
+/// was aborted with `abort_calibration`.
+#[tauri::command]
+async fn calibrate_sequential(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joints: Vec<u8>,
+) -> Result<CalibrationResult, AppError> {
+    let mask = joints.iter().fold(JointMask::default(), |mask, joint| {
+        mask | JointMask::joint(*joint)
+    });
+    state.check_motion_enabled(mask)?;
+    let timeout = Duration::from_millis(
+        state
+            .settings
+            .lock()
+            .await
+            .joint_calibration_timeout_ms
+            .unwrap_or(settings::DEFAULT_JOINT_CALIBRATION_TIMEOUT_MS),
+    );
+    state.calibration_abort.store(false, Ordering::Relaxed);
+
+    let mut result = CalibrationResult::default();
+    for (index, &joint) in joints.iter().enumerate() {
+        if state.calibration_abort.load(Ordering::Relaxed) {
+            result.skipped.extend(&joints[index..]);
+            break;
+        }
+
+        let outcome = state
+            .with_cobot(|cobot| cobot.calibrate_joint(joint, timeout, &state.calibration_abort))
+            .await;
+        let _ = app.emit_all(
+            CALIBRATION_JOINT_EVENT,
+            CalibrationJointProgress {
+                joint,
+                index,
+                total: joints.len(),
+                ok: outcome.is_ok(),
+            },
+        );
+        match outcome {
+            Ok(()) => result.calibrated.push(joint),
+            Err(e) => result.failed.push(CalibrationFailure {
+                joint,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    if !result.calibrated.is_empty() {
+        state.undo_stack.lock().unwrap().clear();
+    }
+    Ok(result)
+}
+
+/// Abort a sequential calibration. The joint being calibrated is stopped, and the remaining
+/// joints are skipped.
+#[tauri::command]
+async fn abort_calibration(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    state.calibration_abort.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Declare the current position of a joint to be 0° in the COBOT's own frame, without moving it.
+/// This discards the joint's calibration, which only recalibrating restores.
+#[tauri::command]
+async fn zero_joint(state: tauri::State<'_, AppState>, joint: u8) -> Result<(), AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .zero_joint(joint)
+                .map_err(|e| format!("Failed to zero joint: {}", e))
+        })
+        .await?;
+    state.undo_stack.lock().unwrap().clear();
+    Ok(())
+}
+
+/// Declare the current position of every joint to be 0° in the COBOT's own frame, without moving
+/// them. This discards the calibration of every joint, which only recalibrating restores.
+#[tauri::command]
+async fn zero_all_joints(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .zero_all_joints()
+                .map_err(|e| format!("Failed to zero joints: {}", e))
+        })
+        .await?;
+    state.undo_stack.lock().unwrap().clear();
+    Ok(())
+}
+
+/// Check the serial link by timing several round trips to the COBOT.
+#[tauri::command]
+async fn loopback_test(state: tauri::State<'_, AppState>) -> Result<LoopbackStats, AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .loopback_test()
+                .map_err(|e| format!("Loopback test failed: {}", e))
+        })
+        .await
+}
+
+/// Convert an instant in the past to milliseconds since the Unix epoch.
+fn instant_to_epoch_ms(instant: Instant) -> u64 {
+    (SystemTime::now() - instant.elapsed())
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Get the angles last read by `get_angles`, in the display frame and the active units, without
+/// querying the cobot.
+///
+/// # Returns
+///
+/// The angles, or `None` if they were read more than `max_age_ms` milliseconds ago or haven't been
+/// read on this connection, in which case `get_angles` should be called instead.
+#[tauri::command]
+async fn get_angles_cached(
+    state: tauri::State<'_, AppState>,
+    max_age_ms: u64,
+) -> Result<Option<Vec<f32>>, AppError> {
+    let joint_states = match &*state.cached_joint_states.lock().unwrap() {
+        Some((joint_states, read_at)) if read_at.elapsed() <= Duration::from_millis(max_age_ms) => {
+            joint_states.clone()
+        }
+        _ => return Ok(None),
+    };
+
+    let settings = state.settings.lock().await;
+    Ok(Some(
+        joint_states
+            .into_iter()
+            .enumerate()
+            .map(|(joint, state)| {
+                settings.degrees_to_units(settings.to_display_angle(joint as u8, state.angle))
+            })
+            .collect(),
+    ))
+}
+
+/// Get the counters of the traffic on the connection since it was opened or last reset.
+#[tauri::command]
+async fn get_comms_stats(state: tauri::State<'_, AppState>) -> Result<CommsStats, AppError> {
+    state
+        .with_cobot(|cobot| Ok::<_, String>(cobot.stats()))
+        .await
+}
+
+/// Get the serial port the COBOT is connected over, and the bytes that have crossed it.
+#[tauri::command]
+async fn get_port_info(state: tauri::State<'_, AppState>) -> Result<PortInfo, AppError> {
+    state
+        .with_cobot(|cobot| {
+            let stats = cobot.stats();
+            Ok::<_, Box<dyn Error>>(PortInfo {
+                port_name: cobot.port_name().unwrap_or_default(),
+                baud_rate: cobot.baud_rate()?,
+                timeout_ms: cobot.response_timeout().as_millis() as u64,
+                bytes_written: stats.bytes_written,
+                bytes_read: stats.bytes_read,
+            })
+        })
+        .await
+}
+
+/// Zero every traffic counter, so an operation can be measured on its own.
+#[tauri::command]
+async fn reset_comms_stats(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot.reset_stats();
+            Ok::<_, String>(())
+        })
+        .await
+}
+
+/// Zero the count of messages discarded because of a CRC mismatch.
+#[tauri::command]
+async fn reset_crc_error_count(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot.reset_crc_error_count();
+            Ok::<_, String>(())
+        })
+        .await
+}
+
+/// Get the times the COBOT was last known to be responsive, so the frontend can show how long ago
+/// it was last seen.
+#[tauri::command]
+async fn get_liveness_timestamps(
+    state: tauri::State<'_, AppState>,
+) -> Result<LivenessInfo, AppError> {
+    state
+        .with_cobot(|cobot| {
+            Ok::<_, AppError>(LivenessInfo {
+                last_command_ms: cobot.last_successful_command_at().map(instant_to_epoch_ms),
+                last_joints_ms: cobot.last_successful_joints_at().map(instant_to_epoch_ms),
+            })
+        })
+        .await
+}
+
+/// Get the number of joints on the COBOT. The count is known once the joints have been read, and
+/// is cached on the connection from then on. Returns an error if the COBOT reports more joints
+/// than a joint mask can address, or none at all.
+#[tauri::command]
+async fn get_joint_count(state: tauri::State<'_, AppState>) -> Result<u8, AppError> {
+    state
+        .with_cobot(|cobot| {
+            let joint_count = match cobot.joint_count() {
+                Some(joint_count) => joint_count,
+                None => cobot
+                    .get_joints()
+                    .map(|joints| joints.len() as u8)
+                    .map_err(|e| format!("Failed to get joint states: {}", e))?,
+            };
+            if !(1..=JointMask::MAX_JOINTS).contains(&joint_count) {
+                return Err(format!(
+                    "COBOT reports {} joints, only 1 to {} are supported",
+                    joint_count,
+                    JointMask::MAX_JOINTS
+                ));
+            }
+            Ok(joint_count)
+        })
+        .await
+}
+
+/// Build a sample of the joints in the display frame and the active units, ready to publish to
+/// observers.
+fn joint_sample(settings: &Settings, joint_states: &[JointState]) -> JointSample {
+    let currents_ma = joint_states
+        .iter()
+        .map(|joint| joint.current_ma)
+        .collect::<Option<Vec<_>>>();
+    let pose = if settings.kinematics.is_empty() {
+        None
+    } else {
+        let firmware_angles = joint_states
+            .iter()
+            .map(|joint| joint.angle)
+            .collect::<Vec<_>>();
+        let angles = settings.corrected_angles(&firmware_angles);
+        kinematics::forward(&settings.kinematics, &angles).ok()
+    };
+    let (angles, speeds) = joint_states
+        .iter()
+        .enumerate()
+        .map(|(joint, state)| {
+            let joint = joint as u8;
+            (
+                settings.degrees_to_units(settings.to_display_angle(joint, state.angle)),
+                settings.degrees_to_units(settings.to_display_speed(joint, state.speed)),
+            )
+        })
+        .unzip::<_, _, Vec<_>, Vec<_>>();
+
+    JointSample {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+        units: settings.angle_units,
+        angles,
+        speeds,
+        currents_ma,
+        pose,
+    }
+}
+
+/// Get the angles of all joints, in the display frame and the active units.
+///
+/// While the COBOT streams its joints, the streamed joints are published to observers and the
+/// joints read here aren't, so observers never see the same moment twice.
+#[tauri::command]
+async fn get_angles(state: tauri::State<'_, AppState>) -> Result<Vec<f32>, AppError> {
+    // The frontend polls the angles, which mustn't keep an idle COBOT connected.
+    let (joint_states, streaming) = state
+        .with_cobot_background(|cobot| {
+            cobot
+                .get_joint_states()
+                .map(|joint_states| (joint_states, cobot.is_streaming()))
+                .map_err(|e| format!("Failed to get joint states: {}", e))
+        })
+        .await?;
+    *state.cached_joint_states.lock().unwrap() = Some((joint_states.clone(), Instant::now()));
+
+    let settings = state.settings.lock().await;
+    let sample = joint_sample(&settings, &joint_states);
+    drop(settings);
+    let angles = sample.angles.clone();
+    if !streaming {
+        // Nobody may be observing, in which case the sample is simply dropped.
+        let _ = state.joint_samples.send(sample);
+    }
+
+    Ok(angles)
+}
+
+/// Get the joints, error flags and feedback mask of the COBOT in a single round trip, with the
+/// joints in the display frame and the active units. Firmware that can't report it all at once
+/// has its joints read on their own.
+#[tauri::command]
+async fn get_status(state: tauri::State<'_, AppState>) -> Result<StatusSample, AppError> {
+    let status = state
+        .with_cobot_background(|cobot| {
+            cobot
+                .get_full_status()
+                .map_err(|e| format!("Failed to get status: {}", e))
+        })
+        .await?;
+    *state.cached_joint_states.lock().unwrap() = Some((status.joints.clone(), Instant::now()));
+
+    let settings = state.settings.lock().await;
+    Ok(StatusSample {
+        joints: joint_sample(&settings, &status.joints),
+        error_flags: status.error_flags,
+        feedback_mask: status.feedback_mask,
+    })
+}
+
+/// Publish the joints the COBOT streams to observers, until the connection they come from is
+/// dropped.
+///
+/// # Arguments
+///
+/// * `streamed` - Joints passed on by the connection's joints handler.
+async fn publish_streamed_joints(
+    app: AppHandle,
+    mut streamed: mpsc::UnboundedReceiver<Vec<JointState>>,
+) {
+    while let Some(joint_states) = streamed.recv().await {
+        let state = app.state::<AppState>();
+        *state.cached_joint_states.lock().unwrap() = Some((joint_states.clone(), Instant::now()));
+        let settings = state.settings.lock().await;
+        let _ = state
+            .joint_samples
+            .send(joint_sample(&settings, &joint_states));
+    }
+}
+
+/// Choose which joints report feedback and, optionally, have the COBOT stream them at a period
+/// instead of only reporting them when asked. Streaming needs firmware version 7.
+///
+/// # Arguments
+///
+/// * `joints` - Joints to enable feedback for.
+/// * `period_ms` - Period to stream the joints at, in milliseconds, from 10 to 2550. `None` stops
+///   streaming.
+#[tauri::command]
+async fn set_feedback(
+    state: tauri::State<'_, AppState>,
+    joints: JointMask,
+    period_ms: Option<u64>,
+) -> Result<(), AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .set_feedback(joints, period_ms.map(Duration::from_millis))
+                .map_err(|e| format!("Failed to set feedback: {}", e))
+        })
+        .await
+}
+
+/// Move a single joint to the given angle, in the display frame and the active units, at the given
+/// speed. If the speed is omitted or `0`, the joint's configured default speed is used. A move
+/// outside the joint's soft limits is refused.
+#[tauri::command]
+async fn move_joint(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    angle: f32,
+    speed: Option<f32>,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::joint(joint))?;
+
+    let settings = state.settings.lock().await;
+    let angle = settings.angle_to_degrees(angle)?;
+    settings.check_soft_limits(joint, angle)?;
+    warn_near_limit(&app, &settings, joint, angle);
+    let angle = settings.to_firmware_angle(joint, angle);
+    let speed = settings.resolve_speed(joint, settings.move_speed_to_degrees(speed));
+    drop(settings);
+
+    if !state
+        .pending_targets
+        .push(joint, Target::Position(angle, speed))
+    {
+        return Ok(());
+    }
+    let result = state
+        .with_cobot(|cobot| {
+            send_queued_targets(&state, cobot, joint, true)
+                .map_err(|e| format!("Failed to move joint: {}", e))
+        })
+        .await;
+    if result.is_err() {
+        // Nobody else will send a target left behind by the failure.
+        state.pending_targets.remove(JointMask::joint(joint));
+    }
+    result
+}
+
+/// Move a single joint by the given amount from where it is now, in the active units, at the given
+/// speed. If the speed is omitted or `0`, the joint's configured default speed is used. A move
+/// that would end outside the joint's soft limits is refused before anything moves.
+#[tauri::command]
+async fn move_joint_relative(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    delta: f32,
+    speed: Option<f32>,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::joint(joint))?;
+
+    let settings = state.settings.lock().await.clone();
+    let delta = settings.angle_to_degrees(delta)?;
+    let speed = settings.resolve_speed(joint, settings.move_speed_to_degrees(speed));
+
+    state
+        .with_cobot(|cobot| {
+            let joint_states = cobot
+                .get_joint_states()
+                .map_err(|e| format!("Failed to get joint states: {}", e))?;
+            let Some(current) = joint_states.get(joint as usize) else {
+                return Err(format!("Joint {} doesn't exist", joint));
+            };
+            let target = settings.to_display_angle(joint, current.angle) + delta;
+            settings.check_soft_limits(joint, target)?;
+            warn_near_limit(&app, &settings, joint, target);
+
+            let angle = settings.to_firmware_angle(joint, target);
+            move_with_undo(&state, cobot, &[(joint, angle, speed)])
+                .map_err(|e| format!("Failed to move joint: {}", e))
+        })
+        .await
+}
+
+/// Move the joints through a path of waypoints, in the display frame and the active units, at the
+/// given speed. Each waypoint gives the angle of every joint from joint 0, and `steps` segments are
+/// interpolated between each pair of waypoints so the joints don't jerk between sparse waypoints.
+/// If any waypoint is outside a joint's soft limits, nothing moves. The pose from before the path
+/// is recorded for undo, not each waypoint.
+#[tauri::command]
+async fn follow_path(
+    state: tauri::State<'_, AppState>,
+    waypoints: Vec<Vec<f32>>,
+    steps: usize,
+    speed: Option<f32>,
+) -> Result<(), AppError> {
+    let Some(joint_count) = waypoints.first().map(Vec::len) else {
+        return Ok(());
+    };
+    if joint_count > JointMask::MAX_JOINTS as usize {
+        return Err(format!("Waypoints have more than {} joints", JointMask::MAX_JOINTS).into());
+    }
+    if waypoints
+        .iter()
+        .any(|waypoint| waypoint.len() != joint_count)
+    {
+        return Err("Every waypoint must have the same number of joints".into());
+    }
+    state.check_motion_enabled(JointMask::first(joint_count as u8))?;
+
+    let settings = state.settings.lock().await.clone();
+    let speed = settings.move_speed_to_degrees(speed);
+    let path = trajectory::smooth_trajectory(&waypoints, steps)
+        .into_iter()
+        .map(|waypoint| {
+            waypoint
+                .into_iter()
+                .enumerate()
+                .map(|(joint, angle)| {
+                    let joint = joint as u8;
+                    let angle = settings.angle_to_degrees(angle)?;
+                    settings.check_soft_limits(joint, angle)?;
+                    Ok((
+                        joint,
+                        settings.to_firmware_angle(joint, angle),
+                        settings.resolve_speed(joint, speed),
+                    ))
+                })
+                .collect::<Result<Vec<_>, AppError>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let _motion = state.start_motion();
+    state
+        .with_cobot(|cobot| {
+            follow_waypoints(&state, cobot, &path)
+                .map_err(|e| format!("Failed to follow path: {}", e))
+        })
+        .await
+}
+
+/// Move every joint by the given amounts from where they are now, in the active units, in a single
+/// move at the given speed. Joints with a zero delta, or beyond the end of `deltas`, stay put. If
+/// any joint would end outside its soft limits, nothing moves.
+#[tauri::command]
+async fn move_all_relative(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    deltas: Vec<f32>,
+    speed: Option<f32>,
+) -> Result<(), AppError> {
+    let moved = deltas
+        .iter()
+        .enumerate()
+        .filter(|(_, delta)| **delta != 0.0)
+        .fold(JointMask::default(), |mask, (joint, _)| {
+            mask | JointMask::joint(joint as u8)
+        });
+    state.check_motion_enabled(moved)?;
+
+    let settings = state.settings.lock().await.clone();
+    let deltas = deltas
+        .into_iter()
+        .map(|delta| settings.angle_to_degrees(delta))
+        .collect::<Result<Vec<_>, _>>()?;
+    let speed = settings.move_speed_to_degrees(speed);
+
+    state
+        .with_cobot(|cobot| {
+            let joint_states = cobot
+                .get_joint_states()
+                .map_err(|e| format!("Failed to get joint states: {}", e))?;
+            if deltas.len() > joint_states.len() {
+                return Err(format!(
+                    "Got {} deltas, but the COBOT has {} joints",
+                    deltas.len(),
+                    joint_states.len()
+                ));
+            }
+
+            let mut targets = Vec::new();
+            for (joint, (delta, current)) in deltas.iter().zip(&joint_states).enumerate() {
+                let joint = joint as u8;
+                if *delta == 0.0 {
+                    continue;
+                }
+                let target = settings.to_display_angle(joint, current.angle) + delta;
+                settings.check_soft_limits(joint, target)?;
+                warn_near_limit(&app, &settings, joint, target);
+                let angle = settings.to_firmware_angle(joint, target);
+                targets.push((joint, angle, settings.resolve_speed(joint, speed)));
+            }
+            if targets.is_empty() {
+                return Ok(());
+            }
+
+            move_with_undo(&state, cobot, &targets).map_err(|e| format!("Failed to move: {}", e))
+        })
+        .await
+}
+
+/// Move a single joint to the given angle, in the display frame and the active units, at the
+/// speed that gets it there in the given time. If that's faster than the joint's speed limit, the
+/// joint moves at its limit instead. A move outside the joint's soft limits is refused.
+#[tauri::command]
+async fn move_joint_timed(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    target: f32,
+    duration_ms: u64,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::joint(joint))?;
+    let _motion = state.start_motion();
+
+    let settings = state.settings.lock().await;
+    let angle = settings.angle_to_degrees(target)?;
+    settings.check_soft_limits(joint, angle)?;
+    warn_near_limit(&app, &settings, joint, angle);
+    let angle = settings.to_firmware_angle(joint, angle);
+    let max_speed = settings.max_speed(joint);
+    drop(settings);
+
+    state
+        .with_cobot(|cobot| {
+            let pose = cobot
+                .get_joints_cached(JOINT_CACHE_MAX_AGE)
+                .map_err(|e| format!("Failed to get joint states: {}", e))?
+                .into_iter()
+                .map(|joint| joint.0)
+                .collect();
+            let command_id = cobot
+                .start_move_to_in_duration(
+                    joint,
+                    angle,
+                    Duration::from_millis(duration_ms),
+                    max_speed,
+                )
+                .map_err(|e| format!("Failed to move joint: {}", e))?;
+            // A move the COBOT refused, or that was never needed, has nothing to undo.
+            let Some(command_id) = command_id else {
+                return Ok(());
+            };
+            state.push_undo(pose);
+            cobot
+                .wait_for_done(command_id)
+                .map_err(|e| format!("Failed to move joint: {}", e))
+        })
+        .await
+}
+
+/// Move every joint to 0° in the display frame, in a single move, at the given speed in the
+/// active units. If the speed is omitted or `0`, each joint's configured default speed is used.
+/// If 0° is outside any joint's soft limits, nothing moves.
+#[tauri::command]
+async fn go_to_zero(state: tauri::State<'_, AppState>, speed: Option<f32>) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::first(JointMask::MAX_JOINTS))?;
+
+    let settings = state.settings.lock().await.clone();
+    let speed = settings.move_speed_to_degrees(speed);
+
+    state
+        .with_cobot(|cobot| {
+            let joint_count = match cobot.joint_count() {
+                Some(joint_count) => joint_count,
+                None => cobot
+                    .get_joints()
+                    .map_err(|e| format!("Failed to get joint states: {}", e))?
+                    .len() as u8,
+            };
+            let joints = (0..joint_count)
+                .map(|joint| {
+                    settings.check_soft_limits(joint, 0.0)?;
+                    Ok((
+                        joint,
+                        settings.to_firmware_angle(joint, 0.0),
+                        settings.resolve_speed(joint, speed),
+                    ))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            move_with_undo(&state, cobot, &joints)
+                .map_err(|e| format!("Failed to move to zero: {}", e))
+        })
+        .await
+}
+
+/// Hold every joint at its current angle by moving it to exactly where it is at the default speed,
+/// so the firmware servos it there and corrects any drift under load. Unlike a stop, which may
+/// leave the joints free, the joints stay held until `release_hold` or a stop. Motion must be
+/// enabled, as for any move, but the hold outlasts the enable timeout since nothing moves. If the
+/// COBOT is streaming feedback, the streamed angles are held instead of reading them again.
+#[tauri::command]
+async fn hold_position(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::first(JointMask::MAX_JOINTS))?;
+    let _motion = state.start_motion();
+
+    let held = state
+        .with_cobot(|cobot| {
+            let joints = cobot
+                .get_joint_states()
+                .map_err(|e| format!("Failed to get joint states: {}", e))?
+                .iter()
+                .enumerate()
+                .map(|(joint, state)| (joint as u8, state.angle, None))
+                .collect::<Vec<_>>();
+            cobot
+                .move_to(&joints)
+                .map_err(|e| format!("Failed to hold position: {}", e))?;
+            Ok::<_, String>(JointMask::first(joints.len() as u8))
+        })
+        .await?;
+    *state.holding.lock().unwrap() = held;
+    Ok(())
+}
+
+/// Stop holding the joints held by `hold_position`. Does nothing if no joints are held.
+#[tauri::command]
+async fn release_hold(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    let held = std::mem::take(&mut *state.holding.lock().unwrap());
+    if held.is_empty() {
+        return Ok(());
+    }
+
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .stop(held, false)
+                .map_err(|e| format!("Failed to release hold: {}", e))
+        })
+        .await
+}
+
+/// Move a single joint to the given angle, in the display frame and the active units, ramping its
+/// speed up and down so the configured acceleration limit is never exceeded. If the speed is
+/// omitted or `0`, the joint's configured default speed is used. A move outside the joint's soft
+/// limits is refused.
+#[tauri::command]
+async fn ramped_move(
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    angle: f32,
+    speed: Option<f32>,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::joint(joint))?;
+    let _motion = state.start_motion();
+
+    let settings = state.settings.lock().await.clone();
+    let Some(max_accel) = settings.max_accel else {
+        return Err("Acceleration limit not configured".into());
+    };
+    let angle = settings.angle_to_degrees(angle)?;
+    settings.check_soft_limits(joint, angle)?;
+    let angle = settings.to_firmware_angle(joint, angle);
+    let Some(speed) = settings.resolve_speed(joint, settings.move_speed_to_degrees(speed)) else {
+        return Err("Ramped moves need a speed or a default speed for the joint".into());
+    };
+
+    state
+        .with_cobot(|cobot| {
+            let pose = cobot
+                .get_joints_cached(JOINT_CACHE_MAX_AGE)
+                .map_err(|e| format!("Failed to get joint states: {}", e))?
+                .into_iter()
+                .map(|joint| joint.0)
+                .collect();
+            // As with `move_with_undo`, a move the COBOT rejects can't be undone.
+            motion::ramped_move(cobot, joint, angle, speed, max_accel, || {
+                state.push_undo(pose)
+            })
+            .map_err(|e| format!("Failed to move joint: {}", e))
+        })
+        .await
+}
+
+/// Drive a single joint to one of its soft limits, for testing the mechanical endstops.
+///
+/// # Arguments
+///
+/// * `direction` - `1` for the maximum limit or `-1` for the minimum limit.
+/// * `speed` - Speed in the active units. Must be positive and below 20°/s.
+#[tauri::command]
+async fn move_to_soft_limit(
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    direction: i8,
+    speed: f32,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::joint(joint))?;
+
+    let settings = state.settings.lock().await.clone();
+    let speed = settings.speed_to_degrees(speed);
+    if !(speed > 0.0 && speed < LIMIT_TEST_MAX_SPEED) {
+        return Err(format!(
+            "Speed must be positive and below {}°/s",
+            LIMIT_TEST_MAX_SPEED
+        )
+        .into());
+    }
+    let Some(limits) = settings.soft_limits(joint) else {
+        return Err(format!("Joint {} has no soft limits", joint).into());
+    };
+    let limit = match direction {
+        1 => limits.max,
+        -1 => limits.min,
+        _ => return Err("Direction must be 1 or -1".into()),
+    };
+    let angle = settings.to_firmware_angle(joint, limit);
+
+    state
+        .with_cobot(|cobot| {
+            move_with_undo(&state, cobot, &[(joint, angle, Some(speed))])
+                .map_err(|e| format!("Failed to move to soft limit: {}", e))
+        })
+        .await
+}
+
+/// Move the gripper to the given opening, in mm, with the given force or the firmware's default.
+#[tauri::command]
+async fn set_gripper(
+    state: tauri::State<'_, AppState>,
+    opening_mm: f32,
+    force: Option<u8>,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::default())?;
+    let _motion = state.start_motion();
+
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .set_gripper(opening_mm, force)
+                .map_err(|e| format!("Failed to move gripper: {}", e))
+        })
+        .await
+}
+
+/// Move all joints back to the pose they were in before the most recent motion command. The pose
+/// from before the undo is itself pushed onto the undo stack, so undoing twice returns to where
+/// the arm started. A pose outside the current soft limits is refused and left on the stack.
+#[tauri::command]
+async fn undo_last_move(
+    state: tauri::State<'_, AppState>,
+    speed: Option<f32>,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::first(JointMask::MAX_JOINTS))?;
+    let _motion = state.start_motion();
+
+    let settings = state.settings.lock().await.clone();
+    let speed = settings.move_speed_to_degrees(speed);
+
+    state
+        .with_cobot(|cobot| {
+            let pose = state
+                .undo_stack
+                .lock()
+                .unwrap()
+                .pop_back()
+                .ok_or_else(|| "Nothing to undo".to_string())?;
+            let joints = pose
+                .iter()
+                .enumerate()
+                .map(|(joint, angle)| {
+                    let joint = joint as u8;
+                    (joint, *angle, settings.resolve_speed(joint, speed))
+                })
+                .collect::<Vec<_>>();
+
+            // The limits may have been tightened since the pose was recorded.
+            for &(joint, angle, _) in &joints {
+                let angle = settings.to_display_angle(joint, angle);
+                if let Err(e) = settings.check_soft_limits(joint, angle) {
+                    state.undo_stack.lock().unwrap().push_back(pose);
+                    return Err(e);
+                }
+            }
+
+            let current = match cobot.get_joints() {
+                Ok(joint_states) => joint_states.into_iter().map(|joint| joint.0).collect(),
+                Err(e) => {
+                    state.undo_stack.lock().unwrap().push_back(pose);
+                    return Err(format!("Failed to get joint states: {}", e));
+                }
+            };
+            let command_id = match cobot.start_move_to(&joints) {
+                Ok(command_id) => command_id,
+                Err(e) => {
+                    state.undo_stack.lock().unwrap().push_back(pose);
+                    return Err(format!("Failed to undo move: {}", e));
+                }
+            };
+            state.push_undo(current);
+
+            cobot
+                .wait_for_done(command_id)
+                .map_err(|e| format!("Failed to undo move: {}", e))
+        })
+        .await
+}
+
+/// Get the number of moves that can be undone.
+#[tauri::command]
+async fn get_undo_depth(state: tauri::State<'_, AppState>) -> Result<usize, AppError> {
+    Ok(state.undo_stack.lock().unwrap().len())
+}
+
+/// Get the default speed of each joint, in degrees per second. `None` means the firmware's default
+/// speed is used.
+#[tauri::command]
+async fn get_joint_defaults(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Option<f32>>, AppError> {
+    Ok(state.settings.lock().await.default_speeds.clone())
+}
+
+/// Set the default speed of each joint, in degrees per second. `None` leaves the speed of that
+/// joint up to the firmware.
+#[tauri::command]
+async fn set_joint_defaults(
+    state: tauri::State<'_, AppState>,
+    speeds: Vec<Option<f32>>,
+) -> Result<(), AppError> {
+    if let Some(joint) = speeds
+        .iter()
+        .position(|speed| speed.is_some_and(|speed| !speed.is_finite() || speed <= 0.0))
+    {
+        return Err(format!("Invalid default speed for joint {}", joint).into());
+    }
+
+    state.settings.lock().await.default_speeds = speeds;
+    state.save_settings().await
+}
+
+/// Get the speed limit of each joint for timed moves, in degrees per second. `None` means a joint
+/// has no limit.
+#[tauri::command]
+async fn get_max_speeds(state: tauri::State<'_, AppState>) -> Result<Vec<Option<f32>>, AppError> {
+    Ok(state.settings.lock().await.max_speeds.clone())
+}
+
+/// Set the speed limit of each joint for timed moves, in degrees per second. `None` leaves that
+/// joint unlimited.
+#[tauri::command]
+async fn set_max_speeds(
+    state: tauri::State<'_, AppState>,
+    speeds: Vec<Option<f32>>,
+) -> Result<(), AppError> {
+    if let Some(joint) = speeds
+        .iter()
+        .position(|speed| speed.is_some_and(|speed| !speed.is_finite() || speed <= 0.0))
+    {
+        return Err(format!("Invalid speed limit for joint {}", joint).into());
+    }
+
+    state.settings.lock().await.max_speeds = speeds;
+    state.save_settings().await
+}
+
+/// Get the speed limit of each of the first six joints for timed moves, in degrees per second.
+/// `None` means a joint has no limit.
+#[tauri::command]
+async fn get_speed_limits(
+    state: tauri::State<'_, AppState>,
+) -> Result<[Option<f32>; TABLE_JOINTS], AppError> {
+    Ok(state.settings.lock().await.speed_limits())
+}
+
+/// Set the acceleration limit for ramped moves, in degrees per second squared. `None` clears it.
+#[tauri::command]
+async fn set_max_accel(
+    state: tauri::State<'_, AppState>,
+    max_accel: Option<f32>,
+) -> Result<(), AppError> {
+    if max_accel.is_some_and(|max_accel| !max_accel.is_finite() || max_accel <= 0.0) {
+        return Err("Acceleration limit must be positive".into());
+    }
+
+    state.settings.lock().await.max_accel = max_accel;
+    state.save_settings().await
+}
+
+/// Get the display transform of each joint.
+#[tauri::command]
+async fn get_joint_display(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<JointDisplay>, AppError> {
+    Ok(state.settings.lock().await.joint_display.clone())
+}
+
+/// Set the display transform of each joint. Angles shown to and received from the frontend are in
+/// the display frame, while the COBOT is always addressed in its own frame.
+#[tauri::command]
+async fn set_joint_display(
+    state: tauri::State<'_, AppState>,
+    joints: Vec<JointDisplay>,
+) -> Result<(), AppError> {
+    for (joint, display) in joints.iter().enumerate() {
+        if display.sign != 1 && display.sign != -1 {
+            return Err(format!("Sign of joint {} must be 1 or -1", joint).into());
+        }
+        if !display.offset.is_finite() {
+            return Err(format!("Offset of joint {} must be finite", joint).into());
+        }
+    }
+
+    state.settings.lock().await.joint_display = joints;
+    state.save_settings().await
+}
+
+/// Get the position and orientation of the end effector, computed from the current joint angles.
+#[tauri::command]
+async fn get_end_effector_pose(state: tauri::State<'_, AppState>) -> Result<Pose, AppError> {
+    let settings = state.settings.lock().await.clone();
+    if settings.kinematics.is_empty() {
+        return Err(kinematics::KinematicsError::NotConfigured
+            .to_string()
+            .into());
+    }
+
+    let angles = state
+        .with_cobot(|cobot| {
+            cobot
+                .get_joints_cached(JOINT_CACHE_MAX_AGE)
+                .map_err(|e| format!("Failed to get joint states: {}", e))
+        })
+        .await?
+        .into_iter()
+        .map(|(angle, _)| angle)
+        .collect::<Vec<_>>();
+
+    kinematics::forward(&settings.kinematics, &settings.corrected_angles(&angles))
+        .map_err(|e| e.to_string().into())
+}
+
+/// Get the 4x4 homogeneous transform from the base to the end effector, computed from the current
+/// joint angles, as a flat row-major array. Needs exactly six joints of kinematic parameters.
+#[cfg(feature = "nalgebra")]
+#[tauri::command]
+async fn get_end_effector_transform(
+    state: tauri::State<'_, AppState>,
+) -> Result<[f64; 16], AppError> {
+    let settings = state.settings.lock().await.clone();
+    let frame =
+        kinematics::CoordFrame::from_parameters(&settings.kinematics).map_err(|e| e.to_string())?;
+
+    let angles = state
+        .with_cobot(|cobot| {
+            cobot
+                .get_joints_cached(JOINT_CACHE_MAX_AGE)
+                .map_err(|e| format!("Failed to get joint states: {}", e))
+        })
+        .await?
+        .into_iter()
+        .map(|(angle, _)| angle)
+        .collect::<Vec<_>>();
+    let angles = settings.corrected_angles(&angles);
+    let angles: [f32; 6] = angles.as_slice().try_into().map_err(|_| {
+        kinematics::KinematicsError::JointCountMismatch {
+            expected: 6,
+            actual: angles.len(),
+        }
+        .to_string()
+    })?;
+
+    let matrix = frame
+        .forward_kinematics(&angles.map(|angle| angle as f64))
+        .to_homogeneous();
+    // Indexing by (row, column) gives a row-major array whatever nalgebra stores internally.
+    Ok(std::array::from_fn(|i| matrix[(i / 4, i % 4)]))
+}
+
+/// Stand-in for `get_end_effector_transform` when built without the `nalgebra` feature.
+#[cfg(not(feature = "nalgebra"))]
+#[tauri::command]
+async fn get_end_effector_transform() -> Result<[f64; 16], AppError> {
+    Err("Built without nalgebra kinematics support".into())
+}
+
+/// Set the kinematic parameters of each joint, from the base outwards. An empty list clears them.
+#[tauri::command]
+async fn set_kinematics(
+    state: tauri::State<'_, AppState>,
+    joints: Vec<DhParameters>,
+) -> Result<(), AppError> {
+    for (joint, params) in joints.iter().enumerate() {
+        if ![params.d, params.theta_offset, params.a, params.alpha]
+            .iter()
+            .all(|value| value.is_finite())
+        {
+            return Err(format!("Kinematic parameters of joint {} must be finite", joint).into());
+        }
+    }
+
+    state.settings.lock().await.kinematics = joints;
+    state.save_settings().await
+}
+
+/// Measure the backlash of a joint by approaching `center` from below and above, `cycles` times,
+/// moving `amplitude` past it on either side at `speed`. Angles and speeds are in the display frame
+/// and the active units. The report is also kept for debug reports.
+#[tauri::command]
+async fn run_backlash_test(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    center: f32,
+    amplitude: f32,
+    speed: f32,
+    cycles: u32,
+) -> Result<BacklashReport, AppError> {
+    let _motion = state.start_motion();
+    let report = backlash::run(&app, joint, center, amplitude, speed, cycles).await?;
+    state
+        .backlash_reports
+        .lock()
+        .unwrap()
+        .insert(joint, report.clone());
+    Ok(report)
+}
+
+/// Measure how closely a joint follows each of the given speeds, moving it `travel` from where it
+/// is for each. Speeds and travel are in the active units. The report is also kept for debug
+/// reports and `export_speed_test_csv`.
+#[tauri::command]
+async fn run_speed_test(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    speeds: Vec<f32>,
+    travel: f32,
+) -> Result<SpeedTestReport, AppError> {
+    let _motion = state.start_motion();
+    let report = speed_test::run(&app, joint, speeds, travel).await?;
+    state
+        .speed_test_reports
+        .lock()
+        .unwrap()
+        .insert(joint, report.clone());
+    Ok(report)
+}
+
+/// Export the latest speed accuracy test of a joint as CSV.
+#[tauri::command]
+async fn export_speed_test_csv(
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+) -> Result<String, AppError> {
+    match state.speed_test_reports.lock().unwrap().get(&joint) {
+        Some(report) => Ok(report.to_csv()),
+        None => Err(format!("Joint {} hasn't been speed tested", joint).into()),
+    }
+}
+
+/// Wait for a joint to reach an angle, in the display frame and the active units, and stay within
+/// the tolerance for the settle time.
+#[tauri::command]
+async fn wait_for_angle(
+    app: AppHandle,
+    joint: u8,
+    target: f32,
+    tolerance: f32,
+    timeout_ms: u64,
+    settle_ms: Option<u64>,
+) -> Result<AngleCheck, AppError> {
+    let timeout = Duration::from_millis(timeout_ms);
+    let settle = Duration::from_millis(settle_ms.unwrap_or(0));
+    Ok(checks::wait_for_angle(&app, joint, target, tolerance, timeout, settle).await?)
+}
+
+/// Wait for the given joints to slow to within a speed threshold, in the active units per second.
+#[tauri::command]
+async fn wait_for_stopped(
+    app: AppHandle,
+    joints: Vec<u8>,
+    speed_threshold: f32,
+    timeout_ms: u64,
+) -> Result<StoppedCheck, AppError> {
+    let timeout = Duration::from_millis(timeout_ms);
+    Ok(checks::wait_for_stopped(&app, &joints, speed_threshold, timeout).await?)
+}
+
+/// Check that the joints are at a pose, in the display frame and the active units, from a single
+/// reading.
+#[tauri::command]
+async fn assert_pose(
+    app: AppHandle,
+    pose: Vec<f32>,
+    tolerance: f32,
+) -> Result<PoseCheck, AppError> {
+    Ok(checks::assert_pose(&app, &pose, tolerance).await?)
+}
+
+/// Run a YAML test plan from a file, step by step, emitting `cobot://test-plan-step` as each step
+/// starts and finishes. See `cobot_comms::plan` for the format.
+///
+/// # Arguments
+///
+/// * `path` - Path of the test plan.
+///
+/// # Returns
+///
+/// The report of the run, also kept for debug reports. An error is only returned if the plan
+/// can't be read or parsed, or its port can't be opened; failed steps are part of the report.
+#[tauri::command]
+async fn run_test_plan(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<TestPlanReport, AppError> {
+    let source =
+        std::fs::read_to_string(&path).map_err(|e| format!("Failed to read test plan: {}", e))?;
+    let plan = cobot_comms::plan::parse(&source)?;
+    if let Some(connect) = plan.connect {
+        crate::connect(
+            app.clone(),
+            state.clone(),
+            connect.port_name,
+            connect.baud_rate,
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    let _motion = state.start_motion();
+    let report = test_plan::run(&app, plan.name, plan.steps).await?;
+    *state.last_test_plan.lock().unwrap() = Some(report.clone());
+    Ok(report)
+}
+
+/// Pause the running test plan. The joints moved by the step in progress are stopped smoothly,
+/// cancelling any wait on the step so the stop goes out at once, and the plan holds before its
+/// next step until resumed.
+#[tauri::command]
+async fn pause_execution(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    let joints = state.execution.pause()?;
+    stop_step_joints(&state, joints).await
+}
+
+/// Resume the paused test plan. A step interrupted by the pause is run again from its start.
+#[tauri::command]
+async fn resume_execution(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    Ok(state.execution.resume()?)
+}
+
+/// Abort the running or paused test plan, stopping the joints moved by the step in progress.
+#[tauri::command]
+async fn abort_execution(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    let joints = state.execution.step_joints();
+    state.execution.abort()?;
+    stop_step_joints(&state, joints).await
+}
+
+/// Get the state of the test plan executor.
+#[tauri::command]
+async fn get_execution_state(
+    state: tauri::State<'_, AppState>,
+) -> Result<ExecutionState, AppError> {
+    Ok(state.execution.state())
+}
+
+/// Stop the joints of an interrupted test plan step smoothly, ahead of the step itself.
+async fn stop_step_joints(state: &AppState, joints: JointMask) -> Result<(), AppError> {
+    if joints.is_empty() {
+        return Ok(());
+    }
+    state.pending_targets.remove(joints);
+    state
+        .with_cobot_priority(Priority::Emergency, |cobot| {
+            cobot
+                .stop(joints, false)
+                .map_err(|e| format!("Failed to stop joints: {}", e))
+        })
+        .await
+}
+
+/// Export the connection, joint states and configuration as pretty-printed JSON, for attaching
+/// to bug reports. A COBOT in its bootloader is only described by what was recorded before it was
+/// reset: its port, statistics and recent logs.
+#[tauri::command]
+async fn export_debug_report(state: tauri::State<'_, AppState>) -> Result<String, AppError> {
+    let settings = state.settings.lock().await.clone();
+    let undo_depth = state.undo_stack.lock().unwrap().len();
+    let last_test_plan = state.last_test_plan.lock().unwrap().clone();
+    let backlash = state.backlash_reports.lock().unwrap().clone();
+    let speed_tests = state.speed_test_reports.lock().unwrap().clone();
+    let recovered = state.restored_session.lock().unwrap().clone();
+    let watchdog_incidents = state.watchdog_incidents.lock().unwrap().clone();
+
+    let connection = state
+        .with_cobot_background(|cobot| {
+            let joints = match cobot.get_joints() {
+                Ok(joints) => json!(joints
+                    .into_iter()
+                    .map(|(angle, speed)| json!({ "angle": angle, "speed": speed }))
+                    .collect::<Vec<_>>()),
+                Err(e) => json!({ "error": e.to_string() }),
+            };
+            let device_info = match cobot.get_device_info() {
+                Ok(info) => json!(info),
+                Err(e) => json!({ "error": e.to_string() }),
+            };
+            Ok::<_, AppError>(json!({
+                "port_name": cobot.port_name(),
+                "baud_rate": cobot.baud_rate().ok(),
+                "protocol_version": cobot.protocol_version(),
+                "joints": joints,
+                "device_info": device_info,
+                "stats": cobot.stats(),
+                "buffered_responses": cobot.buffered_responses(),
+                "gripper_opening": cobot.gripper_opening(),
+                "recent_logs": cobot.recent_logs().collect::<Vec<_>>(),
+                "recent_faults": cobot.recent_faults().collect::<Vec<_>>(),
+            }))
+        })
+        .await;
+    let connection = match connection {
+        Ok(connection) => connection,
+        // The bootloader doesn't answer requests, so only what was recorded locally is reported.
+        Err(AppError::InBootloader) => match state.cobot.lock().await.as_ref() {
+            Some(cobot) => json!({
+                "port_name": cobot.port_name(),
+                "in_bootloader": true,
+                "stats": cobot.stats(),
+                "recent_logs": cobot.recent_logs().collect::<Vec<_>>(),
+            }),
+            None => json!(null),
+        },
+        Err(AppError::NotConnected) => json!(null),
+        Err(e) => return Err(e),
+    };
+
+    let report = json!({
+        "app_version": env!("CARGO_PKG_VERSION"),
+        "firmware_version": FIRMWARE_VERSION,
+        "connection": connection,
+        "undo_depth": undo_depth,
+        "last_test_plan": last_test_plan,
+        "backlash": backlash,
+        "speed_tests": speed_tests,
+        "recovered": recovered,
+        "watchdog_incidents": watchdog_incidents,
+        "settings": settings,
+    });
+    serde_json::to_string_pretty(&report).map_err(|e| e.to_string().into())
+}
+
+/// Get the session autosaved before the last run of the app crashed, if there is one and it hasn't
+/// been restored or discarded.
+#[tauri::command]
+async fn get_recovered_session(
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<serde_json::Value>, AppError> {
+    Ok(state.recovered_session.lock().unwrap().clone())
+}
+
+/// Restore the session left by a crash into this session's report, under `recovered`.
+#[tauri::command]
+async fn restore_recovered_session(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    let Some(session) = state.recovered_session.lock().unwrap().take() else {
+        return Err("No session to recover".into());
+    };
+    *state.restored_session.lock().unwrap() = Some(session);
+    Ok(())
+}
+
+/// Discard the session left by a crash without restoring it.
+#[tauri::command]
+async fn discard_recovered_session(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    *state.recovered_session.lock().unwrap() = None;
+    Ok(())
+}
+
+/// Set the time without a command from the operator after which the COBOT is disconnected and its
+/// port released, in seconds. `None` never disconnects an idle COBOT. Polling the angles doesn't
+/// count as a command.
+#[tauri::command]
+async fn set_idle_disconnect(
+    state: tauri::State<'_, AppState>,
+    timeout_s: Option<u64>,
+) -> Result<(), AppError> {
+    if timeout_s == Some(0) {
+        return Err("Idle timeout must be positive".into());
+    }
+
+    state.settings.lock().await.idle_disconnect_s = timeout_s;
+    state.save_settings().await
 }
 
-/// Check whether the cobot is connected.
+/// Keep an idle COBOT connected, or let it be disconnected again once it has been idle for
+/// `idle_disconnect_s` from now.
 #[tauri::command]
-async fn is_connected(state: tauri::State<'_, AppState>) -> Result<bool, String> {
-    Ok(state.cobot.lock().await.is_some())
+async fn set_keep_alive(state: tauri::State<'_, AppState>, enabled: bool) -> Result<(), AppError> {
+    state.idle.set_keep_alive(enabled);
+    Ok(())
 }
 
-/// Connect to the cobot over the given serial port.
+/// Get the COBOT last disconnected for being idle, or `None` if it has been reconnected since.
 #[tauri::command]
-async fn connect(
+async fn get_auto_disconnected(
     state: tauri::State<'_, AppState>,
-    port_name: String,
-    baud_rate: u32,
-) -> Result<(), String> {
-    let mut cobot = state.cobot.lock().await;
-    if cobot.is_some() {
-        return Ok(());
+) -> Result<Option<AutoDisconnected>, AppError> {
+    Ok(state.auto_disconnected.lock().unwrap().clone())
+}
+
+/// Reconnect to the COBOT last disconnected for being idle, over the same port and baud rate.
+#[tauri::command]
+async fn reconnect_after_idle(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), AppError> {
+    let disconnected = state.auto_disconnected.lock().unwrap().clone();
+    let Some(AutoDisconnected {
+        port_name: Some(port_name),
+        baud_rate: Some(baud_rate),
+        ..
+    }) = disconnected
+    else {
+        return Err("No idle COBOT to reconnect to".into());
+    };
+
+    connect(app, state, port_name, baud_rate, None, None).await
+}
+
+/// Set the steps run after connecting when `connect` is asked to bring the COBOT up, in the
+/// format of test plan steps. An empty list leaves bring-up to the operator.
+#[tauri::command]
+async fn set_startup_sequence(
+    state: tauri::State<'_, AppState>,
+    steps: Vec<cobot_comms::plan::Step>,
+) -> Result<(), AppError> {
+    state.settings.lock().await.startup_sequence = steps;
+    state.save_settings().await
+}
+
+/// Set the interval between autosaves of the session, in seconds. `None` restores the default.
+/// Takes effect after the next autosave.
+#[tauri::command]
+async fn set_autosave_interval(
+    state: tauri::State<'_, AppState>,
+    interval_s: Option<u64>,
+) -> Result<(), AppError> {
+    if interval_s == Some(0) {
+        return Err("Autosave interval must be positive".into());
     }
 
-    let port = serialport::new(port_name, baud_rate)
-        .timeout(std::time::Duration::from_millis(1000))
-        .open()
-        .map_err(|e| format!("Failed to open port: {}", e))?;
+    state.settings.lock().await.autosave_interval_s = interval_s;
+    state.save_settings().await
+}
 
-    let connection = CobotConnection::new(port, FIRMWARE_VERSION, Duration::from_millis(100));
-    *cobot = Some(Box::new(connection));
+/// Get a health readout of the COBOT's controller: uptime, board temperature, supply voltage, and
+/// joint driver temperatures.
+///
+/// # Returns
+///
+/// The readout, or `None` if the firmware doesn't support it.
+#[tauri::command]
+async fn get_device_info(
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<DeviceInfo>, AppError> {
+    state
+        .with_cobot(|cobot| match cobot.get_device_info() {
+            Ok(info) => Ok(Some(info)),
+            Err(e) if matches!(e.downcast_ref(), Some(CommsError::Unsupported { .. })) => Ok(None),
+            Err(e) => Err(format!("Failed to get device info: {}", e)),
+        })
+        .await
+}
 
-    Ok(())
+/// Get the hardware metadata of the COBOT. Joints without a speed limit for timed moves are given
+/// the firmware's maximum speed as their limit; limits already set are left alone.
+///
+/// # Returns
+///
+/// The metadata, or `None` if the firmware doesn't report it.
+#[tauri::command]
+async fn get_cobot_info(state: tauri::State<'_, AppState>) -> Result<Option<CobotInfo>, AppError> {
+    let info = state
+        .with_cobot(|cobot| match cobot.get_info() {
+            Ok(info) => Ok(Some(info)),
+            Err(e) if matches!(e.downcast_ref(), Some(CommsError::Unsupported { .. })) => Ok(None),
+            Err(e) => Err(format!("Failed to get COBOT info: {}", e)),
+        })
+        .await?;
+    let Some(info) = info else {
+        return Ok(None);
+    };
+
+    let mut settings = state.settings.lock().await;
+    let joint_count = info.joint_count as usize;
+    if settings.max_speeds.len() < joint_count {
+        settings.max_speeds.resize(joint_count, None);
+    }
+    let mut changed = false;
+    for speed in settings.max_speeds.iter_mut().take(joint_count) {
+        if speed.is_none() && info.max_speed_deg_s > 0.0 {
+            *speed = Some(info.max_speed_deg_s);
+            changed = true;
+        }
+    }
+    drop(settings);
+    if changed {
+        state.save_settings().await?;
+    }
+    Ok(Some(info))
 }
 
-/// Disconnect from the cobot.
+/// Download the error log kept by the COBOT's firmware, oldest entry first.
 #[tauri::command]
-async fn disconnect(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let mut cobot = state.cobot.lock().await;
-    *cobot = None;
-    Ok(())
+async fn get_error_log(state: tauri::State<'_, AppState>) -> Result<Vec<CobotLogEntry>, AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .get_error_log()
+                .map_err(|e| format!("Failed to get error log: {}", e))
+        })
+        .await
 }
 
-/// Initialize the cobot.
+/// Get the soft limits of each joint, in the display frame and degrees.
 #[tauri::command]
-async fn init(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let mut cobot = state.cobot.lock().await;
-    if cobot.is_none() {
-        return Err("Not connected".to_string());
+async fn get_soft_limits(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Option<JointLimits>>, AppError> {
+    Ok(state.settings.lock().await.soft_limits.clone())
+}
+
+/// Set the soft limits of each joint, in the display frame and degrees, and save them. `None`
+/// leaves a joint without limits. Each joint's minimum must be below its maximum.
+#[tauri::command]
+async fn set_soft_limits(
+    state: tauri::State<'_, AppState>,
+    joints: Vec<Option<JointLimits>>,
+) -> Result<(), AppError> {
+    settings::validate_soft_limits(&joints)?;
+
+    state.settings.lock().await.soft_limits = joints;
+    state.save_settings().await
+}
+
+/// Get the soft limits of each of the first six joints as `(min, max)`, in the display frame and
+/// degrees. `None` means a joint has no limits.
+#[tauri::command]
+async fn get_joint_limits(
+    state: tauri::State<'_, AppState>,
+) -> Result<[Option<(f32, f32)>; TABLE_JOINTS], AppError> {
+    Ok(state.settings.lock().await.joint_limits())
+}
+
+/// Set the soft limits of the first six joints from a table of `(min, max)`, in the display frame
+/// and degrees, and save them, so a machine profile can be loaded in one call. `None` leaves a
+/// joint without limits, and the limits of any later joints are kept. Each joint's minimum must be
+/// below its maximum.
+#[tauri::command]
+async fn set_joint_limits(
+    state: tauri::State<'_, AppState>,
+    limits: [Option<(f32, f32)>; TABLE_JOINTS],
+) -> Result<(), AppError> {
+    state.settings.lock().await.set_joint_limits(limits)?;
+    state.save_settings().await
+}
+
+/// Get the distance from each joint's soft limits within which moves are warned about, in
+/// degrees. `None` means moves of a joint are never warned about.
+#[tauri::command]
+async fn get_limit_warning_margins(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Option<f32>>, AppError> {
+    Ok(state.settings.lock().await.limit_warning_margins.clone())
+}
+
+/// Set the distance from each joint's soft limits within which moves emit
+/// `cobot://near-limit`, in degrees. `None` turns the warning off for that joint.
+#[tauri::command]
+async fn set_limit_warning_margins(
+    state: tauri::State<'_, AppState>,
+    margins: Vec<Option<f32>>,
+) -> Result<(), AppError> {
+    if let Some(joint) = margins
+        .iter()
+        .position(|margin| margin.is_some_and(|margin| !margin.is_finite() || margin < 0.0))
+    {
+        return Err(format!("Invalid limit warning margin for joint {}", joint).into());
     }
 
-    cobot
-        .as_mut()
-        .unwrap()
-        .init()
-        .map_err(|e| format!("Failed to initialize: {}", e))?;
+    state.settings.lock().await.limit_warning_margins = margins;
+    state.save_settings().await
+}
 
-    Ok(())
+/// Get the correction of each joint's reported angle.
+#[tauri::command]
+async fn get_joint_corrections(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<JointCorrection>, AppError> {
+    Ok(state.settings.lock().await.joint_corrections.clone())
 }
 
-/// Calibrate the cobot.
+/// Set the correction of each joint's reported angle. Corrected angles are used everywhere on the
+/// host, and commanded angles are converted back before being sent to the COBOT.
 #[tauri::command]
-async fn calibrate(state: tauri::State<'_, AppState>, joints: u8) -> Result<(), String> {
-    let mut cobot = state.cobot.lock().await;
-    if cobot.is_none() {
-        return Err("Not connected".to_string());
+async fn set_joint_corrections(
+    state: tauri::State<'_, AppState>,
+    joints: Vec<JointCorrection>,
+) -> Result<(), AppError> {
+    for (joint, correction) in joints.iter().enumerate() {
+        if !correction.scale.is_finite() || correction.scale == 0.0 {
+            return Err(format!("Scale of joint {} must be finite and non-zero", joint).into());
+        }
+        if !correction.offset.is_finite() {
+            return Err(format!("Offset of joint {} must be finite", joint).into());
+        }
     }
 
-    cobot
-        .as_mut()
-        .unwrap()
-        .calibrate(joints)
-        .map_err(|e| format!("Failed to calibrate: {}", e))?;
+    state.settings.lock().await.joint_corrections = joints;
+    state.save_settings().await
+}
 
-    Ok(())
+/// Get the calibration offset of each of the first six joints, which is the real angle of the
+/// joint when it reports 0°, in degrees. Offsets are set with `set_joint_corrections`.
+#[tauri::command]
+async fn get_joint_offsets(
+    state: tauri::State<'_, AppState>,
+) -> Result<[f32; TABLE_JOINTS], AppError> {
+    Ok(state.settings.lock().await.joint_offsets())
 }
 
-/// Get the angles of all joints.
+/// Get the current settings.
 #[tauri::command]
-async fn get_angles(state: tauri::State<'_, AppState>) -> Result<Vec<f32>, String> {
-    let mut cobot = state.cobot.lock().await;
-    if cobot.is_none() {
-        return Err("Not connected".to_string());
+async fn get_settings(state: tauri::State<'_, AppState>) -> Result<Settings, AppError> {
+    Ok(state.settings.lock().await.clone())
+}
+
+/// Set the units of angles and speeds exchanged with the frontend. The COBOT is always addressed in
+/// degrees.
+#[tauri::command]
+async fn set_angle_units(
+    state: tauri::State<'_, AppState>,
+    units: AngleUnits,
+) -> Result<(), AppError> {
+    state.settings.lock().await.angle_units = units;
+    state.save_settings().await
+}
+
+/// Start moving a single joint at a constant speed, in the active units. A positive speed moves
+/// the joint in the positive direction of the display frame. The joint keeps moving until
+/// `stop_joint` is called, so this only waits for the COBOT to acknowledge the request, not for the
+/// motion to finish.
+#[tauri::command]
+async fn move_joint_continuous(
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    speed: f32,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::joint(joint))?;
+
+    let settings = state.settings.lock().await;
+    let speed = settings.to_firmware_speed(joint, settings.speed_to_degrees(speed));
+    drop(settings);
+
+    if !state.pending_targets.push(joint, Target::Speed(speed)) {
+        return Ok(());
+    }
+    let result = state
+        .with_cobot(|cobot| {
+            send_queued_targets(&state, cobot, joint, false)
+                .map_err(|e| format!("Failed to move joint: {}", e))
+        })
+        .await;
+    if result.is_err() {
+        // Nobody else will send a target left behind by the failure.
+        state.pending_targets.remove(JointMask::joint(joint));
     }
+    result
+}
 
-    let joint_states = cobot
-        .as_mut()
-        .unwrap()
-        .get_joints()
-        .map_err(|e| format!("Failed to get joint states: {}", e))?;
+/// Move a single joint at the given speed, in the active units, for `duration_ms`, then stop it.
+/// The sign of the speed gives the direction in the display frame. The joint is polled throughout,
+/// and each poll is emitted as `cobot://monitored-joint`.
+#[tauri::command]
+async fn move_speed_monitored(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    speed: f32,
+    duration_ms: u64,
+) -> Result<(), AppError> {
+    state.check_motion_enabled(JointMask::joint(joint))?;
+    let _motion = state.start_motion();
 
-    let angles = joint_states
-        .into_iter()
-        .map(|joint| joint.0)
-        .collect::<Vec<_>>();
+    let settings = state.settings.lock().await.clone();
+    let firmware_speed = settings.to_firmware_speed(joint, settings.speed_to_degrees(speed));
 
-    Ok(angles)
+    let (tx, mut rx) = mpsc::unbounded_channel::<JointState>();
+    let relay = tauri::async_runtime::spawn(async move {
+        while let Some(joint_state) = rx.recv().await {
+            let monitored = MonitoredJoint {
+                joint,
+                angle: settings
+                    .degrees_to_units(settings.to_display_angle(joint, joint_state.angle)),
+                speed: settings
+                    .degrees_to_units(settings.to_display_speed(joint, joint_state.speed)),
+            };
+            let _ = app.emit_all(MONITORED_JOINT_EVENT, monitored);
+        }
+    });
+
+    let duration = Duration::from_millis(duration_ms);
+    let result = state
+        .with_cobot(|cobot| {
+            cobot
+                .move_speed_monitored(joint, firmware_speed, duration, &tx)
+                .map_err(|e| format!("Failed to run monitored move: {}", e))
+        })
+        .await;
+    // Closing the channel lets the relay emit what's left and finish.
+    drop(tx);
+    let _ = relay.await;
+    result
 }
 
-/// Move a single joint to the given angle at the given speed.
+/// Move a single joint at the given speed, in the active units, until it stalls against an
+/// obstacle. The sign of the speed gives the direction in the display frame.
+///
+/// # Returns
+///
+/// The angle of the joint at contact, in the display frame and the active units.
 #[tauri::command]
-async fn move_joint(
+async fn move_until_contact(
     state: tauri::State<'_, AppState>,
     joint: u8,
-    angle: f32,
     speed: f32,
-) -> Result<(), String> {
-    let mut cobot = state.cobot.lock().await;
-    if cobot.is_none() {
-        return Err("Not connected".to_string());
+    stall_timeout_ms: u64,
+) -> Result<f32, AppError> {
+    state.check_motion_enabled(JointMask::joint(joint))?;
+    let _motion = state.start_motion();
+
+    let settings = state.settings.lock().await.clone();
+    let speed = settings.to_firmware_speed(joint, settings.speed_to_degrees(speed));
+
+    let angle = state
+        .with_cobot(|cobot| {
+            cobot
+                .move_until_contact(joint, speed, Duration::from_millis(stall_timeout_ms))
+                .map_err(|e| format!("Failed to move until contact: {}", e))
+        })
+        .await?;
+
+    Ok(settings.degrees_to_units(settings.to_display_angle(joint, angle)))
+}
+
+/// Get the most recent `last_n` requests sent and responses received on the current connection,
+/// oldest first.
+#[tauri::command]
+async fn get_session_log(
+    state: tauri::State<'_, AppState>,
+    last_n: usize,
+) -> Result<Vec<SessionEntry>, AppError> {
+    state
+        .with_cobot(|cobot| Ok::<_, String>(cobot.session_log().last(last_n)))
+        .await
+}
+
+/// Clear the session log of the current connection.
+#[tauri::command]
+async fn clear_session_log(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    state
+        .with_cobot(|cobot| {
+            cobot.clear_session_log();
+            Ok::<_, String>(())
+        })
+        .await
+}
+
+/// Get the request types, response types, error codes and log levels of the protocol.
+#[tauri::command]
+async fn get_protocol_info() -> Result<ProtocolInfo, AppError> {
+    Ok(ProtocolInfo::new())
+}
+
+/// Relay the protocol events of the current connection to the frontend as `cobot://comms`, until
+/// it's disconnected. Subscribing again replaces the previous relay, so each event is emitted once.
+#[tauri::command]
+async fn subscribe_to_comms_events(
+    app_handle: AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), AppError> {
+    let events = state
+        .with_cobot(|cobot| Ok::<_, String>(cobot.subscribe()))
+        .await?;
+    let relay = tauri::async_runtime::spawn(relay_comms_events(app_handle, events));
+    if let Some(previous) = state.comms_relay.lock().unwrap().replace(relay) {
+        previous.abort();
     }
+    Ok(())
+}
 
-    cobot
-        .as_mut()
-        .unwrap()
-        .move_to(&[(joint, angle, Some(speed))])
-        .map_err(|e| format!("Failed to move joint: {}", e))?;
+/// Stop a single joint.
+///
+/// By default the joint decelerates smoothly. With `immediately`, it stops as fast as it can,
+/// which may shock the joint and should be kept for emergencies.
+#[tauri::command]
+async fn stop_joint(
+    state: tauri::State<'_, AppState>,
+    joint: u8,
+    immediately: bool,
+) -> Result<(), AppError> {
+    // A target that hasn't been sent yet mustn't restart the joint.
+    state.pending_targets.remove(JointMask::joint(joint));
+    state
+        .with_cobot_priority(Priority::Control, |cobot| {
+            cobot
+                .stop(JointMask::joint(joint), immediately)
+                .map_err(|e| format!("Failed to stop joint: {}", e))
+        })
+        .await?;
+    let mut jogging = state.jogging.lock().unwrap();
+    *jogging = *jogging & !JointMask::joint(joint);
+    drop(jogging);
+    Ok(())
+}
 
+/// Stop every joint, either decelerating smoothly or, with `immediately`, as fast as possible.
+/// An immediate stop may shock the joints.
+#[tauri::command]
+async fn stop_all_joints(
+    state: tauri::State<'_, AppState>,
+    immediately: bool,
+) -> Result<(), AppError> {
+    state.pending_targets.clear();
+    // The emergency class cancels any caller waiting on a move, so the stop isn't queued behind it.
+    state
+        .with_cobot_priority(Priority::Emergency, |cobot| {
+            let all_joints = cobot.all_joints();
+            cobot
+                .stop(all_joints, immediately)
+                .map_err(|e| format!("Failed to stop joints: {}", e))
+        })
+        .await?;
+    *state.jogging.lock().unwrap() = JointMask::default();
+    *state.holding.lock().unwrap() = JointMask::default();
     Ok(())
 }
 
-/// Stop a single joint smoothly.
+/// Reset the app out of a bad state without disconnecting: abort any test plan and calibration,
+/// drop queued jog targets and holds, stop every joint immediately, stop streaming, and discard
+/// any stale responses, so the next command starts clean. The connection stays open.
 #[tauri::command]
-async fn stop_joint(state: tauri::State<'_, AppState>, joint: u8) -> Result<(), String> {
-    let mut cobot = state.cobot.lock().await;
-    if cobot.is_none() {
-        return Err("Not connected".to_string());
+async fn abort_all(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    let _ = state.execution.abort();
+    state.calibration_abort.store(true, Ordering::Relaxed);
+    state.pending_targets.clear();
+    *state.jogging.lock().unwrap() = JointMask::default();
+    *state.holding.lock().unwrap() = JointMask::default();
+    *state.cached_joint_states.lock().unwrap() = None;
+
+    state
+        .with_cobot_priority(Priority::Emergency, |cobot| {
+            let all_joints = cobot.all_joints();
+            cobot
+                .stop(all_joints, true)
+                .map_err(|e| format!("Failed to stop joints: {}", e))?;
+            cobot
+                .stop_streaming()
+                .map_err(|e| format!("Failed to stop streaming: {}", e))?;
+            let flushed = cobot.flush_responses();
+            if flushed > 0 {
+                warn!("Discarded {} stale responses", flushed);
+            }
+            Ok::<_, String>(())
+        })
+        .await
+}
+
+/// Enable or disable motion commands. Once enabled, motion stays enabled for the configured
+/// timeout unless this is called again to refresh it.
+#[tauri::command]
+async fn enable_motion(state: tauri::State<'_, AppState>, enabled: bool) -> Result<(), AppError> {
+    let timeout = state
+        .settings
+        .lock()
+        .await
+        .motion_enable_timeout_ms
+        .unwrap_or(settings::DEFAULT_MOTION_ENABLE_TIMEOUT_MS);
+    *state.motion_enabled_until.lock().unwrap() =
+        enabled.then(|| Instant::now() + Duration::from_millis(timeout));
+    Ok(())
+}
+
+/// Set how long motion stays enabled after `enable_motion`, in milliseconds. `None` restores the
+/// default.
+#[tauri::command]
+async fn set_motion_enable_timeout(
+    state: tauri::State<'_, AppState>,
+    timeout_ms: Option<u64>,
+) -> Result<(), AppError> {
+    if timeout_ms == Some(0) {
+        return Err("Motion enable timeout must be positive".into());
     }
 
-    cobot
-        .as_mut()
-        .unwrap()
-        .stop(1 << joint, false)
-        .map_err(|e| format!("Failed to stop joint: {}", e))?;
+    state.settings.lock().await.motion_enable_timeout_ms = timeout_ms;
+    state.save_settings().await
+}
+
+/// Enable or disable the servos of the given joints. Joints with disabled servos can be moved by
+/// hand, and motion commands for them are rejected until they are enabled again.
+///
+/// # Returns
+///
+/// Whether any of the joints were re-enabled after being disabled, in which case they may have
+/// been moved by hand and the measured angle may need to be overridden before moving them.
+#[tauri::command]
+async fn set_servo(
+    state: tauri::State<'_, AppState>,
+    joints: Vec<u8>,
+    enabled: bool,
+) -> Result<bool, AppError> {
+    if let Some(joint) = joints.iter().find(|joint| **joint >= JointMask::MAX_JOINTS) {
+        return Err(format!("Invalid joint {}", joint).into());
+    }
+    let mask = joints.iter().fold(JointMask::default(), |mask, joint| {
+        mask | JointMask::joint(*joint)
+    });
+
+    state
+        .with_cobot(|cobot| {
+            cobot
+                .set_servo(mask, enabled)
+                .map_err(|e| format!("Failed to set servos: {}", e))
+        })
+        .await?;
+
+    let mut servos_disabled = state.servos_disabled.lock().unwrap();
+    let needs_override = enabled && !(*servos_disabled & mask).is_empty();
+    *servos_disabled = if enabled {
+        *servos_disabled & !mask
+    } else {
+        *servos_disabled | mask
+    };
+
+    Ok(needs_override)
+}
+
+/// Get the joints whose servos are enabled.
+#[tauri::command]
+async fn get_servo_state(state: tauri::State<'_, AppState>) -> Result<JointMask, AppError> {
+    let servos_disabled = *state.servos_disabled.lock().unwrap();
+    state
+        .with_cobot(|cobot| Ok::<_, AppError>(cobot.all_joints() & !servos_disabled))
+        .await
+}
+
+/// Set the time to wait for a response from the COBOT other than a DONE, such as an ACK, in
+/// milliseconds. Takes effect immediately, so a slow link can be accommodated without
+/// reconnecting. Must be between 10 ms and 10 s.
+#[tauri::command]
+async fn set_response_timeout(
+    state: tauri::State<'_, AppState>,
+    timeout_ms: u64,
+) -> Result<(), AppError> {
+    if !RESPONSE_TIMEOUT_RANGE_MS.contains(&timeout_ms) {
+        return Err("Response timeout must be between 10 and 10000 ms".into());
+    }
+
+    if let Some(cobot) = state.cobot.lock().await.as_mut() {
+        cobot.set_response_timeout(Duration::from_millis(timeout_ms));
+    }
+    state.settings.lock().await.response_timeout_ms = Some(timeout_ms);
+    state.save_settings().await
+}
+
+/// Get the timeouts used when waiting for the COBOT, in milliseconds.
+#[tauri::command]
+async fn get_timeouts(state: tauri::State<'_, AppState>) -> Result<Timeouts, AppError> {
+    let settings = state.settings.lock().await.clone();
+    let (response, calibration) = match state.cobot.lock().await.as_ref() {
+        Some(cobot) => (cobot.response_timeout(), cobot.calibration_timeout()),
+        None => (
+            Duration::from_millis(
+                settings
+                    .response_timeout_ms
+                    .unwrap_or(settings::DEFAULT_RESPONSE_TIMEOUT_MS),
+            ),
+            settings.calibration_timeout_ms.map_or(
+                cobot_comms::DEFAULT_CALIBRATION_TIMEOUT,
+                Duration::from_millis,
+            ),
+        ),
+    };
+
+    Ok(Timeouts {
+        response_ms: response.as_millis() as u64,
+        calibration_ms: calibration.as_millis() as u64,
+        joint_calibration_ms: settings
+            .joint_calibration_timeout_ms
+            .unwrap_or(settings::DEFAULT_JOINT_CALIBRATION_TIMEOUT_MS),
+    })
+}
+
+/// Set the time to wait for each joint to finish calibrating during a sequential calibration, in
+/// milliseconds. Must be between 10 and 300 seconds.
+#[tauri::command]
+async fn set_joint_calibration_timeout(
+    state: tauri::State<'_, AppState>,
+    timeout_ms: u64,
+) -> Result<(), AppError> {
+    if !CALIBRATION_TIMEOUT_RANGE_MS.contains(&timeout_ms) {
+        return Err("Calibration timeout must be between 10000 and 300000 ms".into());
+    }
+
+    state.settings.lock().await.joint_calibration_timeout_ms = Some(timeout_ms);
+    state.save_settings().await
+}
+
+/// Set the time to wait for calibration and other long operations to finish, in milliseconds.
+/// Must be between 10 and 300 seconds.
+#[tauri::command]
+async fn set_calibration_timeout(
+    state: tauri::State<'_, AppState>,
+    timeout_ms: u64,
+) -> Result<(), AppError> {
+    if !CALIBRATION_TIMEOUT_RANGE_MS.contains(&timeout_ms) {
+        return Err("Calibration timeout must be between 10000 and 300000 ms".into());
+    }
+
+    if let Some(cobot) = state.cobot.lock().await.as_mut() {
+        cobot.set_calibration_timeout(Duration::from_millis(timeout_ms));
+    }
+    state.settings.lock().await.calibration_timeout_ms = Some(timeout_ms);
+    state.save_settings().await
+}
+
+/// Set the severity at or above which a fault reported by the COBOT stops every joint
+/// immediately. `None` never stops the joints on a fault.
+#[tauri::command]
+async fn set_fault_stop_severity(
+    state: tauri::State<'_, AppState>,
+    severity: Option<u8>,
+) -> Result<(), AppError> {
+    if let Some(cobot) = state.cobot.lock().await.as_mut() {
+        cobot.set_fault_stop_severity(severity);
+    }
+    state.settings.lock().await.fault_stop_severity = severity;
+    state.save_settings().await
+}
+
+/// Configure detection of joints that stall partway through a move. A moving joint is suspected
+/// to have stalled if it makes less than `min_progress` degrees of progress towards its target
+/// within `window_ms`. `None` for `window_ms` disables stall detection.
+#[tauri::command]
+async fn set_stall_detection(
+    state: tauri::State<'_, AppState>,
+    window_ms: Option<u64>,
+    min_progress: f32,
+    auto_stop: bool,
+) -> Result<(), AppError> {
+    if window_ms == Some(0) {
+        return Err("Stall window must be positive".into());
+    }
+    if !min_progress.is_finite() || min_progress <= 0.0 {
+        return Err("Minimum progress must be finite and positive".into());
+    }
+
+    let detection = {
+        let mut settings = state.settings.lock().await;
+        settings.stall_window_ms = window_ms;
+        settings.stall_min_progress = Some(min_progress);
+        settings.stall_auto_stop = auto_stop;
+        settings.stall_detection()
+    };
+    if let Some(cobot) = state.cobot.lock().await.as_mut() {
+        cobot.set_stall_detection(detection);
+    }
+    state.save_settings().await
+}
+
+/// Set the thresholds for grading the link to the COBOT. The rates are fractions between 0 and 1,
+/// and the bad rate must not be below the degraded rate.
+#[tauri::command]
+async fn set_link_quality_thresholds(
+    state: tauri::State<'_, AppState>,
+    thresholds: LinkQualityThresholds,
+) -> Result<(), AppError> {
+    if thresholds.window_ms == 0 {
+        return Err("Link quality window must be positive".into());
+    }
+    let valid_rate = |rate: f32| (0.0..=1.0).contains(&rate);
+    if !valid_rate(thresholds.degraded_rate) || !valid_rate(thresholds.bad_rate) {
+        return Err("Link quality rates must be between 0 and 1".into());
+    }
+    if thresholds.bad_rate < thresholds.degraded_rate {
+        return Err("Bad link rate must not be below the degraded rate".into());
+    }
+
+    state.settings.lock().await.link_quality_thresholds = Some(thresholds);
+    state.save_settings().await
+}
+
+/// Set the limit on how often requests are sent to the COBOT. `None` removes the limit, which is
+/// the default. STOP requests are never limited.
+#[tauri::command]
+async fn set_rate_limit(
+    state: tauri::State<'_, AppState>,
+    rate_limit: Option<RateLimit>,
+) -> Result<(), AppError> {
+    if rate_limit.is_some_and(|rate_limit| rate_limit.min_interval_ms == 0) {
+        return Err("Rate limit interval must be positive".into());
+    }
+
+    if let Some(cobot) = state.cobot.lock().await.as_mut() {
+        cobot.set_rate_limit(rate_limit);
+    }
+    state.settings.lock().await.rate_limit = rate_limit;
+    state.save_settings().await
+}
 
+/// Enable or disable payload escaping, on the connection if there is one and for every connection
+/// after it. The firmware must have escaping enabled as well, or it can't read the requests.
+#[tauri::command]
+async fn set_payload_escaping(
+    state: tauri::State<'_, AppState>,
+    enabled: bool,
+) -> Result<(), AppError> {
+    if let Some(cobot) = state.cobot.lock().await.as_mut() {
+        cobot.set_payload_escaping(enabled);
+    }
+    state.settings.lock().await.payload_escaping = enabled;
+    state.save_settings().await
+}
+
+/// Set the lowest level of COBOT log message shown, without changing the level the firmware sends
+/// at. Every message is still kept in the debug report.
+#[tauri::command]
+async fn set_log_display_level(
+    state: tauri::State<'_, AppState>,
+    level: u8,
+) -> Result<(), AppError> {
+    if level > cobot_comms::log_level::NONE {
+        return Err(format!("{} is not a log level", level).into());
+    }
+
+    if let Some(cobot) = state.cobot.lock().await.as_mut() {
+        cobot
+            .set_log_display_level(level)
+            .map_err(|e| format!("Failed to set log display level: {}", e))?;
+    }
+    state.settings.lock().await.log_display_level = Some(level);
+    state.save_settings().await
+}
+
+/// Tell the backend the frontend is still responsive. While any joint is moving under
+/// `move_joint_continuous`, or any other motion command or test plan is in progress, this must be
+/// called more often than the watchdog timeout, otherwise all joints are stopped, any test plan is
+/// aborted, and `cobot://watchdog-triggered` is emitted.
+#[tauri::command]
+async fn heartbeat(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    *state.last_heartbeat.lock().unwrap() = Instant::now();
     Ok(())
 }
 
+/// Set the time without a heartbeat after which motion is stopped, in milliseconds.
+/// `None` restores the default.
+#[tauri::command]
+async fn set_watchdog_timeout(
+    state: tauri::State<'_, AppState>,
+    timeout_ms: Option<u64>,
+) -> Result<(), AppError> {
+    if timeout_ms == Some(0) {
+        return Err("Watchdog timeout must be positive".into());
+    }
+
+    state.settings.lock().await.watchdog_timeout_ms = timeout_ms;
+    state.save_settings().await
+}
+
 fn main() {
     flexi_logger::Logger::try_with_env_or_str("info")
         .unwrap()
         .start()
         .unwrap();
 
-    tauri::Builder::default()
-        .manage(AppState {
-            cobot: Mutex::new(None),
-        })
+    let builder = tauri::Builder::default().setup(|app| {
+        let settings_path = app
+            .path_resolver()
+            .app_config_dir()
+            .map(|dir| dir.join(settings::SETTINGS_FILE));
+        let settings = settings_path
+            .as_deref()
+            .map(Settings::load)
+            .unwrap_or_default();
+        let recovery_path = app
+            .path_resolver()
+            .app_data_dir()
+            .map(|dir| dir.join(recovery::RECOVERY_FILE));
+        let recovered_session = recovery_path.as_deref().and_then(recovery::load);
+
+        let app_handle = app.app_handle();
+        let execution = Execution::new(Box::new(move |state| {
+            let _ = app_handle.emit_all(EXECUTION_STATE_EVENT, state);
+        }));
+
+        app.manage(AppState::new(
+            settings,
+            settings_path,
+            recovery_path,
+            recovered_session,
+            execution,
+        ));
+        tauri::async_runtime::spawn(watchdog(app.app_handle()));
+        tauri::async_runtime::spawn(forward_connection_events(app.app_handle()));
+        tauri::async_runtime::spawn(link_quality_monitor(app.app_handle()));
+        tauri::async_runtime::spawn(recovery::autosave(app.app_handle()));
+        tauri::async_runtime::spawn(idle_monitor(app.app_handle()));
+        Ok(())
+    });
+
+    // Never leave the arm moving once the window can no longer control it. Only the window being
+    // destroyed is handled, since a close request is followed by it, and other ways of closing
+    // the window skip the request.
+    let builder = builder.on_window_event(|event| {
+        if let tauri::WindowEvent::Destroyed = event.event() {
+            let app = event.window().app_handle();
+            let state = app.state::<AppState>();
+            tauri::async_runtime::block_on(state.stop_and_disconnect());
+            // A clean shutdown leaves nothing to recover.
+            recovery::finish(&state);
+        }
+    });
+
+    #[cfg(feature = "ws-bridge")]
+    let builder = builder.manage(bridge::BridgeState::new());
+
+    #[cfg(feature = "mqtt")]
+    let builder = builder.manage(telemetry::TelemetryState::new());
+
+    builder
         .invoke_handler(tauri::generate_handler![
             is_connected,
             connect,
+            probe_baud_rates,
+            connect_with_retry,
             disconnect,
             init,
             calibrate,
+            calibrate_sequential,
+            reset_and_reinit,
+            abort_calibration,
+            zero_joint,
+            zero_all_joints,
+            loopback_test,
+            get_joint_count,
+            get_liveness_timestamps,
+            get_device_info,
+            get_recovered_session,
+            restore_recovered_session,
+            discard_recovered_session,
+            set_autosave_interval,
+            set_idle_disconnect,
+            set_startup_sequence,
+            set_keep_alive,
+            get_auto_disconnected,
+            reconnect_after_idle,
+            get_cobot_info,
+            get_error_log,
             get_angles,
+            get_status,
+            set_feedback,
             move_joint,
-            stop_joint
+            move_joint_timed,
+            move_joint_relative,
+            move_all_relative,
+            follow_path,
+            ramped_move,
+            go_to_zero,
+            move_to_soft_limit,
+            set_gripper,
+            undo_last_move,
+            get_undo_depth,
+            get_joint_defaults,
+            set_joint_defaults,
+            get_max_speeds,
+            set_max_speeds,
+            get_speed_limits,
+            set_max_accel,
+            get_joint_display,
+            set_joint_display,
+            get_soft_limits,
+            set_soft_limits,
+            get_joint_limits,
+            set_joint_limits,
+            get_limit_warning_margins,
+            set_limit_warning_margins,
+            get_joint_corrections,
+            set_joint_corrections,
+            get_joint_offsets,
+            get_settings,
+            set_angle_units,
+            get_end_effector_pose,
+            get_end_effector_transform,
+            export_debug_report,
+            run_test_plan,
+            pause_execution,
+            resume_execution,
+            abort_execution,
+            get_execution_state,
+            run_backlash_test,
+            run_speed_test,
+            export_speed_test_csv,
+            wait_for_angle,
+            wait_for_stopped,
+            assert_pose,
+            set_kinematics,
+            move_joint_continuous,
+            move_until_contact,
+            move_speed_monitored,
+            get_protocol_info,
+            get_session_log,
+            clear_session_log,
+            hold_position,
+            release_hold,
+            subscribe_to_comms_events,
+            stop_joint,
+            stop_all_joints,
+            abort_all,
+            set_servo,
+            get_servo_state,
+            enable_motion,
+            set_motion_enable_timeout,
+            set_calibration_timeout,
+            set_joint_calibration_timeout,
+            set_response_timeout,
+            get_state,
+            get_calibration_status,
+            enter_bootloader,
+            decode_frame,
+            compute_crc8,
+            check_crc8,
+            flash_firmware,
+            get_angles_cached,
+            get_comms_stats,
+            reset_comms_stats,
+            get_port_info,
+            reset_crc_error_count,
+            get_timeouts,
+            set_fault_stop_severity,
+            set_log_display_level,
+            set_stall_detection,
+            set_link_quality_thresholds,
+            set_rate_limit,
+            set_payload_escaping,
+            heartbeat,
+            set_watchdog_timeout,
+            bridge::start_ws_bridge,
+            bridge::stop_ws_bridge,
+            telemetry::start_telemetry,
+            telemetry::stop_telemetry
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn commands_fail_when_not_connected() {
+        let execution = Execution::new(Box::new(|_| {}));
+        let state = AppState::new(Settings::default(), None, None, None, execution);
+        let result = state.with_cobot(|_| Ok::<_, AppError>(())).await;
+        assert!(matches!(result, Err(AppError::NotConnected)));
+
+        // Background polling doesn't get a connection either.
+        let result = state.with_cobot_background(|_| Ok::<_, AppError>(())).await;
+        assert!(matches!(result, Err(AppError::NotConnected)));
+    }
+}
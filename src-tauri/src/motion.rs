@@ -0,0 +1,172 @@
+//! Host-side motion profiles for moves the firmware can't shape itself.
+
+use std::{error::Error, time::Duration};
+
+use cobot_comms::{CobotConnection, JointMask};
+
+/// Interval between speed updates of a ramped move.
+pub const RAMP_TICK: Duration = Duration::from_millis(50);
+
+/// Distance covered while braking from the given speed, one speed step per tick.
+fn braking_distance(mut speed: f32, step: f32, tick: f32) -> f32 {
+    let mut distance = 0.0;
+    while speed > step {
+        speed -= step;
+        distance += speed * tick;
+    }
+    distance
+}
+
+/// Generate the speeds of a trapezoidal move, one per tick. Consecutive speeds, including the
+/// implicit zero before the first and after the last, never differ by more than `max_accel * tick`.
+///
+/// # Arguments
+///
+/// * `distance` - Distance to move, in degrees. Must be non-negative.
+/// * `max_speed` - Speed to cruise at, in degrees per second.
+/// * `max_accel` - Maximum change in speed, in degrees per second squared.
+/// * `tick` - Time each speed is held for.
+///
+/// # Returns
+///
+/// The speed to hold during each tick, in degrees per second. The distance covered falls short of
+/// `distance` by less than one tick at the final speed, so a ramped move should finish with a slow
+/// move to the exact target.
+pub fn ramp_speeds(distance: f32, max_speed: f32, max_accel: f32, tick: Duration) -> Vec<f32> {
+    let tick = tick.as_secs_f32();
+    let step = max_accel * tick;
+    let mut speeds = Vec::new();
+    let mut speed = 0.0;
+    let mut travelled = 0.0;
+
+    loop {
+        let remaining = distance - travelled;
+        let faster = (speed + step).min(max_speed);
+        speed = if faster * tick + braking_distance(faster, step, tick) <= remaining {
+            faster
+        } else if speed * tick + braking_distance(speed, step, tick) <= remaining {
+            speed
+        } else {
+            speed - step
+        };
+
+        if speed <= 0.0 {
+            return speeds;
+        }
+        speeds.push(speed);
+        travelled += speed * tick;
+    }
+}
+
+/// Move a joint to the target angle without exceeding the given acceleration, by streaming
+/// MOVE_SPEED updates along a trapezoidal profile and finishing with a slow MOVE_TO.
+///
+/// # Arguments
+///
+/// * `cobot` - Connection to the COBOT.
+/// * `joint` - Joint to move.
+/// * `target` - Angle to move to, in degrees.
+/// * `max_speed` - Speed to cruise at, in degrees per second.
+/// * `max_accel` - Maximum acceleration, in degrees per second squared.
+/// * `on_started` - Called once the COBOT has acknowledged the first request of the move, and not
+///   at all if it rejects it.
+pub fn ramped_move(
+    cobot: &mut CobotConnection,
+    joint: u8,
+    target: f32,
+    max_speed: f32,
+    max_accel: f32,
+    on_started: impl FnOnce(),
+) -> Result<(), Box<dyn Error>> {
+    let start = cobot
+        .get_joints()?
+        .get(joint as usize)
+        .map(|joint| joint.0)
+        .ok_or("Joint not reported by the COBOT")?;
+    let direction = (target - start).signum();
+    let speeds = ramp_speeds((target - start).abs(), max_speed, max_accel, RAMP_TICK);
+
+    let mut on_started = Some(on_started);
+    for speed in &speeds {
+        if let Err(e) = cobot.move_speed(&[(joint, direction * speed)]) {
+            cobot.stop(JointMask::joint(joint), true)?;
+            return Err(e);
+        }
+        if let Some(on_started) = on_started.take() {
+            on_started();
+        }
+        std::thread::sleep(RAMP_TICK);
+    }
+
+    // The final step of the ramp is at most one speed step, so settling at that speed keeps within
+    // the limit.
+    let settle_speed = (max_accel * RAMP_TICK.as_secs_f32()).min(max_speed);
+    let command_id = cobot.start_move_to(&[(joint, target, Some(settle_speed))])?;
+    if let Some(on_started) = on_started.take() {
+        on_started();
+    }
+    cobot.wait_for_done(command_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the speeds of a ramp against its limits, and that it covers the distance to within
+    /// one tick at its final speed.
+    fn check_ramp(distance: f32, max_speed: f32, max_accel: f32) {
+        let tick = RAMP_TICK.as_secs_f32();
+        let step = max_accel * tick;
+        let speeds = ramp_speeds(distance, max_speed, max_accel, RAMP_TICK);
+
+        let mut previous = 0.0;
+        for &speed in speeds.iter().chain([0.0].iter()) {
+            assert!(speed <= max_speed + 1e-4, "{} exceeds {}", speed, max_speed);
+            assert!(
+                (speed - previous).abs() <= step + 1e-4,
+                "{} to {} exceeds the acceleration limit",
+                previous,
+                speed
+            );
+            previous = speed;
+        }
+
+        let travelled: f32 = speeds.iter().map(|speed| speed * tick).sum();
+        let last = speeds.last().copied().unwrap_or(0.0);
+        assert!(travelled <= distance + 1e-3, "overshot {}", distance);
+        assert!(
+            distance - travelled <= last.max(step) * tick + 1e-3,
+            "stopped {} short of {}",
+            distance - travelled,
+            distance
+        );
+    }
+
+    #[test]
+    fn ramp_cruises_on_long_moves() {
+        check_ramp(90.0, 30.0, 60.0);
+        let speeds = ramp_speeds(90.0, 30.0, 60.0, RAMP_TICK);
+        assert!(speeds.contains(&30.0));
+    }
+
+    #[test]
+    fn ramp_turns_back_before_reaching_cruise_on_short_moves() {
+        check_ramp(2.0, 30.0, 60.0);
+        let speeds = ramp_speeds(2.0, 30.0, 60.0, RAMP_TICK);
+        assert!(speeds.iter().all(|&speed| speed < 30.0));
+    }
+
+    #[test]
+    fn ramp_respects_limits_over_a_range_of_moves() {
+        for distance in [0.1, 1.0, 7.5, 45.0, 180.0] {
+            for (max_speed, max_accel) in [(5.0, 10.0), (20.0, 200.0), (60.0, 30.0)] {
+                check_ramp(distance, max_speed, max_accel);
+            }
+        }
+    }
+
+    #[test]
+    fn ramp_is_empty_for_no_distance() {
+        assert!(ramp_speeds(0.0, 30.0, 60.0, RAMP_TICK).is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+//! Priority classes of the commands sharing the connection to the COBOT, and the gate that hands
+//! the connection to the most urgent of them first.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use cobot_comms::CancelHandle;
+use tokio::sync::{Mutex, MutexGuard, Notify};
+
+/// How urgently a command needs the connection. Commands of a higher class are given the
+/// connection before any `Normal` command waiting for it, whatever order they arrived in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Stopping the arm in an emergency. Also cancels whatever the connection is waiting on, so
+    /// the stop is the next request sent.
+    Emergency,
+
+    /// Stopping the arm, which waits for the request in flight but nothing queued behind it.
+    Control,
+
+    /// Everything else.
+    Normal,
+}
+
+/// Orders the commands waiting for a mutex by their priority class.
+#[derive(Debug, Default)]
+pub struct PriorityGate {
+    /// Number of `Emergency` and `Control` commands waiting for the mutex. `Normal` commands
+    /// give the mutex up while this is nonzero.
+    waiters: AtomicUsize,
+
+    /// Notified when the last of the `waiters` gets the mutex.
+    released: Notify,
+}
+
+impl PriorityGate {
+    /// Lock a mutex for a command of the given priority class.
+    ///
+    /// # Arguments
+    ///
+    /// * `mutex` - Mutex every command sharing the gate locks through it.
+    /// * `priority` - Priority class of the command.
+    /// * `cancel` - Cancelled for an `Emergency` command, to release whoever holds the mutex if
+    ///   it's waiting on the COBOT.
+    pub async fn lock<'a, T>(
+        &self,
+        mutex: &'a Mutex<T>,
+        priority: Priority,
+        cancel: &CancelHandle,
+    ) -> MutexGuard<'a, T> {
+        match priority {
+            Priority::Normal => self.lock_normal(mutex).await,
+            Priority::Emergency | Priority::Control => {
+                self.waiters.fetch_add(1, Ordering::SeqCst);
+                if priority == Priority::Emergency {
+                    cancel.cancel();
+                }
+                let guard = mutex.lock().await;
+                if self.waiters.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    self.released.notify_waiters();
+                }
+                guard
+            }
+        }
+    }
+
+    /// Lock a mutex for a `Normal` command, giving it up to any `Emergency` or `Control` command
+    /// waiting for it.
+    async fn lock_normal<'a, T>(&self, mutex: &'a Mutex<T>) -> MutexGuard<'a, T> {
+        loop {
+            let guard = mutex.lock().await;
+            if self.waiters.load(Ordering::SeqCst) == 0 {
+                return guard;
+            }
+            drop(guard);
+
+            let mut released = std::pin::pin!(self.released.notified());
+            released.as_mut().enable();
+            if self.waiters.load(Ordering::SeqCst) != 0 {
+                released.await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cobot_comms::{
+        checksum::crc8ccitt, received_msg_type, request_type, response_type, CobotConnection,
+        CommsError, JointMask, MockTransport, Transport, FIRMWARE_VERSION,
+    };
+    use std::{
+        io::{Read, Write},
+        sync::Arc,
+        time::Duration,
+    };
+
+    /// COBOT that acknowledges every request as soon as it's written, and finishes every request
+    /// but a move, which it carries on with until it's stopped.
+    struct BusyCobot(MockTransport);
+
+    impl Read for BusyCobot {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for BusyCobot {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let written = self.0.write(buf)?;
+            let (request, command_id) = requests(buf)[0];
+            self.0
+                .push_incoming(&response_frame(response_type::ACK, command_id));
+            if request != request_type::MOVE_TO {
+                self.0
+                    .push_incoming(&response_frame(response_type::DONE, command_id));
+            }
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Transport for BusyCobot {
+        fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+            self.0.set_timeout(timeout)
+        }
+
+        fn clear(&mut self) -> std::io::Result<()> {
+            self.0.clear()
+        }
+
+        fn baud_rate(&self) -> std::io::Result<u32> {
+            self.0.baud_rate()
+        }
+    }
+
+    /// Connection shared by the commands of a test, as the app shares it.
+    struct Shared {
+        gate: PriorityGate,
+        cobot: Mutex<CobotConnection<BusyCobot>>,
+        cancel: CancelHandle,
+    }
+
+    impl Shared {
+        /// Lock the connection as a command of the given class does.
+        async fn lock(&self, priority: Priority) -> MutexGuard<'_, CobotConnection<BusyCobot>> {
+            let cobot = self.gate.lock(&self.cobot, priority, &self.cancel).await;
+            self.cancel.reset();
+            cobot
+        }
+    }
+
+    /// Frame a response to a request as the COBOT would send it.
+    fn response_frame(response_type: u8, command_id: u32) -> Vec<u8> {
+        let mut message = vec![received_msg_type::RESPONSE, response_type];
+        message.extend_from_slice(&command_id.to_le_bytes());
+        let mut frame = vec![0x24, message.len() as u8, crc8ccitt(&message)];
+        frame.extend_from_slice(&message);
+        frame
+    }
+
+    /// Request type and command ID of each frame written, in order.
+    fn requests(mut written: &[u8]) -> Vec<(u8, u32)> {
+        let mut requests = Vec::new();
+        while !written.is_empty() {
+            let body = &written[3..3 + written[1] as usize];
+            requests.push((body[0], u32::from_le_bytes(body[1..5].try_into().unwrap())));
+            written = &written[3 + body.len()..];
+        }
+        requests
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+    async fn emergency_stop_is_sent_next_and_cancels_the_sequence() {
+        let mut cobot = CobotConnection::new(
+            BusyCobot(MockTransport::new()),
+            FIRMWARE_VERSION,
+            Duration::from_millis(20),
+        );
+        let cancel = CancelHandle::default();
+        cobot.set_cancel_handle(cancel.clone());
+        // A move that is never cancelled fails after this, rather than hanging the test.
+        cobot.set_calibration_timeout(Duration::from_secs(2));
+        cobot.assume_calibrated(JointMask::first(JointMask::MAX_JOINTS));
+        let shared = Arc::new(Shared {
+            gate: PriorityGate::default(),
+            cobot: Mutex::new(cobot),
+            cancel,
+        });
+
+        let (started, sequence_started) = tokio::sync::oneshot::channel();
+        let sequence = tokio::spawn({
+            let shared = shared.clone();
+            async move {
+                let mut cobot = shared.lock(Priority::Normal).await;
+                let _ = started.send(());
+                for angle in [10.0, 20.0, 30.0, 40.0] {
+                    cobot
+                        .move_to(&[(0, angle, None)])
+                        .map_err(|e| e.to_string())?;
+                }
+                Ok::<_, String>(())
+            }
+        });
+        sequence_started.await.unwrap();
+
+        let queued = tokio::spawn({
+            let shared = shared.clone();
+            async move {
+                let mut cobot = shared.lock(Priority::Normal).await;
+                cobot
+                    .go_home(JointMask::joint(1))
+                    .map_err(|e| e.to_string())
+            }
+        });
+        // Give the queued command time to start waiting for the connection.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut cobot = shared.lock(Priority::Emergency).await;
+        let all_joints = cobot.all_joints();
+        cobot.stop(all_joints, false).unwrap();
+        drop(cobot);
+
+        assert_eq!(
+            sequence.await.unwrap(),
+            Err(CommsError::Cancelled.to_string())
+        );
+        queued.await.unwrap().unwrap();
+
+        // The stop went out straight after the move being waited on, ahead of the queued command,
+        // and the rest of the sequence was never sent.
+        let shared = Arc::try_unwrap(shared).unwrap_or_else(|_| panic!("connection still shared"));
+        assert_eq!(
+            requests(&shared.cobot.into_inner().into_port().0.written),
+            [
+                (request_type::MOVE_TO, 0),
+                (request_type::STOP, 1),
+                (request_type::GO_HOME, 2)
+            ]
+        );
+    }
+}
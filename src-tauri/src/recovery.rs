@@ -0,0 +1,134 @@
+//! Periodic autosave of the session, so a crash doesn't lose it.
+//!
+//! Every few seconds the session report, the undo history, the session log and the settings are
+//! written to a recovery file in the app data directory. A clean shutdown stops autosaving and
+//! removes the file, so finding one at startup means the last session crashed, and its contents
+//! are offered to the frontend. The file is written to a temporary file first and renamed over the
+//! old one, so a crash while saving leaves the previous save intact. Saving never waits for the
+//! connection, so it can't hold up the serial link; while a command holds it, the session log from
+//! the last save is kept.
+
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use cobot_comms::SessionEntry;
+use log::warn;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+
+use crate::{settings, AppState};
+
+/// Name of the recovery file within the app data directory.
+pub const RECOVERY_FILE: &str = "recovery.json";
+
+/// Read the recovery file left by a session that didn't shut down cleanly, if there is one.
+pub fn load(path: &Path) -> Option<Value> {
+    let contents = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents)
+        .map_err(|e| warn!("Ignoring invalid recovery file {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Stop autosaving and remove the recovery file, on a clean shutdown. A save being written is
+/// finished first, so no save can put the file back once it's removed.
+pub fn finish(state: &AppState) {
+    *state.autosave_enabled.lock().unwrap() = false;
+    if let Some(path) = &state.recovery_path {
+        remove(path);
+    }
+}
+
+/// Remove the recovery file.
+fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove recovery file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Write the snapshot to a temporary file beside the recovery file, then rename it over the
+/// recovery file, which replaces it in one step.
+fn write_atomically(path: &Path, snapshot: &Value) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, serde_json::to_string_pretty(snapshot)?)?;
+    fs::rename(&temp, path)?;
+
+    Ok(())
+}
+
+/// Take a snapshot of the session, marking any session restored from an earlier crash as such.
+///
+/// # Arguments
+///
+/// * `session_log` - Session log of the connection as last read, updated if the connection is
+///   free.
+async fn snapshot(state: &AppState, session_log: &mut Vec<SessionEntry>) -> Value {
+    let saved_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+    let settings = state.settings.lock().await.clone();
+    if let Ok(cobot) = state.cobot.try_lock() {
+        *session_log = cobot
+            .as_ref()
+            .map(|cobot| cobot.session_log().last(usize::MAX))
+            .unwrap_or_default();
+    }
+    json!({
+        "saved_at": saved_at,
+        "last_test_plan": state.last_test_plan.lock().unwrap().clone(),
+        "backlash": state.backlash_reports.lock().unwrap().clone(),
+        "speed_tests": state.speed_test_reports.lock().unwrap().clone(),
+        "undo_stack": state.undo_stack.lock().unwrap().clone(),
+        "recovered": state.restored_session.lock().unwrap().clone(),
+        "watchdog_incidents": state.watchdog_incidents.lock().unwrap().clone(),
+        "session_log": session_log,
+        "settings": settings,
+    })
+}
+
+/// Save the session to the recovery file every `autosave_interval_s` seconds, until `finish` is
+/// called. Does nothing if there is no app data directory.
+pub async fn autosave(app: AppHandle) {
+    let state = app.state::<AppState>();
+    let Some(path) = state.recovery_path.clone() else {
+        return;
+    };
+
+    let mut session_log = Vec::new();
+    while *state.autosave_enabled.lock().unwrap() {
+        let interval = state
+            .settings
+            .lock()
+            .await
+            .autosave_interval_s
+            .unwrap_or(settings::DEFAULT_AUTOSAVE_INTERVAL_S);
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        let snapshot = snapshot(&state, &mut session_log).await;
+        let path = path.clone();
+        let app = app.clone();
+        let saved = tauri::async_runtime::spawn_blocking(move || {
+            // Holding the flag while writing keeps `finish` from removing the file mid-save.
+            let state = app.state::<AppState>();
+            let enabled = state.autosave_enabled.lock().unwrap();
+            if !*enabled {
+                return Ok(());
+            }
+            write_atomically(&path, &snapshot).map_err(|e| e.to_string())
+        })
+        .await;
+        match saved {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Failed to autosave the session: {}", e),
+            Err(e) => warn!("Failed to autosave the session: {}", e),
+        }
+    }
+}
@@ -0,0 +1,815 @@
+//! Host-side settings, persisted as JSON in the app config directory.
+
+use crate::kinematics::DhParameters;
+use cobot_comms::{plan::Step, JointMask, LinkQualityThresholds, RateLimit, StallDetection};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, f32::consts::TAU, fs, path::Path, time::Duration};
+
+/// Name of the settings file within the app config directory.
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Speed that can be passed to a motion command to explicitly use the firmware's default speed
+/// instead of the configured per-joint default.
+pub const FIRMWARE_DEFAULT_SPEED: f32 = -1.0;
+
+/// Heartbeat window used when none is configured, in milliseconds.
+pub const DEFAULT_WATCHDOG_TIMEOUT_MS: u64 = 1500;
+
+/// Interval between autosaves of the session when none is configured, in seconds.
+pub const DEFAULT_AUTOSAVE_INTERVAL_S: u64 = 30;
+
+/// Time motion stays enabled when no timeout is configured, in milliseconds.
+pub const DEFAULT_MOTION_ENABLE_TIMEOUT_MS: u64 = 30_000;
+
+/// Time to wait for a response other than a DONE when none is configured, in milliseconds.
+pub const DEFAULT_RESPONSE_TIMEOUT_MS: u64 = 100;
+
+/// Time to wait for a single joint to calibrate when none is configured, in milliseconds.
+pub const DEFAULT_JOINT_CALIBRATION_TIMEOUT_MS: u64 = 60_000;
+
+/// Progress a moving joint must make within the stall window when none is configured, in degrees.
+pub const DEFAULT_STALL_MIN_PROGRESS: f32 = 1.0;
+
+/// Number of joints in the fixed-size per-joint tables exchanged with the frontend.
+pub const TABLE_JOINTS: usize = 6;
+
+/// Settings that persist between sessions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Speed to use for each joint when a move doesn't specify one, in degrees per second. `None`
+    /// leaves the speed up to the firmware.
+    pub default_speeds: Vec<Option<f32>>,
+
+    /// Speed limit of each joint for timed moves, in degrees per second. `None` if a joint has no
+    /// limit.
+    pub max_speeds: Vec<Option<f32>>,
+
+    /// How each joint is presented to the operator.
+    pub joint_display: Vec<JointDisplay>,
+
+    /// Correction of each joint's reported angle for mechanical offsets.
+    pub joint_corrections: Vec<JointCorrection>,
+
+    /// Soft limits of each joint, in the display frame. `None` if a joint has no limits. Moves to
+    /// an angle outside them are refused.
+    pub soft_limits: Vec<Option<JointLimits>>,
+
+    /// Distance from each joint's soft limits within which a move is warned about, in degrees.
+    /// `None` if moves of a joint are never warned about.
+    pub limit_warning_margins: Vec<Option<f32>>,
+
+    /// Units of the angles and speeds exchanged with the frontend.
+    pub angle_units: AngleUnits,
+
+    /// Kinematic parameters of each joint, from the base outwards. Empty if the arm's kinematics
+    /// aren't configured.
+    pub kinematics: Vec<DhParameters>,
+
+    /// Acceleration limit for ramped moves, in degrees per second squared. `None` if not
+    /// configured.
+    pub max_accel: Option<f32>,
+
+    /// Time without a heartbeat from the frontend after which continuous motion is stopped, in
+    /// milliseconds. `None` to use `DEFAULT_WATCHDOG_TIMEOUT_MS`.
+    pub watchdog_timeout_ms: Option<u64>,
+
+    /// Time motion stays enabled after it is enabled or refreshed, in milliseconds. `None` to use
+    /// `DEFAULT_MOTION_ENABLE_TIMEOUT_MS`.
+    pub motion_enable_timeout_ms: Option<u64>,
+
+    /// Time to wait for calibration and other long operations to finish, in milliseconds. `None`
+    /// to use the connection's default.
+    pub calibration_timeout_ms: Option<u64>,
+
+    /// Time to wait for any other response from the COBOT, such as an ACK, in milliseconds. `None`
+    /// to use `DEFAULT_RESPONSE_TIMEOUT_MS`.
+    pub response_timeout_ms: Option<u64>,
+
+    /// Time to wait for each joint to finish calibrating during a sequential calibration, in
+    /// milliseconds. `None` to use `DEFAULT_JOINT_CALIBRATION_TIMEOUT_MS`.
+    pub joint_calibration_timeout_ms: Option<u64>,
+
+    /// Severity at or above which a fault reported by the COBOT stops every joint. `None` never
+    /// stops the joints on a fault.
+    pub fault_stop_severity: Option<u8>,
+
+    /// Lowest level of COBOT log message shown, independent of the level the firmware sends at.
+    /// `None` shows every message the firmware sends.
+    pub log_display_level: Option<u8>,
+
+    /// Time a moving joint has to make progress before it is suspected to have stalled, in
+    /// milliseconds. `None` disables stall detection.
+    pub stall_window_ms: Option<u64>,
+
+    /// Progress a moving joint must make within the stall window, in degrees. `None` to use
+    /// `DEFAULT_STALL_MIN_PROGRESS`.
+    pub stall_min_progress: Option<f32>,
+
+    /// Whether to stop a joint as soon as it is suspected to have stalled.
+    pub stall_auto_stop: bool,
+
+    /// Thresholds for grading the link to the COBOT. `None` to use the defaults.
+    pub link_quality_thresholds: Option<LinkQualityThresholds>,
+
+    /// Limit on how often requests are sent to the COBOT. `None`, the default, doesn't limit them.
+    pub rate_limit: Option<RateLimit>,
+
+    /// Whether payloads are escaped so they never contain a start byte. The firmware must escape
+    /// them too, so this is off by default for older firmware.
+    pub payload_escaping: bool,
+
+    /// Interval between autosaves of the session for crash recovery, in seconds. `None` to use
+    /// `DEFAULT_AUTOSAVE_INTERVAL_S`.
+    pub autosave_interval_s: Option<u64>,
+
+    /// Time without a command from the operator after which the COBOT is disconnected and its port
+    /// released, in seconds. `None`, the default, never disconnects an idle COBOT.
+    pub idle_disconnect_s: Option<u64>,
+
+    /// Steps run after connecting when `connect` is asked to bring the COBOT up, such as
+    /// initializing, calibrating and moving to a safe pose. Empty if none are configured.
+    pub startup_sequence: Vec<Step>,
+}
+
+/// Units used for angles (and speeds, per second) outside the app. Settings and the COBOT always
+/// use degrees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AngleUnits {
+    #[default]
+    Degrees,
+    Radians,
+}
+
+/// Transform between the firmware's frame for a joint, after any `JointCorrection`, and the frame
+/// shown to the operator.
+///
+/// A display angle is `sign * firmware angle + offset`, so a joint mounted inverted with its
+/// mechanical zero 12.5° from the firmware zero has a sign of `-1` and an offset of `12.5`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JointDisplay {
+    /// Name shown for the joint. Empty to use the default name.
+    pub name: String,
+
+    /// Direction of the joint relative to the firmware, either `1` or `-1`.
+    pub sign: i8,
+
+    /// Angle of the firmware's zero in the display frame, in degrees.
+    pub offset: f32,
+}
+
+impl Default for JointDisplay {
+    fn default() -> Self {
+        JointDisplay {
+            name: String::new(),
+            sign: 1,
+            offset: 0.0,
+        }
+    }
+}
+
+impl JointDisplay {
+    /// Convert an angle reported by the firmware to the display frame.
+    pub fn to_display(&self, angle: f32) -> f32 {
+        self.sign as f32 * angle + self.offset
+    }
+
+    /// Convert an angle in the display frame to the firmware's frame.
+    pub fn to_firmware(&self, angle: f32) -> f32 {
+        self.sign as f32 * (angle - self.offset)
+    }
+
+    /// Convert a signed speed between frames. The conversion is the same in both directions.
+    pub fn convert_speed(&self, speed: f32) -> f32 {
+        self.sign as f32 * speed
+    }
+}
+
+/// Range of angles a joint may be moved to, in degrees.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct JointLimits {
+    /// Lowest allowed angle.
+    pub min: f32,
+
+    /// Highest allowed angle.
+    pub max: f32,
+}
+
+/// Check a table of soft limits before it's saved.
+///
+/// # Arguments
+///
+/// * `joints` - Soft limits of each joint, by joint index. `None` leaves a joint without limits.
+///
+/// # Returns
+///
+/// A message naming the first joint whose limits are beyond the joints a COBOT can have, not
+/// finite, or don't have their minimum below their maximum.
+pub fn validate_soft_limits(joints: &[Option<JointLimits>]) -> Result<(), String> {
+    for (joint, limits) in joints.iter().enumerate() {
+        let Some(limits) = limits else {
+            continue;
+        };
+        if joint >= JointMask::MAX_JOINTS as usize {
+            return Err(format!(
+                "Soft limits given for joint {}, only {} joints are supported",
+                joint,
+                JointMask::MAX_JOINTS
+            ));
+        }
+        if !limits.min.is_finite() || !limits.max.is_finite() || limits.min >= limits.max {
+            return Err(format!("Invalid soft limits for joint {}", joint));
+        }
+    }
+
+    Ok(())
+}
+
+/// Host-side correction of the angle a joint reports, for mechanical offsets the firmware's
+/// calibration doesn't account for.
+///
+/// A corrected angle is `scale * reported angle + offset`, so a joint that reports 90° when it is
+/// physically at 88° can be corrected with a scale of `1` and an offset of `-2`. The corrected
+/// angle is treated as the joint's real angle everywhere on the host.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JointCorrection {
+    /// Ratio of the real angle to the reported angle. Must not be zero.
+    pub scale: f32,
+
+    /// Real angle of the joint when it reports 0°, in degrees.
+    pub offset: f32,
+}
+
+impl Default for JointCorrection {
+    fn default() -> Self {
+        JointCorrection {
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl JointCorrection {
+    /// Correct an angle reported by the firmware.
+    pub fn correct(&self, angle: f32) -> f32 {
+        self.scale * angle + self.offset
+    }
+
+    /// Convert a corrected angle back to the angle the firmware uses.
+    pub fn uncorrect(&self, angle: f32) -> f32 {
+        (angle - self.offset) / self.scale
+    }
+
+    /// Correct a signed speed reported by the firmware.
+    pub fn correct_speed(&self, speed: f32) -> f32 {
+        self.scale * speed
+    }
+
+    /// Convert a corrected signed speed back to the speed the firmware uses.
+    pub fn uncorrect_speed(&self, speed: f32) -> f32 {
+        speed / self.scale
+    }
+}
+
+impl Settings {
+    /// Load the settings from the given file. Missing or unreadable files give the default
+    /// settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the settings file.
+    pub fn load(path: &Path) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Settings::default(),
+        };
+
+        serde_json::from_str(&contents).unwrap_or_else(|e| {
+            warn!("Ignoring invalid settings file {}: {}", path.display(), e);
+            Settings::default()
+        })
+    }
+
+    /// Save the settings to the given file, creating its directory if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the settings file.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Get the display transform of the given joint, or the identity transform if none is
+    /// configured.
+    pub fn joint_display(&self, joint: u8) -> JointDisplay {
+        self.joint_display
+            .get(joint as usize)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get the speed limit of the given joint for timed moves, if it has one.
+    pub fn max_speed(&self, joint: u8) -> Option<f32> {
+        self.max_speeds.get(joint as usize).copied().flatten()
+    }
+
+    /// Get the soft limits of the given joint, if it has any.
+    pub fn soft_limits(&self, joint: u8) -> Option<JointLimits> {
+        self.soft_limits.get(joint as usize).copied().flatten()
+    }
+
+    /// Check that an angle is within the soft limits of the given joint, if it has any.
+    ///
+    /// # Arguments
+    ///
+    /// * `angle` - Angle the joint is moving to, in the display frame and degrees.
+    pub fn check_soft_limits(&self, joint: u8, angle: f32) -> Result<(), String> {
+        match self.soft_limits(joint) {
+            Some(limits) if angle < limits.min || angle > limits.max => Err(format!(
+                "Moving joint {} to {}° would pass its soft limits",
+                joint, angle
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the speed limit of each joint in a per-joint table. `None` means a joint has no limit.
+    pub fn speed_limits(&self) -> [Option<f32>; TABLE_JOINTS] {
+        std::array::from_fn(|joint| self.max_speed(joint as u8))
+    }
+
+    /// Get the soft limits of each joint in a per-joint table, as `(min, max)`. `None` means a
+    /// joint has no limits.
+    pub fn joint_limits(&self) -> [Option<(f32, f32)>; TABLE_JOINTS] {
+        std::array::from_fn(|joint| {
+            self.soft_limits(joint as u8)
+                .map(|limits| (limits.min, limits.max))
+        })
+    }
+
+    /// Set the soft limits of the first six joints from a per-joint table of `(min, max)`, leaving
+    /// any limits of later joints as they are. Nothing is changed if any limits are invalid.
+    pub fn set_joint_limits(
+        &mut self,
+        limits: [Option<(f32, f32)>; TABLE_JOINTS],
+    ) -> Result<(), String> {
+        let mut soft_limits = self.soft_limits.clone();
+        if soft_limits.len() < TABLE_JOINTS {
+            soft_limits.resize(TABLE_JOINTS, None);
+        }
+        for (joint, limits) in limits.into_iter().enumerate() {
+            soft_limits[joint] = limits.map(|(min, max)| JointLimits { min, max });
+        }
+        validate_soft_limits(&soft_limits)?;
+
+        self.soft_limits = soft_limits;
+        Ok(())
+    }
+
+    /// Get the calibration offset of each joint in a per-joint table, in degrees.
+    pub fn joint_offsets(&self) -> [f32; TABLE_JOINTS] {
+        std::array::from_fn(|joint| self.joint_correction(joint as u8).offset)
+    }
+
+    /// Get the limit warning margin of the given joint, if it has one.
+    pub fn limit_warning_margin(&self, joint: u8) -> Option<f32> {
+        self.limit_warning_margins
+            .get(joint as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// Get the correction of the given joint, or the identity correction if none is configured.
+    pub fn joint_correction(&self, joint: u8) -> JointCorrection {
+        self.joint_corrections
+            .get(joint as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Convert an angle reported by the firmware to the display frame, in degrees.
+    pub fn to_display_angle(&self, joint: u8, angle: f32) -> f32 {
+        self.joint_display(joint)
+            .to_display(self.joint_correction(joint).correct(angle))
+    }
+
+    /// Convert an angle in the display frame, in degrees, to the firmware's frame.
+    pub fn to_firmware_angle(&self, joint: u8, angle: f32) -> f32 {
+        self.joint_correction(joint)
+            .uncorrect(self.joint_display(joint).to_firmware(angle))
+    }
+
+    /// Convert a signed speed reported by the firmware to the display frame, in degrees per
+    /// second.
+    pub fn to_display_speed(&self, joint: u8, speed: f32) -> f32 {
+        self.joint_display(joint)
+            .convert_speed(self.joint_correction(joint).correct_speed(speed))
+    }
+
+    /// Convert a signed speed in the display frame, in degrees per second, to the firmware's
+    /// frame.
+    pub fn to_firmware_speed(&self, joint: u8, speed: f32) -> f32 {
+        self.joint_correction(joint)
+            .uncorrect_speed(self.joint_display(joint).convert_speed(speed))
+    }
+
+    /// Get the configured stall detection thresholds, or `None` if stall detection is disabled.
+    pub fn stall_detection(&self) -> Option<StallDetection> {
+        self.stall_window_ms.map(|window_ms| StallDetection {
+            window: Duration::from_millis(window_ms),
+            min_progress: self
+                .stall_min_progress
+                .unwrap_or(DEFAULT_STALL_MIN_PROGRESS),
+            auto_stop: self.stall_auto_stop,
+        })
+    }
+
+    /// Apply the joint corrections to a set of angles reported by the firmware.
+    pub fn corrected_angles(&self, angles: &[f32]) -> Vec<f32> {
+        angles
+            .iter()
+            .enumerate()
+            .map(|(joint, angle)| self.joint_correction(joint as u8).correct(*angle))
+            .collect()
+    }
+
+    /// Convert an angle in the active units to degrees.
+    ///
+    /// In radian mode, angles beyond a full turn are rejected since they are almost certainly
+    /// degrees entered by mistake.
+    pub fn angle_to_degrees(&self, angle: f32) -> Result<f32, Box<dyn Error>> {
+        match self.angle_units {
+            AngleUnits::Degrees => Ok(angle),
+            AngleUnits::Radians if angle.abs() > TAU => Err(format!(
+                "{} rad is more than a full turn; was it meant to be in degrees?",
+                angle
+            )
+            .into()),
+            AngleUnits::Radians => Ok(angle.to_degrees()),
+        }
+    }
+
+    /// Convert an angle or speed in degrees to the active units.
+    pub fn degrees_to_units(&self, value: f32) -> f32 {
+        match self.angle_units {
+            AngleUnits::Degrees => value,
+            AngleUnits::Radians => value.to_radians(),
+        }
+    }
+
+    /// Convert a signed speed in the active units to degrees per second.
+    pub fn speed_to_degrees(&self, speed: f32) -> f32 {
+        match self.angle_units {
+            AngleUnits::Degrees => speed,
+            AngleUnits::Radians => speed.to_degrees(),
+        }
+    }
+
+    /// Convert a requested move speed in the active units to degrees per second, leaving an
+    /// omitted speed, `0`, and `FIRMWARE_DEFAULT_SPEED` as they are.
+    pub fn move_speed_to_degrees(&self, speed: Option<f32>) -> Option<f32> {
+        speed.map(|speed| {
+            if speed > 0.0 {
+                self.speed_to_degrees(speed)
+            } else {
+                speed
+            }
+        })
+    }
+
+    /// Determine the speed to send to the COBOT for a move of the given joint.
+    ///
+    /// A speed is resolved in this order:
+    ///
+    /// 1. The caller converts it from the active units with `move_speed_to_degrees`, which leaves
+    ///    an omitted speed, `0` and `FIRMWARE_DEFAULT_SPEED` untouched so they keep their meaning
+    ///    in radian mode.
+    /// 2. `FIRMWARE_DEFAULT_SPEED` becomes `None` so the firmware picks the speed, even if the
+    ///    joint has a configured default.
+    /// 3. A missing or zero speed is replaced by the joint's configured default. Defaults are
+    ///    stored in degrees per second, so they are never converted from the active units.
+    /// 4. Any other speed is sent as it is.
+    ///
+    /// This is the first step in resolving a speed; any scaling or limiting is applied to its
+    /// result.
+    ///
+    /// # Arguments
+    ///
+    /// * `joint` - Joint being moved.
+    /// * `speed` - Speed requested by the caller, in degrees per second. Use
+    ///   `move_speed_to_degrees` to convert a speed in the active units first.
+    ///
+    /// # Returns
+    ///
+    /// The speed to send, or `None` to use the firmware's default.
+    pub fn resolve_speed(&self, joint: u8, speed: Option<f32>) -> Option<f32> {
+        match speed {
+            Some(speed) if speed == FIRMWARE_DEFAULT_SPEED => None,
+            Some(speed) if speed != 0.0 => Some(speed),
+            _ => self.default_speeds.get(joint as usize).copied().flatten(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Settings with joint 0 upright and joint 1 inverted, both with their zero offset.
+    fn offset_settings() -> Settings {
+        Settings {
+            joint_display: vec![
+                JointDisplay {
+                    name: String::new(),
+                    sign: 1,
+                    offset: 12.5,
+                },
+                JointDisplay {
+                    name: String::new(),
+                    sign: -1,
+                    offset: -30.0,
+                },
+            ],
+            ..Settings::default()
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn angles_round_trip_through_the_firmware_frame() {
+        let settings = offset_settings();
+        for joint in 0..2 {
+            for angle in [-170.0, -12.5, 0.0, 30.0, 95.25] {
+                let firmware = settings.to_firmware_angle(joint, angle);
+                assert_close(settings.to_display_angle(joint, firmware), angle);
+            }
+        }
+    }
+
+    #[test]
+    fn speeds_round_trip_through_the_firmware_frame() {
+        let settings = offset_settings();
+        for joint in 0..2 {
+            for speed in [-45.0, -0.5, 0.0, 10.0, 90.0] {
+                let firmware = settings.to_firmware_speed(joint, speed);
+                assert_close(settings.to_display_speed(joint, firmware), speed);
+            }
+        }
+    }
+
+    #[test]
+    fn display_transform_applies_sign_and_offset() {
+        let settings = offset_settings();
+        assert_close(settings.to_display_angle(0, 10.0), 22.5);
+        assert_close(settings.to_firmware_angle(0, 22.5), 10.0);
+        assert_close(settings.to_display_angle(1, 10.0), -40.0);
+        assert_close(settings.to_firmware_angle(1, -40.0), 10.0);
+
+        // Offsets don't apply to speeds, only the direction does.
+        assert_close(settings.to_display_speed(0, 20.0), 20.0);
+        assert_close(settings.to_display_speed(1, 20.0), -20.0);
+        assert_close(settings.to_firmware_speed(1, -20.0), 20.0);
+    }
+
+    #[test]
+    fn round_trip_includes_joint_corrections() {
+        let settings = Settings {
+            joint_corrections: vec![
+                JointCorrection::default(),
+                JointCorrection {
+                    scale: 0.5,
+                    offset: -2.0,
+                },
+            ],
+            ..offset_settings()
+        };
+        for angle in [-90.0, 0.0, 45.0] {
+            let firmware = settings.to_firmware_angle(1, angle);
+            assert_close(settings.to_display_angle(1, firmware), angle);
+        }
+        for speed in [-30.0, 15.0] {
+            let firmware = settings.to_firmware_speed(1, speed);
+            assert_close(settings.to_display_speed(1, firmware), speed);
+        }
+    }
+
+    #[test]
+    fn angles_in_degrees_are_passed_through() {
+        let settings = Settings::default();
+        assert_eq!(settings.angle_to_degrees(720.0).unwrap(), 720.0);
+        assert_eq!(settings.angle_to_degrees(-45.0).unwrap(), -45.0);
+    }
+
+    #[test]
+    fn radian_angles_beyond_a_full_turn_are_rejected() {
+        let settings = Settings {
+            angle_units: AngleUnits::Radians,
+            ..Settings::default()
+        };
+        assert!(settings.angle_to_degrees(720.0).is_err());
+        assert!(settings.angle_to_degrees(-720.0).is_err());
+
+        // A full turn either way is still accepted.
+        assert_close(settings.angle_to_degrees(TAU).unwrap(), 360.0);
+        assert_close(settings.angle_to_degrees(-TAU).unwrap(), -360.0);
+        assert!(settings.angle_to_degrees(TAU + 1e-3).is_err());
+        assert!(settings.angle_to_degrees(-TAU - 1e-3).is_err());
+    }
+
+    /// Resolve a requested speed in the active units as a move command does.
+    fn requested_speed(settings: &Settings, joint: u8, speed: Option<f32>) -> Option<f32> {
+        settings.resolve_speed(joint, settings.move_speed_to_degrees(speed))
+    }
+
+    fn default_speed_settings(angle_units: AngleUnits) -> Settings {
+        Settings {
+            default_speeds: vec![Some(30.0), None],
+            angle_units,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn omitted_and_zero_speeds_use_the_joint_default() {
+        let settings = default_speed_settings(AngleUnits::Degrees);
+        assert_eq!(requested_speed(&settings, 0, None), Some(30.0));
+        assert_eq!(requested_speed(&settings, 0, Some(0.0)), Some(30.0));
+
+        // Without a default the firmware picks the speed.
+        assert_eq!(requested_speed(&settings, 1, None), None);
+        assert_eq!(requested_speed(&settings, 1, Some(0.0)), None);
+        assert_eq!(requested_speed(&settings, 5, None), None);
+    }
+
+    #[test]
+    fn firmware_default_speed_overrides_the_joint_default() {
+        for units in [AngleUnits::Degrees, AngleUnits::Radians] {
+            let settings = default_speed_settings(units);
+            assert_eq!(
+                requested_speed(&settings, 0, Some(FIRMWARE_DEFAULT_SPEED)),
+                None
+            );
+            assert_eq!(
+                requested_speed(&settings, 1, Some(FIRMWARE_DEFAULT_SPEED)),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn explicit_speeds_are_converted_from_the_active_units() {
+        let settings = default_speed_settings(AngleUnits::Degrees);
+        assert_eq!(requested_speed(&settings, 0, Some(45.0)), Some(45.0));
+
+        let settings = default_speed_settings(AngleUnits::Radians);
+        assert_close(
+            requested_speed(&settings, 0, Some(std::f32::consts::FRAC_PI_2)).unwrap(),
+            90.0,
+        );
+    }
+
+    #[test]
+    fn joint_defaults_are_not_converted_in_radian_mode() {
+        let settings = default_speed_settings(AngleUnits::Radians);
+        assert_eq!(requested_speed(&settings, 0, None), Some(30.0));
+        assert_eq!(requested_speed(&settings, 0, Some(0.0)), Some(30.0));
+    }
+
+    #[test]
+    fn per_joint_tables_cover_exactly_six_joints() {
+        let limits = |min, max| Some(JointLimits { min, max });
+        let settings = Settings {
+            max_speeds: vec![Some(30.0), None, Some(12.5)],
+            soft_limits: vec![None, limits(-90.0, 45.0)],
+            joint_corrections: vec![
+                JointCorrection {
+                    scale: 1.0,
+                    offset: -2.0,
+                };
+                7
+            ],
+            ..Settings::default()
+        };
+
+        // Joints with nothing configured are unlimited and uncorrected.
+        assert_eq!(
+            settings.speed_limits(),
+            [Some(30.0), None, Some(12.5), None, None, None]
+        );
+        assert_eq!(
+            settings.joint_limits(),
+            [None, Some((-90.0, 45.0)), None, None, None, None]
+        );
+        assert_eq!(Settings::default().joint_offsets(), [0.0; TABLE_JOINTS]);
+        // Joints past the sixth are left out.
+        assert_eq!(settings.joint_offsets(), [-2.0; TABLE_JOINTS]);
+    }
+
+    #[test]
+    fn soft_limits_need_their_minimum_below_their_maximum() {
+        let limits = |min, max| Some(JointLimits { min, max });
+        assert_eq!(
+            validate_soft_limits(&[limits(-90.0, 90.0), None, limits(0.0, 0.5)]),
+            Ok(())
+        );
+        assert_eq!(
+            validate_soft_limits(&[limits(-90.0, 90.0), limits(10.0, -10.0)]),
+            Err("Invalid soft limits for joint 1".to_string())
+        );
+        assert_eq!(
+            validate_soft_limits(&[None, None, limits(45.0, 45.0)]),
+            Err("Invalid soft limits for joint 2".to_string())
+        );
+    }
+
+    #[test]
+    fn soft_limits_must_be_finite() {
+        for (min, max) in [
+            (f32::NAN, 90.0),
+            (-90.0, f32::NAN),
+            (f32::NEG_INFINITY, 90.0),
+            (-90.0, f32::INFINITY),
+        ] {
+            assert_eq!(
+                validate_soft_limits(&[None, Some(JointLimits { min, max })]),
+                Err("Invalid soft limits for joint 1".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn soft_limits_must_be_for_an_addressable_joint() {
+        let mut joints = vec![None; JointMask::MAX_JOINTS as usize + 2];
+        assert_eq!(validate_soft_limits(&joints), Ok(()));
+        joints[JointMask::MAX_JOINTS as usize - 1] = Some(JointLimits {
+            min: -10.0,
+            max: 10.0,
+        });
+        assert_eq!(validate_soft_limits(&joints), Ok(()));
+        joints[JointMask::MAX_JOINTS as usize] = Some(JointLimits {
+            min: -10.0,
+            max: 10.0,
+        });
+        assert_eq!(
+            validate_soft_limits(&joints),
+            Err(format!(
+                "Soft limits given for joint {}, only {} joints are supported",
+                JointMask::MAX_JOINTS,
+                JointMask::MAX_JOINTS
+            ))
+        );
+    }
+
+    #[test]
+    fn joint_limit_tables_replace_the_soft_limits_of_six_joints() {
+        let seventh = JointLimits {
+            min: -5.0,
+            max: 5.0,
+        };
+        let mut settings = Settings {
+            soft_limits: vec![None, None, None, None, None, None, Some(seventh)],
+            ..Settings::default()
+        };
+        let table = [
+            Some((-90.0, 90.0)),
+            None,
+            None,
+            None,
+            None,
+            Some((0.0, 180.0)),
+        ];
+        settings.set_joint_limits(table).unwrap();
+        assert_eq!(settings.joint_limits(), table);
+
+        // Moves are checked against the new table, and later joints keep their limits.
+        assert!(settings.check_soft_limits(0, 90.0).is_ok());
+        assert!(settings.check_soft_limits(0, 90.5).is_err());
+        assert!(settings.check_soft_limits(1, 1000.0).is_ok());
+        assert!(settings.check_soft_limits(5, -1.0).is_err());
+        assert!(settings.check_soft_limits(6, 6.0).is_err());
+
+        // An invalid table changes nothing.
+        assert_eq!(
+            settings.set_joint_limits([None, None, Some((10.0, 10.0)), None, None, None]),
+            Err("Invalid soft limits for joint 2".to_string())
+        );
+        assert_eq!(settings.joint_limits(), table);
+    }
+}
@@ -0,0 +1,261 @@
+//! Measurement of how closely a joint follows a commanded speed.
+//!
+//! For each commanded speed, the joint is moved at that speed from where it is, and its angle is
+//! read as fast as the link allows until it has covered the requested travel. The achieved speed
+//! is the slope of a least-squares line through the angle-vs-time samples, leaving out the start of
+//! the travel while the joint accelerates. The joint is stopped once the travel is covered, so the
+//! deceleration is never sampled, and it's stopped again before the next speed. Angles and speeds
+//! are in the display frame and the active units, as in the Tauri commands.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::{checks, AppState};
+
+/// Fraction of the travel at the start of each point left out of the regression, while the joint
+/// accelerates.
+const ACCELERATION_FRACTION: f32 = 0.25;
+
+/// Fewest samples the regression needs for a point to be measured.
+const MIN_SAMPLES: usize = 5;
+
+/// Fraction of the commanded speed below which the joint counts as stopped between points.
+const STOPPED_FRACTION: f32 = 0.02;
+
+/// Time the joint has to stop between points.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Extra time, on top of twice the expected travel time, before a point is given up on.
+const TRAVEL_GRACE: Duration = Duration::from_secs(2);
+
+/// Outcome of a single commanded speed.
+#[derive(Clone, Debug, Serialize)]
+pub struct SpeedPoint {
+    /// Speed commanded, per second. Its sign gives the direction.
+    pub commanded: f32,
+
+    /// Speed achieved during the steady-state portion of the motion, per second.
+    pub achieved: f32,
+
+    /// Difference between the achieved and commanded speeds, as a percentage of the commanded
+    /// speed.
+    pub error_percent: f32,
+
+    /// Number of samples in the regression.
+    pub samples: usize,
+}
+
+/// Outcome of a speed accuracy test.
+#[derive(Clone, Debug, Serialize)]
+pub struct SpeedTestReport {
+    /// Joint tested.
+    pub joint: u8,
+
+    /// Distance the joint was moved for each speed.
+    pub travel: f32,
+
+    /// Outcome of each speed measured, in the order they were commanded.
+    pub points: Vec<SpeedPoint>,
+
+    /// Why the test stopped before measuring every speed, if it did. The joint is stopped
+    /// whenever this is set.
+    pub aborted: Option<String>,
+}
+
+impl SpeedTestReport {
+    /// Format the points as CSV, one row per commanded speed, with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("joint,commanded,achieved,error_percent,samples\n");
+        for point in &self.points {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                self.joint, point.commanded, point.achieved, point.error_percent, point.samples
+            ));
+        }
+        csv
+    }
+}
+
+/// Read the angle of a joint, always from the COBOT, since samples reused from a cache would skew
+/// the regression.
+async fn read_angle(app: &AppHandle, joint: u8) -> Result<f32, String> {
+    let state = app.state::<AppState>();
+    let joint_states = state
+        .with_cobot(|cobot| {
+            cobot
+                .get_joint_states()
+                .map_err(|e| format!("Failed to get joint states: {}", e))
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let settings = state.settings.lock().await;
+    crate::joint_sample(&settings, &joint_states)
+        .angles
+        .get(joint as usize)
+        .copied()
+        .ok_or_else(|| format!("Joint {} doesn't exist", joint))
+}
+
+/// Stop the joint and wait for it to come to rest.
+async fn stop(app: &AppHandle, joint: u8, speed: f32) -> Result<(), String> {
+    crate::stop_joint(app.state::<AppState>(), joint, false)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let threshold = speed.abs() * STOPPED_FRACTION;
+    let check = checks::wait_for_stopped(app, &[joint], threshold, STOP_TIMEOUT).await?;
+    if !check.passed {
+        return Err(format!("Joint {} didn't stop between points", joint));
+    }
+    Ok(())
+}
+
+/// Slope of the least-squares line through the samples, or `None` if they all share a time.
+fn regression_slope(samples: &[(f32, f32)]) -> Option<f32> {
+    let count = samples.len() as f32;
+    let mean_time = samples.iter().map(|(time, _)| time).sum::<f32>() / count;
+    let mean_angle = samples.iter().map(|(_, angle)| angle).sum::<f32>() / count;
+
+    let (covariance, variance) = samples
+        .iter()
+        .fold((0.0, 0.0), |(cov, var), (time, angle)| {
+            let dt = time - mean_time;
+            (cov + dt * (angle - mean_angle), var + dt * dt)
+        });
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// Move the joint at a single speed and measure the speed it achieves. The joint is left moving;
+/// the caller stops it.
+async fn measure(
+    app: &AppHandle,
+    joint: u8,
+    speed: f32,
+    travel: f32,
+    limits: Option<(f32, f32)>,
+) -> Result<SpeedPoint, String> {
+    let start_angle = read_angle(app, joint).await?;
+    let end_angle = start_angle + travel.copysign(speed);
+    if let Some((min, max)) = limits {
+        if end_angle < min || end_angle > max {
+            return Err(format!(
+                "Moving joint {} {} from {} would pass its soft limits",
+                joint, travel, start_angle
+            ));
+        }
+    }
+
+    crate::move_joint_continuous(app.state::<AppState>(), joint, speed)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let deadline = Duration::from_secs_f32(2.0 * travel / speed.abs()) + TRAVEL_GRACE;
+    let start = Instant::now();
+    let mut samples = Vec::new();
+    loop {
+        let angle = read_angle(app, joint).await?;
+        let covered = (angle - start_angle).abs();
+        if let Some((min, max)) = limits {
+            if angle < min || angle > max {
+                return Err(format!(
+                    "Joint {} reached its soft limits at {} with {} samples",
+                    joint,
+                    angle,
+                    samples.len()
+                ));
+            }
+        }
+        if covered >= travel {
+            break;
+        }
+        if covered >= travel * ACCELERATION_FRACTION {
+            samples.push((start.elapsed().as_secs_f32(), angle));
+        }
+        if start.elapsed() >= deadline {
+            return Err(format!(
+                "Joint {} only covered {} of {} in time",
+                joint, covered, travel
+            ));
+        }
+    }
+
+    if samples.len() < MIN_SAMPLES {
+        return Err(format!(
+            "Only {} samples were read at speed {}, increase the travel",
+            samples.len(),
+            speed
+        ));
+    }
+    let achieved = regression_slope(&samples)
+        .ok_or_else(|| format!("Samples at speed {} were all read at once", speed))?;
+    Ok(SpeedPoint {
+        commanded: speed,
+        achieved,
+        error_percent: (achieved - speed) / speed * 100.0,
+        samples: samples.len(),
+    })
+}
+
+/// Measure how closely a joint follows each of the given speeds.
+///
+/// # Arguments
+///
+/// * `joint` - Joint to test.
+/// * `speeds` - Speeds to command, in order. The sign of each gives the direction. Must be
+///   nonzero.
+/// * `travel` - Distance to move the joint for each speed. Must be positive, and long enough to
+///   sample the steady-state portion of the motion at the fastest speed.
+///
+/// # Returns
+///
+/// The outcome of each speed measured, or an error if the arguments are invalid. A problem while
+/// moving, such as the joint nearing its soft limits, stops the joint and ends the test early
+/// with the reason in the report.
+pub async fn run(
+    app: &AppHandle,
+    joint: u8,
+    speeds: Vec<f32>,
+    travel: f32,
+) -> Result<SpeedTestReport, String> {
+    if travel.is_nan() || travel <= 0.0 {
+        return Err("Travel must be positive".into());
+    }
+    if speeds.iter().any(|speed| speed.is_nan() || *speed == 0.0) {
+        return Err("Speeds must be nonzero".into());
+    }
+
+    let settings = app.state::<AppState>().settings.lock().await.clone();
+    let limits = settings.soft_limits(joint).map(|limits| {
+        (
+            settings.degrees_to_units(limits.min),
+            settings.degrees_to_units(limits.max),
+        )
+    });
+
+    let mut report = SpeedTestReport {
+        joint,
+        travel,
+        points: Vec::new(),
+        aborted: None,
+    };
+    for speed in speeds {
+        let point = measure(app, joint, speed, travel, limits).await;
+        // The joint is stopped whether or not the point was measured.
+        let stopped = stop(app, joint, speed).await;
+        match point {
+            Ok(point) => report.points.push(point),
+            Err(e) => {
+                report.aborted = Some(e);
+                break;
+            }
+        }
+        if let Err(e) = stopped {
+            report.aborted = Some(e);
+            break;
+        }
+    }
+    Ok(report)
+}
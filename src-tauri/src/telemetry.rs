@@ -0,0 +1,135 @@
+//! Publishing of joint telemetry to an MQTT broker.
+//!
+//! The publisher observes the joint samples the app already reads from the COBOT rather than
+//! polling it separately, and publishes each one as JSON:
+//!
+//! ```json
+//! { "timestamp": 1697414400000, "angles": [0.0, 45.0, ...], "speeds": [0.0, 10.0, ...] }
+//! ```
+//!
+//! When the arm's kinematics are configured, samples also carry the end effector's `pose`, and
+//! when the firmware reports motor currents, they carry `currents_ma`.
+//!
+//! Samples arriving faster than the configured interval are skipped. While the broker is
+//! unreachable, up to `BUFFERED_SAMPLES` samples are queued and newer ones are dropped; the client
+//! keeps trying to reconnect in the background.
+
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use tauri::{async_runtime::JoinHandle, async_runtime::Mutex, Manager};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::AppState;
+
+/// Number of samples queued while the broker is unreachable.
+const BUFFERED_SAMPLES: usize = 256;
+
+/// Port used when the broker URL doesn't specify one.
+const DEFAULT_PORT: u16 = 1883;
+
+/// Time to wait before reconnecting after the connection to the broker fails.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Running publisher, if any.
+pub struct TelemetryState {
+    tasks: Mutex<Option<(JoinHandle<()>, JoinHandle<()>)>>,
+}
+
+impl TelemetryState {
+    pub fn new() -> Self {
+        TelemetryState {
+            tasks: Mutex::new(None),
+        }
+    }
+}
+
+/// Split a broker URL of the form `[mqtt://]host[:port]` into its host and port.
+fn parse_broker_url(broker_url: &str) -> Result<(String, u16), String> {
+    let address = broker_url.strip_prefix("mqtt://").unwrap_or(broker_url);
+    let address = address.trim_end_matches('/');
+    match address.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid broker port: {}", port))?;
+            Ok((host.to_string(), port))
+        }
+        None if !address.is_empty() => Ok((address.to_string(), DEFAULT_PORT)),
+        None => Err("Broker URL is empty".to_string()),
+    }
+}
+
+/// Start publishing joint samples to the given topic, at most once per `interval_ms`.
+#[tauri::command]
+pub async fn start_telemetry(
+    app: tauri::AppHandle,
+    telemetry: tauri::State<'_, TelemetryState>,
+    broker_url: String,
+    topic: String,
+    interval_ms: u64,
+) -> Result<(), String> {
+    let mut tasks = telemetry.tasks.lock().await;
+    if tasks.is_some() {
+        return Err("Telemetry already running".to_string());
+    }
+
+    let (host, port) = parse_broker_url(&broker_url)?;
+    let mut options = MqttOptions::new("cobot-config-tester", host, port);
+    options.set_keep_alive(Duration::from_secs(5));
+    let (client, mut event_loop) = AsyncClient::new(options, BUFFERED_SAMPLES);
+
+    // The event loop does the network I/O and reconnects whenever it's polled after a failure.
+    let connection = tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = event_loop.poll().await {
+                warn!("MQTT connection error: {}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    });
+
+    let mut samples = app.state::<AppState>().joint_samples.subscribe();
+    let interval = Duration::from_millis(interval_ms);
+    let publisher = tauri::async_runtime::spawn(async move {
+        let mut last_published: Option<Instant> = None;
+        loop {
+            let sample = match samples.recv().await {
+                Ok(sample) => sample,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            };
+            if last_published.is_some_and(|last| last.elapsed() < interval) {
+                continue;
+            }
+            last_published = Some(Instant::now());
+
+            let payload = match serde_json::to_vec(&sample) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Failed to serialize joint sample: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = client.try_publish(&topic, QoS::AtLeastOnce, false, payload) {
+                warn!("Dropping joint sample: {}", e);
+            }
+        }
+    });
+
+    info!("Publishing telemetry to {}", broker_url);
+    *tasks = Some((connection, publisher));
+
+    Ok(())
+}
+
+/// Stop publishing joint samples.
+#[tauri::command]
+pub async fn stop_telemetry(telemetry: tauri::State<'_, TelemetryState>) -> Result<(), String> {
+    if let Some((connection, publisher)) = telemetry.tasks.lock().await.take() {
+        publisher.abort();
+        connection.abort();
+    }
+    Ok(())
+}
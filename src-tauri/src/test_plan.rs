@@ -0,0 +1,227 @@
+//! Scripted test plans, run step by step against the connected COBOT.
+//!
+//! The format of a plan is described in `cobot_comms::plan`. Angles and speeds are in the display
+//! frame and the active units, as in the Tauri commands. Steps are run by calling the same
+//! functions as the Tauri commands, so a plan behaves exactly as if an operator had clicked
+//! through it. A running plan can be paused, resumed and aborted, as described in `execution`.
+
+use std::time::{Duration, Instant};
+
+use cobot_comms::{
+    plan::{Action, Step},
+    JointMask,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    checks,
+    execution::{Execution, ExecutionState},
+    AppState,
+};
+
+/// Event emitted as each step of a test plan starts and finishes.
+const STEP_EVENT: &str = "cobot://test-plan-step";
+
+/// Progress of a step, emitted as `cobot://test-plan-step`.
+#[derive(Clone, Debug, Serialize)]
+struct StepProgress<'a> {
+    /// Position of the step in the plan, from 0.
+    index: usize,
+
+    /// Step in progress.
+    step: &'a Step,
+
+    /// Whether the step passed, or `None` while it's still running.
+    passed: Option<bool>,
+
+    /// Why the step failed, if it did.
+    error: Option<&'a str>,
+}
+
+/// Outcome of a single step.
+#[derive(Clone, Debug, Serialize)]
+pub struct StepReport {
+    /// Step that was run.
+    pub step: Step,
+
+    /// Whether the step passed.
+    pub passed: bool,
+
+    /// Why the step failed, if it did.
+    pub error: Option<String>,
+
+    /// Time the step took, in milliseconds.
+    pub duration_ms: u64,
+}
+
+/// Outcome of a test plan.
+#[derive(Clone, Debug, Serialize)]
+pub struct TestPlanReport {
+    /// Name of the plan, if it has one.
+    pub name: Option<String>,
+
+    /// Whether every step that ran passed.
+    pub passed: bool,
+
+    /// Outcome of each step that ran. Steps after a failure that stopped the plan are missing.
+    pub steps: Vec<StepReport>,
+
+    /// Number of steps in the plan.
+    pub total_steps: usize,
+}
+
+/// Run the steps of a test plan, emitting the progress of each step. The caller connects to the
+/// plan's port first, if it has one.
+///
+/// # Returns
+///
+/// The report of every step that ran, or an error if another plan is already running.
+pub async fn run(
+    app: &AppHandle,
+    name: Option<String>,
+    steps: Vec<Step>,
+) -> Result<TestPlanReport, String> {
+    let execution = &app.state::<AppState>().execution;
+    execution.start()?;
+
+    let mut report = TestPlanReport {
+        name,
+        passed: true,
+        steps: Vec::new(),
+        total_steps: steps.len(),
+    };
+    let mut stopped_early = false;
+
+    for (index, step) in steps.into_iter().enumerate() {
+        // A pause between steps holds the plan here.
+        if execution.wait_while_paused().await == ExecutionState::Aborted {
+            report.passed = false;
+            stopped_early = true;
+            break;
+        }
+        execution.set_step_joints(step.action.joints());
+
+        let _ = app.emit_all(
+            STEP_EVENT,
+            StepProgress {
+                index,
+                step: &step,
+                passed: None,
+                error: None,
+            },
+        );
+
+        let start = Instant::now();
+        let result = run_interruptible(app, execution, &step.action).await;
+        let error = result.err();
+        let _ = app.emit_all(
+            STEP_EVENT,
+            StepProgress {
+                index,
+                step: &step,
+                passed: Some(error.is_none()),
+                error: error.as_deref(),
+            },
+        );
+
+        let stop = error.is_some() && !step.continue_on_failure;
+        report.passed &= error.is_none();
+        report.steps.push(StepReport {
+            step,
+            passed: error.is_none(),
+            error,
+            duration_ms: start.elapsed().as_millis() as u64,
+        });
+        if stop {
+            stopped_early = true;
+            break;
+        }
+    }
+
+    execution.set_step_joints(JointMask::default());
+    let finished = if stopped_early || execution.state() == ExecutionState::Aborted {
+        ExecutionState::Aborted
+    } else {
+        ExecutionState::Idle
+    };
+    execution.set_state(finished);
+    Ok(report)
+}
+
+/// Run a single step, running it again from its start if a pause interrupted it.
+async fn run_interruptible(
+    app: &AppHandle,
+    execution: &Execution,
+    action: &Action,
+) -> Result<(), String> {
+    loop {
+        let result = run_step(app, action).await;
+        if result.is_ok() || execution.state() != ExecutionState::Paused {
+            return result;
+        }
+        if execution.wait_while_paused().await == ExecutionState::Aborted {
+            return Err("Aborted by the operator".into());
+        }
+    }
+}
+
+/// Run a single step through the matching Tauri command.
+async fn run_step(app: &AppHandle, action: &Action) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let result = match *action {
+        Action::Init { force } => crate::init(app.clone(), state, Some(force))
+            .await
+            .map(|_| ()),
+        Action::EnableMotion => crate::enable_motion(state, true).await,
+        Action::Calibrate { .. } => crate::calibrate(app.clone(), state, action.joints()).await,
+        Action::MoveJoint {
+            joint,
+            angle,
+            speed,
+        } => crate::move_joint(app.clone(), state, joint, angle, speed).await,
+        Action::StopAll { immediately } => crate::stop_all_joints(state, immediately).await,
+        Action::Wait { ms } => {
+            let execution = &app.state::<AppState>().execution;
+            if execution.dwell(Duration::from_millis(ms)).await == ExecutionState::Aborted {
+                return Err("Aborted by the operator".into());
+            }
+            Ok(())
+        }
+        Action::ExpectAngle {
+            joint,
+            angle,
+            tolerance,
+            within_ms,
+            settle_ms,
+        } => {
+            let within = Duration::from_millis(within_ms);
+            let settle = Duration::from_millis(settle_ms);
+            let check =
+                checks::wait_for_angle(app, joint, angle, tolerance, within, settle).await?;
+            if !check.passed {
+                return Err(format!(
+                    "Joint {} at {}, expected {} ± {}",
+                    joint, check.achieved, angle, tolerance
+                ));
+            }
+            Ok(())
+        }
+        Action::ExpectStopped {
+            ref joints,
+            speed_threshold,
+            within_ms,
+        } => {
+            let within = Duration::from_millis(within_ms);
+            let check = checks::wait_for_stopped(app, joints, speed_threshold, within).await?;
+            if !check.passed {
+                return Err(format!(
+                    "Joints {:?} still moving at {:?}",
+                    joints, check.speeds
+                ));
+            }
+            Ok(())
+        }
+    };
+    result.map_err(|e| e.to_string())
+}
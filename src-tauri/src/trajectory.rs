@@ -0,0 +1,86 @@
+//! Host-side processing of joint-space trajectories.
+
+/// Smooth a trajectory by linearly interpolating between each consecutive pair of waypoints, so
+/// the joints don't jerk between sparse waypoints.
+///
+/// # Arguments
+///
+/// * `waypoints` - Angle of each joint at each waypoint, in degrees. Any number of joints is
+///   supported, as long as every waypoint has the same number.
+/// * `steps` - Number of segments to split each pair of waypoints into. `0` or `1` leaves the
+///   trajectory unchanged.
+///
+/// # Returns
+///
+/// The original waypoints with `steps - 1` interpolated waypoints between each consecutive pair.
+pub fn smooth_trajectory(waypoints: &[Vec<f32>], steps: usize) -> Vec<Vec<f32>> {
+    if steps <= 1 || waypoints.len() < 2 {
+        return waypoints.to_vec();
+    }
+
+    let mut smoothed = Vec::with_capacity((waypoints.len() - 1) * steps + 1);
+    for pair in waypoints.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        for step in 0..steps {
+            let t = step as f32 / steps as f32;
+            smoothed.push(
+                from.iter()
+                    .zip(to)
+                    .map(|(from, to)| from + (to - from) * t)
+                    .collect(),
+            );
+        }
+    }
+    smoothed.extend(waypoints.last().cloned());
+
+    smoothed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserts_steps_between_each_pair_of_waypoints() {
+        let waypoints = vec![vec![0.0, 10.0], vec![40.0, 10.0], vec![20.0, -30.0]];
+        let smoothed = smooth_trajectory(&waypoints, 4);
+
+        assert_eq!(smoothed.len(), 9);
+        assert_eq!(smoothed[0], waypoints[0]);
+        assert_eq!(smoothed[4], waypoints[1]);
+        assert_eq!(smoothed[8], waypoints[2]);
+        assert_eq!(smoothed[2], vec![20.0, 10.0]);
+    }
+
+    #[test]
+    fn intermediate_points_are_monotonic_between_waypoints() {
+        let waypoints = vec![vec![0.0, 10.0], vec![40.0, 10.0], vec![20.0, -30.0]];
+        let smoothed = smooth_trajectory(&waypoints, 4);
+
+        // Each segment runs from one waypoint to the next, with both ends included.
+        for segment in smoothed.windows(5).step_by(4) {
+            for joint in 0..2 {
+                let direction = segment[4][joint] - segment[0][joint];
+                for pair in segment.windows(2) {
+                    let change = pair[1][joint] - pair[0][joint];
+                    assert!(
+                        change * direction >= 0.0,
+                        "joint {} reverses between {:?} and {:?}",
+                        joint,
+                        pair[0],
+                        pair[1]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_short_or_unsplit_trajectories_unchanged() {
+        let waypoints = vec![vec![0.0], vec![90.0]];
+        assert_eq!(smooth_trajectory(&waypoints, 0), waypoints);
+        assert_eq!(smooth_trajectory(&waypoints, 1), waypoints);
+        assert_eq!(smooth_trajectory(&waypoints[..1], 4), &waypoints[..1]);
+        assert!(smooth_trajectory(&[], 4).is_empty());
+    }
+}
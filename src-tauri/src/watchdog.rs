@@ -0,0 +1,264 @@
+//! Watchdog that stops the COBOT when the frontend stops sending heartbeats while motion it
+//! started is in progress.
+//!
+//! Jogging under `move_joint_continuous` is tracked by the joints it moves, since it goes on after
+//! the command returns. Every other motion, from a single move to a whole test plan, counts as in
+//! progress for as long as its command runs, through a `MotionGuard`.
+
+use std::{
+    error::Error,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use cobot_comms::{CobotConnection, JointMask, Transport};
+use serde::Serialize;
+
+/// Interval at which the watchdog checks for missed heartbeats.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of motions in progress that the frontend supervises with heartbeats.
+#[derive(Debug, Default)]
+pub struct ActiveMotions(AtomicUsize);
+
+impl ActiveMotions {
+    /// Count a motion as in progress until the returned guard is dropped.
+    pub fn start(&self) -> MotionGuard<'_> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        MotionGuard(&self.0)
+    }
+
+    /// Get the number of motions in progress.
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Motion counted by `ActiveMotions` until dropped, however its command ends.
+#[must_use = "the motion stops being counted as soon as the guard is dropped"]
+pub struct MotionGuard<'a>(&'a AtomicUsize);
+
+impl Drop for MotionGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Record of the watchdog stopping the COBOT, kept in debug reports and autosaves.
+#[derive(Clone, Debug, Serialize)]
+pub struct WatchdogIncident {
+    /// Time the watchdog fired, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+
+    /// Time since the last heartbeat, in milliseconds.
+    pub since_heartbeat_ms: u64,
+
+    /// Joints that were jogging under `move_joint_continuous`.
+    pub jogging: JointMask,
+
+    /// Number of other motions that were in progress.
+    pub active_motions: usize,
+
+    /// Why the COBOT couldn't be stopped, or `None` if it was.
+    pub error: Option<String>,
+}
+
+/// Check whether the watchdog must stop the COBOT: some motion is in progress and the last
+/// heartbeat is at least `timeout` old.
+///
+/// # Arguments
+///
+/// * `jogging` - Joints jogging under `move_joint_continuous`.
+/// * `active_motions` - Number of other motions in progress.
+/// * `since_heartbeat` - Time since the last heartbeat.
+/// * `timeout` - Longest allowed time between heartbeats.
+pub fn heartbeat_missed(
+    jogging: JointMask,
+    active_motions: usize,
+    since_heartbeat: Duration,
+    timeout: Duration,
+) -> bool {
+    (!jogging.is_empty() || active_motions > 0) && since_heartbeat >= timeout
+}
+
+/// Stop every joint of the COBOT, decelerating smoothly, after a missed heartbeat.
+pub fn stop_all<T: Transport>(cobot: &mut CobotConnection<T>) -> Result<(), Box<dyn Error>> {
+    let all_joints = cobot.all_joints();
+    cobot.stop(all_joints, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cobot_comms::{
+        checksum::crc8ccitt, received_msg_type, request_type, response_type, MockTransport,
+        FIRMWARE_VERSION,
+    };
+
+    const TIMEOUT: Duration = Duration::from_millis(1500);
+
+    /// Frame a response to a request as the COBOT would send it.
+    fn response_frame(response_type: u8, command_id: u32) -> Vec<u8> {
+        let mut message = vec![received_msg_type::RESPONSE, response_type];
+        message.extend_from_slice(&command_id.to_le_bytes());
+        let mut frame = vec![0x24, message.len() as u8, crc8ccitt(&message)];
+        frame.extend_from_slice(&message);
+        frame
+    }
+
+    #[test]
+    fn guards_count_motions_until_dropped() {
+        let motions = ActiveMotions::default();
+        let first = motions.start();
+        let second = motions.start();
+        assert_eq!(motions.count(), 2);
+        drop(first);
+        assert_eq!(motions.count(), 1);
+        drop(second);
+        assert_eq!(motions.count(), 0);
+    }
+
+    #[test]
+    fn heartbeat_only_missed_during_motion() {
+        let late = TIMEOUT + Duration::from_millis(1);
+        assert!(!heartbeat_missed(JointMask::default(), 0, late, TIMEOUT));
+        assert!(heartbeat_missed(JointMask::joint(2), 0, late, TIMEOUT));
+        assert!(heartbeat_missed(JointMask::default(), 1, late, TIMEOUT));
+        assert!(heartbeat_missed(JointMask::default(), 1, TIMEOUT, TIMEOUT));
+        assert!(!heartbeat_missed(
+            JointMask::joint(2),
+            1,
+            TIMEOUT - Duration::from_millis(1),
+            TIMEOUT
+        ));
+    }
+
+    #[test]
+    fn missed_heartbeat_stops_every_joint() {
+        let motions = ActiveMotions::default();
+        let _motion = motions.start();
+        assert!(heartbeat_missed(
+            JointMask::default(),
+            motions.count(),
+            Duration::from_secs(2),
+            TIMEOUT
+        ));
+
+        let mut port = MockTransport::new();
+        port.push_incoming(&response_frame(response_type::ACK, 0));
+        port.push_incoming(&response_frame(response_type::DONE, 0));
+        let mut cobot = CobotConnection::new(port, FIRMWARE_VERSION, Duration::from_millis(20));
+        stop_all(&mut cobot).unwrap();
+
+        // A smooth STOP of all eight joints a COBOT of unknown size may have, as command 0.
+        let body = [request_type::STOP, 0, 0, 0, 0, 0, 0xFF];
+        let mut expected = vec![0x24, body.len() as u8, crc8ccitt(&body)];
+        expected.extend_from_slice(&body);
+        assert_eq!(cobot.into_port().written, expected);
+    }
+
+    /// Frame a STOP of every joint as `stop_all` sends it.
+    fn stop_all_frame(command_id: u32) -> Vec<u8> {
+        let mut body = vec![request_type::STOP];
+        body.extend_from_slice(&command_id.to_le_bytes());
+        body.extend_from_slice(&[0, 0xFF]);
+        let mut frame = vec![0x24, body.len() as u8, crc8ccitt(&body)];
+        frame.extend_from_slice(&body);
+        frame
+    }
+
+    /// Poll as the watchdog does, every `POLL_INTERVAL` up to `until`, with heartbeats at the
+    /// given times, stopping the COBOT at the first missed heartbeat.
+    ///
+    /// # Returns
+    ///
+    /// The time the COBOT was stopped at, or `None` if it never was.
+    fn run_watchdog<T: Transport>(
+        cobot: &mut CobotConnection<T>,
+        motions: &ActiveMotions,
+        heartbeats: &[Duration],
+        until: Duration,
+    ) -> Option<Duration> {
+        let mut now = Duration::ZERO;
+        while now < until {
+            now += POLL_INTERVAL;
+            let last_heartbeat = heartbeats
+                .iter()
+                .copied()
+                .filter(|&heartbeat| heartbeat <= now)
+                .max()
+                .unwrap_or_default();
+            if heartbeat_missed(
+                JointMask::default(),
+                motions.count(),
+                now - last_heartbeat,
+                TIMEOUT,
+            ) {
+                stop_all(cobot).unwrap();
+                return Some(now);
+            }
+        }
+        None
+    }
+
+    /// Connection that has started calibrating joint 0 as command 0, and answers a STOP as
+    /// command 1.
+    fn calibrating_cobot() -> CobotConnection<MockTransport> {
+        let mut port = MockTransport::new();
+        port.push_incoming(&response_frame(response_type::ACK, 0));
+        port.push_incoming(&response_frame(response_type::ACK, 1));
+        port.push_incoming(&response_frame(response_type::DONE, 1));
+        let mut cobot = CobotConnection::new(port, FIRMWARE_VERSION, Duration::from_millis(20));
+        cobot.start_calibrate(JointMask::joint(0)).unwrap();
+        cobot
+    }
+
+    /// Split the frames written by `calibrating_cobot` into the CALIBRATE and what followed it.
+    fn frames_after_calibrate(cobot: CobotConnection<MockTransport>) -> Vec<u8> {
+        let written = cobot.into_port().written;
+        assert_eq!(written[3], request_type::CALIBRATE);
+        let calibrate_len = 3 + written[1] as usize;
+        written[calibrate_len..].to_vec()
+    }
+
+    #[test]
+    fn stop_is_sent_at_the_first_poll_past_the_timeout() {
+        let mut cobot = calibrating_cobot();
+        let motions = ActiveMotions::default();
+        let motion = motions.start();
+
+        let stopped_at = run_watchdog(&mut cobot, &motions, &[], Duration::from_secs(5));
+        assert_eq!(stopped_at, Some(TIMEOUT));
+        drop(motion);
+
+        // Nothing was sent between the CALIBRATE and the single STOP.
+        assert_eq!(frames_after_calibrate(cobot), stop_all_frame(1));
+    }
+
+    #[test]
+    fn heartbeats_put_off_the_stop() {
+        let mut cobot = calibrating_cobot();
+        let motions = ActiveMotions::default();
+        let motion = motions.start();
+
+        let heartbeats = [Duration::from_millis(500), Duration::from_millis(1200)];
+        let stopped_at = run_watchdog(&mut cobot, &motions, &heartbeats, Duration::from_secs(5));
+        assert_eq!(stopped_at, Some(Duration::from_millis(1200) + TIMEOUT));
+        drop(motion);
+
+        assert_eq!(frames_after_calibrate(cobot), stop_all_frame(1));
+    }
+
+    #[test]
+    fn finished_motion_is_never_stopped() {
+        let mut cobot = calibrating_cobot();
+        let motions = ActiveMotions::default();
+        drop(motions.start());
+
+        assert_eq!(
+            run_watchdog(&mut cobot, &motions, &[], Duration::from_secs(5)),
+            None
+        );
+        assert!(frames_after_calibrate(cobot).is_empty());
+    }
+}